@@ -0,0 +1,162 @@
+//! Compression and decompression throughput across kinds and sizes of data.
+//!
+//! Run with `cargo bench` from this directory, adding `--features cpp` to compare against the
+//! original C++ implementation.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lzokay::{
+    compress::{compress_no_alloc, compress_worst_size, new_dict},
+    decompress::decompress,
+};
+
+const SIZES: [usize; 3] = [4 * 1024, 64 * 1024, 1024 * 1024];
+
+/// A kind of input, named for the report.
+struct Corpus {
+    name: &'static str,
+    generate: fn(usize) -> Vec<u8>,
+}
+
+const CORPORA: [Corpus; 4] = [
+    Corpus { name: "text", generate: text },
+    Corpus { name: "binary", generate: binary },
+    Corpus { name: "zeros", generate: zeros },
+    Corpus { name: "random", generate: random },
+];
+
+/// English text, repeated with small edits so matches vary in length.
+fn text(size: usize) -> Vec<u8> {
+    let source = include_bytes!("../../src/test1.txt");
+    let mut data = Vec::with_capacity(size);
+    let mut round = 0u8;
+    while data.len() < size {
+        let start = data.len();
+        data.extend_from_slice(source);
+        for i in (start..data.len()).step_by(97) {
+            data[i] = data[i].wrapping_add(round);
+        }
+        round = round.wrapping_add(1);
+    }
+    data.truncate(size);
+    data
+}
+
+/// Fixed-size records of counters, flags and padding, like a table dump.
+fn binary(size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size + 16);
+    let mut i = 0u32;
+    while data.len() < size {
+        data.extend_from_slice(&i.to_le_bytes());
+        data.extend_from_slice(&(i.wrapping_mul(2_654_435_761) >> 20).to_le_bytes());
+        data.extend_from_slice(&[(i % 7) as u8, 0, 0, 0, 0xff, 0xff, 0, 1]);
+        i += 1;
+    }
+    data.truncate(size);
+    data
+}
+
+fn zeros(size: usize) -> Vec<u8> { vec![0u8; size] }
+
+/// Incompressible bytes from a xorshift generator.
+fn random(size: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// A compressor under comparison, with a reused dictionary.
+type CompressFn = fn(&[u8], &mut [u8]) -> usize;
+/// A decompressor under comparison.
+type DecompressFn = fn(&[u8], &mut [u8]) -> usize;
+
+fn lzokay_compress(src: &[u8], dst: &mut [u8]) -> usize {
+    thread_local!(static DICT: std::cell::RefCell<lzokay::compress::Dict<'static>> =
+        std::cell::RefCell::new(new_dict()));
+    DICT.with(|dict| compress_no_alloc(src, dst, &mut dict.borrow_mut()).unwrap())
+}
+
+fn lzokay_decompress(src: &[u8], dst: &mut [u8]) -> usize { decompress(src, dst).unwrap() }
+
+#[cfg(feature = "cpp")]
+fn cpp_compress(src: &[u8], dst: &mut [u8]) -> usize {
+    thread_local!(static DICT: std::cell::RefCell<lzokay::cpp::Dict<'static>> =
+        std::cell::RefCell::new(lzokay::cpp::new_dict()));
+    DICT.with(|dict| lzokay::cpp::compress_no_alloc(src, dst, &mut dict.borrow_mut()).unwrap())
+}
+
+#[cfg(feature = "cpp")]
+fn cpp_decompress(src: &[u8], dst: &mut [u8]) -> usize {
+    lzokay::cpp::decompress(src, dst).unwrap()
+}
+
+/// Implementations compared, by name. Other crates can be added here behind a feature.
+fn implementations() -> Vec<(&'static str, CompressFn, DecompressFn)> {
+    #[allow(unused_mut)]
+    let mut implementations: Vec<(&'static str, CompressFn, DecompressFn)> =
+        vec![("lzokay", lzokay_compress, lzokay_decompress)];
+    #[cfg(feature = "cpp")]
+    implementations.push(("cpp", cpp_compress, cpp_decompress));
+    implementations
+}
+
+fn bench_compress(c: &mut Criterion) {
+    for corpus in &CORPORA {
+        let mut group = c.benchmark_group(format!("compress/{}", corpus.name));
+        for &size in &SIZES {
+            let src = (corpus.generate)(size);
+            let mut dst = vec![0u8; compress_worst_size(size)];
+            group.throughput(Throughput::Bytes(size as u64));
+            for (name, compress, _) in implementations() {
+                group.bench_with_input(BenchmarkId::new(name, size), &src, |b, src| {
+                    b.iter(|| compress(src, &mut dst))
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+fn bench_decompress(c: &mut Criterion) {
+    for corpus in &CORPORA {
+        let mut group = c.benchmark_group(format!("decompress/{}", corpus.name));
+        for &size in &SIZES {
+            let src = (corpus.generate)(size);
+            let mut compressed = vec![0u8; compress_worst_size(size)];
+            let compressed_size = lzokay_compress(&src, &mut compressed);
+            compressed.truncate(compressed_size);
+            let mut dst = vec![0u8; size];
+            group.throughput(Throughput::Bytes(size as u64));
+            for (name, _, decompress) in implementations() {
+                group.bench_with_input(BenchmarkId::new(name, size), &compressed, |b, src| {
+                    b.iter(|| decompress(src, &mut dst))
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+/// Compression at each level, on text.
+fn bench_levels(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compress_level/text");
+    let src = text(SIZES[1]);
+    let mut dst = vec![0u8; compress_worst_size(src.len())];
+    let mut dict = new_dict();
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    for level in lzokay::compress::MIN_LEVEL..=lzokay::compress::MAX_LEVEL {
+        dict.set_level(level).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(level), |b| {
+            b.iter(|| compress_no_alloc(&src, &mut dst, &mut dict).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_compress, bench_decompress, bench_levels);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+// C# bindings for the lzokay native library built with the `capi` feature:
+//
+//     cargo rustc --release --features capi --crate-type cdylib
+//
+// Copy the library (liblzokay.so, liblzokay.dylib or lzokay.dll) next to the application, or
+// into a Unity project's Plugins folder, and this file into the project's sources.
+
+using System;
+using System.Runtime.InteropServices;
+
+namespace Lzokay
+{
+    /// <summary>An error reported by the native library, with its <c>LZOKAY_*</c> code.</summary>
+    public sealed class LzokayException : Exception
+    {
+        /// <summary>Unknown error, or an invalid argument.</summary>
+        public const int Error = -1;
+        /// <summary>Compressed input buffer is invalid or truncated.</summary>
+        public const int InputOverrun = -4;
+        /// <summary>Output buffer was not large enough to store the result.</summary>
+        public const int OutputOverrun = -5;
+        /// <summary>Likely indicates bad compressed input.</summary>
+        public const int LookbehindOverrun = -6;
+        /// <summary>Decompression succeeded, but input buffer has remaining data.</summary>
+        public const int InputNotConsumed = -8;
+
+        /// <summary>The <c>LZOKAY_*</c> error code, matching liblzo2's <c>LZO_E_*</c>.</summary>
+        public int Code { get; }
+
+        public LzokayException(int code) : base(Describe(code)) { Code = code; }
+
+        static string Describe(int code)
+        {
+            switch (code)
+            {
+                case InputOverrun: return "input overrun";
+                case OutputOverrun: return "output overrun";
+                case LookbehindOverrun: return "lookbehind overrun";
+                case InputNotConsumed: return "input not consumed";
+                default: return "lzokay error " + code;
+            }
+        }
+    }
+
+    /// <summary>Codecs and optional subsystems compiled into the native library.</summary>
+    /// <remarks>Mirrors <c>LzokayFeatures</c> in <c>lzokay.h</c>: each field is 1 if enabled.</remarks>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct Features
+    {
+        public byte Compress;
+        public byte Decompress;
+        public byte Alloc;
+        public byte Std;
+        public byte Lzo1b;
+        public byte Lzo1f;
+        public byte Lzo1y;
+        public byte Lzo1z;
+        public byte Lzo2a;
+        public byte ConstDecompress;
+        public byte Trace;
+        public byte Stats;
+        public byte Lzop;
+        public byte CppBackend;
+        public byte ForbidUnsafe;
+        public byte Macros;
+        public byte Capi;
+        public byte Sys;
+        public byte Wasm;
+        public byte Uniffi;
+        public byte Jni;
+        public byte Python;
+        public byte Numpy;
+        public byte Abi3;
+        public byte Cli;
+        public byte Arbitrary;
+        public byte Page;
+        public byte Array;
+        public byte Parallel;
+        public byte TestSupport;
+        public byte Zeroize;
+    }
+
+    /// <summary>LZO1X compression and decompression.</summary>
+    public static class Lzo
+    {
+        const string Library = "lzokay";
+
+        [DllImport(Library, EntryPoint = "lzokay_compress_worst_size")]
+        static extern UIntPtr NativeCompressWorstSize(UIntPtr srcSize);
+
+        [DllImport(Library, EntryPoint = "lzokay_compress")]
+        static extern int NativeCompress(
+            ref byte src, UIntPtr srcSize, ref byte dst, UIntPtr dstSize, out UIntPtr outSize);
+
+        [DllImport(Library, EntryPoint = "lzokay_decompress")]
+        static extern int NativeDecompress(
+            ref byte src, UIntPtr srcSize, ref byte dst, UIntPtr dstSize, out UIntPtr outSize);
+
+        [DllImport(Library, EntryPoint = "lzokay_features")]
+        static extern Features NativeFeatures();
+
+        /// <summary>Worst-case compression size of <paramref name="srcSize"/> bytes.</summary>
+        public static int CompressWorstSize(int srcSize) =>
+            checked((int)NativeCompressWorstSize((UIntPtr)checked((uint)srcSize)).ToUInt64());
+
+        /// <summary>Compresses <paramref name="src"/> into <paramref name="dst"/>, returning the
+        /// compressed size.</summary>
+        /// <remarks>For sizing <paramref name="dst"/>, use <see cref="CompressWorstSize"/>.</remarks>
+        public static int Compress(ReadOnlySpan<byte> src, Span<byte> dst) =>
+            Check(NativeCompress(
+                ref MemoryMarshal.GetReference(src), (UIntPtr)(uint)src.Length,
+                ref MemoryMarshal.GetReference(dst), (UIntPtr)(uint)dst.Length, out var size), size);
+
+        /// <summary>Compresses <paramref name="src"/>, returning the compressed bytes.</summary>
+        public static byte[] Compress(ReadOnlySpan<byte> src)
+        {
+            var dst = new byte[CompressWorstSize(src.Length)];
+            Array.Resize(ref dst, Compress(src, dst));
+            return dst;
+        }
+
+        /// <summary>Decompresses <paramref name="src"/> into <paramref name="dst"/>, returning the
+        /// decompressed size.</summary>
+        /// <remarks><paramref name="dst"/> must be large enough to hold the entire output.</remarks>
+        public static int Decompress(ReadOnlySpan<byte> src, Span<byte> dst) =>
+            Check(NativeDecompress(
+                ref MemoryMarshal.GetReference(src), (UIntPtr)(uint)src.Length,
+                ref MemoryMarshal.GetReference(dst), (UIntPtr)(uint)dst.Length, out var size), size);
+
+        /// <summary>Decompresses <paramref name="src"/>, which must decompress to exactly
+        /// <paramref name="expectedSize"/> bytes, as stored by most archive formats.</summary>
+        public static byte[] Decompress(ReadOnlySpan<byte> src, int expectedSize)
+        {
+            var dst = new byte[expectedSize];
+            if (Decompress(src, dst) != expectedSize)
+            {
+                throw new LzokayException(LzokayException.InputOverrun);
+            }
+            return dst;
+        }
+
+        /// <summary>The features compiled into the native library.</summary>
+        public static Features GetFeatures() => NativeFeatures();
+
+        static int Check(int code, UIntPtr size)
+        {
+            if (code != 0)
+            {
+                throw new LzokayException(code);
+            }
+            return checked((int)size.ToUInt64());
+        }
+    }
+}
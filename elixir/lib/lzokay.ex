@@ -0,0 +1,21 @@
+defmodule Lzokay do
+  @moduledoc """
+  LZO1X compression, backed by the lzokay Rust crate.
+
+  Both functions run on a dirty CPU scheduler, so large inputs don't block normal schedulers.
+  Errors are returned as `{:error, reason}`, with `reason` one of `:lookbehind_overrun`,
+  `:output_overrun`, `:input_overrun`, `:input_not_consumed` or `:error`.
+  """
+
+  @doc """
+  Compresses `data`.
+  """
+  @spec compress(binary()) :: {:ok, binary()} | {:error, atom()}
+  defdelegate compress(data), to: Lzokay.Native
+
+  @doc """
+  Decompresses `data`, which must decompress to exactly `size` bytes.
+  """
+  @spec decompress(binary(), non_neg_integer()) :: {:ok, binary()} | {:error, atom()}
+  defdelegate decompress(data, size), to: Lzokay.Native
+end
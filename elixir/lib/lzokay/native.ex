@@ -0,0 +1,7 @@
+defmodule Lzokay.Native do
+  @moduledoc false
+  use Rustler, otp_app: :lzokay, crate: "lzokay_nif"
+
+  def compress(_data), do: :erlang.nif_error(:nif_not_loaded)
+  def decompress(_data, _size), do: :erlang.nif_error(:nif_not_loaded)
+end
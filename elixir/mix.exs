@@ -0,0 +1,18 @@
+defmodule Lzokay.MixProject do
+  use Mix.Project
+
+  def project do
+    [
+      app: :lzokay,
+      version: "2.0.0",
+      elixir: "~> 1.12",
+      deps: deps()
+    ]
+  end
+
+  def application, do: []
+
+  defp deps do
+    [{:rustler, "~> 0.36", runtime: false}]
+  end
+end
@@ -0,0 +1,60 @@
+//! Erlang NIF, loaded by `Lzokay.Native`.
+//!
+//! Input binaries are borrowed from the caller without copying. Output is written directly
+//! into a newly allocated binary, which is shrunk to size and handed over to the VM.
+
+use lzokay::{compress, decompress};
+use rustler::{Atom, Binary, Env, OwnedBinary};
+
+mod atoms {
+    rustler::atoms! {
+        lookbehind_overrun,
+        output_overrun,
+        input_overrun,
+        error,
+        input_not_consumed,
+    }
+}
+
+fn lzo_error(e: lzokay::Error) -> Atom {
+    match e {
+        lzokay::Error::LookbehindOverrun => atoms::lookbehind_overrun(),
+        lzokay::Error::OutputOverrun => atoms::output_overrun(),
+        lzokay::Error::InputOverrun => atoms::input_overrun(),
+        lzokay::Error::Error => atoms::error(),
+        lzokay::Error::InputNotConsumed => atoms::input_not_consumed(),
+        // Nothing here cancels or verifies
+        lzokay::Error::Cancelled | lzokay::Error::VerificationFailed => atoms::error(),
+    }
+}
+
+/// Allocates a binary of `size` bytes, runs `f` over it, and shrinks it to the returned size.
+fn with_binary<'a>(
+    env: Env<'a>,
+    size: usize,
+    f: impl FnOnce(&mut [u8]) -> Result<usize, lzokay::Error>,
+) -> Result<Binary<'a>, Atom> {
+    let mut out = OwnedBinary::new(size).ok_or_else(atoms::error)?;
+    let len = f(out.as_mut_slice()).map_err(lzo_error)?;
+    if len != size && !out.realloc(len) {
+        return Err(atoms::error());
+    }
+    Ok(out.release(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn compress<'a>(env: Env<'a>, data: Binary<'a>) -> Result<Binary<'a>, Atom> {
+    with_binary(env, compress::compress_worst_size(data.len()), |dst| {
+        compress::compress_no_alloc(data.as_slice(), dst, &mut compress::new_dict())
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn decompress<'a>(env: Env<'a>, data: Binary<'a>, size: usize) -> Result<Binary<'a>, Atom> {
+    with_binary(env, size, |dst| match decompress::decompress(data.as_slice(), dst)? {
+        len if len == size => Ok(len),
+        _ => Err(lzokay::Error::InputOverrun),
+    })
+}
+
+rustler::init!("Elixir.Lzokay.Native");
@@ -0,0 +1,16 @@
+defmodule LzokayTest do
+  use ExUnit.Case
+
+  test "round trip" do
+    input = String.duplicate("lzokay ", 1000)
+    {:ok, compressed} = Lzokay.compress(input)
+    assert byte_size(compressed) < byte_size(input)
+    assert Lzokay.decompress(compressed, byte_size(input)) == {:ok, input}
+  end
+
+  test "errors" do
+    {:ok, compressed} = Lzokay.compress("abcdefgh")
+    assert Lzokay.decompress(compressed, 4) == {:error, :output_overrun}
+    assert Lzokay.decompress(binary_part(compressed, 0, 3), 8) == {:error, :input_overrun}
+  end
+end
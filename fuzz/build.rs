@@ -0,0 +1,11 @@
+fn main() {
+    println!("cargo:rerun-if-changed=src/shim.cpp");
+    println!("cargo:rerun-if-changed=../lzokay/lzokay.cpp");
+    cc::Build::new()
+        .cpp(true)
+        .file("../lzokay/lzokay.cpp")
+        .file("src/shim.cpp")
+        .include("../lzokay")
+        .flag_if_supported("-std=c++14")
+        .compile("lzokay_shim");
+}
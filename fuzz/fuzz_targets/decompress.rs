@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lzokay::decompress::decompress;
+
+// Arbitrary input must never panic, whatever the output buffer size.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let dst_size = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let mut dst = vec![0u8; dst_size];
+    if let Ok(size) = decompress(&data[2..], &mut dst) {
+        assert!(size <= dst_size);
+    }
+});
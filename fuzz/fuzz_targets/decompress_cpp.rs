@@ -0,0 +1,44 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lzokay::decompress::decompress;
+
+extern "C" {
+    fn lzokay_cpp_decompress(
+        src: *const u8,
+        src_size: usize,
+        dst: *mut u8,
+        dst_size: usize,
+        out_size: *mut usize,
+    ) -> i32;
+}
+
+// Both implementations must agree on success and output for arbitrary input.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let dst_size = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let src = &data[2..];
+    let mut dst = vec![0u8; dst_size];
+    let result = decompress(src, &mut dst);
+
+    let mut cpp_dst = vec![0u8; dst_size];
+    let mut cpp_size = 0usize;
+    let cpp_result = unsafe {
+        lzokay_cpp_decompress(
+            src.as_ptr(),
+            src.len(),
+            cpp_dst.as_mut_ptr(),
+            cpp_dst.len(),
+            &mut cpp_size,
+        )
+    };
+    match result {
+        Ok(size) => {
+            assert_eq!(cpp_result, 0, "C++ failed where Rust succeeded");
+            assert_eq!(size, cpp_size);
+            assert!(dst[..size] == cpp_dst[..size]);
+        }
+        Err(e) => assert_ne!(cpp_result, 0, "Rust failed with {:?} where C++ succeeded", e),
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lzokay::{arbitrary::ArbitraryStream, decompress::decompress};
+
+// Generated streams are valid, and must decompress to the model's output.
+fuzz_target!(|stream: ArbitraryStream| {
+    let expected = stream.decompressed();
+    let mut dst = vec![0u8; expected.len()];
+    assert_eq!(decompress(&stream.to_bytes(), &mut dst), Ok(expected.len()));
+    assert_eq!(dst, expected);
+});
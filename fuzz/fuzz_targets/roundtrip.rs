@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lzokay::{compress, decompress};
+
+fuzz_target!(|data: &[u8]| {
+    let compressed = compress::compress(data).expect("Failed to compress");
+    assert!(compressed.len() <= compress::compress_worst_size(data.len()));
+    let mut dst = vec![0u8; data.len()];
+    let size = decompress::decompress(&compressed, &mut dst).expect("Failed to decompress");
+    assert_eq!(size, data.len());
+    assert!(dst == data);
+});
@@ -0,0 +1,7 @@
+// C entry points into the upstream C++ implementation, for differential fuzzing.
+#include <lzokay.hpp>
+
+extern "C" int lzokay_cpp_decompress(const uint8_t* src, size_t src_size, uint8_t* dst,
+                                     size_t dst_size, size_t* out_size) {
+  return static_cast<int>(lzokay::decompress(src, src_size, dst, dst_size, *out_size));
+}
@@ -0,0 +1,197 @@
+#ifndef LZOKAY_H
+#define LZOKAY_H
+
+/* Generated by cbindgen from src/capi.rs. Do not edit manually. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Success.
+ */
+#define LZOKAY_OK 0
+
+/**
+ * Unknown error, or an invalid argument.
+ */
+#define LZOKAY_ERROR -1
+
+/**
+ * Compressed input buffer is invalid or truncated.
+ */
+#define LZOKAY_INPUT_OVERRUN -4
+
+/**
+ * Output buffer was not large enough to store the result.
+ */
+#define LZOKAY_OUTPUT_OVERRUN -5
+
+/**
+ * Likely indicates bad compressed input.
+ */
+#define LZOKAY_LOOKBEHIND_OVERRUN -6
+
+/**
+ * Decompression succeeded, but input buffer has remaining data.
+ */
+#define LZOKAY_INPUT_NOT_CONSUMED -8
+
+/**
+ * Stream mode: compress the fed input.
+ */
+#define LZOKAY_STREAM_COMPRESS 0
+
+/**
+ * Stream mode: decompress the fed input.
+ */
+#define LZOKAY_STREAM_DECOMPRESS 1
+
+/**
+ * Opaque streaming context, created by `lzokay_stream_new`.
+ *
+ * Input is currently buffered until `lzokay_stream_finish`.
+ */
+typedef struct LzokayStream LzokayStream;
+
+/**
+ * Codecs and optional subsystems compiled in, as returned by `lzokay_features`.
+ *
+ * Each field is 1 if the Cargo feature of the same name was compiled in, and 0 otherwise,
+ * as bytes rather than `bool` so the struct is blittable for P/Invoke. Fields are only ever
+ * added at the end.
+ */
+typedef struct LzokayFeatures {
+  uint8_t compress;
+  uint8_t decompress;
+  uint8_t alloc;
+  uint8_t std;
+  uint8_t lzo1b;
+  uint8_t lzo1f;
+  uint8_t lzo1y;
+  uint8_t lzo1z;
+  uint8_t lzo2a;
+  uint8_t const_decompress;
+  uint8_t trace;
+  uint8_t stats;
+  uint8_t lzop;
+  uint8_t cpp_backend;
+  uint8_t forbid_unsafe;
+  uint8_t macros;
+  uint8_t capi;
+  uint8_t sys;
+  uint8_t wasm;
+  uint8_t uniffi;
+  uint8_t jni;
+  uint8_t python;
+  uint8_t numpy;
+  uint8_t abi3;
+  uint8_t cli;
+  uint8_t arbitrary;
+  uint8_t page;
+  uint8_t array;
+  uint8_t parallel;
+  uint8_t test_support;
+  uint8_t zeroize;
+} LzokayFeatures;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Worst-case compression size of `src_size` bytes.
+ */
+size_t lzokay_compress_worst_size(size_t src_size);
+
+/**
+ * Compresses `src` into `dst`, storing the compressed size in `out_size`.
+ *
+ * For sizing `dst`, use `lzokay_compress_worst_size`.
+ *
+ * # Safety
+ *
+ * `src` and `dst` must be valid for `src_size` and `dst_size` bytes respectively,
+ * and `out_size` must be valid for writes.
+ */
+int lzokay_compress(const uint8_t *src,
+                    size_t src_size,
+                    uint8_t *dst,
+                    size_t dst_size,
+                    size_t *out_size);
+
+/**
+ * Decompresses `src` into `dst`, storing the decompressed size in `out_size`.
+ *
+ * `dst` must be large enough to hold the entire decompressed output.
+ *
+ * # Safety
+ *
+ * `src` and `dst` must be valid for `src_size` and `dst_size` bytes respectively,
+ * and `out_size` must be valid for writes.
+ */
+int lzokay_decompress(const uint8_t *src,
+                      size_t src_size,
+                      uint8_t *dst,
+                      size_t dst_size,
+                      size_t *out_size);
+
+/**
+ * Creates a streaming context, with `mode` one of `LZOKAY_STREAM_COMPRESS` or
+ * `LZOKAY_STREAM_DECOMPRESS`.
+ *
+ * Returns `NULL` for an unknown mode. Free with `lzokay_stream_free`.
+ */
+struct LzokayStream *lzokay_stream_new(int mode);
+
+/**
+ * Feeds `src_size` bytes of input to the stream.
+ *
+ * # Safety
+ *
+ * `stream` must come from `lzokay_stream_new`, and `src` must be valid for `src_size` bytes.
+ */
+int lzokay_stream_feed(struct LzokayStream *stream, const uint8_t *src, size_t src_size);
+
+/**
+ * Finishes the stream, storing a pointer to the complete output in `out`
+ * and its size in `out_size`.
+ *
+ * The output remains valid until `lzokay_stream_free`. No more input may be fed afterwards.
+ *
+ * # Safety
+ *
+ * `stream` must come from `lzokay_stream_new`, and `out` and `out_size` must be valid
+ * for writes.
+ */
+int lzokay_stream_finish(struct LzokayStream *stream, const uint8_t **out, size_t *out_size);
+
+/**
+ * Frees a streaming context and its output.
+ *
+ * # Safety
+ *
+ * `stream` must come from `lzokay_stream_new` and not be used afterwards. `NULL` is ignored.
+ */
+void lzokay_stream_free(struct LzokayStream *stream);
+
+/**
+ * Returns the features compiled in, as `lzokay_has_feature` does for each one, without
+ * passing strings.
+ */
+struct LzokayFeatures lzokay_features(void);
+
+/**
+ * Returns 1 if the feature `name`, such as `"lzo1y"` or `"cpp-backend"`, was compiled in,
+ * and 0 if it wasn't or is unknown.
+ *
+ * # Safety
+ *
+ * `name` must be a valid NUL-terminated string, or `NULL`.
+ */
+int lzokay_has_feature(const char *name);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* LZOKAY_H */
@@ -0,0 +1,39 @@
+package com.github.encounter.lzokay;
+
+import java.io.IOException;
+import java.nio.ByteBuffer;
+
+/**
+ * LZO1X compression, backed by the lzokay native library built with the {@code jni} feature.
+ */
+public final class Lzokay {
+    static {
+        System.loadLibrary("lzokay");
+    }
+
+    private Lzokay() {}
+
+    /** Worst-case compression size of {@code srcSize} bytes. */
+    public static native int compressWorstSize(int srcSize);
+
+    /** Compresses {@code src}, returning the compressed bytes. */
+    public static native byte[] compress(byte[] src) throws IOException;
+
+    /** Decompresses {@code src}, which must decompress to exactly {@code expectedSize} bytes. */
+    public static native byte[] decompress(byte[] src, int expectedSize) throws IOException;
+
+    /**
+     * Compresses the first {@code srcSize} bytes of the direct buffer {@code src} into the direct
+     * buffer {@code dst}, returning the compressed size. Buffer positions are not modified.
+     */
+    public static native int compressDirect(ByteBuffer src, int srcSize, ByteBuffer dst)
+            throws IOException;
+
+    /**
+     * Decompresses the first {@code srcSize} bytes of the direct buffer {@code src} into the
+     * direct buffer {@code dst}, returning the decompressed size. Buffer positions are not
+     * modified.
+     */
+    public static native int decompressDirect(ByteBuffer src, int srcSize, ByteBuffer dst)
+            throws IOException;
+}
@@ -0,0 +1,108 @@
+//! Compile-time compression for [lzokay](https://docs.rs/lzokay).
+//!
+//! Use through lzokay's `macros` feature, which re-exports [`include_lzo!`].
+
+use std::{env, fs, path::PathBuf};
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+// lzokay can't be a dependency, as it re-exports this crate, so its compressor is built here
+// from the same sources. With none of lzokay's features declared, only the allocation-free
+// API is compiled.
+#[allow(dead_code, unused_imports)]
+#[path = "../../src/compress.rs"]
+mod compress;
+#[allow(dead_code)]
+#[path = "../../src/format.rs"]
+mod format;
+#[path = "../../src/variant.rs"]
+mod variant;
+#[path = "../../src/word.rs"]
+mod word;
+
+/// Error result codes, mirroring `lzokay::Error`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[allow(dead_code, clippy::enum_variant_names)]
+enum Error {
+    LookbehindOverrun,
+    OutputOverrun,
+    InputOverrun,
+    Error,
+    InputNotConsumed,
+    Cancelled,
+    VerificationFailed,
+}
+
+/// Stands in for `lzokay::CancelToken`; nothing cancels compression here.
+#[allow(dead_code)]
+struct CancelToken;
+
+#[allow(dead_code)]
+impl CancelToken {
+    fn is_cancelled(&self) -> bool { false }
+}
+
+/// Compresses `src` with LZO1X at the default level.
+fn compress(src: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut storage = vec![0u8; compress::dict_storage_size()];
+    let mut dict = compress::dict_from_storage(&mut storage);
+    let mut dst =
+        vec![0u8; compress::checked_compress_worst_size(src.len()).ok_or(Error::OutputOverrun)?];
+    let size = compress::compress_no_alloc(src, &mut dst, &mut dict)?;
+    dst.truncate(size);
+    Ok(dst)
+}
+
+/// Compresses a file at compile time, embedding the compressed bytes.
+///
+/// The path is resolved relative to the invoking crate's `CARGO_MANIFEST_DIR`. Expands to a
+/// `(&'static [u8], usize)` of the LZO1X-compressed data and the file's original size. The crate
+/// is rebuilt when the file changes, but only the compressed form is stored in the binary.
+///
+/// Pairs with `decompress` at runtime, or with `decompress_const` (feature `const-decompress`)
+/// to expand the file at compile time:
+///
+/// ```
+/// use lzokay::{decompress::decompress, include_lzo};
+///
+/// const README: (&[u8], usize) = include_lzo!("README.md");
+///
+/// let mut dst = vec![0u8; README.1];
+/// assert_eq!(decompress(README.0, &mut dst), Ok(README.1));
+/// assert_eq!(dst, include_bytes!("../README.md"));
+/// ```
+///
+/// Available in lzokay with feature `macros`.
+#[proc_macro]
+pub fn include_lzo(input: TokenStream) -> TokenStream {
+    let lit = parse_macro_input!(input as LitStr);
+    let mut path = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default());
+    path.push(lit.value());
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            let message = format!("couldn't read {}: {}", path.display(), e);
+            return syn::Error::new(lit.span(), message).to_compile_error().into();
+        }
+    };
+    let compressed = match compress(&data) {
+        Ok(compressed) => compressed,
+        Err(e) => {
+            let message = format!("couldn't compress {}: {:?}", path.display(), e);
+            return syn::Error::new(lit.span(), message).to_compile_error().into();
+        }
+    };
+    let path = path.to_string_lossy();
+    let compressed = proc_macro2::Literal::byte_string(&compressed);
+    let len = proc_macro2::Literal::usize_suffixed(data.len());
+    quote! {
+        {
+            // Registers the file as a dependency; an unused const isn't stored in the binary
+            const _: &[u8] = include_bytes!(#path);
+            (#compressed as &'static [u8], #len)
+        }
+    }
+    .into()
+}
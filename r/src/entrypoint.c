@@ -0,0 +1,4 @@
+// Forward routine registration to Rust, so the linker keeps the static library
+void R_init_lzokay_extendr(void *dll);
+
+void R_init_lzokay(void *dll) { R_init_lzokay_extendr(dll); }
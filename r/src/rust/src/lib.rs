@@ -0,0 +1,32 @@
+//! R bindings, built by the package's `src/Makevars`.
+
+use extendr_api::prelude::*;
+
+fn lzo_error(e: lzokay::Error) -> Error { Error::Other(format!("lzokay: {:?}", e)) }
+
+/// Compresses `data`, returning the compressed raw vector.
+#[extendr]
+fn compress(data: Raw) -> Result<Raw> {
+    let dst = lzokay::compress::compress(data.as_slice()).map_err(lzo_error)?;
+    Ok(Raw::from_bytes(&dst))
+}
+
+/// Decompresses `data`, which must decompress to exactly `size` bytes.
+#[extendr]
+fn decompress(data: Raw, size: f64) -> Result<Raw> {
+    if !(size >= 0.0 && size.fract() == 0.0 && size <= isize::MAX as f64) {
+        return Err(Error::Other("size must be a non-negative whole number".into()));
+    }
+    let mut dst = vec![0u8; size as usize];
+    let len = lzokay::decompress::decompress(data.as_slice(), &mut dst).map_err(lzo_error)?;
+    if len != dst.len() {
+        return Err(lzo_error(lzokay::Error::InputOverrun));
+    }
+    Ok(Raw::from_bytes(&dst))
+}
+
+extendr_module! {
+    mod lzokay;
+    fn compress;
+    fn decompress;
+}
@@ -0,0 +1,241 @@
+//! # Structured stream generation
+//!
+//! Available with feature `arbitrary`.
+//!
+//! [`ArbitraryStream`] implements [`Arbitrary`](::arbitrary::Arbitrary) for a model of an
+//! LZO1X stream as literal runs and matches, which always serializes to a valid stream. Fuzzers
+//! of random bytes rarely get past a decoder's first few instructions; generated streams reach
+//! long literal runs and matches, the largest distances, and the 2-byte matches only allowed
+//! after a short literal run.
+//!
+//! # Examples
+//!
+//! A [cargo-fuzz](https://github.com/rust-fuzz/cargo-fuzz) target checking a decoder against
+//! the model:
+//!
+//! ```no_run
+//! # fn fuzz_target(f: impl Fn(lzokay::arbitrary::ArbitraryStream)) {}
+//! use lzokay::{arbitrary::ArbitraryStream, decompress::decompress};
+//!
+//! fuzz_target(|stream: ArbitraryStream| {
+//!     let expected = stream.decompressed();
+//!     let mut dst = vec![0u8; expected.len()];
+//!     assert_eq!(decompress(&stream.to_bytes(), &mut dst), Ok(expected.len()));
+//!     assert_eq!(dst, expected);
+//! });
+//! ```
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{compress::TokenWriter, format::MAX_DISTANCE as MAX_DIST};
+
+/// Most tokens generated in a stream.
+const MAX_TOKENS: usize = 4096;
+/// Generation stops once the decompressed size reaches this.
+const MAX_SIZE: usize = 256 * 1024;
+/// Distances at the edges of each match encoding's range.
+const EDGE_DISTANCES: [usize; 8] = [1024, 1025, 2048, 2049, 3072, 16384, 16385, MAX_DIST];
+
+/// An element of an [`ArbitraryStream`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StreamToken {
+    /// Bytes copied from the input.
+    Literal(Vec<u8>),
+    /// `len` bytes copied from `dist` bytes back in the output.
+    Match {
+        /// Number of bytes copied.
+        len: usize,
+        /// Distance back from the end of the output, at least 1.
+        dist: usize,
+    },
+}
+
+/// A valid LZO1X stream, as the tokens it's made of.
+///
+/// Consecutive tokens are never both literal runs, and each match is within the output before
+/// it, so the stream can be serialized with [`to_bytes`](ArbitraryStream::to_bytes).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ArbitraryStream {
+    tokens: Vec<StreamToken>,
+}
+
+impl ArbitraryStream {
+    /// The stream's tokens, in order.
+    pub fn tokens(&self) -> &[StreamToken] { &self.tokens }
+
+    /// The stream's compressed bytes, terminated.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Each token needs at most a few bytes of instruction, plus one per 255 of length
+        let bound = self.tokens.iter().fold(3, |bound, token| match token {
+            StreamToken::Literal(lit) => bound + lit.len() + lit.len() / 255 + 4,
+            StreamToken::Match { len, .. } => bound + len / 255 + 5,
+        });
+        let mut dst = vec![0u8; bound];
+        let mut writer = TokenWriter::new(&mut dst);
+        for token in &self.tokens {
+            let result = match token {
+                StreamToken::Literal(lit) => writer.write_literal(lit),
+                StreamToken::Match { len, dist } => writer.write_match(*len, *dist),
+            };
+            result.expect("Generated an invalid token");
+        }
+        let size = writer.finish().expect("Generated stream is too large");
+        dst.truncate(size);
+        dst
+    }
+
+    /// The stream's decompressed output.
+    pub fn decompressed(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        for token in &self.tokens {
+            match token {
+                StreamToken::Literal(lit) => output.extend_from_slice(lit),
+                StreamToken::Match { len, dist } => {
+                    let start = output.len() - dist;
+                    for i in 0..*len {
+                        output.push(output[start + i]);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// Appends literal bytes, extending the last literal run if there is one.
+    fn push_literal(&mut self, lit: Vec<u8>) {
+        match self.tokens.last_mut() {
+            Some(StreamToken::Literal(last)) => last.extend_from_slice(&lit),
+            _ => self.tokens.push(StreamToken::Literal(lit)),
+        }
+    }
+
+    /// Length of the literal run at the end of the stream, 0 after a match.
+    fn trailing_literals(&self) -> usize {
+        match self.tokens.last() {
+            Some(StreamToken::Literal(lit)) => lit.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Generates a literal run, mostly of lengths with distinct encodings: 1 to 3 literals stored
+/// in a match's state bits, short runs, and runs with extended lengths.
+fn literal(u: &mut Unstructured) -> Result<Vec<u8>> {
+    let len = match u.int_in_range(0..=3)? {
+        0 => u.int_in_range(1..=3)?,
+        1 => u.int_in_range(4..=18)?,
+        2 => u.int_in_range(19..=300)?,
+        _ => u.int_in_range(301..=4096)?,
+    };
+    let mut lit = vec![0u8; len];
+    u.fill_buffer(&mut lit)?;
+    Ok(lit)
+}
+
+/// Generates a match within `size` bytes of output, or `None` if the chosen kind doesn't fit.
+fn lookback(
+    u: &mut Unstructured,
+    size: usize,
+    trailing_literals: usize,
+) -> Result<Option<(usize, usize)>> {
+    let max_dist = size.min(MAX_DIST);
+    let (len, dist) = match u.int_in_range(0..=6)? {
+        // M1: 2 bytes within 1 KiB, after 1 to 3 literals
+        0 if (1..=3).contains(&trailing_literals) => (2, u.int_in_range(1..=max_dist.min(1024))?),
+        // M1: 3 bytes within 2 to 3 KiB, after 4 or more literals
+        1 if trailing_literals >= 4 && size >= 2049 => {
+            (3, u.int_in_range(2049..=max_dist.min(3072))?)
+        }
+        // M2: 3 to 8 bytes within 2 KiB
+        2 => (u.int_in_range(3..=8)?, u.int_in_range(1..=max_dist.min(2048))?),
+        // M3: within 16 KiB, with extended lengths
+        3 => (u.int_in_range(3..=600)?, u.int_in_range(1..=max_dist.min(16384))?),
+        // M4: 16 to 48 KiB back
+        4 if size > 16384 => (u.int_in_range(3..=600)?, u.int_in_range(16385..=max_dist)?),
+        // Distances at the edges of the encodings
+        5 => {
+            let dist = *u.choose(&EDGE_DISTANCES)?;
+            if dist > max_dist {
+                return Ok(None);
+            }
+            (u.int_in_range(3..=40)?, dist)
+        }
+        // Long runs, overlapping their own output
+        6 => (u.int_in_range(256..=8192)?, u.int_in_range(1..=max_dist.min(4))?),
+        _ => return Ok(None),
+    };
+    Ok(Some((len, dist)))
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut stream = ArbitraryStream::default();
+        let mut size = 0;
+        // A stream starts with literals, as matches need earlier output
+        while size == 0 || (!u.is_empty() && stream.tokens.len() < MAX_TOKENS && size < MAX_SIZE) {
+            let trailing = stream.trailing_literals();
+            let token =
+                if size > 0 && u.ratio(2, 3)? { lookback(u, size, trailing)? } else { None };
+            match token {
+                Some((len, dist)) => {
+                    stream.tokens.push(StreamToken::Match { len, dist });
+                    size += len;
+                }
+                None => {
+                    let lit = literal(u)?;
+                    size += lit.len();
+                    stream.push_literal(lit);
+                }
+            }
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use ::arbitrary::{Arbitrary, Unstructured};
+
+    use super::{ArbitraryStream, StreamToken};
+    use crate::decompress::decompress;
+
+    #[test]
+    fn test_arbitrary_streams() {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut data = vec![0u8; 1 << 16];
+        let (mut short_m1, mut far) = (0, 0);
+        for _ in 0..200 {
+            for byte in data.iter_mut() {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                *byte = seed as u8;
+            }
+            let stream = ArbitraryStream::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            let src = stream.to_bytes();
+            let expected = stream.decompressed();
+            let mut dst = vec![0u8; expected.len()];
+            assert_eq!(decompress(&src, &mut dst), Ok(expected.len()));
+            assert_eq!(dst, expected);
+
+            for token in stream.tokens() {
+                match *token {
+                    StreamToken::Match { len: 2, .. } => short_m1 += 1,
+                    StreamToken::Match { dist, .. } if dist > 16384 => far += 1,
+                    _ => {}
+                }
+            }
+        }
+        assert!(short_m1 > 0 && far > 0, "{} {}", short_m1, far);
+
+        // Generation still succeeds without data
+        let stream = ArbitraryStream::arbitrary(&mut Unstructured::new(&[])).unwrap();
+        assert!(!stream.decompressed().is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! # Compression backends
+//!
+//! [`CompressionBackend`] abstracts over where compression and decompression run, so hardware
+//! offload engines can be plugged in without forking the crate. [`Software`] is the default,
+//! pure-Rust implementation.
+//!
+//! Offload engines typically only handle some requests: a limited input size, one direction,
+//! or none at all while the device is busy or missing. A backend reports what it supports
+//! through [`capabilities`](CompressionBackend::capabilities), and may decline any individual
+//! request by returning `None`. [`Fallback`] wraps a backend and runs declined or unsupported
+//! requests in software.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))] {
+//! use lzokay::{
+//!     backend::{Capabilities, CompressionBackend, Fallback},
+//!     compress::{compress_worst_size, new_dict},
+//! };
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! /// An accelerator which is never available.
+//! struct Accelerator;
+//!
+//! impl CompressionBackend for Accelerator {
+//!     fn name(&self) -> &str { "accelerator" }
+//!
+//!     fn capabilities(&self) -> Capabilities { Capabilities::NONE }
+//! }
+//!
+//! let mut backend = Fallback::new(Accelerator);
+//! let mut compressed = vec![0u8; compress_worst_size(input.len())];
+//! let size = backend.compress(&input, &mut compressed, &mut new_dict())?;
+//! let mut dst = [0u8; 512];
+//! backend.decompress(&compressed[..size], &mut dst)?;
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(feature = "compress")]
+use crate::compress::Dict;
+use crate::Error;
+
+/// Requests a [`CompressionBackend`] can handle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Whether the backend can compress.
+    pub compress: bool,
+    /// Whether the backend can decompress.
+    pub decompress: bool,
+    /// Largest source buffer the backend accepts, in bytes.
+    pub max_src_size: usize,
+}
+
+impl Capabilities {
+    /// All requests are supported.
+    pub const ALL: Capabilities =
+        Capabilities { compress: true, decompress: true, max_src_size: usize::MAX };
+    /// No requests are supported.
+    pub const NONE: Capabilities =
+        Capabilities { compress: false, decompress: false, max_src_size: 0 };
+}
+
+/// An implementation of LZO1X compression and decompression.
+///
+/// Streams produced by any backend must be decompressible by any other, but need not be
+/// byte-identical.
+pub trait CompressionBackend {
+    /// Backend name, for diagnostics.
+    fn name(&self) -> &str;
+
+    /// Requests this backend can currently handle.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Compress the supplied buffer, or return `None` to decline the request.
+    ///
+    /// `dict` is provided for software implementations, and may be ignored.
+    #[cfg(feature = "compress")]
+    fn compress(
+        &mut self,
+        _src: &[u8],
+        _dst: &mut [u8],
+        _dict: &mut Dict,
+    ) -> Option<Result<usize, Error>> {
+        None
+    }
+
+    /// Decompress `src` into `dst`, or return `None` to decline the request.
+    #[cfg(feature = "decompress")]
+    fn decompress(&mut self, _src: &[u8], _dst: &mut [u8]) -> Option<Result<usize, Error>> { None }
+}
+
+/// The default, pure-Rust backend. Never declines a request.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Software;
+
+impl CompressionBackend for Software {
+    fn name(&self) -> &str { "software" }
+
+    fn capabilities(&self) -> Capabilities { Capabilities::ALL }
+
+    #[cfg(feature = "compress")]
+    fn compress(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        dict: &mut Dict,
+    ) -> Option<Result<usize, Error>> {
+        Some(crate::compress::compress_no_alloc(src, dst, dict))
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(&mut self, src: &[u8], dst: &mut [u8]) -> Option<Result<usize, Error>> {
+        Some(crate::decompress::decompress(src, dst))
+    }
+}
+
+/// Wraps a backend, running requests it doesn't support or declines with [`Software`].
+///
+/// Errors returned by the wrapped backend are passed through as is.
+#[derive(Debug, Clone, Default)]
+pub struct Fallback<B> {
+    backend: B,
+}
+
+impl<B: CompressionBackend> Fallback<B> {
+    /// Wraps `backend`.
+    pub fn new(backend: B) -> Self { Fallback { backend } }
+
+    /// The wrapped backend.
+    pub fn backend(&self) -> &B { &self.backend }
+
+    /// The wrapped backend, mutably.
+    pub fn backend_mut(&mut self) -> &mut B { &mut self.backend }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> B { self.backend }
+
+    /// Compress the supplied buffer.
+    ///
+    /// For sizing `dst`, use [`compress_worst_size`](crate::compress::compress_worst_size).
+    #[cfg(feature = "compress")]
+    pub fn compress(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        dict: &mut Dict,
+    ) -> Result<usize, Error> {
+        let caps = self.backend.capabilities();
+        if caps.compress && src.len() <= caps.max_src_size {
+            if let Some(result) = self.backend.compress(src, dst, dict) {
+                return result;
+            }
+        }
+        crate::compress::compress_no_alloc(src, dst, dict)
+    }
+
+    /// Decompress `src` into `dst`.
+    ///
+    /// `dst` must be large enough to hold the entire decompressed output.
+    #[cfg(feature = "decompress")]
+    pub fn decompress(&mut self, src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        let caps = self.backend.capabilities();
+        if caps.decompress && src.len() <= caps.max_src_size {
+            if let Some(result) = self.backend.decompress(src, dst) {
+                return result;
+            }
+        }
+        crate::decompress::decompress(src, dst)
+    }
+}
+
+impl<B: CompressionBackend> CompressionBackend for Fallback<B> {
+    fn name(&self) -> &str { self.backend.name() }
+
+    fn capabilities(&self) -> Capabilities { Capabilities::ALL }
+
+    #[cfg(feature = "compress")]
+    fn compress(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        dict: &mut Dict,
+    ) -> Option<Result<usize, Error>> {
+        Some(Fallback::compress(self, src, dst, dict))
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(&mut self, src: &[u8], dst: &mut [u8]) -> Option<Result<usize, Error>> {
+        Some(Fallback::decompress(self, src, dst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{Capabilities, CompressionBackend, Fallback};
+    #[cfg(feature = "compress")]
+    use crate::compress::Dict;
+    #[cfg(any(feature = "compress", feature = "decompress"))]
+    use crate::Error;
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+    #[cfg(feature = "decompress")]
+    const COMPRESSED: &[u8] = include_bytes!("test1.bin");
+
+    /// Decompresses small inputs only, and declines every other request.
+    #[cfg_attr(not(feature = "decompress"), allow(dead_code))]
+    struct Flaky {
+        busy: bool,
+        calls: usize,
+        handled: usize,
+    }
+
+    impl CompressionBackend for Flaky {
+        fn name(&self) -> &str { "flaky" }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities { compress: false, decompress: true, max_src_size: INPUT.len() }
+        }
+
+        #[cfg(feature = "compress")]
+        fn compress(
+            &mut self,
+            _src: &[u8],
+            _dst: &mut [u8],
+            _dict: &mut Dict,
+        ) -> Option<Result<usize, Error>> {
+            unreachable!("compress is not supported")
+        }
+
+        #[cfg(feature = "decompress")]
+        fn decompress(&mut self, src: &[u8], dst: &mut [u8]) -> Option<Result<usize, Error>> {
+            self.calls += 1;
+            self.busy = !self.busy;
+            if !self.busy {
+                return None;
+            }
+            self.handled += 1;
+            Some(crate::decompress::decompress(src, dst))
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        // Only used mutably by the decompression and storage-backed compression checks
+        #[cfg_attr(feature = "forbid-unsafe", allow(unused_mut))]
+        let mut backend = Fallback::new(Flaky { busy: false, calls: 0, handled: 0 });
+        assert_eq!(backend.name(), "flaky");
+        assert_eq!(backend.capabilities(), Capabilities::ALL);
+
+        #[cfg(feature = "decompress")]
+        {
+            let mut dst = [0u8; INPUT.len()];
+            for _ in 0..4 {
+                let size = backend.decompress(COMPRESSED, &mut dst).expect("Failed to decompress");
+                assert_eq!(&dst[..size], INPUT);
+            }
+            assert_eq!(backend.backend().handled, 2);
+            // Errors from the backend aren't retried
+            let result = backend.decompress(&COMPRESSED[..10], &mut dst);
+            assert_eq!(result, Err(Error::InputOverrun));
+            assert_eq!(backend.backend().calls, 5);
+        }
+
+        #[cfg(all(feature = "compress", not(feature = "forbid-unsafe")))]
+        {
+            use crate::compress::{compress_worst_size, dict_from_storage, dict_storage_size};
+
+            let mut storage = [0u8; dict_storage_size()];
+            let mut dict = dict_from_storage(&mut storage);
+            let mut dst = [0u8; compress_worst_size(INPUT.len())];
+            backend.compress(INPUT, &mut dst, &mut dict).expect("Failed to compress");
+        }
+    }
+}
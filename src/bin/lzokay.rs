@@ -0,0 +1,365 @@
+//! Command-line tools for LZO streams.
+//!
+//! Built with feature `cli`:
+//!
+//! ```sh
+//! cargo run --features cli -- inspect data.lzo
+//! ```
+
+use std::{
+    env, fmt, fs,
+    io::{self, Read, Write},
+    process,
+};
+
+use lzokay::{
+    decompress::{Token, TokenIter},
+    Error,
+};
+
+const USAGE: &str = "\
+Usage: lzokay <command> [options]
+
+Commands:
+  inspect [--summary] [--offset N] <file>
+      Prints each instruction of a raw LZO1X stream and summary statistics.
+      --summary    Only print the summary
+      --offset N   Start parsing N bytes into the file, skipping a header
+      Reads standard input if <file> is `-`.
+";
+
+/// Kind of an LZO1X instruction.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    /// Literal run, either the stream's first byte or an instruction in state 0.
+    Literal,
+    /// 2-byte match within 1 KiB, or 3-byte match within 3 KiB after a literal run.
+    M1,
+    /// 3 to 8-byte match within 2 KiB.
+    M2,
+    /// Match within 16 KiB.
+    M3,
+    /// Match within 48 KiB.
+    M4,
+    /// Terminating M4.
+    End,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Kind::Literal => "LIT",
+            Kind::M1 => "M1",
+            Kind::M2 => "M2",
+            Kind::M3 => "M3",
+            Kind::M4 => "M4",
+            Kind::End => "END",
+        };
+        f.pad(name)
+    }
+}
+
+/// A decoded instruction.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Op {
+    kind: Kind,
+    /// Offset of the instruction in the input.
+    input_offset: usize,
+    /// Offset in the decompressed output where the instruction's bytes start.
+    output_offset: usize,
+    /// Bytes copied: literal run length or match length.
+    len: usize,
+    /// Match distance, 0 for literal runs.
+    dist: usize,
+    /// Literals copied after a match, from its two state bits.
+    trailing: usize,
+}
+
+/// Groups an LZO1X stream's tokens into instructions, attaching the literals appended by a
+/// match's state bits to the match.
+struct Ops<'a> {
+    src: &'a [u8],
+    tokens: TokenIter<'a>,
+    /// Token read after a match that wasn't its trailing literals.
+    peeked: Option<Option<Result<(usize, Token), Error>>>,
+    outp: usize,
+}
+
+impl<'a> Ops<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        Ops { src, tokens: TokenIter::new(src), peeked: None, outp: 0 }
+    }
+
+    /// Offset in the input after the last instruction.
+    fn input_offset(&self) -> usize { self.tokens.input_offset() }
+
+    /// Size of the output so far.
+    fn output_offset(&self) -> usize { self.outp }
+}
+
+impl Iterator for Ops<'_> {
+    type Item = Result<Op, (usize, Error)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = match self.peeked.take() {
+            Some(next) => next,
+            None => self.tokens.next(),
+        };
+        let (input_offset, token) = match next? {
+            Ok(next) => next,
+            // After an error, the iterator's offset is the failing token's
+            Err(e) => return Some(Err((self.tokens.input_offset(), e))),
+        };
+        let mut op = Op {
+            kind: Kind::Literal,
+            input_offset,
+            output_offset: self.outp,
+            len: 0,
+            dist: 0,
+            trailing: 0,
+        };
+        match token {
+            Token::Literal(len) => op.len = len,
+            Token::Match { len, dist } => {
+                op.kind = match self.src[input_offset] {
+                    64..=255 => Kind::M2,
+                    32..=63 => Kind::M3,
+                    16..=31 => Kind::M4,
+                    _ => Kind::M1,
+                };
+                op.len = len;
+                op.dist = dist;
+                // Literal run instructions are at least 4 bytes, so up to 3 literals after a
+                // match come from its state bits
+                match self.tokens.next() {
+                    Some(Ok((_, Token::Literal(len)))) if len <= 3 => op.trailing = len,
+                    next => self.peeked = Some(next),
+                }
+            }
+            Token::End => op.kind = Kind::End,
+        }
+        self.outp += op.len + op.trailing;
+        Some(Ok(op))
+    }
+}
+
+/// Totals over a stream's instructions.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Summary {
+    /// Instruction count and bytes produced, indexed by `Kind`.
+    counts: [(usize, usize); 6],
+    /// Literals copied after matches.
+    trailing: usize,
+    /// Sum of match distances.
+    total_dist: usize,
+    input_size: usize,
+    output_size: usize,
+}
+
+impl Summary {
+    fn add(&mut self, op: &Op) {
+        let count = &mut self.counts[op.kind as usize];
+        count.0 += 1;
+        count.1 += op.len;
+        self.trailing += op.trailing;
+        self.total_dist += op.dist;
+    }
+
+    fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let kinds = [Kind::Literal, Kind::M1, Kind::M2, Kind::M3, Kind::M4];
+        let matches: usize = kinds[1..].iter().map(|&kind| self.counts[kind as usize].0).sum();
+        let match_bytes: usize = kinds[1..].iter().map(|&kind| self.counts[kind as usize].1).sum();
+        let literal_bytes = self.counts[Kind::Literal as usize].1 + self.trailing;
+        writeln!(out, "{:>8} {:>10} {:>12}", "", "count", "bytes")?;
+        for &kind in &kinds {
+            let (count, bytes) = self.counts[kind as usize];
+            writeln!(out, "{:>8} {:>10} {:>12}", kind, count, bytes)?;
+        }
+        writeln!(out, "{:>8} {:>10} {:>12}", "trailing", "", self.trailing)?;
+        writeln!(out)?;
+        writeln!(out, "compressed:     {}", self.input_size)?;
+        writeln!(out, "decompressed:   {}", self.output_size)?;
+        if self.output_size > 0 {
+            let ratio = self.input_size as f64 / self.output_size as f64;
+            writeln!(out, "ratio:          {:.3} (compressed / decompressed)", ratio)?;
+            let share = |bytes: usize| 100.0 * bytes as f64 / self.output_size as f64;
+            writeln!(out, "literal bytes:  {} ({:.1}%)", literal_bytes, share(literal_bytes))?;
+            writeln!(out, "match bytes:    {} ({:.1}%)", match_bytes, share(match_bytes))?;
+        }
+        if matches > 0 {
+            writeln!(out, "average match:  {:.1} bytes", match_bytes as f64 / matches as f64)?;
+            let dist = self.total_dist as f64 / matches as f64;
+            writeln!(out, "average dist:   {:.1}", dist)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints `src`'s instructions, unless `summary_only`, then its summary. Returns false if the
+/// stream is invalid.
+fn inspect(src: &[u8], base: usize, summary_only: bool, out: &mut impl Write) -> io::Result<bool> {
+    let mut summary = Summary::default();
+    if !summary_only {
+        writeln!(
+            out,
+            "{:>10} {:>10} {:>4} {:>8} {:>6} {:>5}",
+            "input", "output", "op", "len", "dist", "lits"
+        )?;
+    }
+    let mut ops = Ops::new(src);
+    for result in &mut ops {
+        match result {
+            Ok(op) => {
+                summary.add(&op);
+                if summary_only {
+                    continue;
+                }
+                write!(
+                    out,
+                    "{:>10} {:>10} {:>4}",
+                    base + op.input_offset,
+                    op.output_offset,
+                    op.kind
+                )?;
+                match op.kind {
+                    Kind::End => writeln!(out)?,
+                    Kind::Literal => writeln!(out, " {:>8}", op.len)?,
+                    _ => writeln!(out, " {:>8} {:>6} {:>5}", op.len, op.dist, op.trailing)?,
+                }
+            }
+            Err((offset, e)) => {
+                out.flush()?;
+                eprintln!("error: {:?} in instruction at offset {}", e, base + offset);
+                return Ok(false);
+            }
+        }
+    }
+    summary.input_size = ops.input_offset();
+    summary.output_size = ops.output_offset();
+    if !summary_only {
+        writeln!(out)?;
+    }
+    summary.print(out)?;
+    if ops.input_offset() < src.len() {
+        let trailing = src.len() - ops.input_offset();
+        writeln!(out, "trailing data:  {} bytes after the stream", trailing)?;
+    }
+    Ok(true)
+}
+
+fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(path)
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn run_inspect(args: &[String]) -> io::Result<bool> {
+    let mut summary_only = false;
+    let mut offset = 0usize;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--summary" => summary_only = true,
+            "--offset" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--offset needs a value"));
+                offset = value.parse().unwrap_or_else(|_| usage_error("invalid --offset"));
+            }
+            _ if path.is_none() && (arg == "-" || !arg.starts_with('-')) => path = Some(arg),
+            _ => usage_error(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage_error("missing <file>"));
+    let data = read_input(path)?;
+    if offset > data.len() {
+        usage_error("--offset is past the end of the file");
+    }
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let ok = inspect(&data[offset..], offset, summary_only, &mut out)?;
+    out.flush()?;
+    Ok(ok)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("inspect") => run_inspect(&args[1..]),
+        Some("-h") | Some("--help") | Some("help") => {
+            print!("{}", USAGE);
+            return;
+        }
+        Some(command) => usage_error(&format!("unknown command `{}`", command)),
+        None => usage_error("missing command"),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lzokay::Error;
+
+    use super::{inspect, Kind, Ops};
+
+    const INPUT: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED: &[u8] = include_bytes!("../test1.txt");
+
+    #[test]
+    fn test_ops() {
+        let ops: Vec<_> = Ops::new(INPUT).collect::<Result<_, _>>().expect("Failed to parse");
+        assert_eq!(ops.last().map(|op| op.kind), Some(Kind::End));
+        assert_eq!(ops.last().map(|op| op.input_offset), Some(INPUT.len() - 3));
+        // Replaying the instructions reproduces the decompressed output
+        let mut output = Vec::new();
+        for (i, op) in ops.iter().enumerate() {
+            assert_eq!(op.output_offset, output.len());
+            let next = ops.get(i + 1).map_or(INPUT.len(), |next| next.input_offset);
+            if op.kind == Kind::Literal {
+                output.extend_from_slice(&INPUT[next - op.len..next]);
+            } else {
+                for _ in 0..op.len {
+                    output.push(output[output.len() - op.dist]);
+                }
+                output.extend_from_slice(&INPUT[next - op.trailing..next]);
+            }
+        }
+        assert_eq!(output, EXPECTED);
+    }
+
+    #[test]
+    fn test_ops_errors() {
+        let mut ops = Ops::new(&INPUT[..INPUT.len() - 1]);
+        assert_eq!(ops.find_map(Result::err).map(|(_, e)| e), Some(Error::InputOverrun));
+        // One literal, then an M3 match at distance 2
+        let mut ops = Ops::new(&[0x12, b'a', 0x23, 0x04, 0x00, 0x11, 0x00, 0x00]);
+        assert_eq!(ops.next().map(|op| op.map(|op| op.kind)), Some(Ok(Kind::Literal)));
+        assert_eq!(ops.next(), Some(Err((2, Error::LookbehindOverrun))));
+        assert_eq!(ops.next(), None);
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut out = Vec::new();
+        assert!(inspect(INPUT, 0, false, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("decompressed:   {}", EXPECTED.len())), "{}", out);
+        assert!(out.contains(&format!("compressed:     {}", INPUT.len())), "{}", out);
+    }
+}
@@ -0,0 +1,209 @@
+//! # Build script helpers
+//!
+//! Available with features `compress` and `std`.
+//!
+//! Compresses a directory of assets from a downstream `build.rs`, generating a module that
+//! embeds the compressed files. Add lzokay to `[build-dependencies]`, then in `build.rs`:
+//!
+//! ```no_run
+//! lzokay::build_helpers::compress_dir_to_out_dir("assets", "assets")
+//!     .expect("Failed to compress assets");
+//! ```
+//!
+//! And in the crate, with lzokay's `decompress` feature:
+//!
+//! ```ignore
+//! mod assets {
+//!     include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+//! }
+//!
+//! let asset = assets::get("levels/1.bin").unwrap();
+//! let mut level = vec![0u8; asset.size];
+//! lzokay::decompress::decompress(asset.data, &mut level)?;
+//! ```
+//!
+//! The generated module contains an `Asset` struct with the asset's `name` (its path relative to
+//! the directory, with `/` separators), compressed `data` and decompressed `size`; an `ASSETS`
+//! array of every asset, sorted by name; and a `get` function finding an asset by name.
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compress::{compress_with_dict, new_dict, Dict},
+    Error,
+};
+
+/// A compressed asset, as written by [`compress_dir`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Asset {
+    /// Path relative to the source directory, with `/` separators.
+    pub name: String,
+    /// Path of the compressed file.
+    pub path: PathBuf,
+    /// Size of the original file.
+    pub size: usize,
+    /// Size of the compressed file.
+    pub compressed_size: usize,
+}
+
+fn lzo_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+fn non_utf8(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("non-UTF-8 path: {}", path.display()))
+}
+
+/// Compresses every file under `src` into `dst`, keeping the directory layout and adding a
+/// `.lzo` suffix, and returns the assets sorted by name.
+///
+/// File names must be valid UTF-8.
+pub fn compress_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<Vec<Asset>> {
+    let mut assets = Vec::new();
+    let mut dict = new_dict();
+    compress_dir_impl(src.as_ref(), dst.as_ref(), "", &mut dict, &mut assets)?;
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(assets)
+}
+
+fn compress_dir_impl(
+    src: &Path,
+    dst: &Path,
+    prefix: &str,
+    dict: &mut Dict,
+    assets: &mut Vec<Asset>,
+) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_str().ok_or_else(|| non_utf8(&entry.path()))?;
+        let name = format!("{}{}", prefix, file_name);
+        if entry.file_type()?.is_dir() {
+            let prefix = format!("{}/", name);
+            compress_dir_impl(&entry.path(), &dst.join(file_name), &prefix, dict, assets)?;
+            continue;
+        }
+        let data = fs::read(entry.path())?;
+        let compressed = compress_with_dict(&data, dict).map_err(lzo_error)?;
+        let path = dst.join(format!("{}.lzo", file_name));
+        fs::write(&path, &compressed)?;
+        assets.push(Asset { name, path, size: data.len(), compressed_size: compressed.len() });
+    }
+    Ok(())
+}
+
+/// Writes a module embedding `assets` to `path`, for use with `include!`.
+///
+/// The compressed files are referenced by absolute path, so they must stay in place until the
+/// crate including the module is built.
+pub fn write_index(assets: &[Asset], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut assets: Vec<&Asset> = assets.iter().collect();
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut index = String::new();
+    index.push_str(
+        "// Generated by lzokay::build_helpers, do not edit.
+
+/// An LZO1X-compressed asset.
+#[derive(Debug, Clone, Copy)]
+pub struct Asset {
+    /// Path relative to the asset directory, with `/` separators.
+    pub name: &'static str,
+    /// Compressed contents.
+    pub data: &'static [u8],
+    /// Decompressed size.
+    pub size: usize,
+}
+
+/// Every asset, sorted by name.
+pub static ASSETS: &[Asset] = &[
+",
+    );
+    for asset in assets {
+        let file = asset.path.canonicalize()?;
+        let file = file.to_str().ok_or_else(|| non_utf8(&asset.path))?;
+        writeln!(
+            index,
+            "    Asset {{ name: {:?}, data: include_bytes!({:?}), size: {} }},",
+            asset.name, file, asset.size
+        )
+        .unwrap();
+    }
+    index.push_str(
+        "];
+
+/// Finds an asset by name.
+#[allow(dead_code)]
+pub fn get(name: &str) -> Option<&'static Asset> {
+    ASSETS.binary_search_by(|asset| asset.name.cmp(name)).ok().map(|i| &ASSETS[i])
+}
+",
+    );
+    fs::write(path, index)
+}
+
+/// Compresses every file under `src` into `$OUT_DIR/{name}`, and writes a module embedding them
+/// to `$OUT_DIR/{name}.rs`. Returns the assets sorted by name.
+///
+/// For use from a build script: fails if `OUT_DIR` isn't set, and tells Cargo to rerun the
+/// build script when anything under `src` changes.
+pub fn compress_dir_to_out_dir(src: impl AsRef<Path>, name: &str) -> io::Result<Vec<Asset>> {
+    let out_dir = env::var_os("OUT_DIR").ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "OUT_DIR isn't set, run from a build script")
+    })?;
+    let out_dir = PathBuf::from(out_dir);
+    let src = src.as_ref();
+    println!("cargo:rerun-if-changed={}", src.display());
+    let assets = compress_dir(src, out_dir.join(name))?;
+    write_index(&assets, out_dir.join(format!("{}.rs", name)))?;
+    Ok(assets)
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{compress_dir, write_index};
+    use crate::decompress::decompress;
+
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+
+    #[test]
+    fn test_compress_dir() {
+        let root = env::temp_dir().join(format!("lzokay-build-helpers-{}", process::id()));
+        let src = root.join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("b.txt"), INPUT_1).unwrap();
+        fs::write(src.join("nested/a.txt"), INPUT_2).unwrap();
+        fs::write(src.join("empty"), b"").unwrap();
+
+        let assets = compress_dir(&src, root.join("out")).expect("Failed to compress");
+        let names: Vec<&str> = assets.iter().map(|asset| asset.name.as_str()).collect();
+        assert_eq!(names, ["b.txt", "empty", "nested/a.txt"]);
+        for (asset, expected) in assets.iter().zip(&[INPUT_1, b"", INPUT_2]) {
+            assert_eq!(asset.path, root.join("out").join(format!("{}.lzo", asset.name)));
+            let compressed = fs::read(&asset.path).unwrap();
+            assert_eq!(compressed.len(), asset.compressed_size);
+            let mut dst = vec![0u8; asset.size];
+            assert_eq!(decompress(&compressed, &mut dst), Ok(expected.len()));
+            assert_eq!(&dst, expected);
+        }
+
+        write_index(&assets, root.join("index.rs")).expect("Failed to write index");
+        let index = fs::read_to_string(root.join("index.rs")).unwrap();
+        let entry = format!(
+            "    Asset {{ name: \"nested/a.txt\", data: include_bytes!({:?}), size: {} }},",
+            assets[2].path.canonicalize().unwrap().to_str().unwrap(),
+            INPUT_2.len()
+        );
+        assert!(index.contains(&entry), "{}", index);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,420 @@
+//! # C API
+//!
+//! Available with feature `capi`.
+//!
+//! `extern "C"` entry points for linking this crate from C and C++. The matching header is
+//! `include/lzokay.h`, generated with [cbindgen](https://github.com/eqrion/cbindgen).
+//! To build a shared or static library, and regenerate the header:
+//!
+//! ```sh
+//! cargo rustc --release --features capi --crate-type cdylib
+//! cargo rustc --release --features capi --crate-type staticlib
+//! cbindgen --config cbindgen.toml --output include/lzokay.h src/capi.rs
+//! ```
+//!
+//! All functions return `LZOKAY_OK` on success, or one of the negative `LZOKAY_*` error codes,
+//! which match liblzo2's `LZO_E_*` codes.
+//!
+//! For integrating with streaming frameworks, an `LzokayStream` context accepts input in
+//! pieces through `lzokay_stream_feed`, and produces the result in `lzokay_stream_finish`.
+//!
+//! The API is usable through P/Invoke without custom marshalling: arguments are pointers,
+//! integers and the blittable `LzokayFeatures`, and no function needs a string. The
+//! repository's `dotnet/Lzokay.cs` wraps it for C# and Unity.
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+use crate::{compress, decompress, features, Error};
+
+/// Success.
+pub const LZOKAY_OK: c_int = 0;
+/// Unknown error, or an invalid argument.
+pub const LZOKAY_ERROR: c_int = -1;
+/// Compressed input buffer is invalid or truncated.
+pub const LZOKAY_INPUT_OVERRUN: c_int = -4;
+/// Output buffer was not large enough to store the result.
+pub const LZOKAY_OUTPUT_OVERRUN: c_int = -5;
+/// Likely indicates bad compressed input.
+pub const LZOKAY_LOOKBEHIND_OVERRUN: c_int = -6;
+/// Decompression succeeded, but input buffer has remaining data.
+pub const LZOKAY_INPUT_NOT_CONSUMED: c_int = -8;
+
+/// Stream mode: compress the fed input.
+pub const LZOKAY_STREAM_COMPRESS: c_int = 0;
+/// Stream mode: decompress the fed input.
+pub const LZOKAY_STREAM_DECOMPRESS: c_int = 1;
+
+/// Worst-case compression size of `src_size` bytes.
+#[no_mangle]
+pub extern "C" fn lzokay_compress_worst_size(src_size: usize) -> usize {
+    compress::compress_worst_size(src_size)
+}
+
+/// Compresses `src` into `dst`, storing the compressed size in `out_size`.
+///
+/// For sizing `dst`, use `lzokay_compress_worst_size`.
+///
+/// # Safety
+///
+/// `src` and `dst` must be valid for `src_size` and `dst_size` bytes respectively,
+/// and `out_size` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_compress(
+    src: *const u8,
+    src_size: usize,
+    dst: *mut u8,
+    dst_size: usize,
+    out_size: *mut usize,
+) -> c_int {
+    ffi_call(src, src_size, dst, dst_size, out_size, |src, dst| {
+        compress::compress_no_alloc(src, dst, &mut compress::new_dict())
+    })
+}
+
+/// Decompresses `src` into `dst`, storing the decompressed size in `out_size`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+///
+/// # Safety
+///
+/// `src` and `dst` must be valid for `src_size` and `dst_size` bytes respectively,
+/// and `out_size` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_decompress(
+    src: *const u8,
+    src_size: usize,
+    dst: *mut u8,
+    dst_size: usize,
+    out_size: *mut usize,
+) -> c_int {
+    ffi_call(src, src_size, dst, dst_size, out_size, decompress::decompress)
+}
+
+/// Opaque streaming context, created by `lzokay_stream_new`.
+///
+/// Input is currently buffered until `lzokay_stream_finish`.
+pub struct LzokayStream {
+    decompress: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    finished: bool,
+}
+
+/// Creates a streaming context, with `mode` one of `LZOKAY_STREAM_COMPRESS` or
+/// `LZOKAY_STREAM_DECOMPRESS`.
+///
+/// Returns `NULL` for an unknown mode. Free with `lzokay_stream_free`.
+#[no_mangle]
+pub extern "C" fn lzokay_stream_new(mode: c_int) -> *mut LzokayStream {
+    let decompress = match mode {
+        LZOKAY_STREAM_COMPRESS => false,
+        LZOKAY_STREAM_DECOMPRESS => true,
+        _ => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(LzokayStream {
+        decompress,
+        input: Vec::new(),
+        output: Vec::new(),
+        finished: false,
+    }))
+}
+
+/// Feeds `src_size` bytes of input to the stream.
+///
+/// # Safety
+///
+/// `stream` must come from `lzokay_stream_new`, and `src` must be valid for `src_size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_stream_feed(
+    stream: *mut LzokayStream,
+    src: *const u8,
+    src_size: usize,
+) -> c_int {
+    let stream = match stream.as_mut() {
+        Some(stream) if !stream.finished => stream,
+        _ => return LZOKAY_ERROR,
+    };
+    if src_size != 0 {
+        if src.is_null() {
+            return LZOKAY_ERROR;
+        }
+        stream.input.extend_from_slice(slice::from_raw_parts(src, src_size));
+    }
+    LZOKAY_OK
+}
+
+/// Finishes the stream, storing a pointer to the complete output in `out`
+/// and its size in `out_size`.
+///
+/// The output remains valid until `lzokay_stream_free`. No more input may be fed afterwards.
+///
+/// # Safety
+///
+/// `stream` must come from `lzokay_stream_new`, and `out` and `out_size` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_stream_finish(
+    stream: *mut LzokayStream,
+    out: *mut *const u8,
+    out_size: *mut usize,
+) -> c_int {
+    let stream = match stream.as_mut() {
+        Some(stream) if !stream.finished && !out.is_null() && !out_size.is_null() => stream,
+        _ => return LZOKAY_ERROR,
+    };
+    stream.finished = true;
+    let result = if stream.decompress {
+        decompress::decompress_growing(&stream.input, &mut stream.output, usize::MAX)
+            .map_err(|failure| failure.error)
+    } else {
+        compress::compress(&stream.input).map(|output| stream.output = output)
+    };
+    stream.input = Vec::new();
+    if let Err(e) = result {
+        return e.to_lzo_code() as c_int;
+    }
+    *out = stream.output.as_ptr();
+    *out_size = stream.output.len();
+    LZOKAY_OK
+}
+
+/// Frees a streaming context and its output.
+///
+/// # Safety
+///
+/// `stream` must come from `lzokay_stream_new` and not be used afterwards. `NULL` is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_stream_free(stream: *mut LzokayStream) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+/// Codecs and optional subsystems compiled in, as returned by `lzokay_features`.
+///
+/// Each field is 1 if the Cargo feature of the same name was compiled in, and 0 otherwise,
+/// as bytes rather than `bool` so the struct is blittable for P/Invoke. Fields are only ever
+/// added at the end.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LzokayFeatures {
+    pub compress: u8,
+    pub decompress: u8,
+    pub alloc: u8,
+    pub std: u8,
+    pub lzo1b: u8,
+    pub lzo1f: u8,
+    pub lzo1y: u8,
+    pub lzo1z: u8,
+    pub lzo2a: u8,
+    pub const_decompress: u8,
+    pub trace: u8,
+    pub stats: u8,
+    pub lzop: u8,
+    pub cpp_backend: u8,
+    pub forbid_unsafe: u8,
+    pub macros: u8,
+    pub capi: u8,
+    pub sys: u8,
+    pub wasm: u8,
+    pub uniffi: u8,
+    pub jni: u8,
+    pub python: u8,
+    pub numpy: u8,
+    pub abi3: u8,
+    pub cli: u8,
+    pub arbitrary: u8,
+    pub page: u8,
+    pub array: u8,
+    pub parallel: u8,
+    pub test_support: u8,
+    pub zeroize: u8,
+}
+
+/// Returns the features compiled in, as `lzokay_has_feature` does for each one, without
+/// passing strings.
+#[no_mangle]
+pub extern "C" fn lzokay_features() -> LzokayFeatures {
+    let f = features();
+    LzokayFeatures {
+        compress: f.compress as u8,
+        decompress: f.decompress as u8,
+        alloc: f.alloc as u8,
+        std: f.std as u8,
+        lzo1b: f.lzo1b as u8,
+        lzo1f: f.lzo1f as u8,
+        lzo1y: f.lzo1y as u8,
+        lzo1z: f.lzo1z as u8,
+        lzo2a: f.lzo2a as u8,
+        const_decompress: f.const_decompress as u8,
+        trace: f.trace as u8,
+        stats: f.stats as u8,
+        lzop: f.lzop as u8,
+        cpp_backend: f.cpp_backend as u8,
+        forbid_unsafe: f.forbid_unsafe as u8,
+        macros: f.macros as u8,
+        capi: f.capi as u8,
+        sys: f.sys as u8,
+        wasm: f.wasm as u8,
+        uniffi: f.uniffi as u8,
+        jni: f.jni as u8,
+        python: f.python as u8,
+        numpy: f.numpy as u8,
+        abi3: f.abi3 as u8,
+        cli: f.cli as u8,
+        arbitrary: f.arbitrary as u8,
+        page: f.page as u8,
+        array: f.array as u8,
+        parallel: f.parallel as u8,
+        test_support: f.test_support as u8,
+        zeroize: f.zeroize as u8,
+    }
+}
+
+/// Returns 1 if the feature `name`, such as `"lzo1y"` or `"cpp-backend"`, was compiled in,
+/// and 0 if it wasn't or is unknown.
+///
+/// # Safety
+///
+/// `name` must be a valid NUL-terminated string, or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn lzokay_has_feature(name: *const c_char) -> c_int {
+    if name.is_null() {
+        return 0;
+    }
+    let name = CStr::from_ptr(name).to_bytes();
+    features().flags().any(|(flag, enabled)| enabled && flag.as_bytes() == name) as c_int
+}
+
+/// Validates the raw buffers and runs `f` over them, converting the result into a return code.
+unsafe fn ffi_call(
+    src: *const u8,
+    src_size: usize,
+    dst: *mut u8,
+    dst_size: usize,
+    out_size: *mut usize,
+    f: impl FnOnce(&[u8], &mut [u8]) -> Result<usize, Error>,
+) -> c_int {
+    if out_size.is_null() || (src.is_null() && src_size != 0) || (dst.is_null() && dst_size != 0) {
+        return LZOKAY_ERROR;
+    }
+    *out_size = 0;
+    let src = if src_size == 0 { &[][..] } else { slice::from_raw_parts(src, src_size) };
+    let dst = if dst_size == 0 { &mut [][..] } else { slice::from_raw_parts_mut(dst, dst_size) };
+    match f(src, dst) {
+        Ok(size) => {
+            *out_size = size;
+            LZOKAY_OK
+        }
+        Err(e) => e.to_lzo_code() as c_int,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr;
+
+    use super::*;
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_round_trip() {
+        let mut compressed = vec![0u8; lzokay_compress_worst_size(INPUT.len())];
+        let mut compressed_size = 0;
+        let r = unsafe {
+            lzokay_compress(
+                INPUT.as_ptr(),
+                INPUT.len(),
+                compressed.as_mut_ptr(),
+                compressed.len(),
+                &mut compressed_size,
+            )
+        };
+        assert_eq!(r, LZOKAY_OK);
+
+        let mut dst = vec![0u8; INPUT.len()];
+        let mut dst_size = 0;
+        let r = unsafe {
+            lzokay_decompress(
+                compressed.as_ptr(),
+                compressed_size,
+                dst.as_mut_ptr(),
+                dst.len(),
+                &mut dst_size,
+            )
+        };
+        assert_eq!(r, LZOKAY_OK);
+        assert_eq!(&dst[..dst_size], INPUT);
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        unsafe fn run(mode: c_int, chunks: &[&[u8]]) -> Vec<u8> {
+            let stream = lzokay_stream_new(mode);
+            assert!(!stream.is_null());
+            for chunk in chunks {
+                assert_eq!(lzokay_stream_feed(stream, chunk.as_ptr(), chunk.len()), LZOKAY_OK);
+            }
+            let mut out = ptr::null();
+            let mut out_size = 0;
+            assert_eq!(lzokay_stream_finish(stream, &mut out, &mut out_size), LZOKAY_OK);
+            assert_eq!(lzokay_stream_feed(stream, INPUT.as_ptr(), 1), LZOKAY_ERROR);
+            let output = slice::from_raw_parts(out, out_size).to_vec();
+            lzokay_stream_free(stream);
+            output
+        }
+
+        unsafe {
+            let compressed = run(LZOKAY_STREAM_COMPRESS, &[&INPUT[..100], &INPUT[100..]]);
+            let (a, b) = compressed.split_at(compressed.len() / 2);
+            assert_eq!(run(LZOKAY_STREAM_DECOMPRESS, &[a, b]), INPUT);
+            assert!(lzokay_stream_new(2).is_null());
+        }
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(LZOKAY_ERROR, Error::Error.to_lzo_code());
+        assert_eq!(LZOKAY_INPUT_OVERRUN, Error::InputOverrun.to_lzo_code());
+        assert_eq!(LZOKAY_OUTPUT_OVERRUN, Error::OutputOverrun.to_lzo_code());
+        assert_eq!(LZOKAY_LOOKBEHIND_OVERRUN, Error::LookbehindOverrun.to_lzo_code());
+        assert_eq!(LZOKAY_INPUT_NOT_CONSUMED, Error::InputNotConsumed.to_lzo_code());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut size = 0;
+        let r = unsafe { lzokay_decompress(ptr::null(), 10, ptr::null_mut(), 0, &mut size) };
+        assert_eq!(r, LZOKAY_ERROR);
+        let src = [0x11, 0];
+        let r =
+            unsafe { lzokay_decompress(src.as_ptr(), src.len(), ptr::null_mut(), 0, &mut size) };
+        assert_eq!(r, LZOKAY_INPUT_OVERRUN);
+    }
+
+    #[test]
+    fn test_has_feature() {
+        unsafe {
+            assert_eq!(lzokay_has_feature(b"compress\0".as_ptr() as *const c_char), 1);
+            let expected = cfg!(feature = "lzo1y") as c_int;
+            assert_eq!(lzokay_has_feature(b"lzo1y\0".as_ptr() as *const c_char), expected);
+            assert_eq!(lzokay_has_feature(b"unknown\0".as_ptr() as *const c_char), 0);
+            assert_eq!(lzokay_has_feature(ptr::null()), 0);
+        }
+    }
+
+    #[test]
+    fn test_features() {
+        let f = lzokay_features();
+        let bytes: [u8; 31] = unsafe { core::mem::transmute(f) };
+        assert_eq!(features().flags().count(), bytes.len());
+        for ((name, enabled), &byte) in features().flags().zip(&bytes) {
+            assert_eq!(byte, enabled as u8, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! # Checksums
+//!
+//! The Adler-32 and CRC-32 checksums used by LZO containers, equivalent to liblzo2's
+//! `lzo_adler32` and `lzo_crc32`. Both can be computed incrementally, by passing the previous
+//! result as `value`.
+//!
+//! [`xxh32`] and [`xxh64`] compute the XXH32 and XXH64 hashes, several times faster, for
+//! containers that opt into them, such as lzop files with the `lzop::F_XXH64_D` flag.
+//! They take a seed rather than a previous result, and hash a whole buffer at once.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::checksum::{adler32, crc32, ADLER32_INIT, CRC32_INIT};
+//!
+//! assert_eq!(adler32(ADLER32_INIT, b"Wikipedia"), 0x11e6_0398);
+//! let crc = crc32(CRC32_INIT, b"12345");
+//! assert_eq!(crc32(crc, b"6789"), 0xcbf4_3926);
+//! ```
+//!
+//! ```
+//! use lzokay::checksum::{xxh32, xxh64};
+//!
+//! assert_eq!(xxh32(0, b"abc"), 0x32d1_53ff);
+//! assert_eq!(xxh64(0, b"abc"), 0x44bc_2cf5_ad77_0999);
+//! ```
+
+/// Initial value for [`adler32`].
+pub const ADLER32_INIT: u32 = 1;
+/// Initial value for [`crc32`].
+pub const CRC32_INIT: u32 = 0;
+
+const ADLER_BASE: u32 = 65521;
+/// Largest number of bytes that can be summed before `b` may overflow.
+const ADLER_NMAX: usize = 5552;
+
+/// Updates the Adler-32 checksum `value` with `data`.
+pub fn adler32(value: u32, data: &[u8]) -> u32 {
+    let mut a = value & 0xffff;
+    let mut b = value >> 16;
+    for chunk in data.chunks(ADLER_NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_BASE;
+        b %= ADLER_BASE;
+    }
+    (b << 16) | a
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Updates the CRC-32 checksum `value` with `data`.
+pub fn crc32(value: u32, data: &[u8]) -> u32 {
+    let mut crc = !value;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const XXH32_PRIME_1: u32 = 0x9e37_79b1;
+const XXH32_PRIME_2: u32 = 0x85eb_ca77;
+const XXH32_PRIME_3: u32 = 0xc2b2_ae3d;
+const XXH32_PRIME_4: u32 = 0x27d4_eb2f;
+const XXH32_PRIME_5: u32 = 0x1656_67b1;
+
+fn xxh32_round(acc: u32, lane: u32) -> u32 {
+    acc.wrapping_add(lane.wrapping_mul(XXH32_PRIME_2)).rotate_left(13).wrapping_mul(XXH32_PRIME_1)
+}
+
+fn read_u32_le(data: &[u8]) -> u32 { u32::from_le_bytes([data[0], data[1], data[2], data[3]]) }
+
+fn read_u64_le(data: &[u8]) -> u64 {
+    read_u32_le(data) as u64 | (read_u32_le(&data[4..]) as u64) << 32
+}
+
+/// Computes the XXH32 hash of `data` with `seed`, usually 0.
+pub fn xxh32(seed: u32, data: &[u8]) -> u32 {
+    let mut stripes = data.chunks_exact(16);
+    let mut acc = if data.len() >= 16 {
+        let mut v = [
+            seed.wrapping_add(XXH32_PRIME_1).wrapping_add(XXH32_PRIME_2),
+            seed.wrapping_add(XXH32_PRIME_2),
+            seed,
+            seed.wrapping_sub(XXH32_PRIME_1),
+        ];
+        for stripe in &mut stripes {
+            for (i, v) in v.iter_mut().enumerate() {
+                *v = xxh32_round(*v, read_u32_le(&stripe[i * 4..]));
+            }
+        }
+        v[0].rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18))
+    } else {
+        seed.wrapping_add(XXH32_PRIME_5)
+    };
+    acc = acc.wrapping_add(data.len() as u32);
+    let mut lanes = stripes.remainder().chunks_exact(4);
+    for lane in &mut lanes {
+        acc = acc.wrapping_add(read_u32_le(lane).wrapping_mul(XXH32_PRIME_3));
+        acc = acc.rotate_left(17).wrapping_mul(XXH32_PRIME_4);
+    }
+    for &byte in lanes.remainder() {
+        acc = acc.wrapping_add((byte as u32).wrapping_mul(XXH32_PRIME_5));
+        acc = acc.rotate_left(11).wrapping_mul(XXH32_PRIME_1);
+    }
+    acc ^= acc >> 15;
+    acc = acc.wrapping_mul(XXH32_PRIME_2);
+    acc ^= acc >> 13;
+    acc = acc.wrapping_mul(XXH32_PRIME_3);
+    acc ^ acc >> 16
+}
+
+const XXH64_PRIME_1: u64 = 0x9e37_79b1_85eb_ca87;
+const XXH64_PRIME_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const XXH64_PRIME_3: u64 = 0x1656_67b1_9e37_79f9;
+const XXH64_PRIME_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const XXH64_PRIME_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn xxh64_round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(XXH64_PRIME_2)).rotate_left(31).wrapping_mul(XXH64_PRIME_1)
+}
+
+/// Computes the XXH64 hash of `data` with `seed`, usually 0.
+pub fn xxh64(seed: u64, data: &[u8]) -> u64 {
+    let mut stripes = data.chunks_exact(32);
+    let mut acc = if data.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(XXH64_PRIME_1).wrapping_add(XXH64_PRIME_2),
+            seed.wrapping_add(XXH64_PRIME_2),
+            seed,
+            seed.wrapping_sub(XXH64_PRIME_1),
+        ];
+        for stripe in &mut stripes {
+            for (i, v) in v.iter_mut().enumerate() {
+                *v = xxh64_round(*v, read_u64_le(&stripe[i * 8..]));
+            }
+        }
+        let mut acc = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        for &v in &v {
+            acc ^= xxh64_round(0, v);
+            acc = acc.wrapping_mul(XXH64_PRIME_1).wrapping_add(XXH64_PRIME_4);
+        }
+        acc
+    } else {
+        seed.wrapping_add(XXH64_PRIME_5)
+    };
+    acc = acc.wrapping_add(data.len() as u64);
+    let mut lanes = stripes.remainder().chunks_exact(8);
+    for lane in &mut lanes {
+        acc ^= xxh64_round(0, read_u64_le(lane));
+        acc = acc.rotate_left(27).wrapping_mul(XXH64_PRIME_1).wrapping_add(XXH64_PRIME_4);
+    }
+    let mut rest = lanes.remainder();
+    if rest.len() >= 4 {
+        acc ^= (read_u32_le(rest) as u64).wrapping_mul(XXH64_PRIME_1);
+        acc = acc.rotate_left(23).wrapping_mul(XXH64_PRIME_2).wrapping_add(XXH64_PRIME_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        acc ^= (byte as u64).wrapping_mul(XXH64_PRIME_5);
+        acc = acc.rotate_left(11).wrapping_mul(XXH64_PRIME_1);
+    }
+    acc ^= acc >> 33;
+    acc = acc.wrapping_mul(XXH64_PRIME_2);
+    acc ^= acc >> 29;
+    acc = acc.wrapping_mul(XXH64_PRIME_3);
+    acc ^ acc >> 32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{adler32, crc32, xxh32, xxh64, ADLER32_INIT, CRC32_INIT};
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(adler32(ADLER32_INIT, b""), 1);
+        assert_eq!(adler32(ADLER32_INIT, b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(crc32(CRC32_INIT, b""), 0);
+        assert_eq!(crc32(CRC32_INIT, b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(ADLER32_INIT, &[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn test_xxhash() {
+        assert_eq!(xxh32(0, b""), 0x02cc_5d05);
+        assert_eq!(xxh64(0, b""), 0xef46_db37_51d8_e999);
+        assert_eq!(xxh32(0, b"abc"), 0x32d1_53ff);
+        assert_eq!(xxh64(0, b"abc"), 0x44bc_2cf5_ad77_0999);
+        // Long enough for whole stripes, and every kind of remainder
+        let text = b"Nobody inspects the spammish repetition";
+        assert_eq!(xxh32(0, text), 0xe229_3b2f);
+        assert_eq!(xxh64(0, text), 0xfbce_a83c_8a37_8bf1);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let (a, b) = INPUT.split_at(INPUT.len() / 3);
+        assert_eq!(adler32(adler32(ADLER32_INIT, a), b), adler32(ADLER32_INIT, INPUT));
+        assert_eq!(crc32(crc32(CRC32_INIT, a), b), crc32(CRC32_INIT, INPUT));
+    }
+}
@@ -0,0 +1,340 @@
+//! # Codec selection
+//!
+//! [`LzoCodec`] is implemented by a marker type for each supported format, so container code
+//! can be written once and monomorphized per format. [`Codec`] covers the same formats as a
+//! plain value, for selecting a format at runtime (e.g. from a header's method byte).
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))] {
+//! use lzokay::{codec::Codec, compress::new_dict};
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! let codec = Codec::Lzo1x;
+//! let mut compressed = vec![0u8; codec.worst_size(input.len())];
+//! let size = codec.compress(&input, &mut compressed, &mut new_dict())?;
+//! let mut dst = [0u8; 512];
+//! let size = codec.decompress(&compressed[..size], &mut dst)?;
+//! # assert_eq!(size, input.len());
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(feature = "compress")]
+use crate::compress::Dict;
+pub use crate::variant::Lzo1x;
+#[cfg(feature = "lzo1y")]
+pub use crate::variant::Lzo1y;
+#[cfg(feature = "lzo1z")]
+pub use crate::variant::Lzo1z;
+use crate::Error;
+
+/// A compressed stream format.
+pub trait LzoCodec {
+    /// Format name, as used by liblzo2.
+    const NAME: &'static str;
+    /// Whether streams of this format can be produced by this crate.
+    const CAN_COMPRESS: bool;
+
+    /// Working memory needed to compress, besides `src` and `dst`. Decompression needs none.
+    #[cfg(feature = "compress")]
+    const SCRATCH_BYTES: usize = Dict::memory_usage();
+
+    /// Worst-case compression size.
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize;
+
+    /// Compress the supplied buffer.
+    ///
+    /// For sizing `dst`, use [`worst_size`](LzoCodec::worst_size).
+    #[cfg(feature = "compress")]
+    fn compress(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error>;
+
+    /// Decompress `src` into `dst`.
+    ///
+    /// `dst` must be large enough to hold the entire decompressed output.
+    #[cfg(feature = "decompress")]
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error>;
+}
+
+impl LzoCodec for Lzo1x {
+    const CAN_COMPRESS: bool = true;
+    const NAME: &'static str = "LZO1X";
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::compress::compress_worst_size(s) }
+
+    #[cfg(feature = "compress")]
+    fn compress(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+        crate::compress::compress_no_alloc(src, dst, dict)
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::decompress::decompress(src, dst)
+    }
+}
+
+#[cfg(feature = "lzo1y")]
+impl LzoCodec for Lzo1y {
+    const CAN_COMPRESS: bool = true;
+    const NAME: &'static str = "LZO1Y";
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::lzo1y::compress_worst_size(s) }
+
+    #[cfg(feature = "compress")]
+    fn compress(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+        crate::lzo1y::compress_no_alloc(src, dst, dict)
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::lzo1y::decompress(src, dst)
+    }
+}
+
+#[cfg(feature = "lzo1z")]
+impl LzoCodec for Lzo1z {
+    const CAN_COMPRESS: bool = true;
+    const NAME: &'static str = "LZO1Z";
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::lzo1z::compress_worst_size(s) }
+
+    #[cfg(feature = "compress")]
+    fn compress(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+        crate::lzo1z::compress_no_alloc(src, dst, dict)
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::lzo1z::decompress(src, dst)
+    }
+}
+
+/// The LZO1B format. Decompression only.
+#[cfg(all(feature = "lzo1b", feature = "decompress"))]
+pub struct Lzo1b;
+
+#[cfg(all(feature = "lzo1b", feature = "decompress"))]
+impl LzoCodec for Lzo1b {
+    const CAN_COMPRESS: bool = false;
+    const NAME: &'static str = "LZO1B";
+    #[cfg(feature = "compress")]
+    const SCRATCH_BYTES: usize = 0;
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::compress::compress_worst_size(s) }
+
+    /// Always fails with [`Error::Error`].
+    #[cfg(feature = "compress")]
+    fn compress(_src: &[u8], _dst: &mut [u8], _dict: &mut Dict) -> Result<usize, Error> {
+        Err(Error::Error)
+    }
+
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::lzo1b::decompress(src, dst)
+    }
+}
+
+/// The LZO1F format. Decompression only.
+#[cfg(all(feature = "lzo1f", feature = "decompress"))]
+pub struct Lzo1f;
+
+#[cfg(all(feature = "lzo1f", feature = "decompress"))]
+impl LzoCodec for Lzo1f {
+    const CAN_COMPRESS: bool = false;
+    const NAME: &'static str = "LZO1F";
+    #[cfg(feature = "compress")]
+    const SCRATCH_BYTES: usize = 0;
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::compress::compress_worst_size(s) }
+
+    /// Always fails with [`Error::Error`].
+    #[cfg(feature = "compress")]
+    fn compress(_src: &[u8], _dst: &mut [u8], _dict: &mut Dict) -> Result<usize, Error> {
+        Err(Error::Error)
+    }
+
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::lzo1f::decompress(src, dst)
+    }
+}
+
+/// The LZO2A format.
+#[cfg(feature = "lzo2a")]
+pub struct Lzo2a;
+
+#[cfg(feature = "lzo2a")]
+impl LzoCodec for Lzo2a {
+    const CAN_COMPRESS: bool = true;
+    const NAME: &'static str = "LZO2A";
+
+    #[cfg(feature = "compress")]
+    fn worst_size(s: usize) -> usize { crate::lzo2a::compress_worst_size(s) }
+
+    #[cfg(feature = "compress")]
+    fn compress(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+        crate::lzo2a::compress_no_alloc(src, dst, dict)
+    }
+
+    #[cfg(feature = "decompress")]
+    fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        crate::lzo2a::decompress(src, dst)
+    }
+}
+
+/// Runtime selection of a [`LzoCodec`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Codec {
+    /// See [`Lzo1x`].
+    Lzo1x,
+    /// See [`Lzo1b`].
+    #[cfg(all(feature = "lzo1b", feature = "decompress"))]
+    Lzo1b,
+    /// See [`Lzo1f`].
+    #[cfg(all(feature = "lzo1f", feature = "decompress"))]
+    Lzo1f,
+    /// See [`Lzo1y`].
+    #[cfg(feature = "lzo1y")]
+    Lzo1y,
+    /// See [`Lzo1z`].
+    #[cfg(feature = "lzo1z")]
+    Lzo1z,
+    /// See [`Lzo2a`].
+    #[cfg(feature = "lzo2a")]
+    Lzo2a,
+}
+
+/// Evaluates `$e` with `$c` aliased to the marker type of the selected codec.
+macro_rules! dispatch {
+    ($self:expr, $c:ident => $e:expr) => {
+        match $self {
+            Codec::Lzo1x => {
+                type $c = Lzo1x;
+                $e
+            }
+            #[cfg(all(feature = "lzo1b", feature = "decompress"))]
+            Codec::Lzo1b => {
+                type $c = Lzo1b;
+                $e
+            }
+            #[cfg(all(feature = "lzo1f", feature = "decompress"))]
+            Codec::Lzo1f => {
+                type $c = Lzo1f;
+                $e
+            }
+            #[cfg(feature = "lzo1y")]
+            Codec::Lzo1y => {
+                type $c = Lzo1y;
+                $e
+            }
+            #[cfg(feature = "lzo1z")]
+            Codec::Lzo1z => {
+                type $c = Lzo1z;
+                $e
+            }
+            #[cfg(feature = "lzo2a")]
+            Codec::Lzo2a => {
+                type $c = Lzo2a;
+                $e
+            }
+        }
+    };
+}
+
+impl Codec {
+    /// Format name, as used by liblzo2.
+    pub fn name(self) -> &'static str { dispatch!(self, C => C::NAME) }
+
+    /// Whether streams of this format can be produced by this crate.
+    pub fn can_compress(self) -> bool { dispatch!(self, C => C::CAN_COMPRESS) }
+
+    /// Working memory needed to compress, besides `src` and `dst`. Decompression needs none.
+    #[cfg(feature = "compress")]
+    pub fn scratch_bytes(self) -> usize { dispatch!(self, C => C::SCRATCH_BYTES) }
+
+    /// Worst-case compression size.
+    #[cfg(feature = "compress")]
+    pub fn worst_size(self, s: usize) -> usize { dispatch!(self, C => C::worst_size(s)) }
+
+    /// Compress the supplied buffer.
+    ///
+    /// For sizing `dst`, use [`worst_size`](Codec::worst_size).
+    #[cfg(feature = "compress")]
+    pub fn compress(self, src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+        dispatch!(self, C => C::compress(src, dst, dict))
+    }
+
+    /// Decompress `src` into `dst`.
+    ///
+    /// `dst` must be large enough to hold the entire decompressed output.
+    #[cfg(feature = "decompress")]
+    pub fn decompress(self, src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+        dispatch!(self, C => C::decompress(src, dst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_name() {
+        assert_eq!(Codec::Lzo1x.name(), "LZO1X");
+        assert!(Codec::Lzo1x.can_compress());
+        #[cfg(all(feature = "lzo1b", feature = "decompress"))]
+        assert!(!Codec::Lzo1b.can_compress());
+        #[cfg(all(feature = "lzo1f", feature = "decompress"))]
+        assert!(!Codec::Lzo1f.can_compress());
+    }
+
+    #[test]
+    #[cfg(feature = "compress")]
+    fn test_scratch_bytes() {
+        use crate::compress::{dict_storage_size, Dict};
+
+        assert_eq!(Codec::Lzo1x.scratch_bytes(), Dict::memory_usage());
+        assert!(Dict::memory_usage() <= dict_storage_size());
+        #[cfg(all(feature = "lzo1b", feature = "decompress"))]
+        assert_eq!(Codec::Lzo1b.scratch_bytes(), 0);
+        #[cfg(all(feature = "lzo1f", feature = "decompress"))]
+        assert_eq!(Codec::Lzo1f.scratch_bytes(), 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    fn test_round_trip() {
+        use crate::compress::{dict_from_storage, dict_storage_size};
+
+        let mut storage = [0u8; dict_storage_size()];
+        let mut dict = dict_from_storage(&mut storage);
+        // Large enough for the worst case of every format
+        let mut compressed = [0u8; INPUT.len() + INPUT.len() / 8 + 67];
+        let mut dst = [0u8; INPUT.len()];
+        let codecs = [
+            Codec::Lzo1x,
+            #[cfg(feature = "lzo1y")]
+            Codec::Lzo1y,
+            #[cfg(feature = "lzo1z")]
+            Codec::Lzo1z,
+            #[cfg(feature = "lzo2a")]
+            Codec::Lzo2a,
+        ];
+        for &codec in codecs.iter() {
+            assert!(codec.worst_size(INPUT.len()) <= compressed.len());
+            let size =
+                codec.compress(INPUT, &mut compressed, &mut dict).expect("Failed to compress");
+            let size =
+                codec.decompress(&compressed[..size], &mut dst).expect("Failed to decompress");
+            assert_eq!(&dst[..size], INPUT, "{}", codec.name());
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! # Compatibility layers
+//!
+//! APIs shaped after other LZO implementations, to ease porting existing code.
+
+pub mod minilzo;
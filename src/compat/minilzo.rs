@@ -0,0 +1,154 @@
+//! # miniLZO-style API
+//!
+//! Safe functions mirroring the signatures and `LZO_E_*` return codes of miniLZO, so C code
+//! using it can be ported line by line. Pointer and length pairs become slices, and output
+//! lengths are written through `dst_len`.
+//!
+//! New code should prefer the [`compress`](crate::compress) and
+//! [`decompress`](crate::decompress) modules.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(
+//! #     feature = "compress",
+//! #     feature = "decompress",
+//! #     feature = "alloc",
+//! #     not(feature = "forbid-unsafe")
+//! # ))] {
+//! use lzokay::compat::minilzo::*;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! assert_eq!(lzo_init(), LZO_E_OK);
+//! let mut wrkmem = vec![0u8; LZO1X_1_MEM_COMPRESS];
+//! let mut compressed = vec![0u8; input.len() + input.len() / 16 + 64 + 3];
+//! let mut compressed_len = 0;
+//! let r = lzo1x_1_compress(&input, &mut compressed, &mut compressed_len, &mut wrkmem);
+//! assert_eq!(r, LZO_E_OK);
+//!
+//! let mut dst = [0u8; 512];
+//! let mut dst_len = 0;
+//! let r = lzo1x_decompress_safe(&compressed[..compressed_len], &mut dst, &mut dst_len, None);
+//! assert_eq!(r, LZO_E_OK);
+//! # assert_eq!(dst_len, input.len());
+//! # }
+//! ```
+
+#[cfg(feature = "compress")]
+use crate::compress::dict_storage_size;
+#[cfg(all(feature = "compress", not(feature = "forbid-unsafe")))]
+use crate::compress::{compress_no_alloc, dict_from_storage};
+#[cfg(feature = "decompress")]
+use crate::decompress::decompress;
+use crate::Error;
+
+pub const LZO_E_OK: i32 = 0;
+pub const LZO_E_ERROR: i32 = Error::Error.to_lzo_code();
+pub const LZO_E_INPUT_OVERRUN: i32 = Error::InputOverrun.to_lzo_code();
+pub const LZO_E_OUTPUT_OVERRUN: i32 = Error::OutputOverrun.to_lzo_code();
+pub const LZO_E_LOOKBEHIND_OVERRUN: i32 = Error::LookbehindOverrun.to_lzo_code();
+pub const LZO_E_INPUT_NOT_CONSUMED: i32 = Error::InputNotConsumed.to_lzo_code();
+
+/// Work memory required by [`lzo1x_1_compress`].
+///
+/// Unlike miniLZO, this holds the full compression dictionary.
+#[cfg(feature = "compress")]
+pub const LZO1X_1_MEM_COMPRESS: usize = dict_storage_size();
+/// Work memory required by [`lzo1x_decompress`] and [`lzo1x_decompress_safe`].
+#[cfg(feature = "decompress")]
+pub const LZO1X_MEM_DECOMPRESS: usize = 0;
+
+/// Always succeeds; nothing needs initializing.
+pub const fn lzo_init() -> i32 { LZO_E_OK }
+
+/// Compress `src` into `dst`, storing the compressed size in `dst_len`.
+///
+/// `wrkmem` must be at least [`LZO1X_1_MEM_COMPRESS`] bytes, otherwise `LZO_E_ERROR` is
+/// returned.
+///
+/// Unavailable with feature `forbid-unsafe`, as `wrkmem` is reinterpreted as the dictionary.
+#[cfg(all(feature = "compress", not(feature = "forbid-unsafe")))]
+pub fn lzo1x_1_compress(src: &[u8], dst: &mut [u8], dst_len: &mut usize, wrkmem: &mut [u8]) -> i32 {
+    if wrkmem.len() < LZO1X_1_MEM_COMPRESS {
+        *dst_len = 0;
+        return LZO_E_ERROR;
+    }
+    to_code(compress_no_alloc(src, dst, &mut dict_from_storage(wrkmem)), dst_len)
+}
+
+/// Decompress `src` into `dst`, storing the decompressed size in `dst_len`.
+///
+/// Input is always checked, so this is identical to [`lzo1x_decompress_safe`].
+#[cfg(feature = "decompress")]
+pub fn lzo1x_decompress(
+    src: &[u8],
+    dst: &mut [u8],
+    dst_len: &mut usize,
+    wrkmem: Option<&mut [u8]>,
+) -> i32 {
+    lzo1x_decompress_safe(src, dst, dst_len, wrkmem)
+}
+
+/// Decompress `src` into `dst`, storing the decompressed size in `dst_len`.
+///
+/// `wrkmem` is unused. On error, `dst_len` is set to 0.
+#[cfg(feature = "decompress")]
+pub fn lzo1x_decompress_safe(
+    src: &[u8],
+    dst: &mut [u8],
+    dst_len: &mut usize,
+    _wrkmem: Option<&mut [u8]>,
+) -> i32 {
+    to_code(decompress(src, dst), dst_len)
+}
+
+#[cfg(any(feature = "decompress", not(feature = "forbid-unsafe")))]
+fn to_code(result: Result<usize, Error>, dst_len: &mut usize) -> i32 {
+    match result {
+        Ok(size) => {
+            *dst_len = size;
+            LZO_E_OK
+        }
+        Err(e) => {
+            *dst_len = 0;
+            e.to_lzo_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "decompress")]
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    fn test_round_trip() {
+        const INPUT: &[u8] = include_bytes!("../test1.txt");
+
+        let mut wrkmem = [0u8; LZO1X_1_MEM_COMPRESS];
+        let mut compressed = [0u8; INPUT.len() + INPUT.len() / 16 + 64 + 3];
+        let mut compressed_len = 0;
+        let r = lzo1x_1_compress(INPUT, &mut compressed, &mut compressed_len, &mut wrkmem);
+        assert_eq!(r, LZO_E_OK);
+
+        let mut dst = [0u8; INPUT.len()];
+        let mut dst_len = 0;
+        let r = lzo1x_decompress_safe(&compressed[..compressed_len], &mut dst, &mut dst_len, None);
+        assert_eq!(r, LZO_E_OK);
+        assert_eq!(&dst[..dst_len], INPUT);
+
+        let r = lzo1x_1_compress(INPUT, &mut compressed, &mut compressed_len, &mut wrkmem[..1]);
+        assert_eq!(r, LZO_E_ERROR);
+    }
+
+    #[test]
+    #[cfg(feature = "decompress")]
+    fn test_decompress_errors() {
+        let mut dst = [0u8; 16];
+        let mut dst_len = 1;
+        let r = lzo1x_decompress(&[0x11, 0], &mut dst, &mut dst_len, None);
+        assert_eq!(r, LZO_E_INPUT_OVERRUN);
+        assert_eq!(dst_len, 0);
+    }
+}
@@ -3,6 +3,25 @@
 //! Available with feature `compress`.
 //!
 //! [`compress`] and [`compress_with_dict`] available with features `std` and/or `alloc`.
+//! Functions returning a vector take their input as any `AsRef<[u8]>`, such as a `Vec<u8>`, an
+//! array or a slice; the allocation-free ones take slices.
+//!
+//! [`Compressor`] compresses in bounded steps, for firmware that can't block for a whole buffer.
+//! [`compress_with_progress`] reports progress while compressing large buffers, and
+//! [`compress_with_cancel`] can be cancelled from another thread. [`compress_with_deadline`]
+//! bounds compression time, storing what it doesn't get to as literals.
+//! [`compress_with_preset`] lets matches copy from a preset dictionary, for small inputs.
+//! [`TokenWriter`] encodes literal runs and matches chosen by a custom encoder, and
+//! [`transcode`] re-encodes existing streams more compactly. [`compress_with_stats`] and
+//! [`Compressor::stats`] report what the encoder did, for tuning levels to your data, and
+//! [`coverage_map`] shows which parts of the input it matched. [`Dict::set_profile`] freezes
+//! the encoder's output across releases, for content-addressed storage, and
+//! [`Dict::set_opcodes`] restricts it to the instructions a simplified decoder implements.
+//! [`try_compress`] falls back to [`compress_slim`], which needs no dictionary, if one can't be
+//! allocated. With feature `array`, [`compress_to_array`] compresses into a fixed-size array, and with
+//! feature `parallel`, [`compress_parallel`] compresses many blocks on several threads. With
+//! features `std` and `decompress`, [`LzoWriter`] compresses what's written to it, for
+//! `std::io` pipelines.
 //!
 //! # Examples
 //!
@@ -39,6 +58,7 @@
 //! use lzokay::compress::*;
 //! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
 //!
+//! # #[cfg(not(feature = "forbid-unsafe"))] {
 //! // Allocate dst on stack, with worst-case compression size
 //! let mut dst = [0u8; compress_worst_size(input.len())];
 //! // Allocate dictionary storage on stack
@@ -47,50 +67,545 @@
 //! let mut dict = dict_from_storage(&mut storage);
 //! let size = compress_no_alloc(&input, &mut dst, &mut dict)?;
 //! # assert_eq!(size, 10);
+//! # }
 //! # Ok::<(), lzokay::Error>(())
 //! ```
 
+#[cfg(feature = "array")]
+// Relative to this file's directory, also when lzokay-macros includes it by path
+#[path = "compress/array.rs"]
+mod array;
+
+#[cfg(feature = "parallel")]
+#[path = "compress/parallel.rs"]
+mod parallel;
+
+#[path = "compress/partial.rs"]
+mod partial;
+
+#[path = "compress/slim.rs"]
+mod slim;
+
+#[path = "compress/tokens.rs"]
+mod tokens;
+
+#[cfg(all(feature = "std", feature = "decompress"))]
+#[path = "compress/writer.rs"]
+mod writer;
+
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 extern crate alloc;
 
+#[cfg(all(not(feature = "std"), feature = "alloc", not(feature = "forbid-unsafe")))]
+use alloc::alloc::{alloc_zeroed, handle_alloc_error};
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
-use alloc::{boxed::Box, vec::Vec};
-#[cfg(feature = "alloc")]
-use core::ptr::null_mut;
-use core::{marker::PhantomData, mem::size_of};
+use alloc::{boxed::Box, vec, vec::Vec};
+#[cfg(all(feature = "alloc", not(feature = "forbid-unsafe")))]
+use core::alloc::Layout;
+use core::{
+    cmp::min,
+    fmt,
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    time::Duration,
+};
+#[cfg(all(feature = "std", not(feature = "forbid-unsafe")))]
+use std::alloc::{alloc_zeroed, handle_alloc_error};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+#[cfg(feature = "array")]
+pub use self::array::compress_to_array;
+#[cfg(feature = "parallel")]
+pub use self::parallel::compress_parallel;
+#[cfg(feature = "decompress")]
+pub use self::tokens::transcode_no_alloc;
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub use self::tokens::{concat_streams, coverage_map, split_stream, transcode, Coverage, Segment};
+#[cfg(all(feature = "std", feature = "decompress"))]
+pub use self::writer::LzoWriter;
+pub use self::{
+    partial::{Partial, PartialCompressor},
+    slim::compress_slim,
+    tokens::TokenWriter,
+};
+use crate::{
+    format,
+    variant::{Lzo1x, Variant},
+    word, Error,
+};
+
+const HASH_SIZE: usize = 0x4000;
+const MAX_DIST: u32 = format::MAX_DISTANCE as u32;
+const MAX_MATCH_LEN: u32 = format::MAX_MATCH_LEN as u32;
+const BUF_SIZE: u32 = MAX_DIST + MAX_MATCH_LEN;
+
+const M1_MAX_OFFSET: u32 = format::M1_MAX_DISTANCE as u32;
+const M3_MAX_OFFSET: u32 = format::M3_MAX_DISTANCE as u32;
+const M2_MIN_LEN: u32 = format::MIN_MATCH_LEN as u32;
+const M3_MAX_LEN: u32 = format::M3_MAX_SHORT_LEN as u32;
+const M4_MAX_LEN: u32 = format::M4_MAX_SHORT_LEN as u32;
+const M3_MARKER: u8 = 0x20;
+const M4_MARKER: u8 = 0x10;
+/// Max M3 len + 1
+pub(crate) const MAX_MATCH_BY_LENGTH_LEN: usize = 34;
+
+/// Fastest compression level.
+pub const MIN_LEVEL: u8 = 1;
+/// Best compression level.
+pub const MAX_LEVEL: u8 = 9;
+/// Compression level of new dictionaries.
+pub const DEFAULT_LEVEL: u8 = MAX_LEVEL;
+/// Longest match chain searched per level in [`EncoderProfile::Stable1`]. Chains hold at most
+/// [`format::MAX_MATCH_LEN`] positions, so level 9 searches every candidate.
+const STABLE1_MAX_CHAIN: [u32; MAX_LEVEL as usize] =
+    [4, 8, 16, 32, 64, 128, 256, 512, MAX_MATCH_LEN];
+/// Longest match chain searched per level in [`EncoderProfile::Latest`]. The fast levels search
+/// less than [`EncoderProfile::Stable1`]'s, as they stop at the nice length anyway.
+const LATEST_MAX_CHAIN: [u32; MAX_LEVEL as usize] = [2, 4, 8, 16, 32, 64, 256, 1024, MAX_MATCH_LEN];
+/// Nice length per level in [`EncoderProfile::Latest`]. The best levels search until a match
+/// can't be longer, as [`EncoderProfile::Stable1`] does at every level.
+const LATEST_NICE_LEN: [u32; MAX_LEVEL as usize] =
+    [16, 32, 32, 64, 128, 256, 512, MAX_MATCH_LEN, MAX_MATCH_LEN];
+
+/// Heuristics the encoder chooses matches with, see [`Dict::set_profile`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum EncoderProfile {
+    /// The current heuristics, which later releases may change to compress better or faster.
+    /// The default.
+    Latest,
+    /// The heuristics of lzokay 2.0, the release that introduced profiles, frozen: for a given
+    /// input and dictionary settings, the output is the same in every later release.
+    Stable1,
+}
+
+impl EncoderProfile {
+    /// The search limits of `level`.
+    fn search(self, level: u8) -> Search {
+        let i = level as usize - 1;
+        match self {
+            EncoderProfile::Latest => {
+                Search { max_chain: LATEST_MAX_CHAIN[i], nice_len: LATEST_NICE_LEN[i] }
+            }
+            EncoderProfile::Stable1 => {
+                Search { max_chain: STABLE1_MAX_CHAIN[i], nice_len: MAX_MATCH_LEN }
+            }
+        }
+    }
+}
+
+/// How far the encoder searches for a match at each position.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Search {
+    /// Longest match chain searched.
+    pub(crate) max_chain: u32,
+    /// Length of a match good enough to stop searching at.
+    pub(crate) nice_len: u32,
+}
+
+/// A set of match instruction classes, M1 to M4, see [`Dict::set_opcodes`].
+///
+/// ```
+/// use lzokay::compress::Opcodes;
+///
+/// let opcodes = Opcodes::M2 | Opcodes::M3;
+/// assert!(opcodes.contains(Opcodes::M3));
+/// assert!(!opcodes.contains(Opcodes::M1));
+/// assert!(Opcodes::ALL.contains(opcodes));
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Opcodes(u8);
+
+impl Opcodes {
+    /// Every match instruction.
+    pub const ALL: Opcodes = Opcodes(0xf);
+    /// M1 matches, of both lengths.
+    pub const M1: Opcodes = Opcodes(1);
+    /// M2 matches.
+    pub const M2: Opcodes = Opcodes(1 << 1);
+    /// M3 matches.
+    pub const M3: Opcodes = Opcodes(1 << 2);
+    /// M4 matches.
+    pub const M4: Opcodes = Opcodes(1 << 3);
+    /// No match instructions, storing the whole input as literals.
+    pub const NONE: Opcodes = Opcodes(0);
+
+    /// Whether every class in `other` is in the set.
+    pub const fn contains(self, other: Opcodes) -> bool { self.0 & other.0 == other.0 }
+
+    /// Whether the class numbered `class`, 0 to 3 for M1 to M4, is in the set.
+    const fn has_class(self, class: usize) -> bool { self.0 & 1 << class != 0 }
+}
 
-use crate::{bindings, lzokay_result, Error};
+impl core::ops::BitOr for Opcodes {
+    type Output = Opcodes;
 
-type DictStorage = bindings::lzokay_DictBase_storage_type;
+    fn bitor(self, rhs: Opcodes) -> Opcodes { Opcodes(self.0 | rhs.0) }
+}
+
+/// List encoding of previous 3-byte data matches
+struct Match3 {
+    /// key -> chain-head-pos
+    head: [u16; HASH_SIZE],
+    /// key -> chain-size
+    chain_sz: [u16; HASH_SIZE],
+    /// chain-pos -> next-chain-pos
+    chain: [u16; BUF_SIZE as usize],
+    /// chain-pos -> best-match-length
+    best_len: [u16; BUF_SIZE as usize],
+    /// Hash input perturbation and multiplier, from [`Dict::set_seed`]
+    key_xor: u32,
+    key_mul: u32,
+}
+
+/// Encoding of 2-byte data matches
+struct Match2 {
+    /// 2-byte-data -> head-pos
+    head: [u16; 1 << 16],
+}
+
+pub(crate) struct DictStorage {
+    match3: Match3,
+    match2: Match2,
+    /// Circular buffer caching enough data to access the maximum lookback
+    /// distance of 48K + maximum match length of 2K. An additional 2K is
+    /// allocated so the start of the buffer may be replicated at the end,
+    /// therefore providing efficient circular access.
+    buffer: [u8; (BUF_SIZE + MAX_MATCH_LEN) as usize],
+}
+
+enum DictRef<'a> {
+    #[cfg_attr(feature = "forbid-unsafe", allow(dead_code))]
+    Borrowed(&'a mut DictStorage),
+    #[cfg(feature = "alloc")]
+    Owned(Box<DictStorage>),
+}
 
 /// Dictionary type
+///
+/// Holds the compression settings, and the tables and last 48 KiB of input the encoder
+/// searches for matches. Reusing one across calls saves allocating and clearing it each time.
+///
+/// `Dict` is `Send` and `Sync`, owned or borrowing its storage: pools can hand dictionaries to
+/// worker threads and take them back. As compressing takes `&mut Dict`, each one is only ever
+/// in use on one thread at a time. [`Compressor`], [`PartialCompressor`] and [`TokenWriter`]
+/// are `Send` and `Sync` too.
 pub struct Dict<'a> {
-    base: bindings::lzokay_DictBase,
-    #[cfg(feature = "alloc")]
-    storage: Option<Box<[u8; dict_storage_size()]>>,
-    phantom: PhantomData<&'a DictStorage>,
+    storage: DictRef<'a>,
+    level: u8,
+    profile: EncoderProfile,
+    nice_len: u32,
+    opcodes: Opcodes,
+    seed: u32,
+    verify: bool,
 }
 
-/// Creates a new heap-allocated dictionary.
+impl<'a> Dict<'a> {
+    /// Size of a dictionary's storage in bytes, whether allocated by [`new_dict`] or provided to
+    /// [`dict_from_storage`] (which also needs room for alignment, see [`dict_storage_size`]).
+    pub const fn memory_usage() -> usize { size_of::<DictStorage>() }
+
+    /// The compression level, from [`MIN_LEVEL`] to [`MAX_LEVEL`].
+    pub fn level(&self) -> u8 { self.level }
+
+    /// Sets the compression level, from [`MIN_LEVEL`] (fastest) to [`MAX_LEVEL`] (best).
+    ///
+    /// Lower levels search fewer previous matches, and stop at shorter ones. [`DEFAULT_LEVEL`]
+    /// is the best level, so output is unchanged unless a level is set. Resets the nice length
+    /// to the level's.
+    pub fn set_level(&mut self, level: u8) -> Result<(), Error> {
+        if !(MIN_LEVEL..=MAX_LEVEL).contains(&level) {
+            return Err(Error::Error);
+        }
+        self.level = level;
+        self.nice_len = self.profile.search(level).nice_len;
+        Ok(())
+    }
+
+    /// The nice length, see [`set_nice_len`](Dict::set_nice_len).
+    pub fn nice_len(&self) -> usize { self.nice_len as usize }
+
+    /// Sets the length of a match good enough to stop searching for longer ones at, from
+    /// [`format::MIN_MATCH_LEN`] to [`format::MAX_MATCH_LEN`], as zlib's `nice_length`.
+    ///
+    /// The level and profile set one, which this overrides until either is set again. On
+    /// highly repetitive data, where long matches are everywhere, a lower nice length
+    /// compresses faster at little cost in ratio; [`format::MAX_MATCH_LEN`] searches as far as
+    /// the level allows.
+    pub fn set_nice_len(&mut self, len: usize) -> Result<(), Error> {
+        if !(format::MIN_MATCH_LEN..=format::MAX_MATCH_LEN).contains(&len) {
+            return Err(Error::Error);
+        }
+        self.nice_len = len as u32;
+        Ok(())
+    }
+
+    /// The encoder profile, [`EncoderProfile::Latest`] unless set.
+    pub fn profile(&self) -> EncoderProfile { self.profile }
+
+    /// Sets the heuristics the encoder chooses matches with.
+    ///
+    /// The stream format is fixed, but which of the many valid streams for an input the encoder
+    /// produces isn't, and [`EncoderProfile::Latest`] may change between releases. Storage
+    /// that addresses or deduplicates data by its compressed bytes needs them to stay the same:
+    /// a stable profile such as [`EncoderProfile::Stable1`] guarantees it, at the cost of later
+    /// improvements.
+    /// Resets the nice length to the profile's for the level.
+    pub fn set_profile(&mut self, profile: EncoderProfile) {
+        self.profile = profile;
+        self.nice_len = profile.search(self.level).nice_len;
+    }
+
+    /// The match instructions the encoder may emit, [`Opcodes::ALL`] unless set.
+    pub fn opcodes(&self) -> Opcodes { self.opcodes }
+
+    /// Restricts the encoder to a subset of the match instructions.
+    ///
+    /// For simplified decoders, such as in hardware or homebrew, that implement only some of
+    /// LZO1X's instructions. Matches another allowed instruction can't encode are stored as
+    /// literals instead, so compression suffers with each class left out: with only M3, for
+    /// instance, matches are at least 3 bytes and 16 KiB back at most. Literal runs, and the
+    /// terminating instruction, an M4 of distance 16384, are always emitted. Applies to LZO1X,
+    /// LZO1Y and LZO1Z.
+    pub fn set_opcodes(&mut self, opcodes: Opcodes) { self.opcodes = opcodes; }
+
+    /// The seed of the hash finding previous matches, 0 unless set.
+    pub fn seed(&self) -> u32 { self.seed }
+
+    /// Seeds the hash finding previous matches.
+    ///
+    /// Input that makes many positions collide in the hash slows compression, which an attacker
+    /// controlling the data of a shared compression service could exploit. A seed they don't
+    /// know prevents crafting such input. The output may change with the seed, but always
+    /// decompresses to the same data. Seed 0, the default, matches the original LZ👌's output.
+    pub fn set_seed(&mut self, seed: u32) { self.seed = seed; }
+
+    /// Seeds the hash with a random value, see [`set_seed`](Dict::set_seed).
+    ///
+    /// Uses the randomness `std` seeds its `HashMap`s with.
+    #[cfg(feature = "std")]
+    pub fn randomize_seed(&mut self) {
+        use std::{
+            collections::hash_map::RandomState,
+            hash::{BuildHasher, Hasher},
+        };
+        self.seed = RandomState::new().build_hasher().finish() as u32;
+    }
+
+    /// Whether output is verified, see [`set_verify`](Dict::set_verify).
+    #[cfg(all(feature = "decompress", feature = "alloc"))]
+    pub fn verify(&self) -> bool { self.verify }
+
+    /// Verifies each compression's output by decompressing it into a scratch buffer the size
+    /// of the input before returning it, failing with [`Error::VerificationFailed`] unless it
+    /// reproduces the input.
+    ///
+    /// For archival pipelines that must not store data they can't read back: catches encoder
+    /// bugs, and memory corruption of the output or dictionary while compressing, at the cost
+    /// of a decompression and an allocation per call. Applies to LZO1X, LZO1Y and LZO1Z.
+    #[cfg(all(feature = "decompress", feature = "alloc"))]
+    pub fn set_verify(&mut self, verify: bool) { self.verify = verify; }
+
+    /// A new heap-allocated dictionary with the same settings.
+    #[cfg(feature = "parallel")]
+    fn new_like(&self) -> Dict<'static> {
+        Dict {
+            level: self.level,
+            profile: self.profile,
+            nice_len: self.nice_len,
+            opcodes: self.opcodes,
+            seed: self.seed,
+            verify: self.verify,
+            ..new_dict()
+        }
+    }
+
+    #[cfg(any(feature = "alloc", not(feature = "forbid-unsafe")))]
+    fn with_storage(storage: DictRef<'a>) -> Self {
+        Dict {
+            storage,
+            level: DEFAULT_LEVEL,
+            profile: EncoderProfile::Latest,
+            nice_len: EncoderProfile::Latest.search(DEFAULT_LEVEL).nice_len,
+            opcodes: Opcodes::ALL,
+            seed: 0,
+            verify: false,
+        }
+    }
+
+    pub(crate) fn search(&self) -> Search {
+        Search { nice_len: self.nice_len, ..self.profile.search(self.level) }
+    }
+
+    pub(crate) fn storage(&mut self) -> &mut DictStorage {
+        match &mut self.storage {
+            DictRef::Borrowed(storage) => storage,
+            #[cfg(feature = "alloc")]
+            DictRef::Owned(storage) => storage,
+        }
+    }
+}
+
+/// Wipes the dictionary's storage, which holds the last 48 KiB of input and tables indexed
+/// by it, leaving the settings. The dictionary remains usable, and compresses the same after.
+///
+/// Compression leaves fragments of the input in the dictionary until the next; for secrets,
+/// wipe it once done. Unlike filling the storage with zeros, the writes aren't optimized out.
+#[cfg(feature = "zeroize")]
+impl Zeroize for Dict<'_> {
+    fn zeroize(&mut self) {
+        let storage = self.storage();
+        storage.match3.head.zeroize();
+        storage.match3.chain_sz.zeroize();
+        storage.match3.chain.zeroize();
+        storage.match3.best_len.zeroize();
+        storage.match2.head.zeroize();
+        storage.buffer.zeroize();
+    }
+}
+
+/// Shows the settings, and whether the storage is owned or borrowed, but not its contents.
+impl fmt::Debug for Dict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let storage = match self.storage {
+            DictRef::Borrowed(_) => "borrowed",
+            #[cfg(feature = "alloc")]
+            DictRef::Owned(_) => "owned",
+        };
+        f.debug_struct("Dict")
+            .field("level", &self.level)
+            .field("profile", &self.profile)
+            .field("nice_len", &self.nice_len)
+            .field("opcodes", &self.opcodes)
+            .field("seed", &self.seed)
+            .field("verify", &self.verify)
+            .field("storage", &storage)
+            .finish()
+    }
+}
+
+/// A heap-allocated dictionary with the default settings, as from [`new_dict`].
 #[cfg(feature = "alloc")]
+impl Default for Dict<'static> {
+    fn default() -> Self { new_dict() }
+}
+
+/// Fails to compile if the compressor's types stop being `Send` and `Sync`, which the
+/// documentation of [`Dict`] promises.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<Dict>();
+    check::<Compressor>();
+    check::<PartialCompressor>();
+    check::<TokenWriter>();
+    #[cfg(all(feature = "std", feature = "decompress"))]
+    check::<LzoWriter<Vec<u8>>>();
+}
+
+/// Creates a new heap-allocated dictionary.
+#[cfg(all(feature = "alloc", feature = "forbid-unsafe"))]
 pub fn new_dict() -> Dict<'static> {
-    let mut dict = Dict {
-        base: bindings::lzokay_DictBase { _storage: null_mut() },
-        storage: Option::Some(Box::new([0u8; dict_storage_size()])),
-        phantom: PhantomData,
-    };
-    dict.base._storage = dict.storage.as_mut().unwrap().as_mut_ptr() as *mut DictStorage;
-    dict
+    // Without unsafe code, the storage may be built on the stack before it's moved to the heap.
+    let storage = Box::new(DictStorage {
+        match3: Match3 {
+            head: [0; HASH_SIZE],
+            chain_sz: [0; HASH_SIZE],
+            chain: [0; BUF_SIZE as usize],
+            best_len: [0; BUF_SIZE as usize],
+            key_xor: 0,
+            key_mul: 0,
+        },
+        match2: Match2 { head: [0; 1 << 16] },
+        buffer: [0; (BUF_SIZE + MAX_MATCH_LEN) as usize],
+    });
+    Dict::with_storage(DictRef::Owned(storage))
+}
+
+/// Creates a new heap-allocated dictionary.
+#[cfg(all(feature = "alloc", not(feature = "forbid-unsafe")))]
+pub fn new_dict() -> Dict<'static> {
+    match Dict::try_new() {
+        Some(dict) => dict,
+        None => handle_alloc_error(Layout::new::<DictStorage>()),
+    }
+}
+
+#[cfg(all(feature = "alloc", not(feature = "forbid-unsafe")))]
+impl Dict<'static> {
+    /// Creates a new heap-allocated dictionary, or `None` if the allocation fails, where
+    /// [`new_dict`] would abort.
+    ///
+    /// For long-running processes under memory pressure, which can fall back to
+    /// [`compress_slim`] without a dictionary, as [`try_compress`] does.
+    pub fn try_new() -> Option<Dict<'static>> {
+        let layout = Layout::new::<DictStorage>();
+        // Allocated directly on the heap, as the storage is too large to comfortably pass
+        // through the stack. All-zero is a valid value for its integer arrays.
+        unsafe {
+            let ptr = alloc_zeroed(layout) as *mut DictStorage;
+            if ptr.is_null() {
+                return None;
+            }
+            Some(Dict::with_storage(DictRef::Owned(Box::from_raw(ptr))))
+        }
+    }
+}
+
+/// How [`try_compress`] compressed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    /// With a dictionary, as [`compress_no_alloc`].
+    Full,
+    /// With [`compress_slim`], as the dictionary couldn't be allocated. The output is larger.
+    Slim,
+}
+
+/// Compress `src` into `dst` with a new dictionary, or without one if it can't be allocated,
+/// returning the compressed size and which was used.
+///
+/// Degrades instead of aborting under memory pressure: if [`Dict::try_new`] fails, compresses
+/// with [`compress_slim`], whose table fits on the stack. `dst` is the caller's, so nothing
+/// else is allocated. For sizing `dst`, use [`compress_worst_size`]; to reuse a dictionary
+/// between calls, keep one from [`Dict::try_new`] and call [`compress_slim`] if there's none.
+///
+/// ```
+/// use lzokay::compress::{compress_worst_size, try_compress, Mode};
+///
+/// let input = [0u8; 512];
+/// let mut dst = [0u8; compress_worst_size(512)];
+/// let (size, mode) = try_compress(&input, &mut dst)?;
+/// if mode == Mode::Slim {
+///     eprintln!("Compressed without a dictionary, low on memory");
+/// }
+/// # assert_eq!((size, mode), (10, Mode::Full));
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(all(feature = "alloc", not(feature = "forbid-unsafe")))]
+pub fn try_compress(src: &[u8], dst: &mut [u8]) -> Result<(usize, Mode), Error> {
+    match Dict::try_new() {
+        Some(mut dict) => Ok((compress_impl::<Lzo1x>(src, dst, &mut dict)?, Mode::Full)),
+        None => Ok((compress_slim(src, dst)?, Mode::Slim)),
+    }
 }
 
 /// Dictionary storage size, for manual or stack allocation.
-pub const fn dict_storage_size() -> usize { size_of::<DictStorage>() }
+///
+/// Includes slack for aligning the dictionary within arbitrary byte storage.
+pub const fn dict_storage_size() -> usize {
+    size_of::<DictStorage>() + align_of::<DictStorage>() - 1
+}
 
 /// Creates a dictionary from the supplied storage.
 ///
 /// Storage **must** be at least [`dict_storage_size()`] bytes,
 /// otherwise this function will panic.
-pub fn dict_from_storage(storage: &mut [u8]) -> Dict {
+///
+/// Unavailable with feature `forbid-unsafe`, as the storage is reinterpreted as the dictionary.
+#[cfg(not(feature = "forbid-unsafe"))]
+pub fn dict_from_storage(storage: &mut [u8]) -> Dict<'_> {
     if storage.len() < dict_storage_size() {
         panic!(
             "Dictionary storage is not large enough: {}, expected {}",
@@ -98,76 +613,1145 @@ pub fn dict_from_storage(storage: &mut [u8]) -> Dict {
             dict_storage_size()
         );
     }
-    Dict {
-        base: bindings::lzokay_DictBase { _storage: storage.as_mut_ptr() as *mut DictStorage },
-        #[cfg(feature = "alloc")]
-        storage: Option::None,
-        phantom: PhantomData,
-    }
+    let align = align_of::<DictStorage>();
+    let offset = (align - storage.as_ptr() as usize % align) % align;
+    // The storage is large enough to hold an aligned DictStorage at `offset`, and any
+    // bit pattern is a valid value for its integer arrays.
+    let storage = unsafe { &mut *(storage.as_mut_ptr().add(offset) as *mut DictStorage) };
+    Dict::with_storage(DictRef::Borrowed(storage))
 }
 
 /// Worst-case compression size.
+///
+/// Overflows for inputs within about 1/17 of `usize::MAX`; see [`checked_compress_worst_size`].
 pub const fn compress_worst_size(s: usize) -> usize { s + s / 16 + 64 + 3 }
 
+/// Worst-case compression size, or `None` if it doesn't fit in a `usize`.
+pub const fn checked_compress_worst_size(s: usize) -> Option<usize> {
+    if s > usize::MAX - s / 16 - (64 + 3) {
+        None
+    } else {
+        Some(compress_worst_size(s))
+    }
+}
+
 /// Compress the supplied buffer into a heap-allocated vector.
 ///
 /// Creates a new dictionary for each invocation.
 #[cfg(feature = "alloc")]
-pub fn compress(src: &[u8]) -> Result<Vec<u8>, Error> { compress_with_dict(src, &mut new_dict()) }
+pub fn compress(src: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    compress_with_dict(src, &mut new_dict())
+}
 
 /// Compress the supplied buffer into a heap-allocated vector,
 /// with the supplied pre-allocated dictionary.
 #[cfg(feature = "alloc")]
-pub fn compress_with_dict(src: &[u8], dict: &mut Dict) -> Result<Vec<u8>, Error> {
-    let mut out_size = 0usize;
-    let capacity = compress_worst_size(src.len());
-    let mut dst = Vec::with_capacity(capacity);
-    let result = unsafe {
-        let result = bindings::lzokay_compress(
-            src.as_ptr(),
-            src.len(),
-            dst.as_mut_ptr(),
-            capacity,
-            &mut out_size,
-            &mut dict.base,
-        );
-        if result == bindings::lzokay_EResult_Success {
-            dst.set_len(out_size as usize);
+pub fn compress_with_dict(src: impl AsRef<[u8]>, dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    compress_vec_impl::<Lzo1x>(src.as_ref(), dict)
+}
+
+/// Compress the supplied buffer against a preset dictionary into a heap-allocated vector, with
+/// the supplied dictionary.
+///
+/// Matches may copy from the last 48 KiB of `preset` as well as from earlier input, so small
+/// inputs resembling the preset compress much better. Decompress with
+/// [`decompress_with_dict`](crate::decompress::decompress_with_dict), after the same preset.
+/// With an empty preset, the output is identical to [`compress_with_dict`]. For choosing
+/// between several presets, see [`preset`](crate::preset).
+///
+/// ```
+/// use lzokay::compress::*;
+///
+/// # #[cfg(feature = "alloc")] {
+/// let preset = b"{\"temperature\": 21.5, \"humidity\": 40}";
+/// let dst = compress_with_preset(b"{\"temperature\": 22.0}", preset, &mut new_dict())?;
+/// assert!(dst.len() < compress(b"{\"temperature\": 22.0}")?.len());
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(feature = "alloc")]
+pub fn compress_with_preset(
+    src: impl AsRef<[u8]>,
+    preset: &[u8],
+    dict: &mut Dict,
+) -> Result<Vec<u8>, Error> {
+    let src = src.as_ref();
+    let preset = &preset[preset.len().saturating_sub(MAX_DIST as usize)..];
+    let mut joined = Vec::with_capacity(preset.len() + src.len());
+    joined.extend_from_slice(preset);
+    joined.extend_from_slice(src);
+    let mut dst = vec![0u8; checked_compress_worst_size(src.len()).ok_or(Error::OutputOverrun)?];
+    let mut encoder = Encoder::<Lzo1x>::new(&joined, dict);
+    encoder.skip_preset(dict.storage(), preset.len());
+    let result = match encoder.step(&mut dst, dict.storage(), usize::MAX) {
+        Ok(Poll::Ready(size)) => Ok(size),
+        Ok(Poll::Pending) => unreachable!(),
+        Err(e) => Err(e),
+    };
+    #[cfg(feature = "stats")]
+    crate::stats::record_compress(src.len(), &result);
+    dst.truncate(result?);
+    Ok(dst)
+}
+
+/// Input bytes compressed between calls to [`compress_with_progress`]'s callback.
+#[cfg(feature = "alloc")]
+const PROGRESS_INTERVAL: usize = 1 << 20;
+
+/// Compress the supplied buffer into a heap-allocated vector, with the supplied dictionary,
+/// reporting progress after about every MiB of input and at the end.
+///
+/// For showing progress while compressing large buffers. The output is identical to
+/// [`compress_with_dict`].
+///
+/// ```
+/// use lzokay::compress::*;
+/// # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+///
+/// # #[cfg(feature = "alloc")] {
+/// let dst = compress_with_progress(&input, &mut new_dict(), |progress| {
+///     let total = progress.total_in.unwrap();
+///     println!("{}%", progress.bytes_in * 100 / total);
+/// })?;
+/// # assert_eq!(dst.len(), 10);
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(feature = "alloc")]
+pub fn compress_with_progress(
+    src: impl AsRef<[u8]>,
+    dict: &mut Dict,
+    mut progress: impl FnMut(crate::Progress),
+) -> Result<Vec<u8>, Error> {
+    let src = src.as_ref();
+    let mut dst = vec![0u8; checked_compress_worst_size(src.len()).ok_or(Error::OutputOverrun)?];
+    let mut compressor = Compressor::new(src, &mut dst, dict);
+    let total_in = Some(src.len() as u64);
+    let size = loop {
+        let poll = compressor.step(PROGRESS_INTERVAL)?;
+        progress(crate::Progress {
+            bytes_in: compressor.position() as u64,
+            bytes_out: compressor.output_len() as u64,
+            total_in,
+        });
+        if let Poll::Ready(size) = poll {
+            break size;
         }
-        result
     };
-    lzokay_result(dst, result)
+    dst.truncate(size);
+    Ok(dst)
+}
+
+/// Input bytes compressed between checks of a [`CancelToken`](crate::CancelToken).
+const CANCEL_INTERVAL: usize = 64 * 1024;
+
+/// Compress the supplied buffer, failing with [`Error::Cancelled`] soon after `cancel` is
+/// cancelled.
+///
+/// The token is checked before starting and then after every 64 KiB of input. The output is
+/// identical to [`compress_no_alloc`]; for sizing `dst`, use [`compress_worst_size`].
+pub fn compress_with_cancel(
+    src: &[u8],
+    dst: &mut [u8],
+    dict: &mut Dict,
+    cancel: &crate::CancelToken,
+) -> Result<usize, Error> {
+    let mut compressor = Compressor::new(src, dst, dict);
+    loop {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        if let Poll::Ready(size) = compressor.step(CANCEL_INTERVAL)? {
+            return Ok(size);
+        }
+    }
+}
+
+/// Input bytes compressed between checks of [`compress_with_deadline`]'s clock.
+#[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+const DEADLINE_INTERVAL: usize = 16 * 1024;
+
+/// Compress the supplied buffer within a time budget, storing whatever input remains when it
+/// runs out as literals. Returns the compressed size, and whether the budget ran out.
+///
+/// For latency-sensitive pipelines: compression time is bounded by about `budget`, plus a
+/// copy of the remaining input, whatever the data. The output is always a valid stream; it's
+/// identical to [`compress_no_alloc`] if the budget sufficed. For sizing `dst`, use
+/// [`compress_worst_size`].
+///
+/// Not available on `wasm32-unknown-unknown`, which has no clock. There and without `std`, use
+/// [`Compressor::finish_literals`] with a clock of your own.
+#[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub fn compress_with_deadline(
+    src: &[u8],
+    dst: &mut [u8],
+    dict: &mut Dict,
+    budget: std::time::Duration,
+) -> Result<(usize, bool), Error> {
+    let start = std::time::Instant::now();
+    let mut compressor = Compressor::new(src, dst, dict);
+    loop {
+        if start.elapsed() >= budget {
+            return compressor.finish_literals().map(|size| (size, true));
+        }
+        if let Poll::Ready(size) = compressor.step(DEADLINE_INTERVAL)? {
+            return Ok((size, false));
+        }
+    }
+}
+
+/// Compress the supplied buffer into a heap-allocated vector, with statistics about the
+/// encoding.
+///
+/// The output is identical to [`compress_with_dict`].
+#[cfg(feature = "alloc")]
+pub fn compress_with_stats(
+    src: impl AsRef<[u8]>,
+    dict: &mut Dict,
+) -> Result<(Vec<u8>, CompressionStats), Error> {
+    let src = src.as_ref();
+    let mut dst = vec![0u8; checked_compress_worst_size(src.len()).ok_or(Error::OutputOverrun)?];
+    let mut encoder = Encoder::<Lzo1x>::new(src, dict);
+    match encoder.step(&mut dst, dict.storage(), usize::MAX)? {
+        Poll::Ready(size) => dst.truncate(size),
+        Poll::Pending => unreachable!(),
+    }
+    Ok((dst, encoder.stats()))
 }
 
 /// Compress the supplied buffer.
 ///
-/// For sizing `dst`, use [`compress_worst_size`].
+/// For sizing `dst`, use [`compress_worst_size`]. To write after data already in `dst`, use
+/// [`compress_at`]; to fill fixed-size buffers in turn, use [`PartialCompressor`].
 pub fn compress_no_alloc(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
-    let mut out_size = 0usize;
-    let result = unsafe {
-        bindings::lzokay_compress(
-            src.as_ptr(),
-            src.len(),
-            dst.as_mut_ptr(),
-            dst.len(),
-            &mut out_size,
-            &mut dict.base,
-        )
+    compress_impl::<Lzo1x>(src, dst, dict)
+}
+
+/// Compress the supplied buffer into `dst` at `*pos`, advancing `pos` past the output and
+/// returning the compressed size.
+///
+/// For container writers appending a block after headers already in `dst`, without slicing it
+/// and adding up offsets. `pos` is unchanged on failure, including when it's past the end of
+/// `dst`. For sizing `dst`, add [`compress_worst_size`] to `*pos`.
+///
+/// ```
+/// use lzokay::compress::*;
+/// # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+///
+/// # #[cfg(feature = "alloc")] {
+/// let mut dst = [0u8; 8 + compress_worst_size(input.len())];
+/// let mut pos = 0;
+/// dst[..4].copy_from_slice(&(input.len() as u32).to_be_bytes());
+/// pos += 8; // Then the compressed size
+/// let size = compress_at(&input, &mut dst, &mut pos, &mut new_dict())?;
+/// dst[4..8].copy_from_slice(&(size as u32).to_be_bytes());
+/// assert_eq!(pos, 8 + size);
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn compress_at(
+    src: &[u8],
+    dst: &mut [u8],
+    pos: &mut usize,
+    dict: &mut Dict,
+) -> Result<usize, Error> {
+    let dst = dst.get_mut(*pos..).ok_or(Error::OutputOverrun)?;
+    let size = compress_impl::<Lzo1x>(src, dst, dict)?;
+    *pos += size;
+    Ok(size)
+}
+
+pub(crate) struct State<'a> {
+    src: &'a [u8],
+    pub(crate) inp: usize,
+    wind_sz: u32,
+    wind_b: u32,
+    wind_e: u32,
+    cycle1_countdown: u32,
+
+    pub(crate) bufp: usize,
+    pub(crate) buf_sz: u32,
+}
+
+impl State<'_> {
+    /// Access next input byte and advance both ends of circular buffer
+    fn get_byte(&mut self, buf: &mut [u8]) {
+        if self.inp >= self.src.len() {
+            if self.wind_sz > 0 {
+                self.wind_sz -= 1;
+            }
+            buf[self.wind_e as usize] = 0;
+            if self.wind_e < MAX_MATCH_LEN {
+                buf[(BUF_SIZE + self.wind_e) as usize] = 0;
+            }
+        } else {
+            let c = self.src[self.inp];
+            buf[self.wind_e as usize] = c;
+            if self.wind_e < MAX_MATCH_LEN {
+                buf[(BUF_SIZE + self.wind_e) as usize] = c;
+            }
+            self.inp += 1;
+        }
+        self.wind_e += 1;
+        if self.wind_e == BUF_SIZE {
+            self.wind_e = 0;
+        }
+        self.wind_b += 1;
+        if self.wind_b == BUF_SIZE {
+            self.wind_b = 0;
+        }
+    }
+
+    fn pos2off(&self, pos: u32) -> u32 {
+        if self.wind_b > pos {
+            self.wind_b - pos
+        } else {
+            BUF_SIZE - (pos - self.wind_b)
+        }
+    }
+}
+
+impl Match3 {
+    fn make_key(&self, data: &[u8]) -> usize {
+        let (a, b, c) = (data[0] as u32, data[1] as u32, data[2] as u32);
+        ((self.key_mul.wrapping_mul((((a << 5 ^ b) << 5) ^ c) ^ self.key_xor) >> 5) & 0x3fff)
+            as usize
+    }
+
+    fn get_head(&self, key: usize) -> u16 {
+        if self.chain_sz[key] == 0 {
+            u16::MAX
+        } else {
+            self.head[key]
+        }
+    }
+
+    fn init(&mut self, seed: u32) {
+        self.chain_sz = [0; HASH_SIZE];
+        // The seed's low bits perturb the key's input and its high bits the (odd) multiplier,
+        // leaving the original hash when it's 0
+        self.key_xor = seed;
+        self.key_mul = 0x9f5f ^ (seed >> 15 & !1);
+    }
+
+    fn remove(&mut self, pos: u32, b: &[u8]) {
+        let key = self.make_key(&b[pos as usize..]);
+        self.chain_sz[key] = self.chain_sz[key].wrapping_sub(1);
+    }
+
+    fn advance(&mut self, s: &State, b: &[u8]) -> (u32, u32) {
+        let key = self.make_key(&b[s.wind_b as usize..]);
+        let match_pos = self.get_head(key);
+        self.chain[s.wind_b as usize] = match_pos;
+        let match_count = self.chain_sz[key] as u32;
+        self.chain_sz[key] = self.chain_sz[key].wrapping_add(1);
+        self.head[key] = s.wind_b as u16;
+        (match_pos as u32, min(match_count, MAX_MATCH_LEN))
+    }
+
+    fn skip_advance(&mut self, s: &State, b: &[u8]) {
+        let key = self.make_key(&b[s.wind_b as usize..]);
+        self.chain[s.wind_b as usize] = self.get_head(key);
+        self.head[key] = s.wind_b as u16;
+        self.best_len[s.wind_b as usize] = (MAX_MATCH_LEN + 1) as u16;
+        self.chain_sz[key] = self.chain_sz[key].wrapping_add(1);
+    }
+}
+
+impl Match2 {
+    fn make_key(data: &[u8]) -> usize { data[0] as usize ^ ((data[1] as usize) << 8) }
+
+    fn init(&mut self) { self.head = [u16::MAX; 1 << 16]; }
+
+    fn add(&mut self, pos: u16, b: &[u8]) { self.head[Self::make_key(&b[pos as usize..])] = pos; }
+
+    fn remove(&mut self, pos: u32, b: &[u8]) {
+        let p = &mut self.head[Self::make_key(&b[pos as usize..])];
+        if *p as u32 == pos {
+            *p = u16::MAX;
+        }
+    }
+
+    fn search(
+        &self,
+        s: &State,
+        lb_pos: &mut u32,
+        lb_len: &mut u32,
+        best_pos: &mut [u32; MAX_MATCH_BY_LENGTH_LEN],
+        b: &[u8],
+    ) -> bool {
+        let pos = self.head[Self::make_key(&b[s.wind_b as usize..])];
+        if pos == u16::MAX {
+            return false;
+        }
+        if best_pos[2] == 0 {
+            best_pos[2] = pos as u32 + 1;
+        }
+        if *lb_len < 2 {
+            *lb_len = 2;
+            *lb_pos = pos as u32;
+        }
+        true
+    }
+}
+
+impl DictStorage {
+    pub(crate) fn init<'a>(&mut self, src: &'a [u8], seed: u32) -> State<'a> {
+        self.match3.init(seed);
+        self.match2.init();
+
+        let wind_sz = min(src.len(), MAX_MATCH_LEN as usize) as u32;
+        let mut s = State {
+            src,
+            inp: wind_sz as usize,
+            wind_sz,
+            wind_b: 0,
+            wind_e: wind_sz,
+            cycle1_countdown: MAX_DIST,
+            bufp: 0,
+            buf_sz: 0,
+        };
+        self.buffer[..wind_sz as usize].copy_from_slice(&src[..wind_sz as usize]);
+
+        if s.wind_e == BUF_SIZE {
+            s.wind_e = 0;
+        }
+
+        if s.wind_sz < 3 {
+            let start = (s.wind_b + s.wind_sz) as usize;
+            self.buffer[start..start + 3].copy_from_slice(&[0; 3]);
+        }
+        s
+    }
+
+    fn reset_next_input_entry(&mut self, s: &mut State) {
+        // Remove match from about-to-be-clobbered buffer entry
+        if s.cycle1_countdown == 0 {
+            self.match3.remove(s.wind_e, &self.buffer);
+            self.match2.remove(s.wind_e, &self.buffer);
+        } else {
+            s.cycle1_countdown -= 1;
+        }
+    }
+
+    pub(crate) fn advance(
+        &mut self,
+        s: &mut State,
+        lb_off: &mut u32,
+        lb_len: &mut u32,
+        best_off: &mut [u32; MAX_MATCH_BY_LENGTH_LEN],
+        skip: bool,
+        search: Search,
+    ) {
+        if skip {
+            for _ in 0..*lb_len - 1 {
+                self.reset_next_input_entry(s);
+                self.match3.skip_advance(s, &self.buffer);
+                self.match2.add(s.wind_b as u16, &self.buffer);
+                s.get_byte(&mut self.buffer);
+            }
+        }
+
+        *lb_len = 1;
+        *lb_off = 0;
+        let mut lb_pos = 0u32;
+
+        let mut best_pos = [0u32; MAX_MATCH_BY_LENGTH_LEN];
+        let (mut match_pos, match_count) = self.match3.advance(s, &self.buffer);
+
+        let mut best_char = self.buffer[s.wind_b as usize] as i32;
+        let best_len = *lb_len;
+        if *lb_len >= s.wind_sz {
+            if s.wind_sz == 0 {
+                best_char = -1;
+            }
+            *lb_off = 0;
+            self.match3.best_len[s.wind_b as usize] = (MAX_MATCH_LEN + 1) as u16;
+        } else {
+            if self.match2.search(s, &mut lb_pos, lb_len, &mut best_pos, &self.buffer)
+                && s.wind_sz >= 3
+            {
+                let wind_b = s.wind_b as usize;
+                let wind_sz = s.wind_sz as usize;
+                for _ in 0..match_count.min(search.max_chain) {
+                    let ref_data = &self.buffer[wind_b..wind_b + wind_sz];
+                    let match_data = &self.buffer[match_pos as usize..];
+                    let match_len = word::match_len(ref_data, match_data) as u32;
+                    if match_len >= 2 {
+                        if (match_len as usize) < MAX_MATCH_BY_LENGTH_LEN
+                            && best_pos[match_len as usize] == 0
+                        {
+                            best_pos[match_len as usize] = match_pos + 1;
+                        }
+                        if match_len > *lb_len {
+                            *lb_len = match_len;
+                            lb_pos = match_pos;
+                            if match_len == s.wind_sz
+                                || match_len >= search.nice_len
+                                || match_len > self.match3.best_len[match_pos as usize] as u32
+                            {
+                                break;
+                            }
+                        }
+                    }
+                    match_pos = self.match3.chain[match_pos as usize] as u32;
+                }
+            }
+            if *lb_len > best_len {
+                *lb_off = s.pos2off(lb_pos);
+            }
+            self.match3.best_len[s.wind_b as usize] = *lb_len as u16;
+            for (off, &pos) in best_off.iter_mut().zip(best_pos.iter()).skip(2) {
+                *off = if pos > 0 { s.pos2off(pos - 1) } else { 0 };
+            }
+        }
+
+        self.reset_next_input_entry(s);
+
+        self.match2.add(s.wind_b as u16, &self.buffer);
+
+        s.get_byte(&mut self.buffer);
+
+        if best_char < 0 {
+            s.buf_sz = 0;
+            *lb_len = 0;
+            // Signal exit
+        } else {
+            s.buf_sz = s.wind_sz + 1;
+        }
+        s.bufp = s.inp - s.buf_sz as usize;
+    }
+}
+
+fn find_better_match<V: Variant>(
+    best_off: &[u32; MAX_MATCH_BY_LENGTH_LEN],
+    lb_len: &mut u32,
+    lb_off: &mut u32,
+) {
+    let len = *lb_len as usize;
+    if *lb_len <= M2_MIN_LEN || *lb_off <= V::M2_MAX_OFFSET {
+        return;
+    }
+    if *lb_off > V::M2_MAX_OFFSET
+        && *lb_len > M2_MIN_LEN
+        && *lb_len <= V::M2_MAX_LEN + 1
+        && best_off[len - 1] != 0
+        && best_off[len - 1] <= V::M2_MAX_OFFSET
+    {
+        *lb_len -= 1;
+        *lb_off = best_off[len - 1];
+    } else if *lb_off > M3_MAX_OFFSET
+        && *lb_len > M4_MAX_LEN
+        && *lb_len <= V::M2_MAX_LEN + 2
+        && best_off[len - 2] != 0
+        && best_off[len] <= V::M2_MAX_OFFSET
+    {
+        *lb_len -= 2;
+        *lb_off = best_off[len - 2];
+    } else if *lb_off > M3_MAX_OFFSET
+        && *lb_len > M4_MAX_LEN
+        && *lb_len <= M3_MAX_LEN + 1
+        && best_off[len - 1] != 0
+        && best_off[len - 2] <= M3_MAX_OFFSET
+    {
+        *lb_len -= 1;
+        *lb_off = best_off[len - 1];
+    }
+}
+
+#[inline]
+fn needs_out(dst: &[u8], outp: usize, count: usize) -> Result<(), Error> {
+    if dst.len() - outp < count {
+        Err(Error::OutputOverrun)
+    } else {
+        Ok(())
+    }
+}
+
+#[inline]
+fn write_bytes(dst: &mut [u8], outp: &mut usize, bytes: &[u8]) -> Result<(), Error> {
+    needs_out(dst, *outp, bytes.len())?;
+    dst[*outp..*outp + bytes.len()].copy_from_slice(bytes);
+    *outp += bytes.len();
+    Ok(())
+}
+
+/// Writes an extended length as a run of zero bytes and a final remainder byte.
+///
+/// Space must be checked by the caller.
+fn write_zero_byte_length(dst: &mut [u8], outp: &mut usize, mut length: usize) {
+    while length > 255 {
+        dst[*outp] = 0;
+        *outp += 1;
+        length -= 255;
+    }
+    dst[*outp] = length as u8;
+    *outp += 1;
+}
+
+/// Number of instruction bytes before a literal run of `lit_len` bytes written at `outp`.
+fn literal_header_len(outp: usize, lit_len: usize) -> usize {
+    if lit_len == 0 || (outp != 0 && lit_len <= 3) {
+        0
+    } else if (outp == 0 && lit_len <= 238) || lit_len <= 18 {
+        1
+    } else {
+        // A zero byte, then a byte per 255 of the length past 18, rounded up
+        (lit_len - 19) / 255 + 2
+    }
+}
+
+fn encode_literal_header<V: Variant>(
+    dst: &mut [u8],
+    outp: &mut usize,
+    lit_len: usize,
+) -> Result<(), Error> {
+    if lit_len == 0 {
+        return Ok(());
+    }
+    if *outp == 0 && lit_len <= 238 {
+        write_bytes(dst, outp, &[17 + lit_len as u8])
+    } else if lit_len <= 3 {
+        dst[*outp - V::STATE_OFFSET] |= lit_len as u8;
+        Ok(())
+    } else if lit_len <= 18 {
+        write_bytes(dst, outp, &[(lit_len - 3) as u8])
+    } else {
+        needs_out(dst, *outp, (lit_len - 19) / 255 + 2)?;
+        dst[*outp] = 0;
+        *outp += 1;
+        write_zero_byte_length(dst, outp, lit_len - 18);
+        Ok(())
+    }
+}
+
+fn encode_literal_run<V: Variant>(
+    dst: &mut [u8],
+    outp: &mut usize,
+    lit: &[u8],
+) -> Result<(), Error> {
+    encode_literal_header::<V>(dst, outp, lit.len())?;
+    write_bytes(dst, outp, lit)
+}
+
+/// The instruction class encoding a match after a literal run of `last_lit_len` bytes: 0 to 3
+/// for M1 to M4, or `None` if no class in `opcodes` can.
+///
+/// Matches of 2 bytes must be encodable as M1 by the caller.
+fn match_class<V: Variant>(
+    opcodes: Opcodes,
+    lb_len: u32,
+    lb_off: u32,
+    last_lit_len: usize,
+) -> Option<usize> {
+    let class = if lb_len == 2 {
+        0
+    } else if lb_len <= V::M2_MAX_LEN && lb_off <= V::M2_MAX_OFFSET && opcodes.has_class(1) {
+        1
+    } else if lb_len == M2_MIN_LEN
+        && lb_off > V::M2_MAX_OFFSET
+        && lb_off <= M1_MAX_OFFSET + V::M2_MAX_OFFSET
+        && last_lit_len >= 4
+        && opcodes.has_class(0)
+    {
+        0
+    } else if lb_off <= M3_MAX_OFFSET {
+        2
+    } else {
+        3
     };
-    lzokay_result(out_size as usize, result)
+    if opcodes.has_class(class) {
+        Some(class)
+    } else {
+        None
+    }
+}
+
+/// Encodes a match with the instruction class chosen by [`match_class`].
+fn encode_lookback_match<V: Variant>(
+    dst: &mut [u8],
+    outp: &mut usize,
+    mut lb_len: u32,
+    mut lb_off: u32,
+    class: usize,
+) -> Result<(), Error> {
+    if class == 0 && lb_len == 2 {
+        lb_off -= 1;
+        write_bytes(dst, outp, &V::encode_m1(lb_off))
+    } else if class == 0 {
+        lb_off -= 1 + V::M2_MAX_OFFSET;
+        write_bytes(dst, outp, &V::encode_m1(lb_off))
+    } else if class == 1 {
+        lb_off -= 1;
+        write_bytes(dst, outp, &V::encode_m2(lb_len, lb_off))
+    } else if class == 2 {
+        lb_off -= 1;
+        if lb_len <= M3_MAX_LEN {
+            write_bytes(dst, outp, &[M3_MARKER | (lb_len - 2) as u8])?;
+        } else {
+            lb_len -= M3_MAX_LEN;
+            needs_out(dst, *outp, lb_len as usize / 255 + 2)?;
+            dst[*outp] = M3_MARKER;
+            *outp += 1;
+            write_zero_byte_length(dst, outp, lb_len as usize);
+        }
+        write_bytes(dst, outp, &V::encode_distance(lb_off))
+    } else {
+        lb_off -= 0x4000;
+        let high = ((lb_off & 0x4000) >> 11) as u8;
+        if lb_len <= M4_MAX_LEN {
+            write_bytes(dst, outp, &[M4_MARKER | high | (lb_len - 2) as u8])?;
+        } else {
+            lb_len -= M4_MAX_LEN;
+            needs_out(dst, *outp, lb_len as usize / 255 + 2)?;
+            dst[*outp] = M4_MARKER | high;
+            *outp += 1;
+            write_zero_byte_length(dst, outp, lb_len as usize);
+        }
+        write_bytes(dst, outp, &V::encode_distance(lb_off))
+    }
+}
+
+pub(crate) fn compress_impl<V: Variant>(
+    src: &[u8],
+    dst: &mut [u8],
+    dict: &mut Dict,
+) -> Result<usize, Error> {
+    let mut encoder = Encoder::<V>::new(src, dict);
+    let result = match encoder.step(dst, dict.storage(), usize::MAX) {
+        Ok(Poll::Ready(size)) => Ok(size),
+        Ok(Poll::Pending) => unreachable!(),
+        Err(e) => Err(e),
+    };
+    #[cfg(feature = "stats")]
+    crate::stats::record_compress(src.len(), &result);
+    result
+}
+
+/// Compression state between steps, shared by [`compress_impl`] and [`Compressor`].
+struct Encoder<'a, V> {
+    src: &'a [u8],
+    s: State<'a>,
+    outp: usize,
+    // A usize, as literal runs of incompressible input may exceed 4 GiB
+    lit_len: usize,
+    lit_ptr: usize,
+    lb_off: u32,
+    lb_len: u32,
+    best_off: [u32; MAX_MATCH_BY_LENGTH_LEN],
+    search: Search,
+    opcodes: Opcodes,
+    #[cfg_attr(not(all(feature = "decompress", feature = "alloc")), allow(dead_code))]
+    verify: bool,
+    #[cfg_attr(not(all(feature = "decompress", feature = "alloc")), allow(dead_code))]
+    /// Length of the preset dictionary at the start of `src`, which isn't encoded.
+    preset: usize,
+    stats: CompressionStats,
+    _variant: PhantomData<V>,
+}
+
+impl<'a, V: Variant> Encoder<'a, V> {
+    fn new(src: &'a [u8], dict: &mut Dict) -> Self {
+        let (search, opcodes, seed, verify) = (dict.search(), dict.opcodes, dict.seed, dict.verify);
+        let d = dict.storage();
+        let s = d.init(src, seed);
+        let mut encoder = Encoder {
+            src,
+            lit_ptr: s.inp,
+            s,
+            outp: 0,
+            lit_len: 0,
+            lb_off: 0,
+            lb_len: 0,
+            best_off: [0; MAX_MATCH_BY_LENGTH_LEN],
+            search,
+            opcodes,
+            verify,
+            preset: 0,
+            stats: CompressionStats::default(),
+            _variant: PhantomData,
+        };
+        encoder.advance(d, false);
+        encoder
+    }
+
+    #[inline]
+    fn advance(&mut self, d: &mut DictStorage, skip: bool) {
+        d.advance(
+            &mut self.s,
+            &mut self.lb_off,
+            &mut self.lb_len,
+            &mut self.best_off,
+            skip,
+            self.search,
+        );
+    }
+
+    /// Adds the first `len` input positions, a preset dictionary, to the match finder without
+    /// encoding them, so that matches may copy from them.
+    #[cfg(feature = "alloc")]
+    fn skip_preset(&mut self, d: &mut DictStorage, len: usize) {
+        while self.s.bufp < len {
+            self.advance(d, false);
+        }
+        self.lit_ptr = self.s.bufp;
+        self.preset = len;
+    }
+
+    /// Encodes until at least `max_positions` input positions have been consumed, or the input
+    /// is exhausted.
+    fn step(
+        &mut self,
+        dst: &mut [u8],
+        d: &mut DictStorage,
+        max_positions: usize,
+    ) -> Result<Poll, Error> {
+        // wasm32-unknown-unknown has no clock, and `Instant::now` panics there
+        #[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+        let start = std::time::Instant::now();
+        let result = self.step_impl(dst, d, max_positions);
+        #[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+        {
+            self.stats.elapsed += start.elapsed();
+        }
+        #[cfg(all(feature = "decompress", feature = "alloc"))]
+        if let Ok(Poll::Ready(size)) = result {
+            self.check(&dst[..size])?;
+        }
+        result
+    }
+
+    fn step_impl(
+        &mut self,
+        dst: &mut [u8],
+        d: &mut DictStorage,
+        max_positions: usize,
+    ) -> Result<Poll, Error> {
+        let mut positions = 0usize;
+        while self.s.buf_sz > 0 {
+            if positions >= max_positions {
+                return Ok(Poll::Pending);
+            }
+            if self.lit_len == 0 {
+                self.lit_ptr = self.s.bufp;
+            }
+            let lit_len = self.lit_len;
+            let class = match self.match_class() {
+                Some(class) => self.better_match(class),
+                None => {
+                    self.lit_len += 1;
+                    positions += 1;
+                    self.advance(d, false);
+                    continue;
+                }
+            };
+            let lit = &self.src[self.lit_ptr..self.lit_ptr + self.lit_len];
+            encode_literal_run::<V>(dst, &mut self.outp, lit)?;
+            self.stats.add_literals(lit_len);
+            encode_lookback_match::<V>(dst, &mut self.outp, self.lb_len, self.lb_off, class)?;
+            self.stats.add_match(class, self.lb_len, self.lb_off);
+            self.lit_len = 0;
+            positions += self.lb_len as usize;
+            self.advance(d, true);
+        }
+        let lit = &self.src[self.lit_ptr..self.lit_ptr + self.lit_len];
+        encode_literal_run::<V>(dst, &mut self.outp, lit)?;
+        self.stats.add_literals(self.lit_len);
+        self.lit_len = 0;
+
+        // Terminating M4
+        write_bytes(dst, &mut self.outp, &[M4_MARKER | 1, 0, 0])?;
+        Ok(Poll::Ready(self.outp))
+    }
+
+    /// The instruction class encoding the match found after the pending literals, or `None` if
+    /// it can't or isn't worth it.
+    #[inline]
+    fn match_class(&self) -> Option<usize> {
+        let (lb_len, lb_off, lit_len) = (self.lb_len, self.lb_off, self.lit_len);
+        if lb_len < 2
+            || (lb_len == 2 && (lb_off > M1_MAX_OFFSET || lit_len == 0 || lit_len >= 4))
+            || (lb_len == 2 && self.outp == 0)
+            || (self.outp == 0 && lit_len == 0)
+            || (lb_len == M2_MIN_LEN && lb_off > M1_MAX_OFFSET + V::M2_MAX_OFFSET && lit_len >= 4)
+        {
+            return None;
+        }
+        match_class::<V>(self.opcodes, lb_len, lb_off, lit_len)
+    }
+
+    /// Replaces the match found, of instruction class `class`, with a shorter one that encodes
+    /// in fewer bytes, if there is one in an allowed class, returning the class of the match.
+    #[inline]
+    fn better_match(&mut self, class: usize) -> usize {
+        let (lb_len, lb_off) = (self.lb_len, self.lb_off);
+        find_better_match::<V>(&self.best_off, &mut self.lb_len, &mut self.lb_off);
+        if (self.lb_len, self.lb_off) == (lb_len, lb_off) {
+            return class;
+        }
+        match match_class::<V>(self.opcodes, self.lb_len, self.lb_off, self.lit_len) {
+            Some(better) => better,
+            None => {
+                self.lb_len = lb_len;
+                self.lb_off = lb_off;
+                class
+            }
+        }
+    }
+
+    /// Finds the next match, returning the literal run before it as a range of the input, and
+    /// the match's length, offset and instruction class; `None` once the input is exhausted,
+    /// leaving the last literals pending.
+    ///
+    /// Encodes nothing, for [`PartialCompressor`]: the caller must add the size of the
+    /// instructions to `outp` before the next call.
+    fn next_match(&mut self, d: &mut DictStorage) -> Option<((usize, usize), u32, u32, usize)> {
+        while self.s.buf_sz > 0 {
+            if self.lit_len == 0 {
+                self.lit_ptr = self.s.bufp;
+            }
+            let class = match self.match_class() {
+                Some(class) => self.better_match(class),
+                None => {
+                    self.lit_len += 1;
+                    self.advance(d, false);
+                    continue;
+                }
+            };
+            let lit = (self.lit_ptr, self.lit_ptr + self.lit_len);
+            let (lb_len, lb_off) = (self.lb_len, self.lb_off);
+            self.lit_len = 0;
+            self.advance(d, true);
+            return Some((lit, lb_len, lb_off, class));
+        }
+        None
+    }
+
+    /// Ends the stream without searching for more matches, storing the rest of the input,
+    /// including any pending literals, as one literal run.
+    fn finish_literals(&mut self, dst: &mut [u8]) -> Result<usize, Error> {
+        let start = self.s.bufp - self.lit_len;
+        let lit = &self.src[start..];
+        encode_literal_run::<V>(dst, &mut self.outp, lit)?;
+        self.stats.add_literals(lit.len());
+        self.lit_len = 0;
+        self.s.bufp = self.src.len();
+        write_bytes(dst, &mut self.outp, &[M4_MARKER | 1, 0, 0])?;
+        #[cfg(all(feature = "decompress", feature = "alloc"))]
+        self.check(&dst[..self.outp])?;
+        Ok(self.outp)
+    }
+
+    /// Decompresses the finished stream into a scratch buffer, if verifying, and compares it
+    /// with the input.
+    #[cfg(all(feature = "decompress", feature = "alloc"))]
+    fn check(&self, compressed: &[u8]) -> Result<(), Error> {
+        if !self.verify {
+            return Ok(());
+        }
+        let mut scratch = vec![0u8; self.src.len()];
+        scratch[..self.preset].copy_from_slice(&self.src[..self.preset]);
+        match crate::decompress::decompress_after::<V>(compressed, &mut scratch, self.preset) {
+            Ok(len) if self.preset + len == self.src.len() && scratch == self.src => Ok(()),
+            _ => Err(Error::VerificationFailed),
+        }
+    }
+
+    fn stats(&self) -> CompressionStats {
+        CompressionStats { input_len: self.s.bufp, output_len: self.outp, ..self.stats }
+    }
+}
+
+/// Statistics about a compression, from [`compress_with_stats`] or [`Compressor::stats`].
+///
+/// Useful for choosing a level for a kind of data: compare how many bytes each level manages to
+/// match against the time it spends.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CompressionStats {
+    /// Number of input bytes compressed.
+    pub input_len: usize,
+    /// Number of compressed bytes written.
+    pub output_len: usize,
+    /// Number of input bytes stored as literals.
+    pub literal_bytes: usize,
+    /// Number of literal runs.
+    pub literal_runs: usize,
+    /// Number of input bytes encoded as matches.
+    pub match_bytes: usize,
+    /// Number of matches encoded with M1, M2, M3 and M4 instructions, in that order.
+    pub matches: [usize; 4],
+    /// Sum of the distances of every match.
+    pub match_dist_total: u64,
+    /// Time spent compressing. Only measured with feature `std`, and not on
+    /// `wasm32-unknown-unknown`, which has no clock; zero otherwise.
+    pub elapsed: Duration,
+}
+
+impl CompressionStats {
+    /// Total number of matches.
+    pub fn match_count(&self) -> usize { self.matches.iter().sum() }
+
+    /// Average match length, or 0 without matches.
+    pub fn avg_match_len(&self) -> f64 {
+        match self.match_count() {
+            0 => 0.0,
+            count => self.match_bytes as f64 / count as f64,
+        }
+    }
+
+    /// Average match distance, or 0 without matches.
+    pub fn avg_match_dist(&self) -> f64 {
+        match self.match_count() {
+            0 => 0.0,
+            count => self.match_dist_total as f64 / count as f64,
+        }
+    }
+
+    fn add_literals(&mut self, len: usize) {
+        if len > 0 {
+            self.literal_bytes += len;
+            self.literal_runs += 1;
+        }
+    }
+
+    fn add_match(&mut self, class: usize, len: u32, dist: u32) {
+        self.match_bytes += len as usize;
+        self.matches[class] += 1;
+        self.match_dist_total += dist as u64;
+    }
+}
+
+/// Progress of a [`Compressor`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Poll {
+    /// Input remains to be compressed.
+    Pending,
+    /// Compression finished, with the compressed size.
+    Ready(usize),
+}
+
+/// Compresses in bounded steps, for interleaving compression with other work.
+///
+/// Each call to [`step`](Compressor::step) consumes a bounded number of input positions, so
+/// firmware can service interrupts or a watchdog between steps rather than blocking for the
+/// whole buffer. The output is identical to [`compress_no_alloc`].
+///
+/// ```
+/// use lzokay::compress::*;
+/// # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+///
+/// # #[cfg(feature = "alloc")] {
+/// let mut dst = vec![0u8; compress_worst_size(input.len())];
+/// let mut dict = new_dict();
+/// let mut compressor = Compressor::new(&input, &mut dst, &mut dict);
+/// let size = loop {
+///     match compressor.step(4096)? {
+///         Poll::Pending => { /* Pet the watchdog */ }
+///         Poll::Ready(size) => break size,
+///     }
+/// };
+/// # assert_eq!(size, 10);
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub struct Compressor<'a, 'd> {
+    encoder: Encoder<'a, Lzo1x>,
+    dst: &'a mut [u8],
+    dict: &'a mut Dict<'d>,
+    result: Option<Result<usize, Error>>,
+}
+
+impl<'a, 'd> Compressor<'a, 'd> {
+    /// Prepares to compress `src` into `dst` with `dict`.
+    ///
+    /// For sizing `dst`, use [`compress_worst_size`].
+    pub fn new(src: &'a [u8], dst: &'a mut [u8], dict: &'a mut Dict<'d>) -> Self {
+        let encoder = Encoder::new(src, dict);
+        Compressor { encoder, dst, dict, result: None }
+    }
+
+    /// Compresses at least `max_positions` bytes of input, or the rest of it.
+    ///
+    /// A match may carry a step past `max_positions`, by at most the maximum match length of
+    /// 2048 bytes. Once compression finishes or fails, further calls return the same result.
+    pub fn step(&mut self, max_positions: usize) -> Result<Poll, Error> {
+        if let Some(result) = self.result {
+            return result.map(Poll::Ready);
+        }
+        let result = self.encoder.step(self.dst, self.dict.storage(), max_positions);
+        match result {
+            Ok(Poll::Pending) => return result,
+            Ok(Poll::Ready(size)) => self.result = Some(Ok(size)),
+            Err(e) => self.result = Some(Err(e)),
+        }
+        #[cfg(feature = "stats")]
+        crate::stats::record_compress(self.encoder.src.len(), &self.result.unwrap());
+        result
+    }
+
+    /// Finishes compression immediately, storing the rest of the input as literals.
+    ///
+    /// Takes about as long as copying the remaining input, for bounding compression time: see
+    /// [`compress_with_deadline`]. The output is a valid stream, but larger than a complete
+    /// compression. If compression already finished or failed, returns the same result.
+    pub fn finish_literals(&mut self) -> Result<usize, Error> {
+        if let Some(result) = self.result {
+            return result;
+        }
+        let result = self.encoder.finish_literals(self.dst);
+        self.result = Some(result);
+        #[cfg(feature = "stats")]
+        crate::stats::record_compress(self.encoder.src.len(), &result);
+        result
+    }
+
+    /// Number of input bytes compressed so far.
+    pub fn position(&self) -> usize { self.encoder.s.bufp }
+
+    /// Number of compressed bytes written so far.
+    pub fn output_len(&self) -> usize { self.encoder.outp }
+
+    /// Statistics about the compression so far.
+    pub fn stats(&self) -> CompressionStats { self.encoder.stats() }
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn compress_vec_impl<V: Variant>(src: &[u8], dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    let mut dst = vec![0u8; checked_compress_worst_size(src.len()).ok_or(Error::OutputOverrun)?];
+    let size = compress_impl::<V>(src, &mut dst, dict)?;
+    dst.truncate(size);
+    Ok(dst)
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(all(not(feature = "std"), feature = "alloc"))]
+    use super::alloc::{vec, vec::Vec};
+    use crate::compress::{checked_compress_worst_size, compress_worst_size};
+    #[cfg(not(feature = "forbid-unsafe"))]
+    use crate::compress::{compress_no_alloc, dict_from_storage, dict_storage_size};
     #[cfg(feature = "alloc")]
-    use crate::compress::{compress, compress_with_dict, new_dict};
-    use crate::compress::{
-        compress_no_alloc, compress_worst_size, dict_from_storage, dict_storage_size,
+    use crate::{
+        compress::{
+            compress, compress_at, compress_with_cancel, compress_with_dict,
+            compress_with_progress, new_dict, Compressor, EncoderProfile, Poll,
+        },
+        CancelToken,
+    };
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    use crate::{
+        compress::{compress_with_stats, CompressionStats, DEFAULT_LEVEL, MAX_LEVEL, MIN_LEVEL},
+        decompress::decompress,
+        Error,
     };
 
+    #[cfg(any(feature = "alloc", not(feature = "forbid-unsafe")))]
     const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    #[cfg(any(feature = "alloc", not(feature = "forbid-unsafe")))]
     const EXPECTED_1: &[u8] = include_bytes!("test1.bin");
+    #[cfg(any(feature = "alloc", not(feature = "forbid-unsafe")))]
     const INPUT_2: &[u8] = include_bytes!("test2.txt");
+    #[cfg(any(feature = "alloc", not(feature = "forbid-unsafe")))]
     const EXPECTED_2: &[u8] = include_bytes!("test2.bin");
 
     #[test]
@@ -175,6 +1759,10 @@ mod tests {
     fn test_compress() {
         let dst = compress(INPUT_1).expect("Failed to compress");
         assert_eq!(dst, EXPECTED_1);
+        // Any owned or borrowed buffer
+        let borrowed = compress(&dst);
+        assert_eq!(compress(dst.as_slice()), borrowed);
+        assert_eq!(compress(dst), borrowed);
     }
 
     #[test]
@@ -189,6 +1777,204 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "alloc")]
+    fn test_compressor_steps() {
+        let mut dict = new_dict();
+        for &max_positions in &[1, 7, 4096, usize::MAX] {
+            let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
+            let mut compressor = Compressor::new(INPUT_1, &mut dst, &mut dict);
+            let mut steps = 0;
+            let size = loop {
+                let position = compressor.position();
+                match compressor.step(max_positions).expect("Failed to compress") {
+                    Poll::Pending => {
+                        assert!(compressor.position() - position >= max_positions);
+                        assert!(compressor.position() - position < max_positions + 2048);
+                        steps += 1;
+                    }
+                    Poll::Ready(size) => break size,
+                }
+            };
+            assert_eq!(compressor.position(), INPUT_1.len());
+            assert_eq!(compressor.output_len(), size);
+            assert_eq!(compressor.step(max_positions), Ok(Poll::Ready(size)));
+            assert!(steps >= INPUT_1.len() / max_positions.saturating_add(2048));
+            assert_eq!(&dst[..size], EXPECTED_1);
+        }
+
+        // Failures are sticky
+        let mut dst = [0u8; 16];
+        let mut compressor = Compressor::new(INPUT_1, &mut dst, &mut dict);
+        assert_eq!(compressor.step(usize::MAX), Err(crate::Error::OutputOverrun));
+        assert_eq!(compressor.step(usize::MAX), Err(crate::Error::OutputOverrun));
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_compression_stats() {
+        use crate::decompress::{Token, TokenIter};
+
+        let mut dict = new_dict();
+        let (dst, stats) = compress_with_stats(INPUT_1, &mut dict).expect("Failed to compress");
+        assert_eq!(dst, EXPECTED_1);
+        assert_eq!(stats.input_len, INPUT_1.len());
+        assert_eq!(stats.output_len, dst.len());
+        assert_eq!(stats.literal_bytes + stats.match_bytes, INPUT_1.len());
+
+        // Agrees with the tokens in the output
+        let (mut literal_bytes, mut match_bytes, mut matches, mut dist_total) = (0, 0, 0, 0);
+        for token in TokenIter::new(&dst) {
+            match token.unwrap().1 {
+                Token::Literal(len) => literal_bytes += len,
+                Token::Match { len, dist } => {
+                    match_bytes += len;
+                    matches += 1;
+                    dist_total += dist as u64;
+                }
+                Token::End => {}
+            }
+        }
+        assert_eq!(stats.literal_bytes, literal_bytes);
+        assert_eq!(stats.match_bytes, match_bytes);
+        assert_eq!(stats.match_count(), matches);
+        assert_eq!(stats.match_dist_total, dist_total);
+        // Too short for M4's distances
+        assert!(stats.matches[..3].iter().all(|&count| count > 0), "{:?}", stats);
+        assert_eq!(stats.matches[3], 0);
+        assert_eq!(stats.avg_match_len(), match_bytes as f64 / matches as f64);
+
+        // The same from a Compressor
+        let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
+        let mut compressor = Compressor::new(INPUT_1, &mut dst, &mut dict);
+        while compressor.step(1000).unwrap() == Poll::Pending {}
+        let steps_stats = compressor.stats();
+        assert_eq!(CompressionStats { elapsed: stats.elapsed, ..steps_stats }, stats);
+
+        let (_, stats) = compress_with_stats([], &mut dict).unwrap();
+        assert_eq!(stats.match_count(), 0);
+        assert_eq!(stats.avg_match_dist(), 0.0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_finish_literals() {
+        let mut dict = new_dict();
+        for &max_positions in &[0, 1, 2, 7, 100, 1000, 3000] {
+            let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
+            let mut compressor = Compressor::new(INPUT_1, &mut dst, &mut dict);
+            if max_positions > 0 {
+                assert_eq!(compressor.step(max_positions), Ok(Poll::Pending));
+            }
+            let size = compressor.finish_literals().expect("Failed to finish");
+            assert_eq!(compressor.position(), INPUT_1.len());
+            assert_eq!(compressor.step(1), Ok(Poll::Ready(size)));
+            assert_eq!(compressor.finish_literals(), Ok(size));
+            let mut output = vec![0u8; INPUT_1.len()];
+            assert_eq!(
+                decompress(&dst[..size], &mut output),
+                Ok(INPUT_1.len()),
+                "{}",
+                max_positions
+            );
+            assert_eq!(output, INPUT_1);
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "std",
+        feature = "decompress",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    fn test_compress_with_deadline() {
+        use std::time::Duration;
+
+        use super::compress_with_deadline;
+
+        let mut dict = new_dict();
+        let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
+        let budget = Duration::from_secs(3600);
+        let (size, expired) = compress_with_deadline(INPUT_1, &mut dst, &mut dict, budget).unwrap();
+        assert_eq!((&dst[..size], expired), (EXPECTED_1, false));
+
+        // Everything stored as literals
+        let result = compress_with_deadline(INPUT_1, &mut dst, &mut dict, Duration::ZERO);
+        let (size, expired) = result.unwrap();
+        assert!(expired);
+        assert_eq!(size, INPUT_1.len() + (INPUT_1.len() - 18) / 255 + 2 + 3);
+        let mut output = vec![0u8; INPUT_1.len()];
+        assert_eq!(decompress(&dst[..size], &mut output), Ok(INPUT_1.len()));
+        assert_eq!(output, INPUT_1);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_compress_with_cancel() {
+        let mut dict = new_dict();
+        let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
+        let cancel = CancelToken::new();
+        let size = compress_with_cancel(INPUT_1, &mut dst, &mut dict, &cancel).unwrap();
+        assert_eq!(&dst[..size], EXPECTED_1);
+        cancel.cancel();
+        let result = compress_with_cancel(INPUT_1, &mut dst, &mut dict, &cancel);
+        assert_eq!(result, Err(crate::Error::Cancelled));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_compress_with_progress() {
+        let mut input = vec![];
+        while input.len() < 3 << 20 {
+            input.extend_from_slice(INPUT_1);
+        }
+        let mut dict = new_dict();
+        let mut reports = vec![];
+        let dst = compress_with_progress(&input, &mut dict, |progress| reports.push(progress))
+            .expect("Failed to compress");
+        assert_eq!(dst, compress_with_dict(&input, &mut dict).unwrap());
+        assert!(reports.len() >= 3, "{:?}", reports);
+        for pair in reports.windows(2) {
+            assert!(pair[1].bytes_in > pair[0].bytes_in);
+            assert!(pair[1].bytes_out >= pair[0].bytes_out);
+        }
+        let last = reports.last().unwrap();
+        assert_eq!((last.bytes_in, last.bytes_out), (input.len() as u64, dst.len() as u64));
+        assert!(reports.iter().all(|progress| progress.total_in == Some(input.len() as u64)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_compress_at() {
+        let mut dict = new_dict();
+        let mut dst = vec![0xffu8; 4 + EXPECTED_1.len() + EXPECTED_2.len()];
+        let mut pos = 4;
+        assert_eq!(compress_at(INPUT_1, &mut dst, &mut pos, &mut dict), Ok(EXPECTED_1.len()));
+        assert_eq!(pos, 4 + EXPECTED_1.len());
+        assert_eq!(compress_at(INPUT_2, &mut dst, &mut pos, &mut dict), Ok(EXPECTED_2.len()));
+        assert_eq!(pos, dst.len());
+        assert_eq!(dst[..4], [0xff; 4]);
+        assert_eq!(dst[4..4 + EXPECTED_1.len()], *EXPECTED_1);
+        assert_eq!(dst[4 + EXPECTED_1.len()..], *EXPECTED_2);
+
+        // Failures leave the position unchanged
+        let result = compress_at(INPUT_1, &mut dst, &mut pos, &mut dict);
+        assert_eq!((result, pos), (Err(crate::Error::OutputOverrun), dst.len()));
+        let mut pos = dst.len() + 1;
+        let result = compress_at(&[], &mut dst, &mut pos, &mut dict);
+        assert_eq!((result, pos), (Err(crate::Error::OutputOverrun), dst.len() + 1));
+    }
+
+    #[test]
+    fn test_checked_compress_worst_size() {
+        assert_eq!(checked_compress_worst_size(1000), Some(compress_worst_size(1000)));
+        let max = usize::MAX - usize::MAX / 17 - 67;
+        assert!(checked_compress_worst_size(max).is_some());
+        assert_eq!(checked_compress_worst_size(max + 100), None);
+        assert_eq!(checked_compress_worst_size(usize::MAX), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "forbid-unsafe"))]
     fn test_compress_no_alloc() {
         let mut dst = [0u8; compress_worst_size(INPUT_1.len())];
         let mut storage = [0u8; dict_storage_size()];
@@ -201,4 +1987,399 @@ mod tests {
             compress_no_alloc(INPUT_2, &mut dst, &mut dict).expect("Failed to compress (2)");
         assert_eq!(&dst[0..out_size], EXPECTED_2);
     }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_levels() {
+        let mut dict = new_dict();
+        assert_eq!(dict.level(), DEFAULT_LEVEL);
+        assert_eq!(dict.set_level(0), Err(Error::Error));
+        assert_eq!(dict.set_level(MAX_LEVEL + 1), Err(Error::Error));
+        let mut last_len = 0;
+        for level in (MIN_LEVEL..=MAX_LEVEL).rev() {
+            dict.set_level(level).unwrap();
+            let dst = compress_with_dict(INPUT_1, &mut dict).expect("Failed to compress");
+            let mut output = vec![0u8; INPUT_1.len()];
+            assert_eq!(decompress(&dst, &mut output), Ok(INPUT_1.len()));
+            assert_eq!(output, INPUT_1);
+            assert!(dst.len() >= last_len);
+            last_len = dst.len();
+            if level == DEFAULT_LEVEL {
+                assert_eq!(dst, EXPECTED_1);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", not(feature = "forbid-unsafe")))]
+    fn test_try_compress() {
+        use super::{compress_slim, try_compress, Dict, Mode};
+
+        assert!(Dict::try_new().is_some());
+        let mut dst = vec![0u8; compress_worst_size(INPUT_1.len())];
+        let (size, mode) = try_compress(INPUT_1, &mut dst).unwrap();
+        assert_eq!((&dst[..size], mode), (EXPECTED_1, Mode::Full));
+        // The fallback compresses less
+        let slim = compress_slim(INPUT_1, &mut dst).unwrap();
+        assert!(slim > size && slim < INPUT_1.len());
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_nice_len() {
+        use crate::format::{MAX_MATCH_LEN, MIN_MATCH_LEN};
+
+        let mut dict = new_dict();
+        assert_eq!(dict.nice_len(), MAX_MATCH_LEN);
+        assert_eq!(dict.set_nice_len(MIN_MATCH_LEN - 1), Err(Error::Error));
+        assert_eq!(dict.set_nice_len(MAX_MATCH_LEN + 1), Err(Error::Error));
+        let input = INPUT_1.repeat(8);
+        let full = compress_with_dict(&input, &mut dict).unwrap();
+        let mut last_len = full.len();
+        for &nice_len in &[256, 32, MIN_MATCH_LEN] {
+            dict.set_nice_len(nice_len).unwrap();
+            assert_eq!(dict.nice_len(), nice_len);
+            let dst = compress_with_dict(&input, &mut dict).unwrap();
+            let mut output = vec![0u8; input.len()];
+            assert_eq!(decompress(&dst, &mut output), Ok(input.len()));
+            assert_eq!(output, input);
+            assert!(dst.len() >= last_len);
+            last_len = dst.len();
+        }
+        assert!(last_len > full.len());
+
+        // Levels and profiles reset it
+        dict.set_level(MIN_LEVEL).unwrap();
+        assert_eq!(dict.nice_len(), 16);
+        dict.set_profile(EncoderProfile::Stable1);
+        assert_eq!(dict.nice_len(), MAX_MATCH_LEN);
+        dict.set_nice_len(MIN_MATCH_LEN).unwrap();
+        dict.set_level(MAX_LEVEL).unwrap();
+        assert_eq!(dict.nice_len(), MAX_MATCH_LEN);
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_opcodes() {
+        use super::{compress_with_stats, Opcodes};
+
+        // Text, then noise, then the text again past M3's reach
+        let mut input = INPUT_1.to_vec();
+        let mut seed = 1u32;
+        for _ in 0..20_000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            input.push((seed >> 16) as u8);
+        }
+        input.extend_from_slice(INPUT_1);
+
+        let mut dict = new_dict();
+        assert_eq!(dict.opcodes(), Opcodes::ALL);
+        let (full, stats) = compress_with_stats(&input, &mut dict).unwrap();
+        assert!(stats.matches.iter().all(|&count| count > 0));
+        let subsets = [
+            Opcodes::M2 | Opcodes::M3 | Opcodes::M4,
+            Opcodes::M1 | Opcodes::M3 | Opcodes::M4,
+            Opcodes::M2 | Opcodes::M3,
+            Opcodes::M3,
+            Opcodes::M4,
+            Opcodes::NONE,
+        ];
+        for &opcodes in &subsets {
+            dict.set_opcodes(opcodes);
+            let (dst, stats) = compress_with_stats(&input, &mut dict).unwrap();
+            for (class, &count) in stats.matches.iter().enumerate() {
+                assert!(count == 0 || opcodes.has_class(class), "{:?}", opcodes);
+            }
+            assert!(dst.len() > full.len());
+            let mut output = vec![0u8; input.len()];
+            assert_eq!(decompress(&dst, &mut output), Ok(input.len()));
+            assert_eq!(output, input);
+            assert_eq!(compress_with_dict(&input, &mut dict).unwrap(), dst);
+        }
+        // Without matches, the input is one literal run
+        let size = super::literal_header_len(0, input.len()) + input.len() + 3;
+        assert_eq!(compress_with_dict(&input, &mut dict).unwrap().len(), size);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_stable_profile() {
+        use crate::checksum::{crc32, CRC32_INIT};
+
+        /// Size and CRC-32 of an output.
+        type Golden = (usize, u32);
+
+        // Outputs of each input for each level and seed. These must never change: a heuristic
+        // change goes in a new profile instead.
+        const STABLE1: [(u8, u32, [Golden; 4]); 10] = [
+            (1, 0, [
+                (1720, 0x8e6a_6583),
+                (1480, 0xf50c_0dd5),
+                (75478, 0x583a_4756),
+                (6117, 0xa5cb_2c01),
+            ]),
+            (2, 0, [
+                (1710, 0x7895_e6d7),
+                (1463, 0xab14_1721),
+                (63790, 0xa709_e012),
+                (5407, 0x3281_553c),
+            ]),
+            (3, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (54889, 0x24c1_dc0f),
+                (4930, 0x9612_b95b),
+            ]),
+            (4, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (49379, 0x822c_7294),
+                (4713, 0xea37_9da1),
+            ]),
+            (5, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (46436, 0x2be6_79af),
+                (4641, 0x4efb_1b6e),
+            ]),
+            (6, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (45265, 0xff27_02bb),
+                (4576, 0x1896_2454),
+            ]),
+            (7, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (44869, 0x6d91_b26f),
+                (4361, 0x25b4_960f),
+            ]),
+            (8, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (44811, 0xe7f2_398a),
+                (4069, 0x88ae_f1f4),
+            ]),
+            (9, 0, [
+                (1706, 0x57ff_c72c),
+                (1463, 0x5385_5fc3),
+                (44811, 0xe7f2_398a),
+                (3831, 0x4cda_c90f),
+            ]),
+            (1, 0xdead_beef, [
+                (1719, 0x7259_8676),
+                (1478, 0xaf8c_e118),
+                (75943, 0xfe1b_25b9),
+                (6117, 0xa5cb_2c01),
+            ]),
+        ];
+
+        // The small inputs are compressed the same from level 3, so each level's search limit
+        // is covered by larger inputs with many candidate matches. The sampled input's chains
+        // are too short to tell levels 8 and 9 apart, unlike the two-letter input's.
+        let large = sampled_input();
+        let long_chains = two_letter_input();
+        let mut dict = new_dict();
+        assert_eq!(dict.profile(), EncoderProfile::Latest);
+        dict.set_profile(EncoderProfile::Stable1);
+        for &(level, seed, expected) in &STABLE1 {
+            dict.set_level(level).unwrap();
+            dict.set_seed(seed);
+            for (&input, &(len, crc)) in
+                [INPUT_1, INPUT_2, &large, &long_chains].iter().zip(&expected)
+            {
+                let dst = compress_with_dict(input, &mut dict).unwrap();
+                assert_eq!((dst.len(), crc32(CRC32_INIT, &dst)), (len, crc), "level {}", level);
+            }
+        }
+        dict.set_level(super::DEFAULT_LEVEL).unwrap();
+        dict.set_seed(0);
+        assert_eq!(compress_with_dict(INPUT_1, &mut dict).unwrap(), EXPECTED_1);
+    }
+
+    /// Deterministic input of 256 KiB: pieces of the test inputs of random lengths and offsets,
+    /// with a few bytes changed, so that matches of many lengths repeat at many distances.
+    #[cfg(feature = "alloc")]
+    fn sampled_input() -> Vec<u8> {
+        let mut seed = 0x9e37_79b9u32;
+        let mut next = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % n
+        };
+        let mut input = Vec::with_capacity(256 << 10);
+        while input.len() < 256 << 10 {
+            let source = if next(2) == 0 { INPUT_1 } else { INPUT_2 };
+            let piece_len = 4 + next(60);
+            let start = next(source.len() - piece_len);
+            input.extend_from_slice(&source[start..start + piece_len]);
+            if next(4) == 0 {
+                let i = input.len() - 1 - next(piece_len);
+                input[i] = b'a' + next(26) as u8;
+            }
+        }
+        input.truncate(256 << 10);
+        input
+    }
+
+    /// Deterministic input of 16 KiB of random `a`s and `b`s, whose match chains are as long as
+    /// chains get, with longer matches the further the encoder searches.
+    #[cfg(feature = "alloc")]
+    fn two_letter_input() -> Vec<u8> {
+        let mut seed = 0x9e37_79b9u32;
+        (0..16 << 10)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                b'a' + (seed & 1) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_seed() {
+        let mut dict = new_dict();
+        assert_eq!(dict.seed(), 0);
+        // At the lowest level, chains are short enough for collisions to change the output
+        dict.set_level(MIN_LEVEL).unwrap();
+        let unseeded = compress_with_dict(INPUT_1, &mut dict).expect("Failed to compress");
+        let mut outputs = vec![];
+        for &seed in &[1, 0xdead_beef, u32::MAX] {
+            dict.set_seed(seed);
+            let dst = compress_with_dict(INPUT_1, &mut dict).expect("Failed to compress");
+            assert_eq!(compress_with_dict(INPUT_1, &mut dict), Ok(dst.clone()));
+            let mut output = vec![0u8; INPUT_1.len()];
+            assert_eq!(decompress(&dst, &mut output), Ok(INPUT_1.len()));
+            assert_eq!(output, INPUT_1);
+            outputs.push(dst);
+        }
+        assert!(outputs.iter().any(|dst| *dst != unseeded));
+
+        #[cfg(feature = "std")]
+        {
+            dict.randomize_seed();
+            let dst = compress_with_dict(INPUT_1, &mut dict).unwrap();
+            let mut output = vec![0u8; INPUT_1.len()];
+            assert_eq!(decompress(&dst, &mut output), Ok(INPUT_1.len()));
+        }
+
+        // Seed 0 reproduces the unseeded output
+        dict.set_seed(0);
+        dict.set_level(DEFAULT_LEVEL).unwrap();
+        assert_eq!(compress_with_dict(INPUT_1, &mut dict).unwrap(), EXPECTED_1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_verify() {
+        use super::Encoder;
+        use crate::variant::Lzo1x;
+
+        let mut dict = new_dict();
+        assert!(!dict.verify());
+        dict.set_verify(true);
+        assert_eq!(compress_with_dict(INPUT_1, &mut dict).unwrap(), EXPECTED_1);
+        assert_eq!(compress_with_dict([], &mut dict).unwrap(), [0x11, 0, 0]);
+        let mut dst = vec![0u8; compress_worst_size(INPUT_2.len())];
+        let mut compressor = Compressor::new(INPUT_2, &mut dst, &mut dict);
+        compressor.step(1000).unwrap();
+        assert!(compressor.finish_literals().is_ok());
+
+        // Output that doesn't reproduce the input fails
+        let encoder = Encoder::<Lzo1x>::new(INPUT_1, &mut dict);
+        assert_eq!(encoder.check(EXPECTED_1), Ok(()));
+        let mut corrupt = EXPECTED_1.to_vec();
+        corrupt[1] ^= 1;
+        assert_eq!(encoder.check(&corrupt), Err(Error::VerificationFailed));
+        assert_eq!(encoder.check(&EXPECTED_1[..10]), Err(Error::VerificationFailed));
+        let encoder = Encoder::<Lzo1x>::new(&INPUT_1[1..], &mut dict);
+        assert_eq!(encoder.check(EXPECTED_1), Err(Error::VerificationFailed));
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_compress_with_preset() {
+        use super::compress_with_preset;
+        use crate::decompress::decompress_with_dict;
+
+        let mut dict = new_dict();
+        assert_eq!(compress_with_preset(INPUT_1, &[], &mut dict).unwrap(), EXPECTED_1);
+        dict.set_verify(true);
+        // The last is longer than the distance matches can reach
+        let long = INPUT_2.repeat(20);
+        for preset in [&INPUT_1[..1000], INPUT_2, &long].iter() {
+            let dst = compress_with_preset(&INPUT_1[1000..], preset, &mut dict).unwrap();
+            let mut output = preset.to_vec();
+            output.resize(INPUT_1.len() - 1000 + preset.len(), 0);
+            let size = decompress_with_dict(&dst, &mut output, preset.len()).unwrap();
+            assert_eq!(&output[preset.len()..preset.len() + size], &INPUT_1[1000..]);
+        }
+        // Matches copy from the preset
+        let plain = compress_with_dict(&INPUT_1[1000..], &mut dict).unwrap();
+        let preset = compress_with_preset(&INPUT_1[1000..], &INPUT_1[..1000], &mut dict).unwrap();
+        assert!(preset.len() < plain.len());
+        assert_eq!(compress_with_preset([], INPUT_2, &mut dict).unwrap(), [0x11, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_dict_traits() {
+        use crate::compress::{compress_with_dict, Dict, MIN_LEVEL};
+
+        let mut dict = Dict::default();
+        dict.set_level(MIN_LEVEL).unwrap();
+        let debug = format!("{:?}", dict);
+        assert!(debug.starts_with("Dict { level: 1, profile: Latest,"), "{}", debug);
+        assert!(debug.ends_with("storage: \"owned\" }"), "{}", debug);
+        assert_eq!(compress_with_dict(INPUT_1, &mut Dict::default()).unwrap(), EXPECTED_1);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_dict_threads() {
+        use std::{sync::Mutex, thread};
+
+        use crate::compress::{compress_with_dict, new_dict, DEFAULT_LEVEL};
+
+        // A pool of dictionaries, each moved to a thread and back
+        let pool = Mutex::new(vec![new_dict(), new_dict()]);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut dict = pool.lock().unwrap().pop().unwrap_or_default();
+                    assert_eq!(compress_with_dict(INPUT_1, &mut dict).unwrap(), EXPECTED_1);
+                    pool.lock().unwrap().push(dict);
+                });
+            }
+        });
+        assert!(pool.lock().unwrap().len() >= 2);
+
+        // Or shared, for reading settings
+        let dict = new_dict();
+        thread::scope(|scope| {
+            scope.spawn(|| assert_eq!(dict.level(), DEFAULT_LEVEL));
+        });
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "zeroize"))]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let mut dict = new_dict();
+        dict.set_level(MIN_LEVEL).unwrap();
+        let expected = compress_with_dict(INPUT_1, &mut dict).unwrap();
+        assert!(dict.storage().buffer.iter().any(|&b| b != 0));
+        dict.zeroize();
+        let storage = dict.storage();
+        assert!(storage.buffer.iter().all(|&b| b == 0));
+        assert!(storage.match2.head.iter().all(|&pos| pos == 0));
+        assert!(storage.match3.chain.iter().all(|&pos| pos == 0));
+        // Settings are kept, and the dictionary is still usable
+        assert_eq!(dict.level(), MIN_LEVEL);
+        assert_eq!(compress_with_dict(INPUT_1, &mut dict), Ok(expected));
+    }
 }
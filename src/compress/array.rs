@@ -0,0 +1,78 @@
+//! Compression into fixed-size arrays.
+
+use super::{compress_no_alloc, compress_worst_size, Dict};
+use crate::Error;
+
+/// Holds the compile-time check that `N` bytes fit the worst-case compression of `M`.
+struct WorstSize<const M: usize, const N: usize>;
+
+impl<const M: usize, const N: usize> WorstSize<M, N> {
+    const CHECK: () = assert!(
+        N >= compress_worst_size(M),
+        "output array is smaller than compress_worst_size of the input"
+    );
+}
+
+/// Compress `src` into an array of `N` bytes, returning the array and the compressed size.
+///
+/// For `#![no_std]` callers wanting an owned, fixed-size result. `N` must be at least
+/// [`compress_worst_size`] of the input's size, which is checked at compile time, so
+/// compression can't run out of room.
+///
+/// ```
+/// use lzokay::compress::{compress_to_array, compress_worst_size, new_dict};
+///
+/// const INPUT: [u8; 512] = [0u8; 512];
+/// # #[cfg(feature = "alloc")] {
+/// let (dst, size) =
+///     compress_to_array::<{ compress_worst_size(INPUT.len()) }, 512>(&INPUT, &mut new_dict())?;
+/// # assert_eq!(size, 10);
+/// let compressed = &dst[..size];
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+///
+/// A smaller array fails to build:
+///
+/// ```compile_fail
+/// # use lzokay::compress::{compress_to_array, new_dict};
+/// let _ = compress_to_array::<16, 512>(&[0u8; 512], &mut new_dict());
+/// ```
+pub fn compress_to_array<const N: usize, const M: usize>(
+    src: &[u8; M],
+    dict: &mut Dict,
+) -> Result<([u8; N], usize), Error> {
+    #[allow(clippy::let_unit_value)]
+    let () = WorstSize::<M, N>::CHECK;
+    let mut dst = [0u8; N];
+    let size = compress_no_alloc(src, &mut dst, dict)?;
+    Ok((dst, size))
+}
+
+#[cfg(all(test, feature = "alloc", feature = "decompress"))]
+mod tests {
+    use super::compress_to_array;
+    use crate::{
+        compress::{compress_worst_size, new_dict},
+        decompress::decompress,
+    };
+
+    #[test]
+    fn test_compress_to_array() {
+        let mut input = [0u8; 4096];
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = (i * i / 7) as u8;
+        }
+        let mut dict = new_dict();
+        let (dst, size) =
+            compress_to_array::<{ compress_worst_size(4096) }, 4096>(&input, &mut dict).unwrap();
+        assert!(size < input.len());
+        let mut output = [0u8; 4096];
+        assert_eq!(decompress(&dst[..size], &mut output), Ok(input.len()));
+        assert_eq!(output[..], input[..]);
+
+        let (dst, size) =
+            compress_to_array::<{ compress_worst_size(0) }, 0>(&[], &mut dict).unwrap();
+        assert_eq!(dst[..size], [0x11, 0, 0]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Compression of many blocks on several threads.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use super::{compress_with_dict, Dict};
+use crate::Error;
+
+/// Compress each of `blocks` on up to `threads` threads, returning the compressed blocks in
+/// order.
+///
+/// Built on scoped threads of `std` alone, for projects that can't take a dependency on a
+/// thread pool such as rayon. A `threads` of 0 starts one per CPU, as reported by
+/// [`available_parallelism`](std::thread::available_parallelism). Threads take the next
+/// block as they finish the last, each with a dictionary of the same settings as `dict`, so
+/// the output is identical to compressing the blocks one after the other with
+/// [`compress_with_dict`]. Fails with the error of the first failing block.
+///
+/// ```
+/// use lzokay::compress::{compress_parallel, new_dict};
+///
+/// let blocks = [&[0u8; 512][..], b"abcd"];
+/// let compressed = compress_parallel(&blocks, 2, &new_dict())?;
+/// assert_eq!(compressed[0], [0x12, 0, 0x20, 0, 0xdf, 0, 0, 0x11, 0, 0]);
+/// assert_eq!(compressed[1], [0x15, b'a', b'b', b'c', b'd', 0x11, 0, 0]);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn compress_parallel(
+    blocks: &[&[u8]],
+    threads: usize,
+    dict: &Dict,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        threads => threads,
+    }
+    .min(blocks.len());
+    let next = AtomicUsize::new(0);
+    let worker = || {
+        let mut dict = dict.new_like();
+        let mut compressed = Vec::new();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            match blocks.get(index) {
+                Some(block) => compressed.push((index, compress_with_dict(block, &mut dict))),
+                None => return compressed,
+            }
+        }
+    };
+    let mut results: Vec<Option<Result<Vec<u8>, Error>>> = Vec::new();
+    results.resize_with(blocks.len(), || None);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads).map(|_| scope.spawn(worker)).collect();
+        for handle in handles {
+            for (index, result) in handle.join().unwrap() {
+                results[index] = Some(result);
+            }
+        }
+    });
+    // Every index below the number of blocks was taken by a thread
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compress_parallel;
+    use crate::compress::{compress_with_dict, new_dict, Opcodes};
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.txt");
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.bin");
+    const INPUT_2: &[u8] = include_bytes!("../test2.txt");
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.bin");
+
+    #[test]
+    fn test_compress_parallel() {
+        let blocks = [INPUT_1, INPUT_2, &[], INPUT_1, INPUT_2, INPUT_1];
+        let dict = new_dict();
+        for &threads in &[0, 1, 2, 3, 64] {
+            let compressed = compress_parallel(&blocks, threads, &dict).unwrap();
+            let expected =
+                [EXPECTED_1, EXPECTED_2, &[0x11, 0, 0], EXPECTED_1, EXPECTED_2, EXPECTED_1];
+            assert_eq!(compressed, expected, "{}", threads);
+        }
+        assert_eq!(compress_parallel(&[], 0, &dict), Ok(vec![]));
+
+        // Threads use the dictionary's settings
+        let mut dict = new_dict();
+        dict.set_level(1).unwrap();
+        dict.set_opcodes(Opcodes::M3 | Opcodes::M4);
+        let compressed = compress_parallel(&blocks, 4, &dict).unwrap();
+        for (block, compressed) in blocks.iter().zip(&compressed) {
+            assert_eq!(*compressed, compress_with_dict(block, &mut dict).unwrap());
+        }
+    }
+}
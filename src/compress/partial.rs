@@ -0,0 +1,297 @@
+//! Compression into a sequence of fixed-size buffers.
+
+use core::cmp::min;
+
+use super::{encode_lookback_match, CompressionStats, Dict, Encoder, M4_MARKER};
+use crate::variant::{Lzo1x, Variant};
+
+/// Result of [`PartialCompressor::compress_into`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Partial {
+    /// The buffer is full, and the stream continues in the next.
+    Full {
+        /// Number of input bytes encoded by the output written so far.
+        consumed: usize,
+    },
+    /// The stream ended, with this many bytes in the buffer.
+    Finished(usize),
+}
+
+/// Compresses into a sequence of buffers, filling each exactly before continuing in the next.
+///
+/// For writers of fixed-size sectors, which would otherwise need a buffer of
+/// [`compress_worst_size`] to copy from. The stream is split at arbitrary bytes: the buffers
+/// concatenated are identical to the output of [`compress_no_alloc`]. The output isn't
+/// verified, even with [`Dict::set_verify`], as it's never whole.
+///
+/// ```
+/// use lzokay::compress::*;
+/// # #[allow(non_upper_case_globals)] const input: [u8; 2048] = [0u8; 2048];
+///
+/// # #[cfg(feature = "alloc")] {
+/// let mut dict = new_dict();
+/// let mut compressor = PartialCompressor::new(&input, &mut dict);
+/// let mut sector = [0u8; 4];
+/// let mut sectors = 0;
+/// let last = loop {
+///     match compressor.compress_into(&mut sector) {
+///         Partial::Full { .. } => sectors += 1, // Write the sector
+///         Partial::Finished(size) => break size,
+///     }
+/// };
+/// # assert_eq!((sectors, last), (3, 4));
+/// # }
+/// ```
+pub struct PartialCompressor<'a, 'd> {
+    encoder: Encoder<'a, Lzo1x>,
+    dict: &'a mut Dict<'d>,
+    spill: Spill,
+    consumed: usize,
+    /// Whether the terminating instruction has been queued.
+    ended: bool,
+    finished: bool,
+}
+
+impl<'a, 'd> PartialCompressor<'a, 'd> {
+    /// Prepares to compress `src` with `dict`.
+    pub fn new(src: &'a [u8], dict: &'a mut Dict<'d>) -> Self {
+        let encoder = Encoder::new(src, dict);
+        PartialCompressor {
+            encoder,
+            dict,
+            spill: Spill::default(),
+            consumed: 0,
+            ended: false,
+            finished: false,
+        }
+    }
+
+    /// Compresses into `dst` until it's full or the stream ends.
+    ///
+    /// Once the stream has ended, further calls return [`Partial::Finished(0)`].
+    pub fn compress_into(&mut self, dst: &mut [u8]) -> Partial {
+        let src = self.encoder.src;
+        let mut outp = 0;
+        loop {
+            if !self.spill.write(src, dst, &mut outp, &mut self.consumed) {
+                return Partial::Full { consumed: self.consumed };
+            }
+            let encoder = &mut self.encoder;
+            if self.ended {
+                if !self.spill.held.is_empty() {
+                    self.spill.released = true;
+                    continue;
+                }
+                if !self.finished {
+                    self.finished = true;
+                    #[cfg(feature = "stats")]
+                    crate::stats::record_compress(src.len(), &Ok(encoder.outp));
+                }
+                return Partial::Finished(outp);
+            }
+            match encoder.next_match(self.dict.storage()) {
+                Some((lit, lb_len, lb_off, class)) => {
+                    self.spill.push_literals::<Lzo1x>(lit, &mut encoder.outp);
+                    encoder.stats.add_literals(lit.1 - lit.0);
+                    let end = lit.1 + lb_len as usize;
+                    self.spill.push_match::<Lzo1x>(lb_len, lb_off, class, end, &mut encoder.outp);
+                    encoder.stats.add_match(class, lb_len, lb_off);
+                }
+                None => {
+                    let lit = (encoder.lit_ptr, encoder.lit_ptr + encoder.lit_len);
+                    self.spill.push_literals::<Lzo1x>(lit, &mut encoder.outp);
+                    encoder.stats.add_literals(encoder.lit_len);
+                    encoder.lit_len = 0;
+                    // Terminating M4
+                    self.spill.next = Instruction::new(&[M4_MARKER | 1, 0, 0], src.len());
+                    encoder.outp += 3;
+                    self.ended = true;
+                }
+            }
+        }
+    }
+
+    /// Number of input bytes encoded by the output written so far.
+    pub fn consumed(&self) -> usize { self.consumed }
+
+    /// Statistics about the compression so far.
+    pub fn stats(&self) -> CompressionStats { self.encoder.stats() }
+}
+
+/// A match or terminating instruction, for [`Spill`].
+#[derive(Default)]
+struct Instruction {
+    bytes: [u8; 16],
+    start: usize,
+    end: usize,
+    /// Input position after the match.
+    pos: usize,
+}
+
+impl Instruction {
+    fn new(bytes: &[u8], pos: usize) -> Self {
+        let mut instruction = Instruction { end: bytes.len(), pos, ..Default::default() };
+        instruction.bytes[..bytes.len()].copy_from_slice(bytes);
+        instruction
+    }
+
+    fn is_empty(&self) -> bool { self.start == self.end }
+
+    /// Writes as much as fits, returning whether it's all written.
+    fn write(&mut self, dst: &mut [u8], outp: &mut usize) -> bool {
+        let len = min(self.end - self.start, dst.len() - *outp);
+        dst[*outp..*outp + len].copy_from_slice(&self.bytes[self.start..self.start + len]);
+        *outp += len;
+        self.start += len;
+        self.is_empty()
+    }
+}
+
+/// Instructions encoded by a [`PartialCompressor`] but not yet written, in stream order.
+#[derive(Default)]
+struct Spill {
+    /// The last match, held back until the next literal run, whose length may be stored in its
+    /// state bits.
+    held: Instruction,
+    /// Whether the held match is complete and can be written.
+    released: bool,
+    /// Zero bytes of a long literal run's length.
+    zeros: usize,
+    /// The literal run's last length byte.
+    len_byte: Option<u8>,
+    /// Range of the input still to copy as literals.
+    lit: (usize, usize),
+    /// The match after the literal run, held once the run is written.
+    next: Instruction,
+}
+
+impl Spill {
+    /// Writes as much as fits, up to the held match, returning whether it's all written.
+    fn write(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        outp: &mut usize,
+        consumed: &mut usize,
+    ) -> bool {
+        if self.released {
+            if !self.held.write(dst, outp) {
+                return false;
+            }
+            *consumed = self.held.pos;
+            self.released = false;
+        }
+        let zeros = min(self.zeros, dst.len() - *outp);
+        dst[*outp..*outp + zeros].iter_mut().for_each(|b| *b = 0);
+        *outp += zeros;
+        self.zeros -= zeros;
+        if self.zeros > 0 {
+            return false;
+        }
+        if let Some(byte) = self.len_byte {
+            if *outp == dst.len() {
+                return false;
+            }
+            dst[*outp] = byte;
+            *outp += 1;
+            self.len_byte = None;
+        }
+        if self.lit.0 < self.lit.1 {
+            let len = min(self.lit.1 - self.lit.0, dst.len() - *outp);
+            dst[*outp..*outp + len].copy_from_slice(&src[self.lit.0..self.lit.0 + len]);
+            *outp += len;
+            self.lit.0 += len;
+            if self.lit.0 < self.lit.1 {
+                return false;
+            }
+            *consumed = self.lit.1;
+        }
+        if !self.next.is_empty() {
+            self.held = core::mem::take(&mut self.next);
+        }
+        true
+    }
+
+    /// Queues a literal run after the held match, releasing it. Encodes the run's length as
+    /// [`encode_literal_header`] does.
+    fn push_literals<V: Variant>(&mut self, lit: (usize, usize), outp: &mut usize) {
+        let len = lit.1 - lit.0;
+        if len == 0 {
+        } else if *outp == 0 && len <= 238 {
+            self.len_byte = Some(17 + len as u8);
+        } else if len <= 3 {
+            self.held.bytes[self.held.end - V::STATE_OFFSET] |= len as u8;
+        } else if len <= 18 {
+            self.len_byte = Some((len - 3) as u8);
+        } else {
+            // A zero byte, then the length beyond 18 in zero bytes of 255 and a remainder
+            let extra = (len - 19) / 255;
+            self.zeros = 1 + extra;
+            self.len_byte = Some((len - 18 - extra * 255) as u8);
+        }
+        *outp += self.zeros + self.len_byte.map_or(0, |_| 1) + len;
+        self.lit = lit;
+        self.released = true;
+    }
+
+    /// Queues a match of instruction class `class` after the literal run.
+    fn push_match<V: Variant>(
+        &mut self,
+        lb_len: u32,
+        lb_off: u32,
+        class: usize,
+        pos: usize,
+        outp: &mut usize,
+    ) {
+        let mut next = Instruction { pos, ..Default::default() };
+        let result =
+            encode_lookback_match::<V>(&mut next.bytes, &mut next.end, lb_len, lb_off, class);
+        // At most 12 bytes for the longest match
+        debug_assert!(result.is_ok());
+        *outp += next.end;
+        self.next = next;
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    #[cfg(not(feature = "std"))]
+    use super::super::alloc::vec;
+    use super::{Partial, PartialCompressor};
+    use crate::compress::new_dict;
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.txt");
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.bin");
+    const INPUT_2: &[u8] = include_bytes!("../test2.txt");
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.bin");
+
+    #[test]
+    fn test_partial_compressor() {
+        let mut dict = new_dict();
+        for &(input, expected) in [(INPUT_1, EXPECTED_1), (INPUT_2, EXPECTED_2)].iter() {
+            for &sector_size in &[1, 2, 7, 512, 4096] {
+                let mut output = vec![];
+                let mut compressor = PartialCompressor::new(input, &mut dict);
+                let mut sector = vec![0u8; sector_size];
+                let mut consumed = 0;
+                loop {
+                    match compressor.compress_into(&mut sector) {
+                        Partial::Full { consumed: now } => {
+                            assert!(now >= consumed && now <= input.len());
+                            consumed = now;
+                            output.extend_from_slice(&sector);
+                        }
+                        Partial::Finished(size) => {
+                            output.extend_from_slice(&sector[..size]);
+                            break;
+                        }
+                    }
+                }
+                assert_eq!(compressor.consumed(), input.len());
+                assert_eq!(compressor.compress_into(&mut sector), Partial::Finished(0));
+                assert_eq!(compressor.stats().output_len, expected.len());
+                assert_eq!(output, expected, "{}", sector_size);
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! A greedy encoder with a small hash table, for when a dictionary can't be allocated.
+
+use core::convert::TryInto;
+
+use super::{
+    encode_literal_run, encode_lookback_match, match_class, write_bytes, Opcodes, M4_MARKER,
+    MAX_DIST, MAX_MATCH_LEN,
+};
+use crate::{variant::Lzo1x, word, Error};
+
+/// Number of hash bits, so the table takes 32 KiB with 64-bit positions.
+const HASH_BITS: u32 = 12;
+/// Shortest match the hash finds.
+const MIN_LEN: usize = 4;
+
+/// Compress `src` into `dst` without a dictionary, returning the compressed size.
+///
+/// A fallback for when a dictionary's memory isn't available: matches are found greedily with
+/// a table of the last position of each hash of 4 bytes, held on the stack, so the output is
+/// larger than with a dictionary, but compression is fast and needs neither heap nor
+/// dictionary storage. For sizing `dst`, use [`compress_worst_size`](super::compress_worst_size).
+///
+/// ```
+/// use lzokay::compress::{compress_slim, compress_worst_size};
+///
+/// let input = [0u8; 512];
+/// let mut dst = [0u8; compress_worst_size(512)];
+/// let size = compress_slim(&input, &mut dst)?;
+/// # assert!(size < 16);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn compress_slim(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut table = [0usize; 1 << HASH_BITS];
+    let mut outp = 0;
+    let mut lit_start = 0;
+    let mut ip = 0;
+    while src.len() - ip >= MIN_LEN {
+        let key = u32::from_le_bytes(src[ip..ip + MIN_LEN].try_into().unwrap());
+        let hash = (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize;
+        // Positions are stored plus one, leaving 0 for empty entries
+        let candidate = table[hash].wrapping_sub(1);
+        table[hash] = ip + 1;
+        let lit_len = ip - lit_start;
+        // A stream can't start with a match
+        if candidate >= ip || ip - candidate > MAX_DIST as usize || (outp == 0 && lit_len == 0) {
+            ip += 1;
+            continue;
+        }
+        let end = (ip + MAX_MATCH_LEN as usize).min(src.len());
+        let len = word::match_len(&src[ip..end], &src[candidate..]);
+        if len < MIN_LEN {
+            ip += 1;
+            continue;
+        }
+        let (lb_len, lb_off) = (len as u32, (ip - candidate) as u32);
+        // Matches of 4 or more bytes within the maximum distance always have a class
+        let class = match_class::<Lzo1x>(Opcodes::ALL, lb_len, lb_off, lit_len).unwrap_or(2);
+        encode_literal_run::<Lzo1x>(dst, &mut outp, &src[lit_start..ip])?;
+        encode_lookback_match::<Lzo1x>(dst, &mut outp, lb_len, lb_off, class)?;
+        ip += len;
+        lit_start = ip;
+    }
+    encode_literal_run::<Lzo1x>(dst, &mut outp, &src[lit_start..])?;
+    write_bytes(dst, &mut outp, &[M4_MARKER | 1, 0, 0])?;
+    Ok(outp)
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use super::compress_slim;
+    use crate::{compress::compress_worst_size, decompress::decompress, Error};
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("../test2.txt");
+
+    #[test]
+    fn test_compress_slim() {
+        let mut run = [0u8; 100_000];
+        run[50_000..].iter_mut().for_each(|byte| *byte = 1);
+        let inputs: [&[u8]; 6] = [INPUT_1, INPUT_2, &run, b"abcd", b"a", &[]];
+        for &input in inputs.iter() {
+            let mut dst = [0u8; compress_worst_size(100_000)];
+            let size = compress_slim(input, &mut dst).unwrap();
+            assert!(size < input.len() || input.len() < 8);
+            let mut output = [0u8; 100_000];
+            assert_eq!(decompress(&dst[..size], &mut output), Ok(input.len()));
+            assert_eq!(output[..input.len()], *input);
+        }
+
+        // Running out of room fails
+        let mut dst = [0u8; 64];
+        assert_eq!(compress_slim(INPUT_1, &mut dst), Err(Error::OutputOverrun));
+    }
+}
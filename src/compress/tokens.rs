@@ -0,0 +1,938 @@
+//! Streams written token by token, and surgery on existing streams.
+
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+use core::cmp::min;
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "decompress"))]
+use super::alloc::{vec, vec::Vec};
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+use super::{compress_with_dict, compress_worst_size, Dict};
+use super::{
+    encode_literal_header, encode_lookback_match, literal_header_len, match_class, needs_out,
+    write_bytes, Opcodes, M1_MAX_OFFSET, M4_MARKER, MAX_DIST,
+};
+#[cfg(feature = "decompress")]
+use crate::decompress::{Token, TokenIter};
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+use crate::format;
+use crate::{
+    variant::{Lzo1x, Variant},
+    Error,
+};
+
+/// Writes an LZO1X stream of literal runs and matches chosen by the caller.
+///
+/// For custom encoders, such as optimal parsers or transcoders, that decide what to encode but
+/// not how. Each token is checked against the format's constraints, so any sequence of
+/// successful calls produces a valid stream. Consecutive literal runs are merged, as the format
+/// can't encode them separately. A failed call leaves the stream unchanged.
+///
+/// ```
+/// use lzokay::compress::*;
+///
+/// let mut dst = [0u8; 16];
+/// let mut writer = TokenWriter::new(&mut dst);
+/// writer.write_literal(b"abcd")?;
+/// writer.write_match(5, 4)?;
+/// let size = writer.finish()?;
+/// assert_eq!(dst[..size], [0x15, b'a', b'b', b'c', b'd', 0x8c, 0x00, 0x11, 0x00, 0x00]);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub struct TokenWriter<'a> {
+    dst: &'a mut [u8],
+    outp: usize,
+    /// Decompressed size so far, bounding match distances.
+    size: usize,
+    /// Length of the current literal run, 0 after a match.
+    lit_len: usize,
+    /// Where the current literal run's instruction starts.
+    lit_start: usize,
+}
+
+impl<'a> TokenWriter<'a> {
+    /// Starts a stream in `dst`.
+    pub fn new(dst: &'a mut [u8]) -> Self {
+        TokenWriter { dst, outp: 0, size: 0, lit_len: 0, lit_start: 0 }
+    }
+
+    /// Appends literal bytes, extending the current literal run if the last token was one.
+    pub fn write_literal(&mut self, lit: &[u8]) -> Result<(), Error> {
+        if lit.is_empty() {
+            return Ok(());
+        }
+        let old_len = self.lit_len;
+        let start = if old_len == 0 { self.outp } else { self.lit_start };
+        let len = old_len.checked_add(lit.len()).ok_or(Error::OutputOverrun)?;
+        let header = literal_header_len(start, len);
+        needs_out(self.dst, start, header.checked_add(len).ok_or(Error::OutputOverrun)?)?;
+        if old_len > 0 {
+            // Re-encode the run's header, moving its literals to follow the new one
+            let old_header = literal_header_len(start, old_len);
+            if old_header == 0 {
+                self.dst[start - Lzo1x::STATE_OFFSET] &= !0x3;
+            }
+            let old = start + old_header;
+            self.dst.copy_within(old..old + old_len, start + header);
+        }
+        self.outp = start;
+        encode_literal_header::<Lzo1x>(self.dst, &mut self.outp, len)?;
+        self.outp += old_len;
+        write_bytes(self.dst, &mut self.outp, lit)?;
+        self.lit_start = start;
+        self.lit_len = len;
+        self.size += lit.len();
+        Ok(())
+    }
+
+    /// Appends a match copying `len` bytes from `dist` bytes back in the output.
+    ///
+    /// `dist` must be within the output so far and at most 49151. `len` must be at least 3, or
+    /// 2 with a `dist` of at most 1024 directly after a run of 1 to 3 literals.
+    pub fn write_match(&mut self, len: usize, dist: usize) -> Result<(), Error> {
+        if dist == 0 || dist > self.size {
+            return Err(Error::LookbehindOverrun);
+        }
+        if dist > MAX_DIST as usize
+            || len < 2
+            || len > u32::MAX as usize
+            || (len == 2
+                && (self.lit_len == 0 || self.lit_len > 3 || dist > M1_MAX_OFFSET as usize))
+        {
+            return Err(Error::Error);
+        }
+        let (lb_len, lb_off) = (len as u32, dist as u32);
+        // Every class is allowed, and the checks above leave out matches M1 can't encode
+        let class = match_class::<Lzo1x>(Opcodes::ALL, lb_len, lb_off, self.lit_len).unwrap_or(0);
+        let outp = self.outp;
+        let result =
+            encode_lookback_match::<Lzo1x>(self.dst, &mut self.outp, lb_len, lb_off, class);
+        if let Err(e) = result {
+            self.outp = outp;
+            return Err(e);
+        }
+        self.lit_len = 0;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Number of compressed bytes written so far.
+    pub fn output_len(&self) -> usize { self.outp }
+
+    /// Size of the stream's decompressed output so far.
+    pub fn decompressed_len(&self) -> usize { self.size }
+
+    /// Terminates the stream, returning its compressed size.
+    pub fn finish(mut self) -> Result<usize, Error> {
+        write_bytes(self.dst, &mut self.outp, &[M4_MARKER | 1, 0, 0])?;
+        Ok(self.outp)
+    }
+}
+
+/// Re-encodes an LZO1X stream from its tokens, returning it in a heap-allocated vector.
+///
+/// See [`transcode_no_alloc`].
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub fn transcode(src: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    let src = src.as_ref();
+    let mut dst = vec![0u8; src.len()];
+    let size = transcode_no_alloc(src, &mut dst)?;
+    dst.truncate(size);
+    Ok(dst)
+}
+
+/// Re-encodes an LZO1X stream from its tokens, without decompressing it.
+///
+/// Each token is written with its shortest encoding, and consecutive matches at the same
+/// distance are joined, such as long runs that another encoder (or this one) split at a length
+/// limit. This shrinks streams from encoders that spend extra bytes on instructions; the
+/// decompressed output is unchanged, and the result is never larger than `src`, so `dst` can be
+/// the size of `src`.
+///
+/// `src` must be a complete stream, without trailing data.
+#[cfg(feature = "decompress")]
+pub fn transcode_no_alloc(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut writer = TokenWriter::new(dst);
+    let mut tokens = TokenIter::new(src);
+    // Match held back in case the next one continues it
+    let mut pending: Option<(usize, usize)> = None;
+    while let Some(token) = tokens.next() {
+        let token = token?.1;
+        if let Some((len, dist)) = pending {
+            match token {
+                Token::Match { len: next, dist: next_dist }
+                    if next_dist == dist && len + next <= u32::MAX as usize =>
+                {
+                    pending = Some((len + next, dist));
+                    continue;
+                }
+                _ => writer.write_match(len, dist)?,
+            }
+            pending = None;
+        }
+        match token {
+            Token::Literal(len) => {
+                let end = tokens.input_offset();
+                writer.write_literal(&src[end - len..end])?;
+            }
+            Token::Match { len, dist } => pending = Some((len, dist)),
+            Token::End if tokens.input_offset() == src.len() => return writer.finish(),
+            Token::End => return Err(Error::InputNotConsumed),
+        }
+    }
+    // Iteration only stops early on an error, returned above
+    Err(Error::Error)
+}
+
+/// Joins two LZO1X streams into one decompressing to the output of `a` followed by that of
+/// `b`, without compressing either again.
+///
+/// For append-only archives, which can grow a compressed log with each new record. `a` is
+/// copied as is up to its last match, and `b` from its first match decoded in the same state
+/// as in `b` alone, usually its first or second. Only the instructions between are encoded
+/// again: `a`'s terminator is dropped, `b`'s first literal run loses its special encoding and
+/// joins `a`'s last literals, and a 2-byte match `b` can no longer encode after them becomes
+/// literals. Matches of `b` don't reach into `a`'s output, so the result is about as large as
+/// the two streams together.
+///
+/// Both must be complete streams, without trailing data.
+///
+/// ```
+/// use lzokay::compress::{compress, concat_streams};
+///
+/// let joined = concat_streams(&compress(b"hello, ")?, &compress(b"world")?)?;
+/// # let mut dst = [0u8; 12];
+/// # assert_eq!(lzokay::decompress::decompress(&joined, &mut dst), Ok(12));
+/// # assert_eq!(&dst, b"hello, world");
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub fn concat_streams(a: &[u8], b: &[u8]) -> Result<Vec<u8>, Error> {
+    let (offset, seam, trailing) = find_seam(a)?;
+    Ok(join_streams(&a[..offset], seam, &a[trailing], b)?.dst)
+}
+
+/// The end of a stream that another is joined onto, from its last match: the only part of it
+/// [`join_streams`] encodes again, with the literals after it.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub(super) struct Seam {
+    /// The last match's length and distance, if any.
+    pub(super) last_match: Option<(usize, usize)>,
+    /// Decompressed size before the last match, bounding match distances.
+    pub(super) size: usize,
+    /// Literals before the last match.
+    pub(super) lit_len: usize,
+}
+
+/// A stream joined by [`join_streams`], and its own seam.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(super) struct Joined {
+    /// The prefix and the joined stream after it.
+    pub(super) dst: Vec<u8>,
+    /// Where the last match's instruction starts in `dst`, or where the stream starts without
+    /// one.
+    pub(super) seam_offset: usize,
+    pub(super) seam: Seam,
+    /// Number of literals after the last match, stored just before the terminator.
+    pub(super) trailing_len: usize,
+}
+
+/// Finds the seam of stream `a`, returning where its last match's instruction starts, and the
+/// range of the literals after it.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn find_seam(a: &[u8]) -> Result<(usize, Seam, core::ops::Range<usize>), Error> {
+    let mut tokens = TokenIter::new(a);
+    let mut offset = 0;
+    let mut seam = Seam::default();
+    let mut lit_len = 0;
+    let mut trailing = 0..0;
+    while let Some(token) = tokens.next() {
+        match token? {
+            (_, Token::Literal(len)) => {
+                lit_len += len;
+                trailing = tokens.input_offset() - len..tokens.input_offset();
+            }
+            (token_offset, Token::Match { len, dist }) => {
+                offset = token_offset;
+                let size = tokens.output_offset() - len;
+                seam = Seam { last_match: Some((len, dist)), size, lit_len };
+                lit_len = 0;
+                trailing = 0..0;
+            }
+            (_, Token::End) => break,
+        }
+    }
+    if tokens.input_offset() != a.len() {
+        return Err(Error::InputNotConsumed);
+    }
+    Ok((offset, seam, trailing))
+}
+
+/// Joins stream `b` onto the end of another, given by its `seam` and the `trailing` literals
+/// after it, and appends the result to `prefix`, the other stream up to its seam.
+///
+/// `b` is copied from its first match decoded in the same state as in `b` alone. Only the
+/// instructions before are encoded again: the seam's match and literals, `b`'s first literal
+/// run, which joins them, and a 2-byte match `b` can no longer encode after them, which
+/// becomes literals. `b` must be a complete stream, without trailing data.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub(super) fn join_streams(
+    prefix: &[u8],
+    seam: Seam,
+    trailing: &[u8],
+    b: &[u8],
+) -> Result<Joined, Error> {
+    let seam_len = seam.last_match.map_or(0, |(len, _)| len);
+    // Merging literal runs costs at most a byte per 255 literals and a few more, as does the
+    // length of the seam's match
+    let lit_len = trailing.len() + b.len();
+    let mut dst = vec![0u8; prefix.len() + seam_len / 255 + lit_len + lit_len / 255 + 32];
+    dst[..prefix.len()].copy_from_slice(prefix);
+    // The run's start is only used to extend it, and a match comes first
+    let mut writer = TokenWriter {
+        dst: &mut dst,
+        outp: prefix.len(),
+        size: seam.size,
+        lit_len: seam.lit_len,
+        lit_start: 0,
+    };
+    // The joined stream's seam, updated as matches are written
+    let mut joined = (prefix.len(), seam);
+    if let Some((len, dist)) = seam.last_match {
+        writer.write_match(len, dist)?;
+    }
+    writer.write_literal(trailing)?;
+
+    let mut tokens = TokenIter::new(b);
+    // b's output while encoding, for 2-byte matches that become literals
+    let mut output = Vec::new();
+    let mut lit_len = 0;
+    while let Some(token) = tokens.next() {
+        match token? {
+            (_, Token::Literal(len)) => {
+                let lit = &b[tokens.input_offset() - len..tokens.input_offset()];
+                writer.write_literal(lit)?;
+                output.extend_from_slice(lit);
+                lit_len += len;
+            }
+            // After the same number of literals, from 0 to 4 or more, b's instructions decode
+            // the same
+            (offset, Token::Match { len, dist }) if min(writer.lit_len, 4) == min(lit_len, 4) => {
+                let seam = Seam {
+                    last_match: Some((len, dist)),
+                    size: writer.size,
+                    lit_len: writer.lit_len,
+                };
+                let outp = writer.output_len();
+                return append_joined(dst, outp, seam, b, offset, &mut tokens);
+            }
+            (_, Token::Match { len, dist }) => {
+                copy_match(&mut output, len, dist);
+                // Only M1 encodes 2-byte matches, after 1 to 3 literals
+                if len == 2 && writer.lit_len > 3 {
+                    writer.write_literal(&output[output.len() - 2..])?;
+                } else {
+                    let seam = Seam {
+                        last_match: Some((len, dist)),
+                        size: writer.size,
+                        lit_len: writer.lit_len,
+                    };
+                    joined = (writer.output_len(), seam);
+                    writer.write_match(len, dist)?;
+                }
+                lit_len = 0;
+            }
+            (_, Token::End) if tokens.input_offset() == b.len() => {
+                let (seam_offset, seam) = joined;
+                let seam_len = seam.last_match.map_or(0, |(len, _)| len);
+                let trailing_len = writer.size - seam.size - seam_len;
+                let size = writer.finish()?;
+                dst.truncate(size);
+                return Ok(Joined { dst, seam_offset, seam, trailing_len });
+            }
+            (_, Token::End) => return Err(Error::InputNotConsumed),
+        }
+    }
+    // Iteration only stops early on an error, returned above
+    Err(Error::Error)
+}
+
+/// Truncates `dst` to `outp` and appends `b` from `offset`, where the match `tokens` last
+/// returned starts, once the rest of `b` is validated, finding the joined stream's seam from
+/// `seam`, that match's.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn append_joined(
+    mut dst: Vec<u8>,
+    outp: usize,
+    mut seam: Seam,
+    b: &[u8],
+    offset: usize,
+    tokens: &mut TokenIter,
+) -> Result<Joined, Error> {
+    // Joined output offsets are b's shifted by the output before the match
+    let shift = seam.size - (tokens.output_offset() - seam.last_match.map_or(0, |(len, _)| len));
+    let mut seam_offset = outp;
+    let mut lit_len = 0;
+    while let Some(token) = tokens.next() {
+        match token? {
+            (_, Token::Literal(len)) => lit_len += len,
+            (token_offset, Token::Match { len, dist }) => {
+                seam_offset = outp + (token_offset - offset);
+                let size = shift + tokens.output_offset() - len;
+                seam = Seam { last_match: Some((len, dist)), size, lit_len };
+                lit_len = 0;
+            }
+            (_, Token::End) => {}
+        }
+    }
+    if tokens.input_offset() != b.len() {
+        return Err(Error::InputNotConsumed);
+    }
+    dst.truncate(outp);
+    dst.extend_from_slice(&b[offset..]);
+    Ok(Joined { dst, seam_offset, seam, trailing_len: lit_len })
+}
+
+/// Splits an LZO1X stream into two streams, decompressing to its output before and after
+/// `offset`, returning them in heap-allocated vectors.
+///
+/// For serving ranges of large compressed blobs from shards that decompress on their own. As
+/// with [`concat_streams`], only the instructions near the cut are encoded again: the first
+/// stream is `src` up to its last match before `offset`, and ends with the instructions
+/// reaching it, a match crossing it cut short. The second starts with the rest of that
+/// instruction as literals, and matches copying from before `offset` become literals too,
+/// until 48 KiB past it, where no match can reach back; from there it's `src` as is. Those
+/// literals make the two larger than `src`, by at most 48 KiB and usually much less.
+///
+/// `src` is decompressed up to about 48 KiB past `offset`, which must be at most its
+/// decompressed size, or this fails with [`Error::Error`]. `src` must be a complete stream,
+/// without trailing data.
+///
+/// ```
+/// use lzokay::compress::{compress, split_stream};
+///
+/// let (head, tail) = split_stream(&compress(b"hello, hello, hello")?, 10)?;
+/// # let mut dst = [0u8; 10];
+/// # assert_eq!(lzokay::decompress::decompress(&head, &mut dst), Ok(10));
+/// # assert_eq!(&dst, b"hello, hel");
+/// # assert_eq!(lzokay::decompress::decompress(&tail, &mut dst), Ok(9));
+/// # assert_eq!(&dst[..9], b"lo, hello");
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub fn split_stream(src: &[u8], offset: usize) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    // Decode up to the cut, finding the last match ending by it, where its input and output
+    // offsets and the literals before it resume encoding
+    let mut tokens = TokenIter::new(src);
+    let mut output = Vec::new();
+    let mut seam = (0, 0, 0);
+    let mut seam_match = None;
+    let mut lit_len = 0;
+    // Output offset of the last match, which may cross the cut
+    let mut last_match = None;
+    while output.len() < offset {
+        match tokens.next().unwrap_or(Err(Error::Error))? {
+            (_, Token::Literal(len)) => {
+                output.extend_from_slice(&src[tokens.input_offset() - len..tokens.input_offset()]);
+                lit_len += len;
+            }
+            (token_offset, Token::Match { len, dist }) => {
+                let start = output.len();
+                copy_match(&mut output, len, dist);
+                if output.len() <= offset {
+                    seam = (token_offset, start, lit_len);
+                    seam_match = Some((len, dist));
+                }
+                last_match = Some((start, dist));
+                lit_len = 0;
+            }
+            (_, Token::End) => return Err(Error::Error),
+        }
+    }
+
+    let (seam_in, seam_out, seam_lit_len) = seam;
+    let mut head = vec![0u8; seam_in + compress_worst_size(offset - seam_out)];
+    head[..seam_in].copy_from_slice(&src[..seam_in]);
+    // The run's start is only used to extend it, and a match comes first
+    let mut writer = TokenWriter {
+        dst: &mut head,
+        outp: seam_in,
+        size: seam_out,
+        lit_len: seam_lit_len,
+        lit_start: 0,
+    };
+    let mut pos = seam_out;
+    if let Some((len, dist)) = seam_match {
+        writer.write_match(len, dist)?;
+        pos += len;
+    }
+    match last_match {
+        // A match crossing the cut is cut short, if still long enough for every instruction
+        Some((start, dist)) if start >= pos && start + format::MIN_MATCH_LEN <= offset => {
+            writer.write_literal(&output[pos..start])?;
+            writer.write_match(offset - start, dist)?;
+        }
+        _ => writer.write_literal(&output[pos..offset])?,
+    }
+    let size = writer.finish()?;
+    head.truncate(size);
+
+    // Find where the second stream continues as `src`, encoding as the loop below does. The
+    // literals before the next match, in `src` and in the second stream, decide how it decodes
+    let cut_lit_len = lit_len;
+    let mut bound = output.len();
+    let (mut src_lit_len, mut lit_len) = (cut_lit_len, output.len() - offset);
+    for token in tokens.clone() {
+        match token? {
+            (_, Token::Literal(len)) => {
+                bound += len;
+                src_lit_len += len;
+                lit_len += len;
+            }
+            (_, Token::Match { .. }) if split_resumes(bound - offset, lit_len, src_lit_len) => {
+                break;
+            }
+            (_, Token::Match { len, dist }) => {
+                lit_len = if split_as_literals(bound - offset, len, dist, lit_len) {
+                    lit_len + len
+                } else {
+                    0
+                };
+                bound += len;
+                src_lit_len = 0;
+            }
+            (_, Token::End) => break,
+        }
+    }
+
+    let mut tail = vec![0u8; compress_worst_size(bound - offset)];
+    let mut writer = TokenWriter::new(&mut tail);
+    writer.write_literal(&output[offset..])?;
+    let mut src_lit_len = cut_lit_len;
+    while let Some(token) = tokens.next() {
+        match token? {
+            (_, Token::Literal(len)) => {
+                let lit = &src[tokens.input_offset() - len..tokens.input_offset()];
+                writer.write_literal(lit)?;
+                output.extend_from_slice(lit);
+                src_lit_len += len;
+            }
+            (token_offset, Token::Match { .. })
+                if split_resumes(output.len() - offset, writer.lit_len, src_lit_len) =>
+            {
+                let outp = writer.output_len();
+                let tail = append_rest(tail, outp, src, token_offset, &mut tokens)?;
+                return Ok((head, tail));
+            }
+            (_, Token::Match { len, dist }) => {
+                let start = output.len();
+                copy_match(&mut output, len, dist);
+                if split_as_literals(start - offset, len, dist, writer.lit_len) {
+                    writer.write_literal(&output[start..])?;
+                } else {
+                    writer.write_match(len, dist)?;
+                }
+                src_lit_len = 0;
+            }
+            (_, Token::End) if tokens.input_offset() == src.len() => {
+                let size = writer.finish()?;
+                tail.truncate(size);
+                return Ok((head, tail));
+            }
+            (_, Token::End) => return Err(Error::InputNotConsumed),
+        }
+    }
+    // Iteration only stops early on an error, returned above
+    Err(Error::Error)
+}
+
+/// Whether [`split_stream`]'s second stream stores a match `pos` bytes after the cut, and
+/// after `lit_len` literals, as literals: when it copies from before the cut, or is 2 bytes
+/// long and not after 1 to 3 literals, which only M1 encodes.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn split_as_literals(pos: usize, len: usize, dist: usize, lit_len: usize) -> bool {
+    dist > pos || (len == 2 && !(1..=3).contains(&lit_len))
+}
+
+/// Whether [`split_stream`]'s second stream continues as `src` from a match `pos` bytes after
+/// the cut: when no later match reaches before the cut, and the match decodes the same after
+/// `lit_len` literals as after `src_lit_len` in `src`.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn split_resumes(pos: usize, lit_len: usize, src_lit_len: usize) -> bool {
+    pos >= format::MAX_DISTANCE && min(lit_len, 4) == min(src_lit_len, 4)
+}
+
+/// Appends the output of a match to `output`, a byte at a time as it may overlap itself.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn copy_match(output: &mut Vec<u8>, len: usize, dist: usize) {
+    let start = output.len() - dist;
+    for i in start..start + len {
+        output.push(output[i]);
+    }
+}
+
+/// Truncates `dst` to `outp` and appends `src` from `offset`, where the instruction `tokens`
+/// last returned starts, once the rest of `src` is validated.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+fn append_rest(
+    mut dst: Vec<u8>,
+    outp: usize,
+    src: &[u8],
+    offset: usize,
+    tokens: &mut TokenIter,
+) -> Result<Vec<u8>, Error> {
+    for token in &mut *tokens {
+        token?;
+    }
+    if tokens.input_offset() != src.len() {
+        return Err(Error::InputNotConsumed);
+    }
+    dst.truncate(outp);
+    dst.extend_from_slice(&src[offset..]);
+    Ok(dst)
+}
+
+/// How a [`Segment`] of the input was encoded.
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Coverage {
+    /// Stored as literals.
+    Literal,
+    /// Copied from `dist` bytes earlier in the input.
+    Match {
+        /// Distance back to the copied bytes.
+        dist: usize,
+    },
+}
+
+/// A range of the input and how it was encoded, from [`coverage_map`].
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Segment {
+    /// The input bytes covered.
+    pub range: core::ops::Range<usize>,
+    /// Literals or a match.
+    pub coverage: Coverage,
+}
+
+/// Compresses `src` with `dict`, and returns which ranges of it the encoder matched and which it
+/// stored as literals.
+///
+/// The segments are in order and cover all of `src`. Mapping where matches fall, and how far
+/// back they reach, shows which parts of the data compress and whether splitting it into chunks
+/// or priming a dictionary would lose matches.
+///
+/// ```
+/// use lzokay::compress::*;
+///
+/// let src = b"abcdabcdabcd";
+/// let map = coverage_map(src, &mut new_dict())?;
+/// assert_eq!(map, [
+///     Segment { range: 0..4, coverage: Coverage::Literal },
+///     Segment { range: 4..12, coverage: Coverage::Match { dist: 4 } },
+/// ]);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(all(feature = "alloc", feature = "decompress"))]
+pub fn coverage_map(src: impl AsRef<[u8]>, dict: &mut Dict) -> Result<Vec<Segment>, Error> {
+    let compressed = compress_with_dict(src, dict)?;
+    let mut segments = Vec::new();
+    let mut tokens = TokenIter::new(&compressed);
+    while let Some(token) = tokens.next() {
+        let (len, coverage) = match token?.1 {
+            Token::Literal(len) => (len, Coverage::Literal),
+            Token::Match { len, dist } => (len, Coverage::Match { dist }),
+            Token::End => break,
+        };
+        let end = tokens.output_offset();
+        segments.push(Segment { range: end - len..end, coverage });
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(not(feature = "std"), feature = "alloc", feature = "decompress"))]
+    use super::vec;
+    use super::TokenWriter;
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    use crate::{
+        compress::{compress, compress_with_stats, coverage_map, new_dict, Coverage, Segment},
+        decompress::decompress,
+        Error,
+    };
+
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    const INPUT_1: &[u8] = include_bytes!("../test1.txt");
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.bin");
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    const INPUT_2: &[u8] = include_bytes!("../test2.txt");
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.bin");
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_coverage_map() {
+        let mut dict = new_dict();
+        let (_, stats) = compress_with_stats(INPUT_1, &mut dict).unwrap();
+        let map = coverage_map(INPUT_1, &mut dict).expect("Failed to compress");
+        let mut pos = 0;
+        let mut literal_bytes = 0;
+        for segment in &map {
+            assert_eq!(segment.range.start, pos);
+            assert!(segment.range.end > pos);
+            pos = segment.range.end;
+            match segment.coverage {
+                Coverage::Literal => literal_bytes += segment.range.len(),
+                Coverage::Match { dist } => {
+                    let Segment { range, .. } = segment;
+                    assert_eq!(
+                        INPUT_1[range.clone()],
+                        INPUT_1[range.start - dist..range.end - dist]
+                    );
+                }
+            }
+        }
+        assert_eq!(pos, INPUT_1.len());
+        assert_eq!(literal_bytes, stats.literal_bytes);
+        assert_eq!(
+            map.iter().filter(|s| s.coverage != Coverage::Literal).count(),
+            stats.match_count()
+        );
+
+        assert_eq!(coverage_map([], &mut dict), Ok(vec![]));
+    }
+
+    /// Writes `parts` as literals, optionally after a literal and a match.
+    fn write_literals(dst: &mut [u8], after_match: bool, parts: &[&[u8]]) -> usize {
+        let mut writer = TokenWriter::new(dst);
+        if after_match {
+            writer.write_literal(b"abc").unwrap();
+            writer.write_match(6, 3).unwrap();
+        }
+        for part in parts {
+            writer.write_literal(part).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_token_writer_literals() {
+        // Merged runs are encoded as a single run, across every header size
+        let mut lit = [0u8; 600];
+        for (i, byte) in lit.iter_mut().enumerate() {
+            *byte = (i * 13 % 251) as u8;
+        }
+        for &split in &[1, 2, 3, 4, 5, 18, 19, 238, 239, 273, 274, 300, 528, 599] {
+            for &after_match in &[false, true] {
+                let (mut expected, mut actual) = ([0u8; 700], [0u8; 700]);
+                let size = write_literals(&mut expected, after_match, &[&lit]);
+                let parts: [&[u8]; 4] = [&lit[..1], &[], &lit[1..split], &lit[split..]];
+                assert_eq!(write_literals(&mut actual, after_match, &parts), size);
+                assert_eq!(actual[..size], expected[..size], "split at {}", split);
+            }
+        }
+    }
+
+    #[test]
+    fn test_token_writer_errors() {
+        use crate::Error;
+
+        let mut dst = [0u8; 32];
+        let mut writer = TokenWriter::new(&mut dst);
+        assert_eq!(writer.write_match(3, 1), Err(Error::LookbehindOverrun));
+        writer.write_literal(b"abcd").unwrap();
+        assert_eq!(writer.write_match(3, 5), Err(Error::LookbehindOverrun));
+        assert_eq!(writer.write_match(1, 1), Err(Error::Error));
+        // 2-byte matches need 1 to 3 literals before them
+        assert_eq!(writer.write_match(2, 1), Err(Error::Error));
+        writer.write_match(3, 4).unwrap();
+        assert_eq!(writer.write_match(2, 1), Err(Error::Error));
+        writer.write_literal(b"e").unwrap();
+        writer.write_match(2, 1).unwrap();
+        assert_eq!(writer.decompressed_len(), 10);
+
+        // Failures leave the stream unchanged
+        let output_len = writer.output_len();
+        assert_eq!(writer.write_literal(&[0; 32]), Err(Error::OutputOverrun));
+        assert_eq!(writer.write_match(5000, 1), Err(Error::OutputOverrun));
+        assert_eq!(writer.output_len(), output_len);
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "decompress")]
+    fn test_token_writer_transcode() {
+        use crate::decompress::{decompress, Token, TokenIter};
+
+        // Re-encoding the compressor's tokens reproduces its output
+        let input: &[u8] = include_bytes!("../test1.bin");
+        let mut dst = [0u8; 2048];
+        let mut writer = TokenWriter::new(&mut dst);
+        let mut tokens = TokenIter::new(input);
+        while let Some(token) = tokens.next() {
+            match token.unwrap().1 {
+                Token::Literal(len) => {
+                    let end = tokens.input_offset();
+                    writer.write_literal(&input[end - len..end]).unwrap();
+                }
+                Token::Match { len, dist } => writer.write_match(len, dist).unwrap(),
+                Token::End => {}
+            }
+        }
+        let size = writer.finish().unwrap();
+        assert_eq!(&dst[..size], input);
+
+        let expected: &[u8] = include_bytes!("../test1.txt");
+        let mut output = [0u8; 4096];
+        assert_eq!(decompress(&dst[..size], &mut output), Ok(expected.len()));
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_transcode() {
+        use crate::compress::transcode;
+
+        // M3 where M2 would do
+        let src = [0x15, b'a', b'b', b'c', b'd', 0x23, 0x0c, 0x00, 0x11, 0x00, 0x00];
+        assert_eq!(
+            transcode(src),
+            Ok(vec![0x15, b'a', b'b', b'c', b'd', 0x8c, 0x00, 0x11, 0x00, 0x00])
+        );
+        assert_eq!(transcode(EXPECTED_1).as_deref(), Ok(EXPECTED_1));
+
+        // Matches split at the compressor's maximum length are joined
+        let input = [0u8; 20000];
+        let compressed = compress(input).unwrap();
+        let transcoded = transcode(&compressed).unwrap();
+        assert!(transcoded.len() < compressed.len());
+        let mut output = vec![1u8; input.len()];
+        assert_eq!(decompress(&transcoded, &mut output), Ok(input.len()));
+        assert_eq!(output[..], input[..]);
+
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        assert_eq!(transcode(&trailing), Err(Error::InputNotConsumed));
+        assert_eq!(transcode(&compressed[..compressed.len() - 1]), Err(Error::InputOverrun));
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_concat_streams() {
+        #[cfg(not(feature = "std"))]
+        use super::Vec;
+        use super::{find_seam, join_streams};
+        use crate::compress::concat_streams;
+
+        /// A stream of `lit` literals, a 2-byte M1 match, then `trailing` literals.
+        fn m1_stream(lit: usize, trailing: usize) -> (Vec<u8>, Vec<u8>) {
+            let input = [&b"xyz"[..lit], &b"xy"[..], &b"abcde"[..trailing]].concat();
+            let mut dst = vec![0u8; 32];
+            let mut writer = TokenWriter::new(&mut dst);
+            writer.write_literal(&input[..lit]).unwrap();
+            writer.write_match(2, lit).unwrap();
+            writer.write_literal(&input[lit + 2..]).unwrap();
+            let size = writer.finish().unwrap();
+            dst.truncate(size);
+            (input, dst)
+        }
+
+        let mut streams = vec![
+            (INPUT_1.to_vec(), EXPECTED_1.to_vec()),
+            (INPUT_2.to_vec(), EXPECTED_2.to_vec()),
+            (vec![], vec![0x11, 0, 0]),
+            (b"a".to_vec(), compress(b"a").unwrap()),
+            (vec![7; 300], compress([7; 300]).unwrap()),
+        ];
+        for &(lit, trailing) in &[(2, 0), (2, 2), (3, 3), (2, 5)] {
+            streams.push(m1_stream(lit, trailing));
+        }
+        for (input_a, a) in &streams {
+            for (input_b, b) in &streams {
+                let joined = concat_streams(a, b).unwrap();
+                // The seam is found as the streams are joined, for joining more
+                let (offset, seam, trailing) = find_seam(a).unwrap();
+                let result = join_streams(&a[..offset], seam, &a[trailing], b).unwrap();
+                let (offset, seam, trailing) = find_seam(&joined).unwrap();
+                assert_eq!(result.dst, joined);
+                assert_eq!((result.seam_offset, result.seam), (offset, seam));
+                assert_eq!(result.trailing_len, trailing.len());
+                let expected = [&input_a[..], &input_b[..]].concat();
+                let mut output = vec![0u8; expected.len()];
+                assert_eq!(decompress(&joined, &mut output), Ok(expected.len()));
+                assert_eq!(output, expected);
+                assert!(joined.len() <= a.len() + b.len() + 2);
+            }
+        }
+        // a is copied up to its last match
+        let joined = concat_streams(EXPECTED_1, EXPECTED_2).unwrap();
+        assert_eq!(joined[..EXPECTED_1.len() - 16], EXPECTED_1[..EXPECTED_1.len() - 16]);
+
+        let mut trailing = EXPECTED_1.to_vec();
+        trailing.push(0);
+        assert_eq!(concat_streams(&trailing, EXPECTED_2), Err(Error::InputNotConsumed));
+        assert_eq!(concat_streams(EXPECTED_2, &trailing), Err(Error::InputNotConsumed));
+        assert_eq!(concat_streams(EXPECTED_1, &EXPECTED_2[..10]), Err(Error::InputOverrun));
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc", feature = "decompress"))]
+    fn test_split_stream() {
+        #[cfg(not(feature = "std"))]
+        use super::Vec;
+        use crate::compress::split_stream;
+
+        fn check(input: &[u8], src: &[u8], offset: usize) -> (Vec<u8>, Vec<u8>) {
+            let (head, tail) = split_stream(src, offset).unwrap();
+            let mut output = vec![0u8; input.len()];
+            assert_eq!(decompress(&head, &mut output), Ok(offset), "{}", offset);
+            assert_eq!(output[..offset], input[..offset]);
+            assert_eq!(decompress(&tail, &mut output), Ok(input.len() - offset), "{}", offset);
+            assert_eq!(output[..input.len() - offset], input[offset..]);
+            (head, tail)
+        }
+
+        for offset in 0..=INPUT_1.len() {
+            check(INPUT_1, EXPECTED_1, offset);
+        }
+        // 2-byte matches, which need 1 to 3 literals before
+        let mut dst = [0u8; 32];
+        let mut writer = TokenWriter::new(&mut dst);
+        writer.write_literal(b"xy").unwrap();
+        writer.write_match(2, 2).unwrap();
+        writer.write_literal(b"z").unwrap();
+        writer.write_match(2, 3).unwrap();
+        writer.write_literal(b"abcd").unwrap();
+        let size = writer.finish().unwrap();
+        for offset in 0..=11 {
+            check(b"xyxyzxyabcd", &dst[..size], offset);
+        }
+
+        // Past 48 KiB from the cut, the second stream is the end of the first
+        let mut seed = 1u32;
+        let input: Vec<u8> = (0..200_000)
+            .flat_map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let start = (seed >> 8) as usize % (INPUT_1.len() - 8);
+                INPUT_1[start..start + 8].to_vec()
+            })
+            .take(200_000)
+            .collect();
+        let src = compress(&input).unwrap();
+        for &offset in &[0, 1, 5000, 100_000, 199_999, 200_000] {
+            let (head, tail) = check(&input, &src, offset);
+            assert!(head.len() + tail.len() < src.len() + 16 * 1024);
+            if offset < 100_000 {
+                assert_eq!(tail[tail.len() - 1000..], src[src.len() - 1000..]);
+            }
+        }
+
+        assert_eq!(split_stream(EXPECTED_1, INPUT_1.len() + 1), Err(Error::Error));
+        let mut trailing = EXPECTED_1.to_vec();
+        trailing.push(0);
+        assert_eq!(split_stream(&trailing, 10), Err(Error::InputNotConsumed));
+        assert_eq!(split_stream(&EXPECTED_1[..100], 10), Err(Error::InputOverrun));
+    }
+}
@@ -0,0 +1,266 @@
+//! Compression of a raw LZO1X stream into a [`Write`]r.
+
+use std::{
+    cmp::min,
+    io::{self, Write},
+};
+
+use super::{
+    compress_no_alloc, compress_worst_size, new_dict,
+    tokens::{join_streams, Joined, Seam},
+    Dict, MAX_DIST,
+};
+use crate::Error;
+
+/// Input compressed at a time, as lzop's default block size.
+const BLOCK_SIZE: usize = 256 * 1024;
+
+/// Compresses data written to it into a raw LZO1X stream, written to an underlying writer as
+/// it's compressed.
+///
+/// For pipelines built around writers such as flate2's `GzEncoder`. Input is compressed in
+/// blocks of 256 KiB, each joined onto the stream so far as by
+/// [`concat_streams`](super::concat_streams), so a single stream is written without holding
+/// all of it. Only the stream's last match and the literals after it are held back, as the
+/// next block's literals join them: memory use is a block, plus the longest run of input that
+/// doesn't compress, whose length the format stores before it.
+///
+/// [`flush`](Write::flush) compresses the input written so far as a block, writes all but
+/// that end of the stream, and flushes the underlying writer. [`finish`](LzoWriter::finish)
+/// writes the rest. Input that fits in one block, without flushes, is compressed the same as
+/// by [`compress_with_dict`](super::compress_with_dict); matches don't reach across blocks.
+///
+/// Dropping the writer finishes it too, ignoring errors.
+///
+/// ```
+/// use std::io::Write;
+///
+/// use lzokay::compress::{compress, LzoWriter};
+///
+/// let mut writer = LzoWriter::new(Vec::new());
+/// writer.write_all(b"hello, ")?;
+/// writer.write_all(b"hello")?;
+/// let compressed = writer.finish()?;
+/// assert_eq!(Ok(compressed), compress(b"hello, hello"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct LzoWriter<W: Write> {
+    /// The underlying writer, taken once the stream is finished.
+    inner: Option<W>,
+    dict: Dict<'static>,
+    /// Input not yet compressed, up to a block.
+    buf: Vec<u8>,
+    /// A compressed block, before joining.
+    block: Vec<u8>,
+    /// The end of the stream so far, not yet written, with the literals after it.
+    seam: Seam,
+    trailing: Vec<u8>,
+}
+
+impl<W: Write> LzoWriter<W> {
+    /// Compresses into `inner` with a new dictionary.
+    pub fn new(inner: W) -> Self { Self::with_dict(inner, new_dict()) }
+
+    /// Compresses into `inner` with `dict`, and its settings such as the level.
+    pub fn with_dict(inner: W, dict: Dict<'static>) -> Self {
+        LzoWriter {
+            inner: Some(inner),
+            dict,
+            buf: Vec::new(),
+            block: Vec::new(),
+            seam: Seam::default(),
+            trailing: Vec::new(),
+        }
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W { self.inner.as_ref().unwrap() }
+
+    /// The underlying writer, mutably. Writing to it will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut W { self.inner.as_mut().unwrap() }
+
+    /// Compresses the rest of the input and writes the end of the stream, returning the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        // Taken first, so that dropping doesn't finish again if this fails
+        let mut inner = self.inner.take().unwrap();
+        self.write_end(&mut inner)?;
+        Ok(inner)
+    }
+
+    /// Compresses the buffered input and joins it onto the stream.
+    fn join_block(&mut self) -> io::Result<Joined> {
+        self.block.resize(compress_worst_size(self.buf.len()), 0);
+        let size =
+            compress_no_alloc(&self.buf, &mut self.block, &mut self.dict).map_err(lzo_error)?;
+        self.buf.clear();
+        join_streams(&[], self.seam, &self.trailing, &self.block[..size]).map_err(lzo_error)
+    }
+
+    /// Compresses the buffered input, and writes the stream up to its new end.
+    fn write_block(&mut self) -> io::Result<()> {
+        let joined = self.join_block()?;
+        // Literals are stored just before the terminator
+        let end = joined.dst.len() - 3;
+        self.trailing.clear();
+        self.trailing.extend_from_slice(&joined.dst[end - joined.trailing_len..end]);
+        // The size only bounds match distances, and mustn't overflow
+        self.seam = Seam { size: min(joined.seam.size, MAX_DIST as usize), ..joined.seam };
+        self.inner.as_mut().unwrap().write_all(&joined.dst[..joined.seam_offset])
+    }
+
+    fn write_end(&mut self, inner: &mut W) -> io::Result<()> {
+        let joined = self.join_block()?;
+        inner.write_all(&joined.dst)?;
+        inner.flush()
+    }
+}
+
+impl<W: Write> Write for LzoWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        let len = min(buf.len(), BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Compresses the input written so far, writes all but the end of the stream, and flushes
+    /// the underlying writer.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for LzoWriter<W> {
+    fn drop(&mut self) {
+        if let Some(mut inner) = self.inner.take() {
+            let _ = self.write_end(&mut inner);
+        }
+    }
+}
+
+fn lzo_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::{LzoWriter, BLOCK_SIZE};
+    use crate::{
+        compress::{compress, compress_with_dict, concat_streams, new_dict, MIN_LEVEL},
+        decompress::decompress,
+    };
+
+    const INPUT: &[u8] = include_bytes!("../test1.txt");
+    const EXPECTED: &[u8] = include_bytes!("../test1.bin");
+
+    /// Fails its first write, then writes to `data`.
+    #[derive(Default)]
+    struct FailOnce {
+        data: Vec<u8>,
+        failed: bool,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !self.failed {
+                self.failed = true;
+                return Err(io::Error::other("failed"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn test_lzo_writer() {
+        let mut writer = LzoWriter::new(Vec::new());
+        for chunk in INPUT.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        // Flushing writes the stream up to its last match
+        writer.flush().unwrap();
+        assert!(!writer.get_ref().is_empty() && EXPECTED.starts_with(writer.get_ref()));
+        assert_eq!(writer.finish().unwrap(), EXPECTED);
+
+        // Empty input still writes a stream
+        assert_eq!(LzoWriter::new(Vec::new()).finish().unwrap(), compress(b"").unwrap());
+
+        let mut dict = new_dict();
+        dict.set_level(MIN_LEVEL).unwrap();
+        let mut writer = LzoWriter::with_dict(Vec::new(), dict);
+        writer.write_all(INPUT).unwrap();
+        let mut dict = new_dict();
+        dict.set_level(MIN_LEVEL).unwrap();
+        assert_eq!(writer.finish().unwrap(), compress_with_dict(INPUT, &mut dict).unwrap());
+
+        // Dropping finishes
+        let mut output = Vec::new();
+        LzoWriter::new(&mut output).write_all(INPUT).unwrap();
+        assert_eq!(output, EXPECTED);
+    }
+
+    #[test]
+    fn test_lzo_writer_blocks() {
+        let mut seed = 1u32;
+        let input: Vec<u8> = (0..BLOCK_SIZE * 3 + 1000)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                // Runs of random bytes, which don't compress, between text
+                if i % 100_000 < 1000 {
+                    (seed >> 16) as u8
+                } else {
+                    INPUT[(seed >> 8) as usize % 64 + i % 1000]
+                }
+            })
+            .collect();
+
+        let mut writer = LzoWriter::new(Vec::new());
+        for chunk in input.chunks(10_000) {
+            writer.write_all(chunk).unwrap();
+            // Memory use is bounded by a block, and the longest literal run
+            assert!(writer.buf.capacity() <= 2 * BLOCK_SIZE);
+            assert!(writer.trailing.len() <= 2000);
+        }
+        // Written as it's compressed
+        assert!(writer.get_ref().len() > input.len() / 4);
+        let compressed = writer.finish().unwrap();
+
+        let expected = input
+            .chunks(BLOCK_SIZE)
+            .map(|block| compress(block).unwrap())
+            .fold(compress(b"").unwrap(), |stream, block| concat_streams(&stream, &block).unwrap());
+        assert_eq!(compressed, expected);
+        let mut output = vec![0u8; input.len()];
+        assert_eq!(decompress(&compressed, &mut output), Ok(input.len()));
+        assert_eq!(output, input);
+
+        // Flushing after each write joins many small blocks
+        let mut writer = LzoWriter::new(Vec::new());
+        for chunk in input[..100_000].chunks(7) {
+            writer.write_all(chunk).unwrap();
+            writer.flush().unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+        assert_eq!(decompress(&compressed, &mut output), Ok(100_000));
+        assert_eq!(output[..100_000], input[..100_000]);
+    }
+
+    #[test]
+    fn test_lzo_writer_error() {
+        // A failed finish isn't retried when dropped
+        let mut output = FailOnce::default();
+        let mut writer = LzoWriter::new(&mut output);
+        writer.write_all(INPUT).unwrap();
+        assert!(writer.finish().is_err());
+        assert!(output.data.is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! # C++ backend
+//!
+//! Available with feature `cpp-backend`.
+//!
+//! The original C++ implementation of [LZ👌](https://github.com/jackoalan/lzokay), exposed with
+//! the same API as the [`compress`](crate::compress) and [`decompress`](crate::decompress)
+//! modules. Useful for validating the Rust port against upstream when debugging.
+//!
+//! Requires the `lzokay` submodule to be checked out. The bindings are pre-generated; to
+//! regenerate `src/sys/bindings.rs` after updating the submodule, build with the
+//! `regen-bindings` feature, which requires libclang.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::cpp;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! # #[cfg(feature = "alloc")] {
+//! let compressed = cpp::compress(&input)?;
+//! assert_eq!(compressed, lzokay::compress::compress(&input)?);
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+extern crate alloc;
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{
+    marker::PhantomData,
+    mem::{align_of, size_of},
+};
+
+pub use crate::compress::compress_worst_size;
+use crate::{sys, Error};
+
+type DictStorage = sys::lzokay_DictBase_storage_type;
+
+fn lzokay_result<T>(result: T, error: sys::lzokay_EResult) -> Result<T, Error> {
+    if error == sys::lzokay_EResult_Success {
+        Result::Ok(result)
+    } else {
+        Result::Err(match error {
+            sys::lzokay_EResult_LookbehindOverrun => Error::LookbehindOverrun,
+            sys::lzokay_EResult_OutputOverrun => Error::OutputOverrun,
+            sys::lzokay_EResult_InputOverrun => Error::InputOverrun,
+            sys::lzokay_EResult_InputNotConsumed => Error::InputNotConsumed,
+            _ => Error::Error,
+        })
+    }
+}
+
+/// Dictionary type for the C++ backend.
+pub struct Dict<'a> {
+    base: sys::lzokay_DictBase,
+    #[cfg(feature = "alloc")]
+    _storage: Option<Box<[u8]>>,
+    phantom: PhantomData<&'a mut DictStorage>,
+}
+
+/// Creates a new heap-allocated dictionary.
+#[cfg(feature = "alloc")]
+pub fn new_dict() -> Dict<'static> {
+    let mut storage = vec![0u8; dict_storage_size()].into_boxed_slice();
+    let base = sys::lzokay_DictBase { _storage: aligned_storage(&mut storage) };
+    Dict { base, _storage: Some(storage), phantom: PhantomData }
+}
+
+/// Dictionary storage size, for manual or stack allocation.
+///
+/// Includes slack for aligning the dictionary within arbitrary byte storage.
+pub const fn dict_storage_size() -> usize {
+    size_of::<DictStorage>() + align_of::<DictStorage>() - 1
+}
+
+/// Creates a dictionary from the supplied storage.
+///
+/// Storage **must** be at least [`dict_storage_size()`] bytes,
+/// otherwise this function will panic.
+pub fn dict_from_storage(storage: &mut [u8]) -> Dict<'_> {
+    if storage.len() < dict_storage_size() {
+        panic!(
+            "Dictionary storage is not large enough: {}, expected {}",
+            storage.len(),
+            dict_storage_size()
+        );
+    }
+    Dict {
+        base: sys::lzokay_DictBase { _storage: aligned_storage(storage) },
+        #[cfg(feature = "alloc")]
+        _storage: None,
+        phantom: PhantomData,
+    }
+}
+
+fn aligned_storage(storage: &mut [u8]) -> *mut DictStorage {
+    let align = align_of::<DictStorage>();
+    let offset = (align - storage.as_ptr() as usize % align) % align;
+    storage[offset..].as_mut_ptr() as *mut DictStorage
+}
+
+/// Compress the supplied buffer into a heap-allocated vector.
+///
+/// Creates a new dictionary for each invocation.
+#[cfg(feature = "alloc")]
+pub fn compress(src: &[u8]) -> Result<Vec<u8>, Error> { compress_with_dict(src, &mut new_dict()) }
+
+/// Compress the supplied buffer into a heap-allocated vector,
+/// with the supplied pre-allocated dictionary.
+#[cfg(feature = "alloc")]
+pub fn compress_with_dict(src: &[u8], dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    let mut dst = vec![0u8; compress_worst_size(src.len())];
+    let size = compress_no_alloc(src, &mut dst, dict)?;
+    dst.truncate(size);
+    Ok(dst)
+}
+
+/// Compress the supplied buffer.
+///
+/// For sizing `dst`, use [`compress_worst_size`].
+pub fn compress_no_alloc(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+    let mut out_size = 0usize;
+    let result = unsafe {
+        sys::lzokay_compress(
+            src.as_ptr(),
+            src.len(),
+            dst.as_mut_ptr(),
+            dst.len(),
+            &mut out_size,
+            &mut dict.base,
+        )
+    };
+    lzokay_result(out_size, result)
+}
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut out_size = 0usize;
+    let result = unsafe {
+        sys::lzokay_decompress(src.as_ptr(), src.len(), dst.as_mut_ptr(), dst.len(), &mut out_size)
+    };
+    lzokay_result(out_size, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compress, cpp, decompress};
+
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+    const COMPRESSED_1: &[u8] = include_bytes!("test1.bin");
+
+    /// Both backends must produce identical streams.
+    #[test]
+    fn test_compress_matches() {
+        let mut storage = [0u8; compress::dict_storage_size()];
+        let mut dict = compress::dict_from_storage(&mut storage);
+        let mut cpp_storage = [0u8; cpp::dict_storage_size()];
+        let mut cpp_dict = cpp::dict_from_storage(&mut cpp_storage);
+        let mut dst = [0u8; compress::compress_worst_size(INPUT_1.len())];
+        let mut cpp_dst = [0u8; compress::compress_worst_size(INPUT_1.len())];
+        for input in [INPUT_1, INPUT_2].iter() {
+            let size = compress::compress_no_alloc(input, &mut dst, &mut dict)
+                .expect("Failed to compress");
+            let cpp_size = cpp::compress_no_alloc(input, &mut cpp_dst, &mut cpp_dict)
+                .expect("Failed to compress (C++)");
+            assert_eq!(&dst[..size], &cpp_dst[..cpp_size]);
+        }
+    }
+
+    /// Both backends must agree on output and errors.
+    #[test]
+    fn test_decompress_matches() {
+        let mut dst = [0u8; INPUT_1.len()];
+        let mut cpp_dst = [0u8; INPUT_1.len()];
+        let mut trailing = [0u8; COMPRESSED_1.len() + 1];
+        trailing[..COMPRESSED_1.len()].copy_from_slice(COMPRESSED_1);
+        let cases: [(&[u8], usize); 5] = [
+            (COMPRESSED_1, INPUT_1.len()),
+            (&COMPRESSED_1[..COMPRESSED_1.len() - 1], INPUT_1.len()),
+            (COMPRESSED_1, 100),
+            (&trailing, INPUT_1.len()),
+            (&[0x15, 1, 2, 3, 4, 0xfc, 0xff, 0x11, 0, 0], INPUT_1.len()),
+        ];
+        for &(src, dst_len) in cases.iter() {
+            let result = decompress::decompress(src, &mut dst[..dst_len]);
+            let cpp_result = cpp::decompress(src, &mut cpp_dst[..dst_len]);
+            assert_eq!(result, cpp_result);
+            if let Ok(size) = result {
+                assert_eq!(&dst[..size], &cpp_dst[..size]);
+            }
+        }
+    }
+}
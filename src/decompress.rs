@@ -2,6 +2,18 @@
 //!
 //! Available with feature `decompress`.
 //!
+//! [`TokenIter`] parses a stream's literal runs and matches without producing output.
+//! `decompress_traced` (feature `trace`) also counts the instructions decoded and records a
+//! bounded trace of them. [`decompress_with_cancel`] can be cancelled from another thread, and
+//! [`decompress_budgeted`] spreads decompression over several calls of bounded work.
+//! [`decompress_batch`] decompresses many small blocks of known sizes in one call.
+//! [`decompress_with_dict`] decompresses streams compressed against a preset dictionary, such
+//! as by `compress_with_preset`.
+//! [`Decompressor`] decompresses input fed in pieces, such as packets, and [`LzoReader`] a
+//! stream from a reader, without knowing the output size.
+//! [`diff_streams`] finds where two encodings of the same data diverge, for comparing
+//! encoders.
+//!
 //! # Examples
 //!
 //! Decompressing a buffer with known output size:
@@ -16,28 +28,599 @@
 //! # Ok::<(), lzokay::Error>(())
 //! ```
 
-use crate::{bindings, lzokay_result, Error};
+mod batch;
+mod budget;
+#[cfg(feature = "const-decompress")]
+mod compile_time;
+mod diff;
+#[cfg(all(feature = "alloc", not(target_pointer_width = "16")))]
+mod incremental;
+#[cfg(all(feature = "std", not(target_pointer_width = "16")))]
+mod reader;
+// The decoder's 64 KiB window doesn't fit in a 16-bit address space.
+#[cfg(all(feature = "alloc", not(target_pointer_width = "16")))]
+pub(crate) mod stream;
+mod tokens;
+mod trace;
+
+#[cfg(feature = "alloc")]
+pub use self::batch::decompress_batch;
+#[cfg(feature = "const-decompress")]
+pub use self::compile_time::decompress_const;
+#[cfg(all(feature = "alloc", not(target_pointer_width = "16")))]
+pub use self::incremental::{Decompressor, Status};
+#[cfg(all(feature = "std", not(target_pointer_width = "16")))]
+pub use self::reader::LzoReader;
+#[cfg(feature = "trace")]
+pub use self::trace::{DecompressStats, Opcode, Trace, TraceEntry};
+use self::trace::{Observer, Opcode as Op, TraceEntry as Entry};
+pub use self::{
+    batch::{decompress_batch_no_alloc, BatchError},
+    budget::{decompress_budgeted, Budget, DecompressState, Progress},
+    diff::{diff_streams, StreamDiff},
+    tokens::{Token, TokenIter},
+};
+use crate::{
+    variant::{Lzo1x, Variant},
+    CancelToken, Error,
+};
+
+/// Maximum number of zero bytes in a length run before the length overflows `usize`.
+const MAX255_COUNT: usize = usize::MAX / 255 - 2;
+const M3_MARKER: u8 = 0x20;
+const M4_MARKER: u8 = 0x10;
+
+/// Fails to compile if the decompressor's state types stop being `Send` and `Sync`, so that
+/// suspended or partial decompressions can move between threads.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<DecompressState>();
+    check::<TokenIter>();
+    #[cfg(all(feature = "alloc", not(target_pointer_width = "16")))]
+    check::<stream::Decoder>();
+    #[cfg(all(feature = "alloc", not(target_pointer_width = "16")))]
+    check::<Decompressor>();
+    #[cfg(all(feature = "std", not(target_pointer_width = "16")))]
+    check::<LzoReader<&[u8]>>();
+}
 
 /// Decompress `src` into `dst`.
 ///
 /// `dst` must be large enough to hold the entire decompressed output.
+///
+/// The work done is linear in `src.len()` and the output written, whatever the input: every
+/// instruction consumes at least one input byte, and a length is rejected once its run of zero
+/// bytes implies more output than `dst` has room for. Matches overlapping their own output,
+/// such as runs of one byte, are copied in doubling blocks rather than a byte at a time.
 pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
-    let mut out_size = 0usize;
-    let result = unsafe {
-        bindings::lzokay_decompress(
-            src.as_ptr(),
-            src.len(),
-            dst.as_mut_ptr(),
-            dst.len(),
-            &mut out_size,
-        )
-    };
-    lzokay_result(out_size as usize, result)
+    decompress_impl::<Lzo1x>(src, dst)
+}
+
+/// Decompress `src` into `dst` after a preset dictionary, the first `dict_len` bytes of `dst`,
+/// returning the size of the output after it.
+///
+/// For streams from liblzo2's `lzo1x_999_compress_dict`, whose matches may copy from the
+/// dictionary, as `lzo1x_decompress_dict_safe` decompresses them. `dst` must be large enough
+/// to hold the dictionary and the entire decompressed output.
+///
+/// ```
+/// use lzokay::decompress::decompress_with_dict;
+/// # #[allow(non_upper_case_globals)] const input: [u8; 9] = [0x13, b',', b' ', 0x23, 0x18, 0, 0x11, 0, 0];
+///
+/// let mut dst = [0u8; 12];
+/// dst[..5].copy_from_slice(b"hello");
+/// let size = decompress_with_dict(&input, &mut dst, 5)?;
+/// assert_eq!(&dst[5..5 + size], b", hello");
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn decompress_with_dict(src: &[u8], dst: &mut [u8], dict_len: usize) -> Result<usize, Error> {
+    decompress_after::<Lzo1x>(src, dst, dict_len)
+}
+
+/// [`decompress_with_dict`] for any variant, also verifying compression against a preset.
+pub(crate) fn decompress_after<V: Variant>(
+    src: &[u8],
+    dst: &mut [u8],
+    dict_len: usize,
+) -> Result<usize, Error> {
+    if dict_len > dst.len() {
+        return Err(Error::OutputOverrun);
+    }
+    let mut outp = dict_len;
+    decompress_at::<V, _>(src, dst, &mut 0, &mut outp, &mut ()).map(|size| size - dict_len)
+}
+
+/// Decompress `src` into `dst`, recording the instructions decoded into `trace`.
+///
+/// Slower than [`decompress`], for analyzing streams: which instructions an encoder chooses,
+/// and which paths of the decoder they take. Available with feature `trace`.
+#[cfg(feature = "trace")]
+pub fn decompress_traced(src: &[u8], dst: &mut [u8], trace: &mut Trace) -> Result<usize, Error> {
+    decompress_at::<Lzo1x, _>(src, dst, &mut 0, &mut 0, trace)
+}
+
+/// Instructions decoded between checks of a [`CancelToken`].
+const CANCEL_INTERVAL: u32 = 4096;
+
+/// Decompress `src` into `dst`, failing with [`Error::Cancelled`] soon after `cancel` is
+/// cancelled.
+///
+/// The token is checked before starting and then every few thousand instructions, so even
+/// a corrupt stream expanding to a huge output is stopped promptly.
+pub fn decompress_with_cancel(
+    src: &[u8],
+    dst: &mut [u8],
+    cancel: &CancelToken,
+) -> Result<usize, Error> {
+    if cancel.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    let mut observer = Cancellation { token: cancel, countdown: CANCEL_INTERVAL };
+    decompress_at::<Lzo1x, _>(src, dst, &mut 0, &mut 0, &mut observer)
+}
+
+/// Checks a [`CancelToken`] every [`CANCEL_INTERVAL`] instructions.
+struct Cancellation<'a> {
+    token: &'a CancelToken,
+    countdown: u32,
+}
+
+impl Observer for Cancellation<'_> {
+    #[inline]
+    fn instruction(&mut self, _entry: Entry) -> Result<(), Error> {
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = CANCEL_INTERVAL;
+            if self.token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses without a known output size, growing `output` until the result fits,
+/// up to `limit` bytes.
+#[cfg(any(feature = "capi", feature = "python"))]
+pub(crate) fn decompress_growing(
+    src: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), Failure> {
+    // Each input byte expands to at most 255 output bytes (a zero byte in a length run)
+    let max_size = src.len().saturating_mul(255).min(limit);
+    let mut size = src.len().saturating_mul(4).min(max_size);
+    loop {
+        output.resize(size, 0);
+        match decompress_detailed::<Lzo1x>(src, output) {
+            Ok(len) => {
+                output.truncate(len);
+                return Ok(());
+            }
+            Err(Failure { error: Error::OutputOverrun, .. }) if size < max_size => {
+                size = size.saturating_mul(2).min(max_size)
+            }
+            Err(failure) => return Err(failure),
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn needs_in(src: &[u8], inp: usize, count: usize) -> Result<(), Error> {
+    if src.len() - inp < count {
+        Err(Error::InputOverrun)
+    } else {
+        Ok(())
+    }
+}
+
+#[inline]
+pub(crate) fn needs_out(dst: &[u8], outp: usize, count: usize) -> Result<(), Error> {
+    if dst.len() - outp < count {
+        Err(Error::OutputOverrun)
+    } else {
+        Ok(())
+    }
+}
+
+/// Skips a run of zero bytes in an extended length, returning the run length.
+///
+/// Each zero byte adds 255 to the length, so a run longer than `remaining / 255` can only
+/// overrun the `remaining` output. It fails as soon as it's that long, rather than scanning
+/// the rest of the run: the zero bytes scanned are bounded by the output size, not the input.
+#[inline]
+pub(crate) fn consume_zero_byte_length(
+    src: &[u8],
+    inp: &mut usize,
+    remaining: usize,
+) -> Result<usize, Error> {
+    let max = remaining / 255;
+    let start = *inp;
+    let end = src.len().min(start.saturating_add(max).saturating_add(1));
+    while *inp < end && src[*inp] == 0 {
+        *inp += 1;
+    }
+    let offset = *inp - start;
+    if offset > MAX255_COUNT {
+        return Err(Error::Error);
+    }
+    if offset > max {
+        return Err(Error::OutputOverrun);
+    }
+    Ok(offset)
+}
+
+#[inline]
+pub(crate) fn copy_literal(
+    src: &[u8],
+    inp: &mut usize,
+    dst: &mut [u8],
+    outp: &mut usize,
+    len: usize,
+) -> Result<(), Error> {
+    needs_in(src, *inp, len)?;
+    needs_out(dst, *outp, len)?;
+    dst[*outp..*outp + len].copy_from_slice(&src[*inp..*inp + len]);
+    *inp += len;
+    *outp += len;
+    Ok(())
+}
+
+/// Copies `len` bytes from `dist` bytes behind the output cursor, which may overlap the copy.
+#[inline]
+pub(crate) fn copy_lookbehind(
+    dst: &mut [u8],
+    outp: &mut usize,
+    dist: usize,
+    len: usize,
+) -> Result<(), Error> {
+    if dist == 0 || dist > *outp {
+        return Err(Error::LookbehindOverrun);
+    }
+    needs_out(dst, *outp, len)?;
+    let lbcur = *outp - dist;
+    // An overlapping copy repeats the `dist` bytes behind the cursor. Copying whole periods
+    // from `lbcur`, each copy doubles the source, so long runs take O(log(len / dist)) copies.
+    let mut copied = 0;
+    while copied < len {
+        let n = (len - copied).min(dist + copied);
+        dst.copy_within(lbcur..lbcur + n, *outp + copied);
+        copied += n;
+    }
+    *outp += len;
+    Ok(())
+}
+
+pub(crate) fn decompress_impl<V: Variant>(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let result = decompress_detailed::<V>(src, dst).map_err(|failure| failure.error);
+    #[cfg(feature = "stats")]
+    crate::stats::record_decompress(src.len(), &result);
+    result
+}
+
+/// A decompression error, with the input and output positions where it occurred.
+#[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
+pub(crate) struct Failure {
+    pub(crate) error: Error,
+    /// Offset into the compressed input.
+    pub(crate) input_offset: usize,
+    /// Offset into the output, which is also the number of bytes written.
+    pub(crate) output_offset: usize,
+}
+
+/// Like [`decompress_impl`], but reports where an error occurred.
+#[inline]
+pub(crate) fn decompress_detailed<V: Variant>(
+    src: &[u8],
+    dst: &mut [u8],
+) -> Result<usize, Failure> {
+    let mut inp = 0usize;
+    let mut outp = 0usize;
+    decompress_at::<V, _>(src, dst, &mut inp, &mut outp, &mut ()).map_err(|error| Failure {
+        error,
+        input_offset: inp,
+        output_offset: outp,
+    })
+}
+
+#[inline(always)]
+fn decompress_at<V: Variant, O: Observer>(
+    src: &[u8],
+    dst: &mut [u8],
+    inp: &mut usize,
+    outp: &mut usize,
+    observer: &mut O,
+) -> Result<usize, Error> {
+    decompress_from::<V, O>(src, dst, inp, outp, 0, 0, observer)
+}
+
+/// Like [`decompress_at`], resuming after an instruction at `inp` and `outp`, with the literal
+/// state and last match distance it left.
+#[inline(always)]
+fn decompress_from<V: Variant, O: Observer>(
+    src: &[u8],
+    dst: &mut [u8],
+    inp: &mut usize,
+    outp: &mut usize,
+    mut state: usize,
+    mut last_dist: usize,
+    observer: &mut O,
+) -> Result<usize, Error> {
+    if src.len() < 3 {
+        return Err(Error::InputOverrun);
+    }
+    let mut lblen;
+
+    // First byte encoding
+    if *inp > 0 {
+        // Resuming after the first instruction
+    } else if src[0] >= 22 {
+        // 22..255 : copy literal string
+        //           length = (byte - 17) = 4..238
+        //           state = 4 [ don't copy extra literals ]
+        //           skip byte
+        let len = src[0] as usize - 17;
+        *inp += 1;
+        copy_literal(src, inp, dst, outp, len)?;
+        observer.instruction(literal_entry(0, 0, len))?;
+        state = 4;
+    } else if src[0] >= 18 {
+        // 18..21 : copy 0..3 literals
+        //          state = (byte - 17) = 0..3  [ copy <state> literals ]
+        //          skip byte
+        let nstate = src[0] as usize - 17;
+        *inp += 1;
+        state = nstate;
+        copy_literal(src, inp, dst, outp, nstate)?;
+        observer.instruction(literal_entry(0, 0, nstate))?;
+    }
+    // 0..17 : follow regular instruction encoding, see below. It is worth
+    //         noting that codes 16 and 17 will represent a block copy from
+    //         the dictionary which is empty, and that they will always be
+    //         invalid at this place.
+
+    loop {
+        needs_in(src, *inp, 1)?;
+        let (inst_start, out_start) = (*inp, *outp);
+        let inst = src[*inp];
+        *inp += 1;
+        let lbdist;
+        let nstate;
+        let opcode;
+        if inst & 0xC0 != 0 {
+            // [M2]
+            // 1 L L D D D S S  (128..255)
+            //   Copy 5-8 bytes from block within 2kB distance
+            //   state = S (copy S literals after this block)
+            //   length = 5 + L
+            // Always followed by exactly one byte : H H H H H H H H
+            //   distance = (H << 3) + D + 1
+            //
+            // 0 1 L D D D S S  (64..127)
+            //   Copy 3-4 bytes from block within 2kB distance
+            //   state = S (copy S literals after this block)
+            //   length = 3 + L
+            // Always followed by exactly one byte : H H H H H H H H
+            //   distance = (H << 3) + D + 1
+            //
+            // See the variant implementations for other formats' layouts.
+            if V::m2_reuses_offset(inst) {
+                if last_dist == 0 {
+                    return Err(Error::LookbehindOverrun);
+                }
+                let (_, len, s) = V::decode_m2(inst, 0);
+                lbdist = last_dist;
+                lblen = len;
+                nstate = s;
+            } else {
+                needs_in(src, *inp, 1)?;
+                let (dist, len, s) = V::decode_m2(inst, src[*inp]);
+                *inp += 1;
+                lbdist = dist;
+                lblen = len;
+                nstate = s;
+            }
+            opcode = Op::M2;
+        } else if inst & M3_MARKER != 0 {
+            // [M3]
+            // 0 0 1 L L L L L  (32..63)
+            //   Copy of small block within 16kB distance (preferably less than 34B)
+            //   length = 2 + (L ?: 31 + (zero_bytes * 255) + non_zero_byte)
+            // Always followed by exactly one LE16 :  D D D D D D D D : D D D D D D S S
+            //   distance = D + 1
+            //   state = S (copy S literals after this block)
+            lblen = (inst & 0x1f) as usize + 2;
+            if lblen == 2 {
+                let offset = consume_zero_byte_length(src, inp, dst.len() - *outp)?;
+                needs_in(src, *inp, 1)?;
+                lblen += offset * 255 + 31 + src[*inp] as usize;
+                *inp += 1;
+            }
+            needs_in(src, *inp, 2)?;
+            let (dist, s) = V::decode_distance(src[*inp], src[*inp + 1]);
+            *inp += 2;
+            lbdist = dist + 1;
+            nstate = s;
+            opcode = Op::M3;
+        } else if inst & M4_MARKER != 0 {
+            // [M4]
+            // 0 0 0 1 H L L L  (16..31)
+            //   Copy of a block within 16..48kB distance (preferably less than 10B)
+            //   length = 2 + (L ?: 7 + (zero_bytes * 255) + non_zero_byte)
+            // Always followed by exactly one LE16 :  D D D D D D D D : D D D D D D S S
+            //   distance = 16384 + (H << 14) + D
+            //   state = S (copy S literals after this block)
+            //   End of stream is reached if distance == 16384
+            lblen = (inst & 0x7) as usize + 2;
+            if lblen == 2 {
+                let offset = consume_zero_byte_length(src, inp, dst.len() - *outp)?;
+                needs_in(src, *inp, 1)?;
+                lblen += offset * 255 + 7 + src[*inp] as usize;
+                *inp += 1;
+            }
+            needs_in(src, *inp, 2)?;
+            let (dist, s) = V::decode_distance(src[*inp], src[*inp + 1]);
+            *inp += 2;
+            let dist = (((inst & 0x8) as usize) << 11) + dist;
+            if dist == 0 {
+                // Stream finished
+                if lblen == 3 {
+                    observer.instruction(Entry {
+                        opcode: Op::End,
+                        ..literal_entry(inst_start, *outp, 0)
+                    })?;
+                }
+                break;
+            }
+            lbdist = dist + 16384;
+            nstate = s;
+            opcode = Op::M4;
+        } else {
+            // [M1] Depends on the number of literals copied by the last instruction.
+            if state == 0 {
+                // If last instruction did not copy any literal (state == 0), this
+                // encoding will be a copy of 4 or more literal, and must be interpreted
+                // like this :
+                //
+                //    0 0 0 0 L L L L  (0..15)  : copy long literal string
+                //    length = 3 + (L ?: 15 + (zero_bytes * 255) + non_zero_byte)
+                //    state = 4  (no extra literals are copied)
+                let mut len = inst as usize + 3;
+                if len == 3 {
+                    let offset = consume_zero_byte_length(src, inp, dst.len() - *outp)?;
+                    needs_in(src, *inp, 1)?;
+                    len += offset * 255 + 15 + src[*inp] as usize;
+                    *inp += 1;
+                }
+                copy_literal(src, inp, dst, outp, len)?;
+                observer.instruction(literal_entry(inst_start, out_start, len))?;
+                state = 4;
+                continue;
+            } else if state != 4 {
+                // If last instruction used to copy between 1 to 3 literals (encoded in
+                // the instruction's opcode or distance), the instruction is a copy of a
+                // 2-byte block from the dictionary within a 1kB distance. It is worth
+                // noting that this instruction provides little savings since it uses 2
+                // bytes to encode a copy of 2 other bytes but it encodes the number of
+                // following literals for free. It must be interpreted like this :
+                //
+                //    0 0 0 0 D D S S  (0..15)  : copy 2 bytes from <= 1kB distance
+                //    length = 2
+                //    state = S (copy S literals after this block)
+                //  Always followed by exactly one byte : H H H H H H H H
+                //    distance = (H << 2) + D + 1
+                needs_in(src, *inp, 1)?;
+                let (dist, s) = V::decode_m1(inst, src[*inp]);
+                *inp += 1;
+                lbdist = dist + 1;
+                lblen = 2;
+                nstate = s;
+                opcode = Op::M1;
+            } else {
+                // If last instruction used to copy 4 or more literals (as detected by
+                // state == 4), the instruction becomes a copy of a 3-byte block from the
+                // dictionary from a 2..3kB distance, and must be interpreted like this :
+                //
+                //    0 0 0 0 D D S S  (0..15)  : copy 3 bytes from 2..3 kB distance
+                //    length = 3
+                //    state = S (copy S literals after this block)
+                //  Always followed by exactly one byte : H H H H H H H H
+                //    distance = (H << 2) + D + 2049
+                needs_in(src, *inp, 1)?;
+                let (dist, s) = V::decode_m1(inst, src[*inp]);
+                *inp += 1;
+                lbdist = dist + V::M2_MAX_OFFSET as usize + 1;
+                lblen = 3;
+                nstate = s;
+                opcode = Op::M1;
+            }
+        }
+        if lbdist > *outp {
+            return Err(Error::LookbehindOverrun);
+        }
+        needs_in(src, *inp, nstate)?;
+        needs_out(dst, *outp, lblen + nstate)?;
+        // Copy lookbehind
+        copy_lookbehind(dst, outp, lbdist, lblen)?;
+        last_dist = lbdist;
+        state = nstate;
+        // Copy literal
+        copy_literal(src, inp, dst, outp, nstate)?;
+        observer.instruction(Entry {
+            input_offset: inst_start,
+            output_offset: out_start,
+            opcode,
+            len: lblen,
+            dist: lbdist,
+            trailing_literals: nstate,
+        })?;
+    }
+
+    // Ensure terminating M4 was encountered
+    if lblen != 3 {
+        return Err(Error::Error);
+    }
+    if *inp == src.len() {
+        Ok(*outp)
+    } else {
+        Err(Error::InputNotConsumed)
+    }
+}
+
+#[inline(always)]
+fn literal_entry(input_offset: usize, output_offset: usize, len: usize) -> Entry {
+    Entry { input_offset, output_offset, opcode: Op::Literal, len, dist: 0, trailing_literals: 0 }
+}
+
+/// Proof harnesses for [Kani](https://github.com/model-checking/kani), covering every input up
+/// to a small size. Run with `cargo kani --no-default-features --features decompress`.
+#[cfg(kani)]
+mod verification {
+    use super::{consume_zero_byte_length, decompress};
+
+    /// Decompression never indexes out of bounds or panics, for any input of up to 8 bytes and
+    /// any output size up to 16 bytes, and a reported size fits in the output.
+    #[kani::proof]
+    #[kani::unwind(18)]
+    fn decompress_in_bounds() {
+        let src: [u8; 8] = kani::any();
+        let src_len: usize = kani::any();
+        kani::assume(src_len <= src.len());
+        let mut dst = [0u8; 16];
+        let dst_len: usize = kani::any();
+        kani::assume(dst_len <= dst.len());
+        if let Ok(size) = decompress(&src[..src_len], &mut dst[..dst_len]) {
+            assert!(size <= dst_len);
+        }
+    }
+
+    /// Skipping a zero-byte length run terminates within the input, from any starting position.
+    #[kani::proof]
+    #[kani::unwind(10)]
+    fn consume_zero_byte_length_terminates() {
+        let src: [u8; 8] = kani::any();
+        let start: usize = kani::any();
+        kani::assume(start <= src.len());
+        let mut inp = start;
+        let result = consume_zero_byte_length(&src, &mut inp, usize::MAX);
+        assert!(inp >= start && inp <= src.len());
+        assert!(inp == src.len() || src[inp] != 0);
+        assert_eq!(result, Ok(inp - start));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::decompress::decompress;
+    #[cfg(all(not(feature = "std"), feature = "alloc"))]
+    extern crate alloc;
+
+    #[cfg(all(not(feature = "std"), feature = "alloc"))]
+    use alloc::vec;
+
+    use super::{decompress_at, decompress_with_cancel, decompress_with_dict, Cancellation};
+    use crate::{decompress::decompress, variant::Lzo1x, CancelToken, Error};
 
     const INPUT_1: &[u8] = include_bytes!("test1.bin");
     const EXPECTED_1: &[u8] = include_bytes!("test1.txt");
@@ -60,4 +643,101 @@ mod tests {
         let size = decompress(INPUT_2, &mut dst).expect("Failed to decompress (2)");
         assert_eq!(&dst[0..size], EXPECTED_2);
     }
+
+    #[test]
+    fn test_decompress_with_dict() {
+        // Without a dictionary, the output is the same
+        let mut dst = [0u8; EXPECTED_1.len() + 3];
+        assert_eq!(decompress_with_dict(INPUT_1, &mut dst, 3), Ok(EXPECTED_1.len()));
+        assert_eq!(&dst[3..], EXPECTED_1);
+
+        // ", ", then an M3 match of 5 bytes at distance 7 from the dictionary
+        let mut dst = [0u8; 16384 + 8];
+        dst[..5].copy_from_slice(b"hello");
+        let stream = [0x13, b',', b' ', 0x23, 0x18, 0, 0x11, 0, 0];
+        assert_eq!(decompress_with_dict(&stream, &mut dst[..12], 5), Ok(7));
+        assert_eq!(&dst[..12], b"hello, hello");
+        // An M4 match of 3 bytes at distance 16385, as the first instruction
+        let stream = [0x11, 0x04, 0, 0x11, 0, 0];
+        assert_eq!(decompress_with_dict(&stream, &mut dst, 16384 + 4), Ok(3));
+        assert_eq!(&dst[16384 + 4..16384 + 7], b"lo,");
+
+        assert_eq!(
+            decompress_with_dict(&stream, &mut dst[..16384 + 5], 16384 + 4),
+            Err(Error::OutputOverrun)
+        );
+        assert_eq!(decompress_with_dict(&stream, &mut dst, 16384 + 9), Err(Error::OutputOverrun));
+        assert_eq!(decompress_with_dict(&stream, &mut dst[..8], 4), Err(Error::LookbehindOverrun));
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let mut dst = [0u8; EXPECTED_1.len()];
+        assert_eq!(decompress(&INPUT_1[..INPUT_1.len() - 1], &mut dst), Err(Error::InputOverrun));
+        assert_eq!(decompress(INPUT_1, &mut dst[..100]), Err(Error::OutputOverrun));
+        let mut trailing = [0u8; INPUT_1.len() + 1];
+        trailing[..INPUT_1.len()].copy_from_slice(INPUT_1);
+        assert_eq!(decompress(&trailing, &mut dst), Err(Error::InputNotConsumed));
+        // M2 match reaching before the start of the output
+        let lookbehind = [0x15, 1, 2, 3, 4, 0xfc, 0xff, 0x11, 0, 0];
+        assert_eq!(decompress(&lookbehind, &mut dst), Err(Error::LookbehindOverrun));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_decompress_adversarial() {
+        use super::{decompress_detailed, TokenIter};
+
+        // A long literal whose length run is 1 MiB of zeros stops after the zeros that fit
+        let mut src = vec![0u8; 1 << 20];
+        src.extend_from_slice(&[1, 0x11, 0, 0]);
+        let mut dst = [0u8; 4096];
+        let failure = decompress_detailed::<Lzo1x>(&src, &mut dst).unwrap_err();
+        assert_eq!(failure.error, Error::OutputOverrun);
+        assert_eq!(failure.input_offset, 1 + dst.len() / 255 + 1);
+
+        // Maximal expansion: one literal, then a 1 MiB match at distance 1
+        let zeros = 4096;
+        let mut src = vec![0x12, b'a', 0x20];
+        src.resize(src.len() + zeros, 0);
+        src.extend_from_slice(&[0xff, 0, 0, 0x11, 0, 0]);
+        let size = 1 + 2 + zeros * 255 + 31 + 0xff;
+        let mut dst = vec![0u8; size];
+        assert_eq!(decompress(&src, &mut dst), Ok(size));
+        assert!(dst.iter().all(|&b| b == b'a'));
+        assert!(size <= src.len() * 255);
+        assert_eq!(decompress(&src, &mut dst[..size - 1]), Err(Error::OutputOverrun));
+
+        // Densest instructions: 2-byte M2 matches, each consuming input
+        let count = 10000;
+        let mut src = vec![0x15, b'a', b'b', b'c', b'd'];
+        for _ in 0..count {
+            src.extend_from_slice(&[0x40, 0x00]);
+        }
+        src.extend_from_slice(&[0x11, 0, 0]);
+        let mut dst = vec![0u8; 4 + count * 3];
+        assert_eq!(decompress(&src, &mut dst), Ok(dst.len()));
+        assert!(TokenIter::new(&src).count() <= src.len() / 2);
+    }
+
+    #[test]
+    fn test_decompress_with_cancel() {
+        let mut dst = [0u8; EXPECTED_1.len()];
+        let cancel = CancelToken::new();
+        assert_eq!(decompress_with_cancel(INPUT_1, &mut dst, &cancel), Ok(EXPECTED_1.len()));
+        assert_eq!(&dst[..], EXPECTED_1);
+        cancel.cancel();
+        assert_eq!(decompress_with_cancel(INPUT_1, &mut dst, &cancel), Err(Error::Cancelled));
+
+        // Cancelled during decompression, at the first check
+        let mut observer = Cancellation { token: &cancel, countdown: 3 };
+        let (mut inp, mut outp) = (0, 0);
+        let result =
+            decompress_at::<Lzo1x, _>(INPUT_1, &mut dst, &mut inp, &mut outp, &mut observer);
+        assert_eq!(result, Err(Error::Cancelled));
+        assert!(inp > 0 && inp < INPUT_1.len());
+
+        cancel.reset();
+        assert_eq!(decompress_with_cancel(INPUT_1, &mut dst, &cancel), Ok(EXPECTED_1.len()));
+    }
 }
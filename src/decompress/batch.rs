@@ -0,0 +1,142 @@
+//! Decompression of many small blocks in one call.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+extern crate alloc;
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::{vec, vec::Vec};
+
+use super::decompress_detailed;
+use crate::{variant::Lzo1x, Error};
+
+/// A block of a batch that failed to decompress.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BatchError {
+    /// Index of the block in the batch.
+    pub index: usize,
+    /// Why it failed.
+    pub error: Error,
+}
+
+/// Decompress each of `blocks` into a vector of the corresponding size in `sizes`.
+///
+/// For archives of many small blocks with known sizes. Each block must decompress to exactly
+/// its size; a shorter output fails with [`Error::InputOverrun`]. Stops at the first block
+/// that fails.
+///
+/// Panics if `blocks` and `sizes` have different lengths.
+///
+/// ```
+/// use lzokay::decompress::decompress_batch;
+///
+/// let a = [0x12, b'a', 0x20, 0x00, 0xdf, 0x00, 0x00, 0x11, 0x00, 0x00];
+/// let b = [0x15, b'a', b'b', b'c', b'd', 0x11, 0x00, 0x00];
+/// let outputs = decompress_batch(&[&a, &b], &[512, 4]).unwrap();
+/// assert_eq!(outputs[0], [b'a'; 512]);
+/// assert_eq!(outputs[1], b"abcd");
+/// ```
+#[cfg(feature = "alloc")]
+pub fn decompress_batch(blocks: &[&[u8]], sizes: &[usize]) -> Result<Vec<Vec<u8>>, BatchError> {
+    assert_eq!(blocks.len(), sizes.len(), "blocks and sizes differ in length");
+    let mut outputs = Vec::with_capacity(blocks.len());
+    for (index, (&src, &size)) in blocks.iter().zip(sizes).enumerate() {
+        let mut dst = vec![0u8; size];
+        decompress_block(src, &mut dst).map_err(|error| BatchError { index, error })?;
+        outputs.push(dst);
+    }
+    Ok(outputs)
+}
+
+/// Decompress each of `blocks` into consecutive regions of `dst`, of the corresponding sizes
+/// in `sizes`, returning the total size.
+///
+/// Without allocation: `dst` holds the blocks' outputs back to back, and must be at least the
+/// sum of `sizes`, otherwise the block that doesn't fit fails with [`Error::OutputOverrun`].
+/// Otherwise as [`decompress_batch`].
+///
+/// Panics if `blocks` and `sizes` have different lengths.
+pub fn decompress_batch_no_alloc(
+    blocks: &[&[u8]],
+    sizes: &[usize],
+    dst: &mut [u8],
+) -> Result<usize, BatchError> {
+    assert_eq!(blocks.len(), sizes.len(), "blocks and sizes differ in length");
+    let mut outp = 0;
+    for (index, (&src, &size)) in blocks.iter().zip(sizes).enumerate() {
+        let fail = |error| BatchError { index, error };
+        if dst.len() - outp < size {
+            return Err(fail(Error::OutputOverrun));
+        }
+        decompress_block(src, &mut dst[outp..outp + size]).map_err(fail)?;
+        outp += size;
+    }
+    Ok(outp)
+}
+
+/// Decompresses a block, which must fill `dst`.
+#[inline]
+fn decompress_block(src: &[u8], dst: &mut [u8]) -> Result<(), Error> {
+    let result = decompress_detailed::<Lzo1x>(src, dst).map_err(|failure| failure.error);
+    #[cfg(feature = "stats")]
+    crate::stats::record_decompress(src.len(), &result);
+    match result {
+        Ok(len) if len == dst.len() => Ok(()),
+        Ok(_) => Err(Error::InputOverrun),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress_batch_no_alloc, BatchError};
+    use crate::Error;
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("../test2.bin");
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.txt");
+    const SIZES: [usize; 3] = [EXPECTED_1.len(), EXPECTED_2.len(), EXPECTED_1.len()];
+    const TOTAL: usize = EXPECTED_1.len() * 2 + EXPECTED_2.len();
+
+    #[test]
+    fn test_decompress_batch() {
+        let blocks = [INPUT_1, INPUT_2, INPUT_1];
+        let mut dst = [0u8; TOTAL];
+        assert_eq!(decompress_batch_no_alloc(&blocks, &SIZES, &mut dst), Ok(TOTAL));
+        let (first, rest) = dst.split_at(EXPECTED_1.len());
+        let (second, third) = rest.split_at(EXPECTED_2.len());
+        assert_eq!((first, second, third), (EXPECTED_1, EXPECTED_2, EXPECTED_1));
+        assert_eq!(decompress_batch_no_alloc(&[], &[], &mut []), Ok(0));
+
+        #[cfg(feature = "alloc")]
+        {
+            let outputs = super::decompress_batch(&blocks, &SIZES).unwrap();
+            assert_eq!(outputs, [EXPECTED_1, EXPECTED_2, EXPECTED_1]);
+        }
+    }
+
+    #[test]
+    fn test_decompress_batch_errors() {
+        let mut dst = [0u8; TOTAL];
+        let truncated = [INPUT_1, &INPUT_2[..INPUT_2.len() - 1], INPUT_1];
+        let error = BatchError { index: 1, error: Error::InputOverrun };
+        assert_eq!(decompress_batch_no_alloc(&truncated, &SIZES, &mut dst), Err(error));
+        // Output shorter or longer than the block's size
+        let short = [EXPECTED_1.len() + 1];
+        let error = BatchError { index: 0, error: Error::InputOverrun };
+        assert_eq!(decompress_batch_no_alloc(&[INPUT_1], &short, &mut dst), Err(error));
+        let long = [EXPECTED_1.len() - 1];
+        let error = BatchError { index: 0, error: Error::OutputOverrun };
+        assert_eq!(decompress_batch_no_alloc(&[INPUT_1], &long, &mut dst), Err(error));
+        // Not enough room for every block
+        let blocks = [INPUT_1, INPUT_2, INPUT_1];
+        let error = BatchError { index: 2, error: Error::OutputOverrun };
+        assert_eq!(decompress_batch_no_alloc(&blocks, &SIZES, &mut dst[..TOTAL - 1]), Err(error));
+
+        #[cfg(feature = "alloc")]
+        {
+            let error = BatchError { index: 1, error: Error::InputOverrun };
+            assert_eq!(super::decompress_batch(&truncated, &SIZES), Err(error));
+        }
+    }
+}
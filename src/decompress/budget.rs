@@ -0,0 +1,232 @@
+//! Decompression in bounded steps, for cooperative schedulers and hosts metering work.
+
+use super::{
+    decompress_from,
+    trace::{Observer, Opcode as Op, TraceEntry as Entry},
+};
+use crate::{variant::Lzo1x, Error};
+
+/// How much work a call to [`decompress_budgeted`] may do.
+///
+/// Checked after each instruction, so a call always decodes at least one, and may overshoot
+/// an output budget by the length of the last. A match's length is bounded by the room left
+/// in the output, and its copy takes time logarithmic in its length when it overlaps itself.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Budget {
+    /// Decode at most this many instructions.
+    Instructions(usize),
+    /// Stop once this many bytes of output have been written.
+    OutputBytes(usize),
+}
+
+/// Result of a call to [`decompress_budgeted`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Progress {
+    /// The budget ran out; call again to continue.
+    Suspended,
+    /// The stream is complete, with this decompressed size.
+    Finished(usize),
+}
+
+/// Where [`decompress_budgeted`] stopped, to resume from on the next call.
+///
+/// `Send` and `Sync`, so a scheduler may resume a decompression on another thread.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DecompressState {
+    inp: usize,
+    outp: usize,
+    /// Literals copied by the last instruction, as in `decompress_at`.
+    state: usize,
+    last_dist: usize,
+    finished: bool,
+}
+
+impl DecompressState {
+    /// Starts at the beginning of a stream.
+    pub fn new() -> Self { Self::default() }
+
+    /// Offset in the input of the next instruction.
+    pub fn input_offset(&self) -> usize { self.inp }
+
+    /// Number of bytes of output written so far.
+    pub fn output_offset(&self) -> usize { self.outp }
+}
+
+/// Decompress `src` into `dst`, stopping once `budget` is spent and resuming from `state`.
+///
+/// Each call must pass the same `src` and `dst`, whose earlier output later matches copy
+/// from. Decompressing over several calls gives the same result as [`decompress`], including
+/// errors; after an error, `state` can't be resumed. Once finished, further calls return
+/// the same result.
+///
+/// [`decompress`]: super::decompress
+///
+/// ```
+/// use lzokay::decompress::{decompress_budgeted, Budget, DecompressState, Progress};
+///
+/// let src = [0x12, b'a', 0x20, 0x00, 0xdf, 0x00, 0x00, 0x11, 0x00, 0x00];
+/// let mut dst = [0u8; 512];
+/// let mut state = DecompressState::new();
+/// let size = loop {
+///     match decompress_budgeted(&src, &mut dst, &mut state, Budget::Instructions(1))? {
+///         Progress::Suspended => {} // Yield to other tasks
+///         Progress::Finished(size) => break size,
+///     }
+/// };
+/// assert_eq!(size, 512);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn decompress_budgeted(
+    src: &[u8],
+    dst: &mut [u8],
+    state: &mut DecompressState,
+    budget: Budget,
+) -> Result<Progress, Error> {
+    if state.finished {
+        return Ok(Progress::Finished(state.outp));
+    }
+    let mut observer =
+        Metered { budget, exhausted: false, state: state.state, last_dist: state.last_dist };
+    let (mut inp, mut outp) = (state.inp, state.outp);
+    let result = decompress_from::<Lzo1x, _>(
+        src,
+        dst,
+        &mut inp,
+        &mut outp,
+        state.state,
+        state.last_dist,
+        &mut observer,
+    );
+    state.inp = inp;
+    state.outp = outp;
+    state.state = observer.state;
+    state.last_dist = observer.last_dist;
+    match result {
+        Ok(size) => {
+            state.finished = true;
+            Ok(Progress::Finished(size))
+        }
+        // Stopped by the observer between instructions
+        Err(Error::Cancelled) if observer.exhausted => Ok(Progress::Suspended),
+        Err(e) => Err(e),
+    }
+}
+
+/// Spends a [`Budget`], keeping the decoder state needed to resume after each instruction.
+struct Metered {
+    budget: Budget,
+    exhausted: bool,
+    state: usize,
+    last_dist: usize,
+}
+
+impl Observer for Metered {
+    fn instruction(&mut self, entry: Entry) -> Result<(), Error> {
+        match entry.opcode {
+            // Finishing is checked by the decoder itself
+            Op::End => return Ok(()),
+            // A long literal run, or the first byte's run of 1 to 4
+            Op::Literal => self.state = entry.len.min(4),
+            _ => {
+                self.state = entry.trailing_literals;
+                self.last_dist = entry.dist;
+            }
+        }
+        let (remaining, spent) = match &mut self.budget {
+            Budget::Instructions(count) => (count, 1),
+            Budget::OutputBytes(bytes) => (bytes, entry.len + entry.trailing_literals),
+        };
+        *remaining = remaining.saturating_sub(spent);
+        if *remaining == 0 {
+            self.exhausted = true;
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress_budgeted, Budget, DecompressState, Progress};
+    use crate::{decompress::decompress, Error};
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("../test2.bin");
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.txt");
+
+    /// Decompresses `src` in steps of `budget`, returning the result and the number of calls.
+    fn decompress_steps(
+        src: &[u8],
+        dst: &mut [u8],
+        budget: Budget,
+    ) -> (Result<usize, Error>, usize) {
+        let mut state = DecompressState::new();
+        let mut calls = 0;
+        loop {
+            calls += 1;
+            let before = state.output_offset();
+            match decompress_budgeted(src, dst, &mut state, budget) {
+                Ok(Progress::Suspended) => {
+                    if let Budget::OutputBytes(bytes) = budget {
+                        assert!(state.output_offset() - before >= bytes);
+                    }
+                }
+                Ok(Progress::Finished(size)) => return (Ok(size), calls),
+                Err(e) => return (Err(e), calls),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_budgeted() {
+        let mut dst = [0u8; 4096];
+        for &(input, expected) in [(INPUT_1, EXPECTED_1), (INPUT_2, EXPECTED_2)].iter() {
+            let (_, whole) = decompress_steps(input, &mut dst, Budget::Instructions(usize::MAX));
+            assert_eq!(whole, 1);
+            for &budget in [
+                Budget::Instructions(0),
+                Budget::Instructions(1),
+                Budget::Instructions(7),
+                Budget::OutputBytes(1),
+                Budget::OutputBytes(100),
+            ]
+            .iter()
+            {
+                dst.iter_mut().for_each(|b| *b = 0);
+                let (result, calls) = decompress_steps(input, &mut dst, budget);
+                assert_eq!(result, Ok(expected.len()));
+                assert_eq!(&dst[..expected.len()], expected);
+                assert!(calls > 1);
+            }
+        }
+
+        // Finished state reports the same size again
+        let mut state = DecompressState::new();
+        let budget = Budget::OutputBytes(usize::MAX);
+        let result = decompress_budgeted(INPUT_1, &mut dst, &mut state, budget);
+        assert_eq!(result, Ok(Progress::Finished(EXPECTED_1.len())));
+        assert_eq!(state.input_offset(), INPUT_1.len());
+        let result = decompress_budgeted(INPUT_1, &mut dst, &mut state, budget);
+        assert_eq!(result, Ok(Progress::Finished(EXPECTED_1.len())));
+    }
+
+    /// Corrupt streams must fail the same way as with `decompress`.
+    #[test]
+    fn test_decompress_budgeted_errors() {
+        let mut dst = [0u8; EXPECTED_1.len() * 2];
+        let mut expected = [0u8; EXPECTED_1.len() * 2];
+        let mut src = [0u8; INPUT_1.len()];
+        src.copy_from_slice(INPUT_1);
+        for i in 0..src.len() {
+            src[i] ^= 0x55;
+            let result = decompress(&src, &mut expected);
+            let (budgeted, _) = decompress_steps(&src, &mut dst, Budget::Instructions(3));
+            assert_eq!(budgeted, result);
+            if let Ok(len) = result {
+                assert_eq!(dst[..len], expected[..len]);
+            }
+            src[i] ^= 0x55;
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! LZO1X decompression usable in `const` contexts.
+//!
+//! Trait methods and `?` aren't available in a `const fn`, so this is a separate copy of the
+//! decoder, limited to LZO1X and a fixed-size output. It accepts and rejects exactly the same
+//! streams as [`decompress`](super::decompress).
+
+use super::{M3_MARKER, M4_MARKER, MAX255_COUNT};
+use crate::Error;
+
+/// Decompresses an LZO1X stream into an array of `N` bytes, returning the array and the
+/// decompressed size.
+///
+/// As a `const fn`, this can expand data at compile time, so only the compressed form is stored
+/// in the binary while the expanded form costs nothing at runtime:
+///
+/// ```
+/// use lzokay::decompress::decompress_const;
+///
+/// const COMPRESSED: &[u8] = &[0x15, b'a', b'b', b'c', b'd', 0x23, 0x0c, 0x00, 0x11, 0, 0];
+/// const TABLE: [u8; 9] = match decompress_const::<9>(COMPRESSED) {
+///     Ok((table, _)) => table,
+///     Err(_) => panic!("invalid table"),
+/// };
+/// assert_eq!(&TABLE, b"abcdabcda");
+/// ```
+///
+/// At runtime, prefer [`decompress`](super::decompress), which is faster.
+pub const fn decompress_const<const N: usize>(src: &[u8]) -> Result<([u8; N], usize), Error> {
+    let mut dst = [0u8; N];
+    let mut inp = 0;
+    let mut outp = 0;
+    if src.len() < 3 {
+        return Err(Error::InputOverrun);
+    }
+    // Number of literals copied by the last instruction, as in `decompress`
+    let mut state = 0;
+    // Literals to copy before the next instruction
+    let mut literals = 0;
+    if src[0] >= 22 {
+        literals = src[0] as usize - 17;
+        inp = 1;
+        state = 4;
+    } else if src[0] >= 18 {
+        literals = src[0] as usize - 17;
+        inp = 1;
+        state = literals;
+    }
+
+    let lblen = loop {
+        if src.len() - inp < literals {
+            return Err(Error::InputOverrun);
+        }
+        if N - outp < literals {
+            return Err(Error::OutputOverrun);
+        }
+        let mut i = 0;
+        while i < literals {
+            dst[outp + i] = src[inp + i];
+            i += 1;
+        }
+        inp += literals;
+        outp += literals;
+
+        if inp >= src.len() {
+            return Err(Error::InputOverrun);
+        }
+        let inst = src[inp];
+        inp += 1;
+        let lbdist;
+        let lblen;
+        if inst & 0xc0 != 0 {
+            // M2
+            if inp >= src.len() {
+                return Err(Error::InputOverrun);
+            }
+            lbdist = ((src[inp] as usize) << 3) + ((inst as usize >> 2) & 0x7) + 1;
+            lblen = (inst as usize >> 5) + 1;
+            state = inst as usize & 0x3;
+            inp += 1;
+        } else if inst & (M3_MARKER | M4_MARKER) != 0 {
+            // M3 (32..63) or M4 (16..31), which share the length and distance encoding
+            let is_m3 = inst & M3_MARKER != 0;
+            let (mask, base) = if is_m3 { (0x1f, 31) } else { (0x7, 7) };
+            let mut len = (inst & mask) as usize + 2;
+            if len == 2 {
+                match extended_length(src, inp, base) {
+                    Ok((extended, next)) => {
+                        len += extended;
+                        inp = next;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if src.len() - inp < 2 {
+                return Err(Error::InputOverrun);
+            }
+            let distance = src[inp] as usize | (src[inp + 1] as usize) << 8;
+            inp += 2;
+            if is_m3 {
+                lbdist = (distance >> 2) + 1;
+            } else {
+                let dist = (((inst & 0x8) as usize) << 11) + (distance >> 2);
+                if dist == 0 {
+                    // Stream finished
+                    break len;
+                }
+                lbdist = dist + 16384;
+            }
+            lblen = len;
+            state = distance & 0x3;
+        } else if state == 0 {
+            // Long literal run
+            let mut len = inst as usize + 3;
+            if len == 3 {
+                match extended_length(src, inp, 15) {
+                    Ok((extended, next)) => {
+                        len += extended;
+                        inp = next;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            literals = len;
+            state = 4;
+            continue;
+        } else {
+            // M1, a 2-byte match after 1..3 literals or a 3-byte match after 4 or more
+            if inp >= src.len() {
+                return Err(Error::InputOverrun);
+            }
+            let dist = (inst as usize >> 2) + ((src[inp] as usize) << 2);
+            inp += 1;
+            if state != 4 {
+                lbdist = dist + 1;
+                lblen = 2;
+            } else {
+                lbdist = dist + 0x0800 + 1;
+                lblen = 3;
+            }
+            state = inst as usize & 0x3;
+        }
+
+        if lbdist > outp {
+            return Err(Error::LookbehindOverrun);
+        }
+        if src.len() - inp < state {
+            return Err(Error::InputOverrun);
+        }
+        if N - outp < lblen + state {
+            return Err(Error::OutputOverrun);
+        }
+        let mut i = 0;
+        while i < lblen {
+            dst[outp + i] = dst[outp - lbdist + i];
+            i += 1;
+        }
+        outp += lblen;
+        literals = state;
+    };
+
+    // Ensure terminating M4 was encountered
+    if lblen != 3 {
+        return Err(Error::Error);
+    }
+    if inp == src.len() {
+        Ok((dst, outp))
+    } else {
+        Err(Error::InputNotConsumed)
+    }
+}
+
+/// Reads an extended length starting at `inp`: a run of zero bytes and a non-zero byte, added
+/// to `base`. Returns the length and the position after it.
+const fn extended_length(src: &[u8], mut inp: usize, base: usize) -> Result<(usize, usize), Error> {
+    let start = inp;
+    while inp < src.len() && src[inp] == 0 {
+        inp += 1;
+    }
+    let zeros = inp - start;
+    if zeros > MAX255_COUNT {
+        return Err(Error::Error);
+    }
+    if inp >= src.len() {
+        return Err(Error::InputOverrun);
+    }
+    Ok((zeros * 255 + base + src[inp] as usize, inp + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decompress_const;
+    use crate::{decompress::decompress, Error};
+
+    const INPUT: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED: &[u8] = include_bytes!("../test1.txt");
+    const DECOMPRESSED: ([u8; 4096], usize) = match decompress_const(INPUT) {
+        Ok(result) => result,
+        Err(_) => panic!("Failed to decompress"),
+    };
+
+    #[test]
+    fn test_decompress_const() {
+        let (dst, size) = DECOMPRESSED;
+        assert_eq!(&dst[..size], EXPECTED);
+        assert_eq!(decompress_const::<100>(INPUT).err(), Some(Error::OutputOverrun));
+        assert_eq!(
+            decompress_const::<4096>(&INPUT[..INPUT.len() - 1]).err(),
+            Some(Error::InputOverrun)
+        );
+    }
+
+    #[test]
+    fn test_decompress_const_matches_decompress() {
+        // Corrupt each byte in turn, and check both decoders agree on the result
+        let mut src = [0u8; INPUT.len()];
+        src.copy_from_slice(INPUT);
+        for i in 0..src.len() {
+            for &value in &[0x00, 0x11, 0x20, 0x40, 0xff] {
+                let original = src[i];
+                src[i] = value;
+                let mut dst = [0u8; 4096];
+                let expected = decompress(&src, &mut dst).map(|size| (size, true));
+                let actual = decompress_const::<4096>(&src)
+                    .map(|(output, size)| (size, output[..size] == dst[..size]));
+                assert_eq!(actual, expected, "byte {} = {:#x}", i, value);
+                src[i] = original;
+            }
+        }
+    }
+}
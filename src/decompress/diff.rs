@@ -0,0 +1,102 @@
+//! Comparison of how two streams encode their output.
+
+use super::{Token, TokenIter};
+use crate::Error;
+
+/// Where two streams first differ, from [`diff_streams`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StreamDiff {
+    /// Offset in the decompressed output where the streams first differ.
+    pub output_offset: usize,
+    /// The first stream's token covering `output_offset`, with its input offset.
+    pub a: (usize, Token),
+    /// The second stream's token covering `output_offset`, with its input offset.
+    pub b: (usize, Token),
+}
+
+/// Parses two streams in lockstep, returning where they first differ, or `None` if they're
+/// identical up to their terminating instructions.
+///
+/// For comparing encoders, such as liblzo2 and this crate, on the same data: streams differ
+/// where they start encoding the output with different tokens, or with literals of different
+/// bytes, which is where their decompressed outputs diverge. Neither stream is decompressed,
+/// so no output buffer is needed. Fails if either stream is invalid before the difference.
+///
+/// ```
+/// use lzokay::decompress::{diff_streams, Token};
+///
+/// // "abcd" then a match of 5, or of 4 and a literal "a"
+/// let a = [0x15, b'a', b'b', b'c', b'd', 0x23, 0x0c, 0x00, 0x11, 0x00, 0x00];
+/// let b = [0x15, b'a', b'b', b'c', b'd', 0x22, 0x0d, 0x00, b'a', 0x11, 0x00, 0x00];
+/// let diff = diff_streams(&a, &b)?.unwrap();
+/// assert_eq!(diff.output_offset, 4);
+/// assert_eq!(diff.a, (5, Token::Match { len: 5, dist: 4 }));
+/// assert_eq!(diff.b, (5, Token::Match { len: 4, dist: 4 }));
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub fn diff_streams(a: &[u8], b: &[u8]) -> Result<Option<StreamDiff>, Error> {
+    let (mut tokens_a, mut tokens_b) = (TokenIter::new(a), TokenIter::new(b));
+    loop {
+        let output_offset = tokens_a.output_offset();
+        let (next_a, next_b) = match (tokens_a.next().transpose()?, tokens_b.next().transpose()?) {
+            (Some(next_a), Some(next_b)) => (next_a, next_b),
+            // Iteration stops after a terminating instruction, which only equals another
+            _ => return Ok(None),
+        };
+        let differs_at = match (next_a.1, next_b.1) {
+            (Token::Literal(len), Token::Literal(len_b)) if len == len_b => {
+                let end_a = tokens_a.input_offset();
+                let end_b = tokens_b.input_offset();
+                let mut literals = a[end_a - len..end_a].iter().zip(&b[end_b - len..end_b]);
+                literals.position(|(byte_a, byte_b)| byte_a != byte_b)
+            }
+            (token_a, token_b) if token_a == token_b => None,
+            _ => Some(0),
+        };
+        if let Some(i) = differs_at {
+            return Ok(Some(StreamDiff { output_offset: output_offset + i, a: next_a, b: next_b }));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compress", feature = "alloc"))]
+mod tests {
+    use super::diff_streams;
+    use crate::{
+        compress::{compress_with_dict, new_dict},
+        decompress::Token,
+        Error,
+    };
+
+    const INPUT: &[u8] = include_bytes!("../test1.txt");
+    const COMPRESSED: &[u8] = include_bytes!("../test1.bin");
+
+    #[test]
+    fn test_diff_streams() {
+        assert_eq!(diff_streams(COMPRESSED, COMPRESSED), Ok(None));
+
+        // Levels choose different matches
+        let mut dict = new_dict();
+        dict.set_level(1).unwrap();
+        let fast = compress_with_dict(INPUT, &mut dict).unwrap();
+        let diff = diff_streams(COMPRESSED, &fast).unwrap().unwrap();
+        assert_ne!(diff.a.1, diff.b.1);
+        assert!(diff.output_offset > 0 && diff.output_offset < INPUT.len());
+        assert_eq!(
+            diff_streams(&fast, COMPRESSED).unwrap().unwrap().output_offset,
+            diff.output_offset
+        );
+
+        // A changed literal is found at its byte
+        let mut changed = COMPRESSED.to_vec();
+        changed[10] ^= 1;
+        let diff = diff_streams(COMPRESSED, &changed).unwrap().unwrap();
+        assert_eq!(diff.output_offset, 10 - 1);
+        assert_eq!((diff.a, diff.b), ((0, Token::Literal(63)), diff.a));
+
+        // Errors before the difference fail, and ones after it aren't reached
+        let truncated = &COMPRESSED[..COMPRESSED.len() - 1];
+        assert_eq!(diff_streams(COMPRESSED, truncated), Err(Error::InputOverrun));
+        assert!(diff_streams(&changed, truncated).unwrap().is_some());
+    }
+}
@@ -0,0 +1,230 @@
+//! Decompression of input fed in pieces.
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use super::stream::Decoder;
+use crate::Error;
+
+/// Result of a call to [`Decompressor::feed`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Status {
+    /// The input so far is decoded; feed more.
+    NeedsMoreInput,
+    /// This many bytes of output are in [`Decompressor::output`]. More may follow without more
+    /// input: feed an empty slice to continue.
+    Output(usize),
+    /// The stream is complete.
+    Finished,
+}
+
+/// Decompresses a stream fed in pieces of any size, such as packets from a socket.
+///
+/// Each call to [`feed`](Decompressor::feed) decodes up to 64 KiB of output, held until the
+/// next call. The last 48 KiB of output are kept for matches to copy from, and input is only
+/// buffered while it ends in an incomplete instruction, so memory use doesn't depend on the
+/// size of the stream. The output and errors are the same as
+/// [`decompress`](super::decompress)'s; after an error, further calls fail with it too.
+///
+/// ```
+/// use lzokay::decompress::{Decompressor, Status};
+/// # let packets: [&[u8]; 3] = [&[0x12, 0, 0x20], &[0, 0xdf, 0, 0], &[0x11, 0, 0]];
+///
+/// let mut decompressor = Decompressor::new();
+/// let mut output = Vec::new();
+/// for packet in packets.iter() {
+///     let mut status = decompressor.feed(packet)?;
+///     while let Status::Output(_) = status {
+///         output.extend_from_slice(decompressor.output());
+///         status = decompressor.feed(&[])?;
+///     }
+/// }
+/// assert!(decompressor.is_finished());
+/// # assert_eq!(output, [0u8; 512]);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+pub struct Decompressor {
+    decoder: Decoder,
+    /// Input fed but not yet consumed, from `pos`.
+    input: Vec<u8>,
+    pos: usize,
+    /// Length of the output of the last call.
+    output_len: usize,
+    error: Option<Error>,
+}
+
+impl Decompressor {
+    /// Starts at the beginning of a stream.
+    pub fn new() -> Self {
+        Decompressor {
+            decoder: Decoder::new(),
+            input: Vec::new(),
+            pos: 0,
+            output_len: 0,
+            error: None,
+        }
+    }
+
+    /// Adds `src` to the input, and decodes up to the next piece of output.
+    ///
+    /// Fails with [`Error::InputNotConsumed`] if input is fed past the end of the stream.
+    pub fn feed(&mut self, src: &[u8]) -> Result<Status, Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let result = self.decode(src);
+        if let Err(error) = result {
+            self.error = Some(error);
+            self.output_len = 0;
+        }
+        result
+    }
+
+    fn decode(&mut self, src: &[u8]) -> Result<Status, Error> {
+        // Input is only copied when it isn't all consumed by the first chunk of output
+        let len = if self.pos == self.input.len() {
+            self.input.clear();
+            self.pos = 0;
+            let (consumed, chunk) = self.decoder.next_chunk(src, usize::MAX)?;
+            let len = chunk.len();
+            self.input.extend_from_slice(&src[consumed..]);
+            len
+        } else {
+            // Consumed input is dropped once it's over half the buffer, so that decoding a large
+            // piece doesn't shift the rest of it for every chunk
+            if !src.is_empty() && self.pos > self.input.len() / 2 {
+                self.input.drain(..self.pos);
+                self.pos = 0;
+            }
+            self.input.extend_from_slice(src);
+            let (consumed, chunk) = self.decoder.next_chunk(&self.input[self.pos..], usize::MAX)?;
+            self.pos += consumed;
+            chunk.len()
+        };
+        self.output_len = len;
+        if len > 0 {
+            Ok(Status::Output(len))
+        } else if self.decoder.is_finished() {
+            if self.pos == self.input.len() {
+                Ok(Status::Finished)
+            } else {
+                Err(Error::InputNotConsumed)
+            }
+        } else {
+            Ok(Status::NeedsMoreInput)
+        }
+    }
+
+    /// The output of the last call to [`feed`](Decompressor::feed), if it returned
+    /// [`Status::Output`], and otherwise empty.
+    pub fn output(&self) -> &[u8] { self.decoder.last_chunk(self.output_len) }
+
+    /// Whether the end of the stream has been decoded.
+    pub fn is_finished(&self) -> bool { self.decoder.is_finished() }
+
+    /// Total bytes of input consumed so far.
+    pub fn total_in(&self) -> u64 { self.decoder.total_in() }
+
+    /// Total bytes of output produced so far.
+    pub fn total_out(&self) -> u64 { self.decoder.total_out() }
+}
+
+impl Default for Decompressor {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(all(test, feature = "compress"))]
+mod tests {
+    #[cfg(not(feature = "std"))]
+    use super::alloc::{vec, vec::Vec};
+    use super::{Decompressor, Status};
+    use crate::{compress::compress_worst_size, Error};
+
+    const INPUT: &[u8] = include_bytes!("../test1.txt");
+    const EXPECTED: &[u8] = include_bytes!("../test1.bin");
+
+    /// Feeds `src` in pieces of `size` bytes, returning the output and the last status.
+    fn run(src: &[u8], size: usize) -> (Vec<u8>, Result<Status, Error>) {
+        let mut decompressor = Decompressor::new();
+        let mut output = Vec::new();
+        let mut status = Ok(Status::NeedsMoreInput);
+        for piece in src.chunks(size) {
+            status = decompressor.feed(piece);
+            while let Ok(Status::Output(len)) = status {
+                assert_eq!(decompressor.output().len(), len);
+                output.extend_from_slice(decompressor.output());
+                status = decompressor.feed(&[]);
+            }
+        }
+        (output, status)
+    }
+
+    #[test]
+    fn test_decompressor() {
+        for &size in &[1, 2, 7, 1000, EXPECTED.len()] {
+            let (output, status) = run(EXPECTED, size);
+            assert_eq!(status, Ok(Status::Finished), "{}", size);
+            assert_eq!(output, INPUT);
+        }
+        // A stream larger than the window
+        let mut long = [0u8; compress_worst_size(200_000)];
+        let size = crate::compress::compress_slim(&[7u8; 200_000], &mut long).unwrap();
+        let (output, status) = run(&long[..size], 3);
+        assert_eq!(status, Ok(Status::Finished));
+        assert!(output.len() == 200_000 && output.iter().all(|&b| b == 7));
+
+        // The zero bytes of a long length run are consumed, not buffered
+        let mut decompressor = Decompressor::new();
+        for _ in 0..10_000 {
+            assert_eq!(decompressor.feed(&[0; 16]), Ok(Status::NeedsMoreInput));
+            assert!(decompressor.input.is_empty());
+        }
+        assert_eq!(decompressor.total_in(), 160_000);
+    }
+
+    #[test]
+    fn test_decompressor_large_input() {
+        // A 16 MiB literal run, fed at once
+        let len = 16 << 20;
+        let zeros = (len - 18) / 255;
+        let mut src = vec![0u8; 1 + zeros];
+        src.push((len - 18 - zeros * 255) as u8);
+        src.extend((0..len).map(|i| (i % 251) as u8));
+        src.extend_from_slice(&[0x11, 0, 0]);
+
+        let mut decompressor = Decompressor::new();
+        let mut status = decompressor.feed(&src);
+        let buffered = decompressor.input.len();
+        let mut output = Vec::with_capacity(len);
+        while let Ok(Status::Output(_)) = status {
+            output.extend_from_slice(decompressor.output());
+            status = decompressor.feed(&[]);
+            // The unconsumed input isn't shifted for each chunk, only dropped once consumed
+            assert!(decompressor.input.len() == buffered || decompressor.input.is_empty());
+        }
+        assert_eq!(status, Ok(Status::Finished));
+        assert!(
+            output.len() == len && output.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8)
+        );
+    }
+
+    #[test]
+    fn test_decompressor_errors() {
+        let (_, status) = run(&EXPECTED[..EXPECTED.len() - 1], 100);
+        assert_eq!(status, Ok(Status::NeedsMoreInput));
+
+        let mut decompressor = Decompressor::new();
+        assert_eq!(decompressor.feed(EXPECTED), Ok(Status::Output(INPUT.len())));
+        assert_eq!(decompressor.feed(&[]), Ok(Status::Finished));
+        assert_eq!(decompressor.feed(&[0]), Err(Error::InputNotConsumed));
+        assert_eq!(decompressor.feed(&[]), Err(Error::InputNotConsumed));
+        assert!(decompressor.output().is_empty());
+
+        let corrupt = [0x15, 1, 2, 3, 4, 0xfc, 0xff, 0x11, 0, 0];
+        let (output, status) = run(&corrupt, 1);
+        assert_eq!((output.len(), status), (4, Err(Error::LookbehindOverrun)));
+    }
+}
@@ -0,0 +1,198 @@
+//! Decompression of a raw LZO1X stream from a [`Read`]er.
+
+use std::io::{self, Read};
+
+use super::stream::Decoder;
+use crate::Error;
+
+/// Size of the input buffer. The decoder consumes input up to the last few bytes of an
+/// incomplete instruction, including the zero bytes of a length, so it never fills up.
+const BUF_SIZE: usize = 0x10000;
+
+/// Decompresses a raw LZO1X stream read from an underlying reader.
+///
+/// For streams too large to hold in memory, or whose decompressed size isn't known: input is
+/// read in 64 KiB pieces, and only the last 48 KiB of output are kept, for matches to copy
+/// from. The output is the same as [`decompress`](super::decompress)'s, including errors, as
+/// [`io::ErrorKind::InvalidData`], or [`io::ErrorKind::UnexpectedEof`] for a truncated stream.
+/// Data after the end of the stream fails, as [`Error::InputNotConsumed`], once the end is
+/// reached.
+///
+/// ```
+/// use std::io::Read;
+///
+/// use lzokay::decompress::LzoReader;
+/// # let file: &[u8] = &[0x12, 0, 0x20, 0, 0xdf, 0, 0, 0x11, 0, 0];
+///
+/// let mut reader = LzoReader::new(file);
+/// let mut output = Vec::new();
+/// reader.read_to_end(&mut output)?;
+/// # assert_eq!(output, [0u8; 512]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct LzoReader<R> {
+    inner: R,
+    decoder: Decoder,
+    buf: Vec<u8>,
+    /// Range of `buf` read but not yet consumed.
+    pos: usize,
+    end: usize,
+    /// Whether the underlying reader has returned end of file.
+    eof: bool,
+    /// Whether the end of the stream has been checked for trailing data.
+    done: bool,
+}
+
+impl<R: Read> LzoReader<R> {
+    /// Reads a stream from `inner`.
+    pub fn new(inner: R) -> Self {
+        LzoReader {
+            inner,
+            decoder: Decoder::new(),
+            buf: vec![0u8; BUF_SIZE],
+            pos: 0,
+            end: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Total bytes of compressed input consumed so far.
+    pub fn total_in(&self) -> u64 { self.decoder.total_in() }
+
+    /// Total bytes of decompressed output produced so far.
+    pub fn total_out(&self) -> u64 { self.decoder.total_out() }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// The underlying reader, mutably. Reading from it will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Returns the underlying reader. Input read ahead of the stream's position is lost.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Reads more input after the unconsumed part.
+    fn fill(&mut self) -> io::Result<()> {
+        self.buf.copy_within(self.pos..self.end, 0);
+        self.end -= self.pos;
+        self.pos = 0;
+        debug_assert!(self.end < self.buf.len());
+        let len = self.inner.read(&mut self.buf[self.end..])?;
+        self.end += len;
+        self.eof = len == 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LzoReader<R> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        if dst.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let src = &self.buf[self.pos..self.end];
+            let (consumed, produced) = self.decoder.decode(src, dst).map_err(lzo_error)?;
+            self.pos += consumed;
+            if produced > 0 {
+                return Ok(produced);
+            }
+            if self.decoder.is_finished() {
+                if !self.done {
+                    if self.pos == self.end && !self.eof {
+                        self.fill()?;
+                    }
+                    if self.pos < self.end {
+                        return Err(lzo_error(Error::InputNotConsumed));
+                    }
+                    self.done = true;
+                }
+                return Ok(0);
+            }
+            if self.eof {
+                return Err(lzo_error(Error::InputOverrun));
+            }
+            self.fill()?;
+        }
+    }
+}
+
+fn lzo_error(e: Error) -> io::Error {
+    let kind = match e {
+        Error::InputOverrun => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, format!("{:?}", e))
+}
+
+#[cfg(all(test, feature = "compress"))]
+mod tests {
+    use std::io::{self, Read};
+
+    use super::{LzoReader, BUF_SIZE};
+    use crate::compress::compress;
+
+    const INPUT: &[u8] = include_bytes!("../test1.txt");
+
+    /// Returns at most `size` bytes per read.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_lzo_reader() {
+        let compressed = compress(INPUT).unwrap();
+        for &size in &[1, 3, 1000, usize::MAX] {
+            let mut reader = LzoReader::new(Trickle(&compressed, size));
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output).unwrap();
+            assert_eq!(output, INPUT);
+            assert_eq!(reader.total_in(), compressed.len() as u64);
+            assert_eq!(reader.total_out(), INPUT.len() as u64);
+            assert_eq!(reader.read(&mut [0u8; 16]).unwrap(), 0);
+        }
+
+        // A literal run whose length bytes alone are larger than the buffer, which isn't grown
+        let zeros = 70_000;
+        let len = 18 + zeros * 255 + 1;
+        let mut stream = vec![0u8; 1 + zeros];
+        stream.push(1);
+        stream.resize(stream.len() + len, 0xaa);
+        stream.extend_from_slice(&[0x11, 0, 0]);
+        let mut output = Vec::new();
+        let mut reader = LzoReader::new(stream.as_slice());
+        reader.read_to_end(&mut output).unwrap();
+        assert!(output.len() == len && output.iter().all(|&b| b == 0xaa));
+        assert_eq!(reader.buf.len(), BUF_SIZE);
+    }
+
+    #[test]
+    fn test_lzo_reader_errors() {
+        let compressed = compress(INPUT).unwrap();
+        let truncated = &compressed[..compressed.len() - 1];
+        let error = LzoReader::new(truncated).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        let error = LzoReader::new(trailing.as_slice()).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let corrupt = [0x15, 1, 2, 3, 4, 0xfc, 0xff, 0x11, 0, 0];
+        let error = LzoReader::new(&corrupt[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A length run of zero bytes from a reader that could yield them indefinitely is read in
+        // constant memory
+        let mut reader = LzoReader::new(io::repeat(0).take(64 << 20));
+        let error = reader.read(&mut [0u8; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.buf.len(), BUF_SIZE);
+        assert_eq!(reader.total_in(), 64 << 20);
+    }
+}
@@ -0,0 +1,497 @@
+//! Resumable LZO1X decoding, for input and output that arrive in pieces.
+//!
+//! [`Decoder`] decodes the same streams as [`decompress`](super::decompress), but may stop at
+//! any point when the input runs out or the output is full, and resume on the next call. The
+//! last 48 KiB of output are kept in a window for lookbehind copies, so callers may discard
+//! output once it's been returned.
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec};
+use core::cmp::min;
+
+use super::{M3_MARKER, M4_MARKER, MAX255_COUNT};
+use crate::{
+    variant::{Lzo1x, Variant},
+    Error,
+};
+
+/// Window size, a power of two covering the longest LZO1X lookbehind distance (49151).
+const WINDOW_SIZE: usize = 0x10000;
+
+/// An operation in progress, resumed by the next call.
+#[derive(Clone, Copy)]
+enum Pending {
+    None,
+    /// Copy `len` literal bytes from the input.
+    Literal {
+        len: usize,
+    },
+    /// Copy `len` bytes from `dist` bytes back, then `literals` literal bytes.
+    Match {
+        dist: usize,
+        len: usize,
+        literals: usize,
+    },
+}
+
+/// A decoded instruction.
+enum Instruction {
+    Literal { len: usize },
+    Match { dist: usize, len: usize, literals: usize },
+    End,
+}
+
+/// Resumable LZO1X decoder.
+pub(crate) struct Decoder {
+    window: Box<[u8]>,
+    /// Total bytes of output, the window's write position modulo its size.
+    total_out: u64,
+    /// Total bytes of input consumed.
+    total_in: u64,
+    /// Literals copied by the last instruction, or 4 for a long literal run.
+    state: usize,
+    last_dist: usize,
+    pending: Pending,
+    /// An instruction byte and the zero bytes of its extended length consumed so far, while
+    /// the rest of the instruction hasn't arrived.
+    run: Option<(u8, usize)>,
+    started: bool,
+    finished: bool,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Decoder {
+            window: vec![0u8; WINDOW_SIZE].into_boxed_slice(),
+            total_out: 0,
+            total_in: 0,
+            state: 0,
+            last_dist: 0,
+            pending: Pending::None,
+            run: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Whether the end of the stream has been decoded.
+    pub(crate) fn is_finished(&self) -> bool { self.finished }
+
+    /// Total bytes of input consumed so far.
+    pub(crate) fn total_in(&self) -> u64 { self.total_in }
+
+    /// Total bytes of output produced so far.
+    pub(crate) fn total_out(&self) -> u64 { self.total_out }
+
+    /// Checks that the stream is complete, once the input is exhausted.
+    #[cfg_attr(not(any(feature = "python", feature = "wasm")), allow(dead_code))]
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        if self.finished {
+            Ok(())
+        } else {
+            Err(Error::InputOverrun)
+        }
+    }
+
+    /// Decodes from `src` into `dst`, returning the bytes of each consumed and produced.
+    ///
+    /// Stops when `dst` is full, at the end of the stream, or when `src` doesn't hold a complete
+    /// instruction. Unconsumed input must be passed again, followed by more input.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn decode(&mut self, src: &[u8], dst: &mut [u8]) -> Result<(usize, usize), Error> {
+        let mut inp = 0usize;
+        let mut outp = 0usize;
+        loop {
+            let (consumed, chunk) = self.next_chunk(&src[inp..], dst.len() - outp)?;
+            if chunk.is_empty() {
+                return Ok((inp + consumed, outp));
+            }
+            dst[outp..outp + chunk.len()].copy_from_slice(chunk);
+            inp += consumed;
+            outp += chunk.len();
+        }
+    }
+
+    /// Decodes from `src` into the window, returning the bytes of input consumed and the output,
+    /// borrowed from the window rather than copied.
+    ///
+    /// For callers that scan the output once, saving a copy. The chunk holds at most `max_len`
+    /// bytes, and ends at the end of the window at the latest, so is at most 64 KiB. It's empty
+    /// at the end of the stream, or when `src` doesn't hold a complete instruction, as for
+    /// [`decode`](Decoder::decode).
+    pub(crate) fn next_chunk(
+        &mut self,
+        src: &[u8],
+        max_len: usize,
+    ) -> Result<(usize, &[u8]), Error> {
+        let start = self.total_out as usize & (WINDOW_SIZE - 1);
+        let mut inp = 0usize;
+        let result = self.decode_window(src, &mut inp, min(max_len, WINDOW_SIZE - start));
+        self.total_in += inp as u64;
+        let len = result?;
+        Ok((inp, &self.window[start..start + len]))
+    }
+
+    /// The last `len` bytes of output, which must be at most the length of the last chunk
+    /// returned by [`next_chunk`](Decoder::next_chunk).
+    pub(crate) fn last_chunk(&self, len: usize) -> &[u8] {
+        let end = (self.total_out as usize).wrapping_sub(1) & (WINDOW_SIZE - 1);
+        &self.window[end + 1 - len..=end]
+    }
+
+    /// Decodes into the window from its write position, returning the bytes produced, at most
+    /// `limit`, which must fit before the end of the window.
+    fn decode_window(&mut self, src: &[u8], inp: &mut usize, limit: usize) -> Result<usize, Error> {
+        let start = self.total_out as usize & (WINDOW_SIZE - 1);
+        let mut outp = 0usize;
+        loop {
+            match self.pending {
+                Pending::Literal { len } if len > 0 => {
+                    let count = min(len, min(src.len() - *inp, limit - outp));
+                    if count == 0 {
+                        return Ok(outp);
+                    }
+                    let pos = start + outp;
+                    self.window[pos..pos + count].copy_from_slice(&src[*inp..*inp + count]);
+                    *inp += count;
+                    outp += count;
+                    self.total_out += count as u64;
+                    self.pending = Pending::Literal { len: len - count };
+                    continue;
+                }
+                Pending::Match { dist, len, literals } if len > 0 => {
+                    let count = min(len, limit - outp);
+                    if count == 0 {
+                        return Ok(outp);
+                    }
+                    for pos in start + outp..start + outp + count {
+                        self.window[pos] = self.window[pos.wrapping_sub(dist) & (WINDOW_SIZE - 1)];
+                    }
+                    outp += count;
+                    self.total_out += count as u64;
+                    self.pending = if count == len {
+                        Pending::Literal { len: literals }
+                    } else {
+                        Pending::Match { dist, len: len - count, literals }
+                    };
+                    continue;
+                }
+                _ => self.pending = Pending::None,
+            }
+            if self.finished {
+                return Ok(outp);
+            }
+            let (consumed, instruction) = self.next_instruction(&src[*inp..])?;
+            *inp += consumed;
+            let instruction = match instruction {
+                Some(instruction) => instruction,
+                None => return Ok(outp),
+            };
+            match instruction {
+                Instruction::Literal { len } => self.pending = Pending::Literal { len },
+                Instruction::Match { dist, len, literals } => {
+                    if dist as u64 > self.total_out {
+                        return Err(Error::LookbehindOverrun);
+                    }
+                    self.last_dist = dist;
+                    self.pending = Pending::Match { dist, len, literals };
+                }
+                Instruction::End => self.finished = true,
+            }
+        }
+    }
+
+    /// Decodes the instruction at the start of `src`, or resumes the one whose extended length
+    /// is in progress, returning the bytes consumed and its effect, or `None` if `src` doesn't
+    /// hold all of it. Updates the literal state.
+    fn next_instruction(&mut self, src: &[u8]) -> Result<(usize, Option<Instruction>), Error> {
+        let (inst, mut pos) = match (self.run, src.first()) {
+            (Some((inst, _)), _) => (inst, 0),
+            (None, Some(&inst)) => (inst, 1),
+            (None, None) => return Ok((0, None)),
+        };
+        if !self.started {
+            self.started = true;
+            // First byte encoding, see `decompress_at`
+            if inst >= 22 {
+                self.state = 4;
+                return Ok((1, Some(Instruction::Literal { len: inst as usize - 17 })));
+            } else if inst >= 18 {
+                self.state = inst as usize - 17;
+                return Ok((1, Some(Instruction::Literal { len: self.state })));
+            }
+        }
+        let dist;
+        let len;
+        let literals;
+        if inst & 0xC0 != 0 {
+            // [M2]
+            if Lzo1x::m2_reuses_offset(inst) {
+                if self.last_dist == 0 {
+                    return Err(Error::LookbehindOverrun);
+                }
+                let (_, l, s) = Lzo1x::decode_m2(inst, 0);
+                dist = self.last_dist;
+                len = l;
+                literals = s;
+            } else {
+                let next = match src.get(pos) {
+                    Some(&next) => next,
+                    None => return Ok((0, None)),
+                };
+                pos += 1;
+                let (d, l, s) = Lzo1x::decode_m2(inst, next);
+                dist = d;
+                len = l;
+                literals = s;
+            }
+        } else if inst & (M3_MARKER | M4_MARKER) != 0 {
+            // [M3] and [M4]
+            let m3 = inst & M3_MARKER != 0;
+            let (mask, base) = if m3 { (0x1f, 31) } else { (0x7, 7) };
+            let mut l = (inst & mask) as usize + 2;
+            if l == 2 {
+                l = match self.extended_length(inst, src, &mut pos, base, 2)? {
+                    Some(extra) => extra + 2,
+                    None => return Ok((pos, None)),
+                };
+            }
+            if src.len() < pos + 2 {
+                return Ok((0, None));
+            }
+            let (d, s) = Lzo1x::decode_distance(src[pos], src[pos + 1]);
+            pos += 2;
+            if m3 {
+                dist = d + 1;
+            } else {
+                let d = (((inst & 0x8) as usize) << 11) + d;
+                if d == 0 {
+                    // Stream finished, with a terminating M4 of length 3
+                    if l != 3 {
+                        return Err(Error::Error);
+                    }
+                    return Ok((pos, Some(Instruction::End)));
+                }
+                dist = d + 16384;
+            }
+            len = l;
+            literals = s;
+        } else if self.state == 0 {
+            // [M1] Long literal run
+            let mut l = inst as usize + 3;
+            if l == 3 {
+                l = match self.extended_length(inst, src, &mut pos, 15, 0)? {
+                    Some(extra) => extra + 3,
+                    None => return Ok((pos, None)),
+                };
+            }
+            self.state = 4;
+            return Ok((pos, Some(Instruction::Literal { len: l })));
+        } else {
+            // [M1] 2 bytes within 1 KiB after 1..3 literals, or 3 bytes within 2..3 KiB after
+            // a long literal run
+            let next = match src.get(pos) {
+                Some(&next) => next,
+                None => return Ok((0, None)),
+            };
+            pos += 1;
+            let (d, s) = Lzo1x::decode_m1(inst, next);
+            if self.state != 4 {
+                dist = d + 1;
+                len = 2;
+            } else {
+                dist = d + Lzo1x::M2_MAX_OFFSET as usize + 1;
+                len = 3;
+            }
+            literals = s;
+        }
+        self.state = literals;
+        Ok((pos, Some(Instruction::Match { dist, len, literals })))
+    }
+
+    /// Reads the extended length of `inst`, zero bytes followed by a non-zero byte, returning
+    /// `zeros * 255 + base + byte` once `src` also holds the `trailing` bytes after it.
+    ///
+    /// Otherwise returns `None`, having consumed `inst` and the zero bytes so far into `run`, so
+    /// that a long run is neither buffered nor scanned again by the next call. As in
+    /// [`consume_zero_byte_length`](super::consume_zero_byte_length), a run fails as soon as
+    /// it's longer than the output still possible.
+    fn extended_length(
+        &mut self,
+        inst: u8,
+        src: &[u8],
+        pos: &mut usize,
+        base: usize,
+        trailing: usize,
+    ) -> Result<Option<usize>, Error> {
+        let start = *pos;
+        let mut end = start;
+        while end < src.len() && src[end] == 0 {
+            end += 1;
+        }
+        let zeros = self.run.map_or(0, |(_, zeros)| zeros) + (end - start);
+        if zeros > MAX255_COUNT {
+            return Err(Error::Error);
+        }
+        if zeros as u64 > (u64::MAX - self.total_out) / 255 {
+            return Err(Error::OutputOverrun);
+        }
+        if src.len() < end + 1 + trailing {
+            self.run = Some((inst, zeros));
+            *pos = end;
+            return Ok(None);
+        }
+        self.run = None;
+        *pos = end + 1;
+        Ok(Some(zeros * 255 + base + src[end] as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "std"))]
+    use super::alloc::{vec, vec::Vec};
+    use super::Decoder;
+    use crate::{decompress::decompress, Error};
+
+    const INPUT_1: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED_1: &[u8] = include_bytes!("../test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("../test2.bin");
+    const EXPECTED_2: &[u8] = include_bytes!("../test2.txt");
+
+    /// Decodes `src`, feeding at most `in_chunk` bytes and reading at most `out_chunk` bytes
+    /// per call.
+    fn decode(src: &[u8], in_chunk: usize, out_chunk: usize) -> Result<(Vec<u8>, usize), Error> {
+        let mut decoder = Decoder::new();
+        let mut output = Vec::new();
+        let mut buf = [0u8; 4096];
+        let mut pos = 0;
+        let mut end = 0;
+        loop {
+            end = (end + in_chunk).min(src.len()).max(end);
+            let (consumed, produced) = decoder.decode(&src[pos..end], &mut buf[..out_chunk])?;
+            pos += consumed;
+            output.extend_from_slice(&buf[..produced]);
+            if decoder.is_finished() && produced == 0 {
+                break;
+            }
+            if consumed == 0 && produced == 0 && end == src.len() {
+                decoder.finish()?;
+            }
+        }
+        assert_eq!(decoder.total_in(), pos as u64);
+        assert_eq!(decoder.total_out(), output.len() as u64);
+        Ok((output, pos))
+    }
+
+    #[test]
+    fn test_decode() {
+        for &(input, expected) in [(INPUT_1, EXPECTED_1), (INPUT_2, EXPECTED_2)].iter() {
+            for &(in_chunk, out_chunk) in
+                [(input.len(), 4096), (1, 4096), (input.len(), 1), (7, 13), (1000, 333)].iter()
+            {
+                let (output, consumed) = decode(input, in_chunk, out_chunk).unwrap();
+                assert_eq!(output, expected);
+                assert_eq!(consumed, input.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_chunk() {
+        // A literal then a 200000-byte M3 match, wrapping the window
+        let mut src = vec![18, b'a', 0x20];
+        let extra = 200_000 - 33;
+        src.resize(src.len() + (extra - 1) / 255, 0);
+        src.push(((extra - 1) % 255 + 1) as u8);
+        src.extend_from_slice(&[0, 0, 0x11, 0, 0]);
+
+        for &(in_chunk, max_len) in [(src.len(), usize::MAX), (src.len(), 1000), (2, 70_000)].iter()
+        {
+            let mut decoder = Decoder::new();
+            let (mut pos, mut end, mut len, mut chunks) = (0, 0, 0, 0);
+            while !decoder.is_finished() {
+                // Input arrives in pieces, and instructions may span them
+                end = (end + in_chunk).min(src.len());
+                let (consumed, chunk) = decoder.next_chunk(&src[pos..end], max_len).unwrap();
+                assert!(chunk.len() <= max_len.min(0x10000));
+                assert!(chunk.iter().all(|&byte| byte == b'a'));
+                // Chunks end at the end of the window at the latest
+                assert!(len / 0x10000 == (len + chunk.len()).saturating_sub(1) / 0x10000);
+                pos += consumed;
+                len += chunk.len();
+                chunks += 1;
+            }
+            assert_eq!((pos, len), (src.len(), 200_001));
+            if max_len == usize::MAX {
+                assert_eq!(chunks, 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extended_length_pieces() {
+        // A literal run whose length is 100000 zero bytes, fed 16 bytes at a time
+        let zeros = 100_000;
+        let len = zeros * 255 + 15 + 1 + 3;
+        let mut src = vec![0u8; 1 + zeros];
+        src.push(1);
+        src.resize(src.len() + len, b'a');
+        src.extend_from_slice(&[0x11, 0, 0]);
+
+        let mut decoder = Decoder::new();
+        let mut pos = 0;
+        let mut buf = [0u8; 4096];
+        while pos + 16 <= 1 + zeros {
+            let end = pos + 16;
+            let (consumed, produced) = decoder.decode(&src[pos..end], &mut buf).unwrap();
+            // Zero bytes are consumed as they arrive, rather than buffered by the caller
+            assert_eq!((consumed, produced), (end - pos, 0));
+            pos = end;
+        }
+        let (output, consumed) = decode(&src, 1 << 20, 4096).unwrap();
+        assert!(output.len() == len && output.iter().all(|&byte| byte == b'a'));
+        assert_eq!(consumed, src.len());
+
+        // The terminating byte and distance of a match are read together
+        let src = [0x13, b'a', b'b', 0x20, 0, 0, 1, 0x04, 0, 0x11, 0, 0];
+        let (output, _) = decode(&src, 1, 4096).unwrap();
+        assert_eq!(output.len(), 2 + 2 * 255 + 31 + 1 + 2);
+        assert!(output.chunks(2).all(|pair| pair == b"ab"));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(&INPUT_1[..INPUT_1.len() - 1], 100, 100), Err(Error::InputOverrun));
+        assert_eq!(decode(&[], 100, 100), Err(Error::InputOverrun));
+        // A match before any output
+        assert_eq!(
+            decode(&[0x12, b'a', 0x2c, 0x10, 0x00], 100, 100),
+            Err(Error::LookbehindOverrun)
+        );
+        // A terminating M4 of the wrong length
+        assert_eq!(decode(&[0x12, b'a', 0x12, 0, 0], 100, 100), Err(Error::Error));
+    }
+
+    /// Corrupt streams must fail the same way as with `decompress`.
+    #[test]
+    fn test_decode_matches_decompress() {
+        let mut dst = vec![0u8; EXPECTED_1.len() * 4];
+        let mut src = INPUT_1.to_vec();
+        for i in (0..src.len()).step_by(3) {
+            src[i] ^= 0x55;
+            let result = decode(&src, 64, 4096);
+            match decompress(&src, &mut dst) {
+                Ok(len) => assert_eq!(result, Ok((dst[..len].to_vec(), src.len()))),
+                // Trailing input is left unconsumed
+                Err(Error::InputNotConsumed) => assert!(result.unwrap().1 < src.len()),
+                Err(Error::OutputOverrun) => {}
+                Err(e) => assert_eq!(result, Err(e)),
+            }
+            src[i] ^= 0x55;
+        }
+    }
+}
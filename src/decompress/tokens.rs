@@ -0,0 +1,287 @@
+//! Token-level parsing of LZO1X streams, without producing output.
+
+use super::{M3_MARKER, M4_MARKER, MAX255_COUNT};
+use crate::{
+    variant::{Lzo1x, Variant},
+    Error,
+};
+
+/// An element of an LZO1X stream.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Token {
+    /// `len` bytes copied from the input.
+    Literal(usize),
+    /// `len` bytes copied from `dist` bytes back in the output.
+    Match {
+        /// Number of bytes copied.
+        len: usize,
+        /// Distance back from the end of the output, at least 1.
+        dist: usize,
+    },
+    /// The terminating instruction.
+    End,
+}
+
+/// Iterates over the tokens of an LZO1X stream, with the input offset where each starts.
+///
+/// Offsets point at the token's instruction, except for the 1 to 3 literals that a match's
+/// state bits append, which point at the literal bytes themselves. A literal token's bytes end
+/// at [`input_offset`](TokenIter::input_offset) once it's returned.
+///
+/// Streams are validated as they're parsed, as by [`decompress`](super::decompress): after an
+/// error, iteration stops and `input_offset` is the offset of the token that failed. Iteration
+/// also stops after [`Token::End`], leaving any trailing input unparsed.
+///
+/// ```
+/// use lzokay::decompress::{Token, TokenIter};
+///
+/// let src = [0x15, b'a', b'b', b'c', b'd', 0x23, 0x0c, 0x00, 0x11, 0x00, 0x00];
+/// let tokens: Vec<_> = TokenIter::new(&src).collect::<Result<_, _>>()?;
+/// assert_eq!(tokens, [
+///     (0, Token::Literal(4)),
+///     (5, Token::Match { len: 5, dist: 4 }),
+///     (8, Token::End),
+/// ]);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenIter<'a> {
+    src: &'a [u8],
+    inp: usize,
+    outp: usize,
+    /// Number of literals copied by the last instruction, as in `decompress`.
+    state: usize,
+    /// Literals appended by the last match, still to be returned.
+    trailing: usize,
+    done: bool,
+}
+
+impl<'a> TokenIter<'a> {
+    /// Starts parsing `src` from its first byte.
+    pub fn new(src: &'a [u8]) -> Self {
+        TokenIter { src, inp: 0, outp: 0, state: 0, trailing: 0, done: false }
+    }
+
+    /// Offset in the input after the last token returned.
+    pub fn input_offset(&self) -> usize { self.inp }
+
+    /// Size of the output so far: where the next token's bytes would be written.
+    pub fn output_offset(&self) -> usize { self.outp }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.src.get(self.inp).ok_or(Error::InputOverrun)?;
+        self.inp += 1;
+        Ok(byte)
+    }
+
+    /// Reads an extended length: a run of zero bytes and a non-zero byte, added to `base`.
+    fn extended_length(&mut self, base: usize) -> Result<usize, Error> {
+        let start = self.inp;
+        while self.src.get(self.inp) == Some(&0) {
+            self.inp += 1;
+        }
+        let zeros = self.inp - start;
+        if zeros > MAX255_COUNT {
+            return Err(Error::Error);
+        }
+        Ok(zeros * 255 + base + self.byte()? as usize)
+    }
+
+    fn literal(&mut self, len: usize) -> Result<Token, Error> {
+        if self.src.len() - self.inp < len {
+            return Err(Error::InputOverrun);
+        }
+        self.inp += len;
+        self.outp += len;
+        Ok(Token::Literal(len))
+    }
+
+    fn next_token(&mut self) -> Result<Token, Error> {
+        if self.trailing > 0 {
+            let len = self.trailing;
+            self.trailing = 0;
+            return self.literal(len);
+        }
+        if self.inp == 0 {
+            if self.src.len() < 3 {
+                return Err(Error::InputOverrun);
+            }
+            if self.src[0] >= 18 {
+                let len = self.src[0] as usize - 17;
+                self.inp = 1;
+                self.state = if len >= 4 { 4 } else { len };
+                return self.literal(len);
+            }
+        }
+
+        let inst = self.byte()?;
+        let (len, dist, state);
+        if inst & 0xc0 != 0 {
+            // M2
+            let (d, l, s) = Lzo1x::decode_m2(inst, self.byte()?);
+            len = l;
+            dist = d;
+            state = s;
+        } else if inst & (M3_MARKER | M4_MARKER) != 0 {
+            // M3 or M4, which share the length and distance encoding
+            let is_m3 = inst & M3_MARKER != 0;
+            let (mask, base) = if is_m3 { (0x1f, 31) } else { (0x7, 7) };
+            let mut l = (inst & mask) as usize + 2;
+            if l == 2 {
+                l += self.extended_length(base)?;
+            }
+            let (b0, b1) = (self.byte()?, self.byte()?);
+            let (d, s) = Lzo1x::decode_distance(b0, b1);
+            if is_m3 {
+                dist = d + 1;
+            } else {
+                let d = (((inst & 0x8) as usize) << 11) + d;
+                if d == 0 {
+                    self.done = true;
+                    // The terminating M4 has a length of 3
+                    return if l == 3 { Ok(Token::End) } else { Err(Error::Error) };
+                }
+                dist = d + 16384;
+            }
+            len = l;
+            state = s;
+        } else if self.state == 0 {
+            // Long literal run
+            let mut l = inst as usize + 3;
+            if l == 3 {
+                l += self.extended_length(15)?;
+            }
+            self.state = 4;
+            return self.literal(l);
+        } else {
+            // M1, a 2-byte match after 1..3 literals or a 3-byte match after 4 or more
+            let (d, s) = Lzo1x::decode_m1(inst, self.byte()?);
+            if self.state == 4 {
+                len = 3;
+                dist = d + Lzo1x::M2_MAX_OFFSET as usize + 1;
+            } else {
+                len = 2;
+                dist = d + 1;
+            }
+            state = s;
+        }
+
+        if dist > self.outp {
+            return Err(Error::LookbehindOverrun);
+        }
+        if self.src.len() - self.inp < state {
+            return Err(Error::InputOverrun);
+        }
+        self.outp += len;
+        self.state = state;
+        self.trailing = state;
+        Ok(Token::Match { len, dist })
+    }
+}
+
+impl Iterator for TokenIter<'_> {
+    type Item = Result<(usize, Token), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (inp, outp) = (self.inp, self.outp);
+        match self.next_token() {
+            Ok(token) => Some(Ok((inp, token))),
+            Err(e) => {
+                self.done = true;
+                self.inp = inp;
+                self.outp = outp;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for TokenIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Token, TokenIter};
+    use crate::{decompress::decompress, Error};
+
+    const INPUT: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED: &[u8] = include_bytes!("../test1.txt");
+
+    /// Rebuilds the output from `src`'s tokens, with the same checks as `decompress`.
+    fn replay(src: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+        let mut tokens = TokenIter::new(src);
+        let mut outp = 0;
+        while let Some(result) = tokens.next() {
+            match result?.1 {
+                Token::Literal(len) => {
+                    if output.len() - outp < len {
+                        return Err(Error::OutputOverrun);
+                    }
+                    let end = tokens.input_offset();
+                    output[outp..outp + len].copy_from_slice(&src[end - len..end]);
+                    outp += len;
+                }
+                Token::Match { len, dist } => {
+                    if output.len() - outp < len {
+                        return Err(Error::OutputOverrun);
+                    }
+                    for _ in 0..len {
+                        output[outp] = output[outp - dist];
+                        outp += 1;
+                    }
+                }
+                Token::End if tokens.input_offset() == src.len() => return Ok(outp),
+                Token::End => return Err(Error::InputNotConsumed),
+            }
+            assert_eq!(outp, tokens.output_offset());
+        }
+        unreachable!("Iteration ended without End or an error")
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut output = [0u8; 4096];
+        assert_eq!(replay(INPUT, &mut output), Ok(EXPECTED.len()));
+        assert_eq!(&output[..EXPECTED.len()], EXPECTED);
+
+        let mut tokens = TokenIter::new(INPUT);
+        assert_eq!(tokens.by_ref().last(), Some(Ok((INPUT.len() - 3, Token::End))));
+        assert_eq!(tokens.input_offset(), INPUT.len());
+        assert_eq!(tokens.output_offset(), EXPECTED.len());
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn test_tokens_errors() {
+        let mut tokens = TokenIter::new(&INPUT[..INPUT.len() - 1]);
+        assert_eq!(tokens.find_map(Result::err), Some(Error::InputOverrun));
+        assert_eq!(tokens.input_offset(), INPUT.len() - 3);
+
+        // One literal, then an M3 match at distance 2
+        let mut tokens = TokenIter::new(&[0x12, b'a', 0x23, 0x04, 0x00, 0x11, 0x00, 0x00]);
+        assert_eq!(tokens.next(), Some(Ok((0, Token::Literal(1)))));
+        assert_eq!(tokens.next(), Some(Err(Error::LookbehindOverrun)));
+        assert_eq!(tokens.input_offset(), 2);
+        assert_eq!(tokens.next(), None);
+
+        // Agrees with decompress on corrupted input
+        let mut src = [0u8; INPUT.len()];
+        src.copy_from_slice(INPUT);
+        for i in 0..src.len() {
+            for &value in &[0x00, 0x11, 0x20, 0x40, 0xff] {
+                let original = src[i];
+                src[i] = value;
+                let mut expected = [0u8; 4096];
+                let mut actual = [0u8; 4096];
+                let result = replay(&src, &mut actual);
+                assert_eq!(result, decompress(&src, &mut expected), "byte {} = {:#x}", i, value);
+                if let Ok(size) = result {
+                    assert_eq!(actual[..size], expected[..size]);
+                }
+                src[i] = original;
+            }
+        }
+    }
+}
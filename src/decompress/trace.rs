@@ -0,0 +1,230 @@
+//! Per-instruction statistics and traces of decompression.
+
+// The observer is always compiled, as `decompress_at` calls it, but only exposed with `trace`
+#![cfg_attr(not(feature = "trace"), allow(dead_code))]
+
+use crate::Error;
+
+/// An instruction class, as dispatched on by the decoder.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Opcode {
+    /// A literal run: the first byte's run, or a long run after a match without literals.
+    Literal,
+    /// A 2-byte match within 1 KiB, or a 3-byte match within 3 KiB after a literal run.
+    M1,
+    /// A 3 to 8-byte match within 2 KiB.
+    M2,
+    /// A match within 16 KiB.
+    M3,
+    /// A match within 16 to 48 KiB.
+    M4,
+    /// The terminating instruction.
+    End,
+}
+
+impl Opcode {
+    /// Every opcode, in the order of [`DecompressStats::opcodes`].
+    pub const ALL: [Opcode; 6] =
+        [Opcode::Literal, Opcode::M1, Opcode::M2, Opcode::M3, Opcode::M4, Opcode::End];
+}
+
+// Deriving with `#[default]` needs Rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for Opcode {
+    fn default() -> Self { Opcode::Literal }
+}
+
+/// A decoded instruction, recorded by a [`Trace`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct TraceEntry {
+    /// Offset of the instruction in the input.
+    pub input_offset: usize,
+    /// Size of the output before the instruction.
+    pub output_offset: usize,
+    /// The instruction's class.
+    pub opcode: Opcode,
+    /// Number of bytes copied from the input or from earlier output, 0 for [`Opcode::End`].
+    pub len: usize,
+    /// Distance back of a match, 0 otherwise.
+    pub dist: usize,
+    /// Number of literals copied after a match, from its state bits.
+    pub trailing_literals: usize,
+}
+
+/// Instruction counts from a [`Trace`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DecompressStats {
+    /// Number of instructions of each class, in the order of [`Opcode::ALL`].
+    pub opcodes: [usize; 6],
+    /// Number of bytes copied from the input, including matches' trailing literals.
+    pub literal_bytes: usize,
+    /// Number of bytes copied by matches.
+    pub match_bytes: usize,
+    /// Number of matches overlapping their own output (a distance below their length), which
+    /// are copied a byte at a time.
+    pub overlapping_matches: usize,
+}
+
+impl DecompressStats {
+    /// Number of instructions of class `opcode`.
+    pub fn count(&self, opcode: Opcode) -> usize { self.opcodes[opcode as usize] }
+}
+
+/// Collects statistics and a bounded trace of the instructions decoded by
+/// [`decompress_traced`](super::decompress_traced).
+///
+/// The first instructions are recorded into a caller-provided buffer, until it's full; the
+/// statistics cover every instruction. Instructions are recorded once they've been applied,
+/// so after an error the trace ends before the instruction that failed.
+///
+/// ```
+/// # #[cfg(feature = "trace")] {
+/// use lzokay::decompress::{decompress_traced, Opcode, Trace, TraceEntry};
+///
+/// let src = [0x15, b'a', b'b', b'c', b'd', 0x8c, 0x00, 0x11, 0x00, 0x00];
+/// let mut dst = [0u8; 9];
+/// let mut entries = [TraceEntry::default(); 2];
+/// let mut trace = Trace::new(&mut entries);
+/// decompress_traced(&src, &mut dst, &mut trace)?;
+/// assert_eq!(trace.entries()[1].opcode, Opcode::M2);
+/// assert_eq!(trace.dropped(), 1);
+/// assert_eq!(trace.stats().count(Opcode::End), 1);
+/// # }
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Trace<'a> {
+    stats: DecompressStats,
+    entries: &'a mut [TraceEntry],
+    len: usize,
+    dropped: usize,
+}
+
+impl<'a> Trace<'a> {
+    /// Creates a trace recording up to `entries.len()` instructions. With an empty buffer,
+    /// only statistics are collected.
+    pub fn new(entries: &'a mut [TraceEntry]) -> Self {
+        Trace { stats: DecompressStats::default(), entries, len: 0, dropped: 0 }
+    }
+
+    /// Statistics over every instruction decoded.
+    pub fn stats(&self) -> &DecompressStats { &self.stats }
+
+    /// The instructions recorded, in order.
+    pub fn entries(&self) -> &[TraceEntry] { &self.entries[..self.len] }
+
+    /// Number of instructions decoded after the buffer filled up.
+    pub fn dropped(&self) -> usize { self.dropped }
+}
+
+/// Notified by the decoder of each instruction it applies. An error stops decompression.
+pub(crate) trait Observer {
+    fn instruction(&mut self, entry: TraceEntry) -> Result<(), Error>;
+}
+
+/// Observes nothing, compiling away in plain decompression.
+impl Observer for () {
+    #[inline(always)]
+    fn instruction(&mut self, _entry: TraceEntry) -> Result<(), Error> { Ok(()) }
+}
+
+impl Observer for Trace<'_> {
+    fn instruction(&mut self, entry: TraceEntry) -> Result<(), Error> {
+        let stats = &mut self.stats;
+        stats.opcodes[entry.opcode as usize] += 1;
+        match entry.opcode {
+            Opcode::Literal => stats.literal_bytes += entry.len,
+            Opcode::End => {}
+            _ => {
+                stats.match_bytes += entry.len;
+                stats.literal_bytes += entry.trailing_literals;
+                if entry.dist < entry.len {
+                    stats.overlapping_matches += 1;
+                }
+            }
+        }
+        if let Some(slot) = self.entries.get_mut(self.len) {
+            *slot = entry;
+            self.len += 1;
+        } else {
+            self.dropped += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod tests {
+    use super::{Opcode, Trace, TraceEntry};
+    use crate::{
+        decompress::{decompress_traced, Token, TokenIter},
+        Error,
+    };
+
+    const INPUT: &[u8] = include_bytes!("../test1.bin");
+    const EXPECTED: &[u8] = include_bytes!("../test1.txt");
+
+    #[test]
+    fn test_trace() {
+        let mut dst = [0u8; 4096];
+        let mut entries = [TraceEntry::default(); 4096];
+        let mut trace = Trace::new(&mut entries);
+        assert_eq!(decompress_traced(INPUT, &mut dst, &mut trace), Ok(EXPECTED.len()));
+        assert_eq!(&dst[..EXPECTED.len()], EXPECTED);
+        assert_eq!(trace.dropped(), 0);
+
+        // Agrees with the stream's tokens, which split off matches' trailing literals
+        let mut tokens = TokenIter::new(INPUT);
+        for entry in trace.entries() {
+            let (offset, token) = tokens.next().unwrap().unwrap();
+            assert_eq!(offset, entry.input_offset);
+            assert_eq!(tokens.output_offset() - token_len(token), entry.output_offset);
+            match (entry.opcode, token) {
+                (Opcode::Literal, Token::Literal(len)) => assert_eq!(len, entry.len),
+                (Opcode::End, Token::End) => {}
+                (_, Token::Match { len, dist }) => {
+                    assert_eq!((len, dist), (entry.len, entry.dist));
+                    if entry.trailing_literals > 0 {
+                        let token = tokens.next().unwrap().unwrap().1;
+                        assert_eq!(token, Token::Literal(entry.trailing_literals));
+                    }
+                }
+                other => panic!("Mismatched {:?}", other),
+            }
+        }
+        assert_eq!(tokens.next(), None);
+
+        let stats = *trace.stats();
+        assert_eq!(stats.count(Opcode::End), 1);
+        assert_eq!(stats.literal_bytes + stats.match_bytes, EXPECTED.len());
+        assert_eq!(stats.opcodes.iter().sum::<usize>(), trace.entries().len());
+        for &opcode in &[Opcode::Literal, Opcode::M1, Opcode::M2, Opcode::M3] {
+            assert!(stats.count(opcode) > 0, "{:?}", stats);
+        }
+
+        // A bounded trace keeps the first entries, and the same statistics
+        let mut first = [TraceEntry::default(); 10];
+        let mut bounded = Trace::new(&mut first);
+        decompress_traced(INPUT, &mut dst, &mut bounded).unwrap();
+        assert_eq!(bounded.entries(), &trace.entries()[..10]);
+        assert_eq!(bounded.dropped(), trace.entries().len() - 10);
+        assert_eq!(*bounded.stats(), stats);
+    }
+
+    #[test]
+    fn test_trace_errors() {
+        let mut dst = [0u8; 4096];
+        let mut trace = Trace::new(&mut []);
+        let result = decompress_traced(&INPUT[..INPUT.len() - 1], &mut dst, &mut trace);
+        assert_eq!(result, Err(Error::InputOverrun));
+        assert_eq!(trace.stats().count(Opcode::End), 0);
+        assert_eq!(trace.dropped(), trace.stats().opcodes.iter().sum::<usize>());
+    }
+
+    fn token_len(token: Token) -> usize {
+        match token {
+            Token::Literal(len) | Token::Match { len, .. } => len,
+            Token::End => 0,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! # Format constants
+//!
+//! Limits of the LZO1X format, for container formats and validators checking streams or sizing
+//! buffers without decoding.
+//!
+//! A stream is a sequence of literal runs and matches, each match copying from earlier output.
+//! Matches come in four instructions, M1 to M4, each covering a range of lengths and distances:
+//!
+//! | Instruction | Length                              | Distance                                            |
+//! |-------------|-------------------------------------|-----------------------------------------------------|
+//! | M1          | [`M1_LEN`]                          | 1 to [`M1_MAX_DISTANCE`]                            |
+//! | M1, long    | [`M1_LONG_LEN`]                     | [`M1_LONG_MIN_DISTANCE`] to [`M1_LONG_MAX_DISTANCE`] |
+//! | M2          | [`MIN_MATCH_LEN`] to [`M2_MAX_LEN`] | 1 to [`M2_MAX_DISTANCE`]                            |
+//! | M3          | [`MIN_MATCH_LEN`] or more           | 1 to [`M3_MAX_DISTANCE`]                            |
+//! | M4          | [`MIN_MATCH_LEN`] or more           | [`M4_MIN_DISTANCE`] to [`MAX_DISTANCE`]             |
+//!
+//! M1 matches only follow a literal run: of 1 to 3 bytes for the short form, and of 4 or more
+//! for the long one. M3 and M4 lengths beyond [`M3_MAX_SHORT_LEN`] and [`M4_MAX_SHORT_LEN`] are
+//! extended with a byte per 255, so the format doesn't bound them; this crate's compressor
+//! emits at most [`MAX_MATCH_LEN`]. A stream ends with [`END_OF_STREAM`].
+//!
+//! ### Worst-case size
+//!
+//! Incompressible input is stored as one literal run, whose length costs a byte per 255
+//! literals and a few more, then [`END_OF_STREAM`]. Matches take at most as many bytes as they
+//! copy, and the compressor leaves out those that would cost more than the literals they
+//! replace, so the bound used by `compress::compress_worst_size`, `len + len / 16 + 64 + 3`,
+//! covers any input with room to spare. It's liblzo's bound, so buffers sized for either
+//! library are large enough for the other.
+
+/// Farthest distance back a match can copy from: 48 KiB - 1.
+pub const MAX_DISTANCE: usize = 0xbfff;
+/// Longest match emitted by this crate's compressor. Decoders accept longer matches.
+pub const MAX_MATCH_LEN: usize = 0x800;
+/// Shortest M2, M3 or M4 match.
+pub const MIN_MATCH_LEN: usize = 3;
+/// Longest literal run encoded by the first instruction of a stream alone. Longer runs, and
+/// runs later in the stream, take more bytes.
+pub const FIRST_LITERAL_MAX_LEN: usize = 238;
+
+/// Length of an M1 match after a run of 1 to 3 literals.
+pub const M1_LEN: usize = 2;
+/// Farthest distance of an M1 match after a run of 1 to 3 literals.
+pub const M1_MAX_DISTANCE: usize = 0x400;
+/// Length of an M1 match after a run of 4 or more literals.
+pub const M1_LONG_LEN: usize = 3;
+/// Nearest distance of an M1 match after a run of 4 or more literals, just past M2's.
+pub const M1_LONG_MIN_DISTANCE: usize = M2_MAX_DISTANCE + 1;
+/// Farthest distance of an M1 match after a run of 4 or more literals.
+pub const M1_LONG_MAX_DISTANCE: usize = M2_MAX_DISTANCE + M1_MAX_DISTANCE;
+
+/// Longest M2 match.
+pub const M2_MAX_LEN: usize = 8;
+/// Farthest distance of an M2 match.
+pub const M2_MAX_DISTANCE: usize = 0x800;
+
+/// Longest M3 match whose length fits in its instruction byte.
+pub const M3_MAX_SHORT_LEN: usize = 33;
+/// Farthest distance of an M3 match.
+pub const M3_MAX_DISTANCE: usize = 0x4000;
+
+/// Longest M4 match whose length fits in its instruction byte.
+pub const M4_MAX_SHORT_LEN: usize = 9;
+/// Nearest distance of an M4 match, just past M3's.
+pub const M4_MIN_DISTANCE: usize = M3_MAX_DISTANCE + 1;
+
+/// The instruction ending a stream: an M4 match of distance 16384, which real matches encode
+/// as M3.
+pub const END_OF_STREAM: [u8; 3] = [0x11, 0x00, 0x00];
+
+#[cfg(all(test, feature = "compress", feature = "decompress"))]
+mod tests {
+    use super::*;
+    use crate::{compress::TokenWriter, decompress::decompress, Error};
+
+    /// Writes a literal run of `lit` bytes then a match, after enough output for any distance,
+    /// returning the result of the match.
+    fn write_match(lit: usize, len: usize, dist: usize) -> Result<(), Error> {
+        let mut dst = [0u8; MAX_DISTANCE * 2];
+        let mut writer = TokenWriter::new(&mut dst);
+        writer.write_literal(&[0x55; MAX_DISTANCE + 1])?;
+        writer.write_match(MIN_MATCH_LEN, 1)?;
+        writer.write_literal(&[0xaa; 16][..lit])?;
+        writer.write_match(len, dist)?;
+        let size = writer.finish()?;
+        assert_eq!(dst[size - 3..size], END_OF_STREAM);
+        let mut output = [0u8; MAX_DISTANCE * 2];
+        let expected = MAX_DISTANCE + 1 + MIN_MATCH_LEN + lit + len;
+        assert_eq!(decompress(&dst[..size], &mut output), Ok(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(write_match(3, M1_LEN, M1_MAX_DISTANCE), Ok(()));
+        assert_eq!(write_match(3, M1_LEN, M1_MAX_DISTANCE + 1), Err(Error::Error));
+        assert_eq!(write_match(4, M1_LEN, 1), Err(Error::Error));
+        assert_eq!(write_match(4, M1_LONG_LEN, M1_LONG_MIN_DISTANCE), Ok(()));
+        assert_eq!(write_match(4, M1_LONG_LEN, M1_LONG_MAX_DISTANCE), Ok(()));
+        assert_eq!(write_match(0, M2_MAX_LEN, M2_MAX_DISTANCE), Ok(()));
+        assert_eq!(write_match(0, MAX_MATCH_LEN, M3_MAX_DISTANCE), Ok(()));
+        assert_eq!(write_match(0, MIN_MATCH_LEN, M4_MIN_DISTANCE), Ok(()));
+        assert_eq!(write_match(0, MAX_MATCH_LEN, MAX_DISTANCE), Ok(()));
+        assert_eq!(write_match(0, MIN_MATCH_LEN, MAX_DISTANCE + 1), Err(Error::Error));
+    }
+}
@@ -0,0 +1,157 @@
+//! # Java bindings
+//!
+//! Available with feature `jni`.
+//!
+//! JNI entry points for the `com.github.encounter.lzokay.Lzokay` class, found in the `java`
+//! directory of the repository. Build the native library with:
+//!
+//! ```sh
+//! cargo rustc --release --features jni --crate-type cdylib
+//! ```
+//!
+//! For Android, use [cargo-ndk](https://github.com/bbqsrc/cargo-ndk) with the same arguments.
+//!
+//! Both `byte[]` arrays and direct `ByteBuffer`s are supported. The latter avoid copying, and
+//! must not overlap. Errors are thrown as `java.io.IOException`, and invalid arguments as
+//! `java.lang.IllegalArgumentException`.
+
+use core::{ptr, slice};
+use std::convert::TryFrom;
+
+use jni::{
+    objects::{JByteArray, JByteBuffer, JClass},
+    sys::{jbyteArray, jint},
+    JNIEnv,
+};
+
+use crate::{compress, decompress, Error};
+
+enum JavaError {
+    Lzo(Error),
+    Jni(jni::errors::Error),
+    Argument(&'static str),
+}
+
+impl From<Error> for JavaError {
+    fn from(e: Error) -> Self { JavaError::Lzo(e) }
+}
+
+impl From<jni::errors::Error> for JavaError {
+    fn from(e: jni::errors::Error) -> Self { JavaError::Jni(e) }
+}
+
+/// Unwraps `result`, or throws the error and returns `fallback`.
+fn unwrap_or_throw<T>(env: &mut JNIEnv, result: Result<T, JavaError>, fallback: T) -> T {
+    let (class, msg) = match result {
+        Ok(value) => return value,
+        // An exception is already pending
+        Err(JavaError::Jni(jni::errors::Error::JavaException)) => return fallback,
+        Err(JavaError::Lzo(e)) => ("java/io/IOException", format!("{:?}", e)),
+        Err(JavaError::Jni(e)) => ("java/io/IOException", e.to_string()),
+        Err(JavaError::Argument(msg)) => ("java/lang/IllegalArgumentException", msg.into()),
+    };
+    // If this fails, there's nothing left to report the error with
+    let _ = env.throw_new(class, msg);
+    fallback
+}
+
+fn to_usize(value: jint, name: &'static str) -> Result<usize, JavaError> {
+    usize::try_from(value).map_err(|_| JavaError::Argument(name))
+}
+
+fn to_jint(value: usize) -> Result<jint, JavaError> {
+    jint::try_from(value).map_err(|_| JavaError::Lzo(Error::OutputOverrun))
+}
+
+/// Runs `f` over the contents of two direct buffers, using the first `src_size` bytes of `src`.
+fn with_direct_buffers(
+    env: &JNIEnv,
+    src: &JByteBuffer,
+    src_size: jint,
+    dst: &JByteBuffer,
+    f: impl FnOnce(&[u8], &mut [u8]) -> Result<usize, Error>,
+) -> Result<jint, JavaError> {
+    let src_size = to_usize(src_size, "srcSize must not be negative")?;
+    if src_size > env.get_direct_buffer_capacity(src)? {
+        return Err(JavaError::Argument("srcSize exceeds src capacity"));
+    }
+    let src_ptr = env.get_direct_buffer_address(src)?;
+    let dst_ptr = env.get_direct_buffer_address(dst)?;
+    let dst_size = env.get_direct_buffer_capacity(dst)?;
+    // SAFETY: Direct buffer memory is valid for its capacity while the buffer is reachable,
+    // which it is for the duration of the call. Overlapping buffers are documented as invalid.
+    let (src, dst) = unsafe {
+        (slice::from_raw_parts(src_ptr, src_size), slice::from_raw_parts_mut(dst_ptr, dst_size))
+    };
+    to_jint(f(src, dst)?)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_github_encounter_lzokay_Lzokay_compressWorstSize(
+    mut env: JNIEnv,
+    _class: JClass,
+    src_size: jint,
+) -> jint {
+    let result = to_usize(src_size, "srcSize must not be negative")
+        .and_then(|size| to_jint(compress::compress_worst_size(size)));
+    unwrap_or_throw(&mut env, result, 0)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_github_encounter_lzokay_Lzokay_compress(
+    mut env: JNIEnv,
+    _class: JClass,
+    src: JByteArray,
+) -> jbyteArray {
+    let result = (|| {
+        let src = env.convert_byte_array(&src)?;
+        let dst = compress::compress(&src)?;
+        Ok(env.byte_array_from_slice(&dst)?.into_raw())
+    })();
+    unwrap_or_throw(&mut env, result, ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_github_encounter_lzokay_Lzokay_decompress(
+    mut env: JNIEnv,
+    _class: JClass,
+    src: JByteArray,
+    expected_size: jint,
+) -> jbyteArray {
+    let result = (|| {
+        let expected_size = to_usize(expected_size, "expectedSize must not be negative")?;
+        let src = env.convert_byte_array(&src)?;
+        let mut dst = vec![0u8; expected_size];
+        if decompress::decompress(&src, &mut dst)? != expected_size {
+            return Err(JavaError::Lzo(Error::InputOverrun));
+        }
+        Ok(env.byte_array_from_slice(&dst)?.into_raw())
+    })();
+    unwrap_or_throw(&mut env, result, ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_github_encounter_lzokay_Lzokay_compressDirect(
+    mut env: JNIEnv,
+    _class: JClass,
+    src: JByteBuffer,
+    src_size: jint,
+    dst: JByteBuffer,
+) -> jint {
+    let result = with_direct_buffers(&env, &src, src_size, &dst, |src, dst| {
+        compress::compress_no_alloc(src, dst, &mut compress::new_dict())
+    });
+    unwrap_or_throw(&mut env, result, 0)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_github_encounter_lzokay_Lzokay_decompressDirect(
+    mut env: JNIEnv,
+    _class: JClass,
+    src: JByteBuffer,
+    src_size: jint,
+    dst: JByteBuffer,
+) -> jint {
+    let result = with_direct_buffers(&env, &src, src_size, &dst, decompress::decompress);
+    unwrap_or_throw(&mut env, result, 0)
+}
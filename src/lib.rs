@@ -1,7 +1,8 @@
 #![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
 //! # LZ👌-rs
 //!
-//! Rust wrapper for [LZ👌](https://github.com/jackoalan/lzokay), a minimal, MIT-licensed
+//! Pure-Rust port of [LZ👌](https://github.com/jackoalan/lzokay), a minimal, MIT-licensed
 //! implementation of the [LZO compression format](http://www.oberhumer.com/opensource/lzo/).
 //!
 //! See the original [README](https://github.com/jackoalan/lzokay/blob/master/README.md) for more information.
@@ -11,23 +12,28 @@
 //! - MIT-licensed
 //! - Simple compression and decompression routines
 //! - `#![no_std]` compatible
+//! - Runs on WebAssembly, including `wasm32-wasip1`
+//! - Decompression runs on 16-bit targets, such as AVR and MSP430
 //!
 //! ### Usage
 //!
-//! See the [`compress`] or [`decompress`] documentation for reference.
+//! See the [`compress`] or [`decompress`] documentation for reference, or [`codec`] for
+//! selecting between formats. [`build_helpers`] compresses assets from a build script,
+//! [`preset`] chooses between preset dictionaries for each block, and [`format`] lists the
+//! limits of the LZO1X format.
 //!
 //! In `Cargo.toml`:
 //!
 //! ```toml
 //! [dependencies]
-//! lzokay = "1.0.1"
+//! lzokay = "2.0.0"
 //! ```
 //!
 //! Or, to only enable certain features:
 //!
 //! ```toml
 //! [dependencies.lzokay]
-//! version = "1.0.1"
+//! version = "2.0.0"
 //! default-features = false
 //! features = ["decompress", "compress"]
 //! ```
@@ -35,46 +41,156 @@
 //! - `decompress`: Enables decompression functions.
 //! - `compress`: Enables compression functions.
 //! - `alloc`: Enables optional compression functions that perform heap allocation.
-//!            Without `std`, this uses `extern crate alloc`.
+//!   Without `std`, this uses `extern crate alloc`.
 //! - `std`: Enables use of `std`. Implies `alloc`.
+//! - `lzo1b`: Enables the [LZO1B](lzo1b) format decoder.
+//! - `lzo1f`: Enables the [LZO1F](lzo1f) format decoder.
+//! - `lzo1y`: Enables the [LZO1Y](lzo1y) format variant.
+//! - `lzo1z`: Enables the [LZO1Z](lzo1z) format variant.
+//! - `lzo2a`: Enables the [LZO2A](lzo2a) format.
+//! - `const-decompress`: Enables [`decompress_const`](decompress::decompress_const), for
+//!   decompressing at compile time. Implies `decompress`.
+//! - `array`: Enables [`compress_to_array`](compress::compress_to_array), for compressing into
+//!   fixed-size arrays. Implies `compress`.
+//! - `trace`: Enables [`decompress_traced`](decompress::decompress_traced), for counting and
+//!   tracing the instructions decoded. Implies `decompress`.
+//! - `macros`: Enables [`include_lzo!`], for compressing files at compile time.
+//! - `cli`: Builds the `lzokay` command-line tool, whose `inspect` command prints the
+//!   instructions of a compressed stream. Implies `decompress` and `std`.
+//! - `lzop`: Enables reading and writing [lzop files](lzop). Implies `compress`, `decompress`
+//!   and `std`.
+//! - `capi`: Exports a [C API](capi). Implies `compress`, `decompress` and `std`.
+//! - `wasm`: Exports [JavaScript bindings](wasm) with wasm-bindgen. Implies `compress`,
+//!   `decompress` and `std`.
+//! - `uniffi`: Exports [Swift and Kotlin bindings](mobile) with UniFFI. Implies `compress`,
+//!   `decompress` and `std`.
+//! - `jni`: Exports [Java bindings](java) for Android and the JVM. Implies `compress`,
+//!   `decompress` and `std`.
+//! - `python`: Builds a [Python extension module](python) with PyO3. Implies `compress`,
+//!   `decompress`, `std`, `lzop` and `parallel`. Requires Rust 1.75.
+//! - `numpy`: Adds numpy array compression to the Python module. Implies `python`.
+//! - `abi3`: Builds the Python module against the stable ABI, so one wheel covers CPython 3.11
+//!   and later. Implies `python`.
+//! - `cpp-backend`: Builds the original [C++ implementation](cpp) alongside, for comparison.
+//!   Implies `compress` and `decompress`.
+//! - `sys`: Exposes the [raw bindings](sys) used by `cpp-backend`. Implies `cpp-backend`.
+//! - `regen-bindings`: Regenerates the `cpp-backend` bindings with bindgen. Requires libclang.
+//! - `stats`: Keeps [global counters](stats) of the calls made and bytes processed.
+//! - `page`: Enables [page compression](page) for storage engines. Implies `compress` and
+//!   `decompress`.
+//! - `parallel`: Enables [`compress_parallel`](compress::compress_parallel), for compressing
+//!   many blocks on several threads without a thread pool dependency. Implies `compress` and
+//!   `std`.
+//! - `arbitrary`: Implements `Arbitrary` for [valid streams](arbitrary), for fuzzing decoders.
+//!   Implies `compress` and `alloc`.
+//! - `test-support`: Exposes [reference streams](test_support) for testing crates that wrap
+//!   this one. Implies `compress` and `alloc`.
+//! - `zeroize`: Implements `Zeroize` for [dictionaries](compress::Dict) and the
+//!   [lzop](lzop) reader and writer, for wiping the data they buffer after compressing secrets.
+//! - `forbid-unsafe`: Builds with `#![forbid(unsafe_code)]`, for environments that must show
+//!   an absence of unsafe code. Leaves out [`dict_from_storage`](compress::dict_from_storage)
+//!   and miniLZO's [`lzo1x_1_compress`](compat::minilzo::lzo1x_1_compress), and can't be combined
+//!   with the FFI features (`capi`, `wasm`, `uniffi`, `jni`, `python` and `cpp-backend`).
 //!
-//! All features are enabled by default.
+//! `compress`, `decompress` and `std` are enabled by default. [`features`] reports which were
+//! compiled in, at runtime.
+//!
+//! On 16-bit targets, only `decompress` is supported: the compression dictionary alone is
+//! larger than the address space.
 //!
 //! ### License
 //!
 //! LZ👌 and LZ👌-rs are available under the MIT License and have no external dependencies.
 
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(any(feature = "compress", feature = "decompress"))]
+pub mod backend;
+#[cfg(all(feature = "compress", feature = "std"))]
+pub mod build_helpers;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checksum;
+#[cfg(any(feature = "compress", feature = "decompress"))]
+pub mod codec;
+#[cfg(any(feature = "compress", feature = "decompress"))]
+pub mod compat;
 #[cfg(feature = "compress")]
 pub mod compress;
+#[cfg(feature = "cpp-backend")]
+pub mod cpp;
 #[cfg(feature = "decompress")]
 pub mod decompress;
+pub mod format;
+#[cfg(feature = "jni")]
+pub mod java;
+#[cfg(all(feature = "lzo1b", feature = "decompress"))]
+pub mod lzo1b;
+#[cfg(all(feature = "lzo1f", feature = "decompress"))]
+pub mod lzo1f;
+#[cfg(all(feature = "lzo1y", any(feature = "compress", feature = "decompress")))]
+pub mod lzo1y;
+#[cfg(all(feature = "lzo1z", any(feature = "compress", feature = "decompress")))]
+pub mod lzo1z;
+#[cfg(all(feature = "lzo2a", any(feature = "compress", feature = "decompress")))]
+pub mod lzo2a;
+#[cfg(feature = "lzop")]
+pub mod lzop;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+#[cfg(feature = "page")]
+pub mod page;
+#[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
+pub mod preset;
+#[cfg(feature = "python")]
+// Restoring lzop mtimes needs `File::set_modified`
+#[clippy::msrv = "1.75"]
+pub mod python;
+#[cfg(all(feature = "stats", any(feature = "compress", feature = "decompress")))]
+pub mod stats;
+#[cfg(feature = "sys")]
+pub mod sys;
+#[cfg(all(feature = "cpp-backend", not(feature = "sys")))]
+mod sys;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(any(feature = "compress", feature = "decompress"))]
+mod variant;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "compress")]
+mod word;
 
-mod bindings {
-    #![allow(unknown_lints)]
-    #![allow(non_upper_case_globals)]
-    #![allow(non_camel_case_types)]
-    #![allow(non_snake_case)]
-    #![allow(deref_nullptr)]
-    #![allow(dead_code)]
-    #[cfg(not(feature = "std"))]
-    mod types {
-        pub type c_uchar = u8;
-        pub type c_ushort = u16;
-        pub type c_uint = u32;
-        pub type c_int = i32;
-    }
-    #[cfg(feature = "std")]
-    mod types {
-        pub type c_uchar = ::std::os::raw::c_uchar;
-        pub type c_ushort = ::std::os::raw::c_ushort;
-        pub type c_uint = ::std::os::raw::c_uint;
-        pub type c_int = ::std::os::raw::c_int;
-    }
-    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
-}
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "macros")]
+pub use lzokay_macros::include_lzo;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+#[cfg(all(
+    feature = "forbid-unsafe",
+    any(
+        feature = "capi",
+        feature = "wasm",
+        feature = "uniffi",
+        feature = "jni",
+        feature = "python",
+        feature = "cpp-backend"
+    )
+))]
+compile_error!("`forbid-unsafe` can't be combined with FFI features");
+
+#[cfg(all(feature = "compress", target_pointer_width = "16"))]
+compile_error!(
+    "compression isn't supported on 16-bit targets; disable default features and enable \
+     `decompress`"
+);
 
 /// Error result codes
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Error {
     /// Likely indicates bad compressed LZO input.
     LookbehindOverrun,
@@ -86,40 +202,404 @@ pub enum Error {
     Error,
     /// Decompression succeeded, but input buffer has remaining data.
     InputNotConsumed,
+    /// The operation was cancelled with a [`CancelToken`].
+    Cancelled,
+    /// Compressed output didn't decompress to the input, when verifying with
+    /// `Dict::set_verify` or `verify_roundtrip`.
+    VerificationFailed,
+}
+
+/// A flag for cancelling long operations, from another thread or an interrupt handler.
+///
+/// Checked periodically by [`compress_with_cancel`](compress::compress_with_cancel) and
+/// [`decompress_with_cancel`](decompress::decompress_with_cancel), which then fail with
+/// [`Error::Cancelled`].
+#[derive(Debug, Default)]
+pub struct CancelToken(AtomicBool);
+
+impl CancelToken {
+    /// Creates a token that isn't cancelled.
+    pub const fn new() -> Self { CancelToken(AtomicBool::new(false)) }
+
+    /// Requests cancellation of the operations checking this token.
+    pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed) }
+
+    /// Whether [`cancel`](CancelToken::cancel) was called since the token was created or reset.
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+
+    /// Clears a cancellation, for reusing the token.
+    pub fn reset(&self) { self.0.store(false, Ordering::Relaxed) }
+}
+
+/// Progress of a long operation, passed to progress callbacks.
+///
+/// Reported by [`compress_with_progress`](compress::compress_with_progress) and the lzop
+/// [`Reader`](lzop::Reader) and [`Writer`](lzop::Writer).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of input bytes processed so far.
+    pub bytes_in: u64,
+    /// Number of output bytes produced so far.
+    pub bytes_out: u64,
+    /// Total number of input bytes, if known.
+    pub total_in: Option<u64>,
+}
+
+/// Sizes and timings of a round trip, from [`verify_roundtrip`].
+#[cfg(all(feature = "compress", feature = "decompress", feature = "std"))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RoundTripReport {
+    /// Size of the input.
+    pub input_len: usize,
+    /// Size of the compressed stream.
+    pub compressed_len: usize,
+    /// Time spent compressing.
+    pub compress_time: std::time::Duration,
+    /// Time spent decompressing.
+    pub decompress_time: std::time::Duration,
 }
 
-fn lzokay_result<T>(result: T, error: bindings::lzokay_EResult) -> Result<T, Error> {
-    if error == bindings::lzokay_EResult_Success {
-        Result::Ok(result)
-    } else {
-        Result::Err(match error {
-            bindings::lzokay_EResult_LookbehindOverrun => Error::LookbehindOverrun,
-            bindings::lzokay_EResult_OutputOverrun => Error::OutputOverrun,
-            bindings::lzokay_EResult_InputOverrun => Error::InputOverrun,
-            bindings::lzokay_EResult_InputNotConsumed => Error::InputNotConsumed,
-            _ => Error::Error,
-        })
+#[cfg(all(feature = "compress", feature = "decompress", feature = "std"))]
+impl RoundTripReport {
+    /// Compressed size as a fraction of the input size, or 1 for an empty input.
+    pub fn ratio(&self) -> f64 {
+        match self.input_len {
+            0 => 1.0,
+            len => self.compressed_len as f64 / len as f64,
+        }
+    }
+}
+
+/// Compresses `src`, decompresses the result and checks it matches, reporting sizes and
+/// timings.
+///
+/// A sanity check for CI jobs and data pipelines to run on representative data. Fails with
+/// [`Error::VerificationFailed`] if the stream doesn't decompress to `src`.
+///
+/// ```
+/// let report = lzokay::verify_roundtrip(&[0u8; 4096])?;
+/// println!("{:.1}%, in {:?}", report.ratio() * 100.0, report.compress_time);
+/// # assert!(report.ratio() < 0.01);
+/// # Ok::<(), lzokay::Error>(())
+/// ```
+#[cfg(all(feature = "compress", feature = "decompress", feature = "std"))]
+pub fn verify_roundtrip(src: impl AsRef<[u8]>) -> Result<RoundTripReport, Error> {
+    use std::time::Instant;
+
+    let src = src.as_ref();
+    let start = Instant::now();
+    let compressed = compress::compress(src)?;
+    let compress_time = start.elapsed();
+    let mut dst = vec![0u8; src.len()];
+    let start = Instant::now();
+    let result = decompress::decompress(&compressed, &mut dst);
+    let decompress_time = start.elapsed();
+    match result {
+        Ok(len) if len == src.len() && dst == src => Ok(RoundTripReport {
+            input_len: src.len(),
+            compressed_len: compressed.len(),
+            compress_time,
+            decompress_time,
+        }),
+        _ => Err(Error::VerificationFailed),
+    }
+}
+
+/// Codecs and optional subsystems compiled into this build, as returned by [`features`].
+///
+/// Each flag is set when the corresponding API is available, which for some features also
+/// depends on others: the LZO1Y module, for example, needs `compress` or `decompress` too.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Features {
+    /// LZO1X compression, from feature `compress`.
+    pub compress: bool,
+    /// LZO1X decompression, from feature `decompress`.
+    pub decompress: bool,
+    /// Allocating functions, from feature `alloc`.
+    pub alloc: bool,
+    /// Use of `std`, from feature `std`.
+    pub std: bool,
+    /// The [LZO1B](lzo1b) decoder.
+    pub lzo1b: bool,
+    /// The [LZO1F](lzo1f) decoder.
+    pub lzo1f: bool,
+    /// The [LZO1Y](lzo1y) variant.
+    pub lzo1y: bool,
+    /// The [LZO1Z](lzo1z) variant.
+    pub lzo1z: bool,
+    /// The [LZO2A](lzo2a) format.
+    pub lzo2a: bool,
+    /// Compile-time decompression, from feature `const-decompress`.
+    pub const_decompress: bool,
+    /// Traced decompression, from feature `trace`.
+    pub trace: bool,
+    /// Global counters, from feature `stats`.
+    pub stats: bool,
+    /// lzop files, from feature `lzop`.
+    pub lzop: bool,
+    /// The original C++ implementation, from feature `cpp-backend`.
+    pub cpp_backend: bool,
+    /// Built without unsafe code, from feature `forbid-unsafe`.
+    pub forbid_unsafe: bool,
+    /// The [`include_lzo!`] macro, from feature `macros`.
+    pub macros: bool,
+    /// The [C API](capi), from feature `capi`.
+    pub capi: bool,
+    /// The [raw bindings](sys) to the C++ implementation, from feature `sys`.
+    pub sys: bool,
+    /// The [JavaScript bindings](wasm), from feature `wasm`.
+    pub wasm: bool,
+    /// The [Swift and Kotlin bindings](mobile), from feature `uniffi`.
+    pub uniffi: bool,
+    /// The [Java bindings](java), from feature `jni`.
+    pub jni: bool,
+    /// The [Python extension module](python), from feature `python`.
+    pub python: bool,
+    /// numpy support in the Python module, from feature `numpy`.
+    pub numpy: bool,
+    /// The Python module built against the stable ABI, from feature `abi3`.
+    pub abi3: bool,
+    /// The command-line tool, from feature `cli`.
+    pub cli: bool,
+    /// `Arbitrary` [valid streams](arbitrary), from feature `arbitrary`.
+    pub arbitrary: bool,
+    /// [Page compression](page), from feature `page`.
+    pub page: bool,
+    /// Compression into fixed-size arrays, from feature `array`.
+    pub array: bool,
+    /// Compression on several threads, from feature `parallel`.
+    pub parallel: bool,
+    /// The [reference streams](test_support), from feature `test-support`.
+    pub test_support: bool,
+    /// `Zeroize` implementations, from feature `zeroize`.
+    pub zeroize: bool,
+}
+
+impl Features {
+    /// Every flag with its name, matching the Cargo feature names, for reporting. Lists every
+    /// feature but the ones for building and testing this crate, in field order.
+    ///
+    /// ```
+    /// let enabled: Vec<&str> = lzokay::features().flags().filter(|f| f.1).map(|f| f.0).collect();
+    /// assert_eq!(enabled.contains(&"decompress"), cfg!(feature = "decompress"));
+    /// ```
+    pub fn flags(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        FLAGS.iter().map(move |&(name, flag)| (name, flag(self)))
+    }
+}
+
+/// Reads one flag of [`Features`].
+type Flag = fn(&Features) -> bool;
+
+/// The name of each [`Features`] flag, matching the Cargo feature names, in field order.
+const FLAGS: &[(&str, Flag)] = &[
+    ("compress", |f| f.compress),
+    ("decompress", |f| f.decompress),
+    ("alloc", |f| f.alloc),
+    ("std", |f| f.std),
+    ("lzo1b", |f| f.lzo1b),
+    ("lzo1f", |f| f.lzo1f),
+    ("lzo1y", |f| f.lzo1y),
+    ("lzo1z", |f| f.lzo1z),
+    ("lzo2a", |f| f.lzo2a),
+    ("const-decompress", |f| f.const_decompress),
+    ("trace", |f| f.trace),
+    ("stats", |f| f.stats),
+    ("lzop", |f| f.lzop),
+    ("cpp-backend", |f| f.cpp_backend),
+    ("forbid-unsafe", |f| f.forbid_unsafe),
+    ("macros", |f| f.macros),
+    ("capi", |f| f.capi),
+    ("sys", |f| f.sys),
+    ("wasm", |f| f.wasm),
+    ("uniffi", |f| f.uniffi),
+    ("jni", |f| f.jni),
+    ("python", |f| f.python),
+    ("numpy", |f| f.numpy),
+    ("abi3", |f| f.abi3),
+    ("cli", |f| f.cli),
+    ("arbitrary", |f| f.arbitrary),
+    ("page", |f| f.page),
+    ("array", |f| f.array),
+    ("parallel", |f| f.parallel),
+    ("test-support", |f| f.test_support),
+    ("zeroize", |f| f.zeroize),
+];
+
+/// Returns the codecs and optional subsystems compiled into this build, so applications and
+/// bindings can report capabilities and choose code paths at runtime.
+pub const fn features() -> Features {
+    const CODEC: bool = cfg!(any(feature = "compress", feature = "decompress"));
+    Features {
+        compress: cfg!(feature = "compress"),
+        decompress: cfg!(feature = "decompress"),
+        alloc: cfg!(feature = "alloc"),
+        std: cfg!(feature = "std"),
+        lzo1b: cfg!(all(feature = "lzo1b", feature = "decompress")),
+        lzo1f: cfg!(all(feature = "lzo1f", feature = "decompress")),
+        lzo1y: cfg!(feature = "lzo1y") && CODEC,
+        lzo1z: cfg!(feature = "lzo1z") && CODEC,
+        lzo2a: cfg!(feature = "lzo2a") && CODEC,
+        const_decompress: cfg!(feature = "const-decompress"),
+        trace: cfg!(feature = "trace"),
+        stats: cfg!(feature = "stats") && CODEC,
+        lzop: cfg!(feature = "lzop"),
+        cpp_backend: cfg!(feature = "cpp-backend"),
+        forbid_unsafe: cfg!(feature = "forbid-unsafe"),
+        macros: cfg!(feature = "macros"),
+        capi: cfg!(feature = "capi"),
+        sys: cfg!(feature = "sys"),
+        wasm: cfg!(feature = "wasm"),
+        uniffi: cfg!(feature = "uniffi"),
+        jni: cfg!(feature = "jni"),
+        python: cfg!(feature = "python"),
+        numpy: cfg!(feature = "numpy"),
+        abi3: cfg!(feature = "abi3"),
+        cli: cfg!(feature = "cli"),
+        arbitrary: cfg!(feature = "arbitrary"),
+        page: cfg!(feature = "page"),
+        array: cfg!(feature = "array"),
+        parallel: cfg!(feature = "parallel"),
+        test_support: cfg!(feature = "test-support"),
+        zeroize: cfg!(feature = "zeroize"),
+    }
+}
+
+impl Error {
+    /// Returns the equivalent liblzo2 `LZO_E_*` error code.
+    ///
+    /// liblzo2 can't be cancelled or verify its output, so [`Error::Cancelled`] and
+    /// [`Error::VerificationFailed`] map to `LZO_E_ERROR` (-1) like [`Error::Error`], and
+    /// [`from_lzo_code`](Error::from_lzo_code) converts them back as [`Error::Error`].
+    pub const fn to_lzo_code(&self) -> i32 {
+        match self {
+            Error::LookbehindOverrun => -6,
+            Error::OutputOverrun => -5,
+            Error::InputOverrun => -4,
+            Error::Error => -1,
+            Error::InputNotConsumed => -8,
+            Error::Cancelled => -1,
+            Error::VerificationFailed => -1,
+        }
+    }
+
+    /// Converts a liblzo2 `LZO_E_*` error code, returning `None` for `LZO_E_OK` (0).
+    ///
+    /// The mapping is lossy, so not every code round trips through
+    /// [`to_lzo_code`](Error::to_lzo_code):
+    ///
+    /// - `LZO_E_INPUT_OVERRUN` (-4) and `LZO_E_EOF_NOT_FOUND` (-7) both map to
+    ///   [`Error::InputOverrun`], as this crate's decompressors report a stream without an end
+    ///   marker as running out of input. It converts back to -4.
+    /// - `LZO_E_OUTPUT_OVERRUN` (-5), `LZO_E_LOOKBEHIND_OVERRUN` (-6) and
+    ///   `LZO_E_INPUT_NOT_CONSUMED` (-8) map to the variants of the same name, and back.
+    /// - Every other code, such as `LZO_E_OUT_OF_MEMORY` (-2), `LZO_E_NOT_COMPRESSIBLE` (-3) or
+    ///   `LZO_E_NOT_YET_IMPLEMENTED` (-9), maps to [`Error::Error`], which converts back to
+    ///   `LZO_E_ERROR` (-1).
+    pub const fn from_lzo_code(code: i32) -> Option<Error> {
+        match code {
+            0 => None,
+            -4 | -7 => Some(Error::InputOverrun),
+            -5 => Some(Error::OutputOverrun),
+            -6 => Some(Error::LookbehindOverrun),
+            -8 => Some(Error::InputNotConsumed),
+            _ => Some(Error::Error),
+        }
     }
 }
 
 #[cfg(test)]
-#[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
 mod tests {
-    #[cfg(not(feature = "std"))]
+    #[cfg(all(not(feature = "std"), feature = "alloc"))]
     extern crate alloc;
 
-    #[cfg(not(feature = "std"))]
+    #[cfg(all(
+        not(feature = "std"),
+        feature = "alloc",
+        feature = "compress",
+        feature = "decompress"
+    ))]
     use alloc::vec;
 
-    use super::{compress::compress, decompress::decompress};
+    use super::Error;
 
+    #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
     const INPUT: &[u8] = include_bytes!("test1.txt");
 
     #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
     fn test_round_trip() {
+        use super::{compress::compress, decompress::decompress};
+
         let compressed = compress(INPUT).expect("Failed to compress");
         let mut dst = vec![0u8; INPUT.len()];
         decompress(&compressed, &mut dst).expect("Failed to decompress");
         assert_eq!(INPUT, dst.as_slice());
     }
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", feature = "std"))]
+    fn test_verify_roundtrip() {
+        let report = super::verify_roundtrip(INPUT).expect("Failed to round trip");
+        assert_eq!(report.input_len, INPUT.len());
+        assert!(report.compressed_len < INPUT.len());
+        assert!(report.ratio() > 0.0 && report.ratio() < 1.0);
+        let report = super::verify_roundtrip(vec![]).expect("Failed to round trip");
+        assert_eq!((report.compressed_len, report.ratio()), (3, 1.0));
+    }
+
+    #[test]
+    fn test_lzo_code() {
+        for err in [
+            Error::LookbehindOverrun,
+            Error::OutputOverrun,
+            Error::InputOverrun,
+            Error::Error,
+            Error::InputNotConsumed,
+        ]
+        .iter()
+        {
+            assert_eq!(Error::from_lzo_code(err.to_lzo_code()).as_ref(), Some(err));
+        }
+        assert_eq!(Error::Cancelled.to_lzo_code(), Error::Error.to_lzo_code());
+        assert_eq!(Error::VerificationFailed.to_lzo_code(), Error::Error.to_lzo_code());
+        assert_eq!(Error::from_lzo_code(0), None);
+
+        // Every liblzo2 code, and the code each converts back to
+        let codes = [
+            (-1, Error::Error, -1),
+            (-2, Error::Error, -1),
+            (-3, Error::Error, -1),
+            (-4, Error::InputOverrun, -4),
+            (-5, Error::OutputOverrun, -5),
+            (-6, Error::LookbehindOverrun, -6),
+            (-7, Error::InputOverrun, -4),
+            (-8, Error::InputNotConsumed, -8),
+            (-9, Error::Error, -1),
+            (-10, Error::Error, -1),
+            (-11, Error::Error, -1),
+            (-12, Error::Error, -1),
+            (-99, Error::Error, -1),
+        ];
+        for &(code, err, back) in codes.iter() {
+            assert_eq!(Error::from_lzo_code(code), Some(err), "{}", code);
+            assert_eq!(err.to_lzo_code(), back, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_features() {
+        let features = super::features();
+        assert_eq!(features.compress, cfg!(feature = "compress"));
+        assert_eq!(features.std, cfg!(feature = "std"));
+        assert_eq!(features.trace, cfg!(feature = "trace"));
+        assert_eq!(features.parallel, cfg!(feature = "parallel"));
+        assert_eq!(features.flags().find(|f| f.0 == "lzo1y"), Some(("lzo1y", features.lzo1y)));
+        assert_eq!(
+            features.flags().find(|f| f.0 == "cpp-backend").unwrap().1,
+            features.cpp_backend
+        );
+        assert_eq!(features.flags().last(), Some(("zeroize", features.zeroize)));
+    }
 }
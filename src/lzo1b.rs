@@ -0,0 +1,178 @@
+//! # LZO1B routines
+//!
+//! Available with features `lzo1b` and `decompress`.
+//!
+//! LZO1B is an early LZO format, superseded by LZO1X but still found in old archives. Only
+//! decompression is supported. Its instructions are:
+//!
+//! - `0 0 0 L L L L L` (1..31): a literal run of L bytes.
+//! - `0 0 0 0 0 0 0 0` (0): a longer literal run, followed by one byte N:
+//!   32 + N (32..279) bytes when N < 248, 280 bytes when N == 248,
+//!   and 256 << (N - 248) (512..32768) bytes otherwise.
+//!   A match must follow a literal run, except after one of 280 bytes or more.
+//! - `0 0 0 D D D D D` (0..31): after a literal run or another instruction of this kind,
+//!   copy 3 bytes, then one literal byte.
+//! - `L L L D D D D D` (64..255): copy L + 1 (3..8) bytes.
+//!   - Both are followed by exactly one byte : H H H H H H H H,
+//!     distance = (H << 5) + D + 1.
+//! - `0 0 1 L L L L L` (32..63): copy L + 2 bytes, or
+//!   33 + (zero_bytes * 255) + non_zero_byte bytes when L == 0.
+//!   - Always followed by exactly one LE16 distance.
+//!     End of stream is reached if it's 0.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::lzo1b;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 10] = [4, b'a', b'b', b'c', b'd', 0xe3, 0, 0x21, 0, 0];
+//!
+//! let mut dst = [0u8; 12];
+//! let size = lzo1b::decompress(&input, &mut dst)?;
+//! # assert_eq!(&dst[..size], b"abcdabcdabcd");
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+use crate::{
+    decompress::{consume_zero_byte_length, copy_literal, copy_lookbehind, needs_in},
+    Error,
+};
+
+/// Instructions below this are literal runs, or R1 matches after a literal run.
+const R0MIN: u8 = 32;
+/// Second byte of a long literal run from which it's 280 bytes or more.
+const R0FAST: u8 = 248;
+const M2_MARKER: u8 = 64;
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut inp = 0usize;
+    let mut outp = 0usize;
+    loop {
+        needs_in(src, inp, 1)?;
+        let mut inst = src[inp];
+        inp += 1;
+        if inst < R0MIN {
+            let mut len = inst as usize;
+            if len == 0 {
+                // [R0]
+                needs_in(src, inp, 1)?;
+                let n = src[inp];
+                inp += 1;
+                if n >= R0FAST {
+                    // Not necessarily followed by a match
+                    len = match n - R0FAST {
+                        0 => 280,
+                        shift => 256 << shift,
+                    };
+                    copy_literal(src, &mut inp, dst, &mut outp, len)?;
+                    continue;
+                }
+                len = n as usize + R0MIN as usize;
+            }
+            copy_literal(src, &mut inp, dst, &mut outp, len)?;
+            // A match must follow, and R1 matches may repeat
+            loop {
+                needs_in(src, inp, 1)?;
+                inst = src[inp];
+                inp += 1;
+                if inst >= R0MIN {
+                    break;
+                }
+                // [R1]
+                needs_in(src, inp, 2)?;
+                let dist = ((src[inp] as usize) << 5) + inst as usize + 1;
+                inp += 1;
+                copy_lookbehind(dst, &mut outp, dist, 3)?;
+                copy_literal(src, &mut inp, dst, &mut outp, 1)?;
+            }
+        }
+        if inst >= M2_MARKER {
+            // [M2]
+            needs_in(src, inp, 1)?;
+            let dist = ((src[inp] as usize) << 5) + (inst & 0x1f) as usize + 1;
+            inp += 1;
+            copy_lookbehind(dst, &mut outp, dist, (inst as usize >> 5) + 1)?;
+        } else {
+            // [M3] and [M4]
+            let mut len = (inst & 0x1f) as usize;
+            if len == 0 {
+                let offset = consume_zero_byte_length(src, &mut inp, dst.len() - outp)?;
+                needs_in(src, inp, 1)?;
+                len = offset * 255 + 31 + src[inp] as usize;
+                inp += 1;
+            }
+            needs_in(src, inp, 2)?;
+            let dist = src[inp] as usize + ((src[inp + 1] as usize) << 8);
+            inp += 2;
+            if dist == 0 {
+                // Stream finished
+                break;
+            }
+            copy_lookbehind(dst, &mut outp, dist, len + 2)?;
+        }
+    }
+    if inp == src.len() {
+        Ok(outp)
+    } else {
+        Err(Error::InputNotConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lzo1b::decompress, Error};
+
+    /// "abcd", then an M2 match of 8 bytes at distance 4.
+    const M2_STREAM: &[u8] = &[4, b'a', b'b', b'c', b'd', 0xe3, 0x00, 0x21, 0, 0];
+    /// "abc", then an R1 match of 3 bytes at distance 3 followed by "x", then an M3 match of 7
+    /// bytes at distance 7.
+    const R1_STREAM: &[u8] = &[3, b'a', b'b', b'c', 0x02, 0x00, b'x', 0x25, 7, 0, 0x21, 0, 0];
+
+    #[test]
+    fn test_decompress() {
+        let mut dst = [0u8; 14];
+        let size = decompress(M2_STREAM, &mut dst).expect("Failed to decompress (1)");
+        assert_eq!(&dst[..size], b"abcdabcdabcd");
+        let size = decompress(R1_STREAM, &mut dst).expect("Failed to decompress (2)");
+        assert_eq!(&dst[..size], b"abcabcxabcabcx");
+        assert_eq!(decompress(&[0x21, 0, 0], &mut dst), Ok(0));
+
+        // Long literal runs, the longest of which may be followed by another literal run
+        let mut dst = [0u8; 32769];
+        for &(n, len) in &[(0u8, 32usize), (247, 279), (248, 280), (249, 512), (255, 32768)] {
+            let mut stream = [0xaau8; 2 + 32768 + 5];
+            stream[0] = 0;
+            stream[1] = n;
+            let end: &[u8] = if n >= 248 { &[1, 0xbb, 0x21, 0, 0] } else { &[0x21, 0, 0] };
+            stream[2 + len..2 + len + end.len()].copy_from_slice(end);
+            let stream = &stream[..2 + len + end.len()];
+            let size = decompress(stream, &mut dst).expect("Failed to decompress (3)");
+            assert!(dst[..len].iter().all(|&b| b == 0xaa));
+            assert_eq!(size, if n >= 248 { len + 1 } else { len });
+        }
+
+        // An M4 match of 33 + 255 + 2 bytes
+        let stream = [1, b'a', 0x20, 0, 2, 1, 0, 0x21, 0, 0];
+        let mut dst = [0u8; 291];
+        assert_eq!(decompress(&stream, &mut dst), Ok(291));
+        assert!(dst.iter().all(|&b| b == b'a'));
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let mut dst = [0u8; 14];
+        assert_eq!(decompress(&M2_STREAM[..9], &mut dst), Err(Error::InputOverrun));
+        assert_eq!(decompress(M2_STREAM, &mut dst[..11]), Err(Error::OutputOverrun));
+        assert_eq!(decompress(&R1_STREAM[..6], &mut dst), Err(Error::InputOverrun));
+        // M2 match reaching before the start of the output
+        let lookbehind = [4, 1, 2, 3, 4, 0xe3, 0xff, 0x21, 0, 0];
+        assert_eq!(decompress(&lookbehind, &mut dst), Err(Error::LookbehindOverrun));
+        // A literal run followed directly by another
+        let literals = [1, b'a', 1, b'b', 0x21, 0, 0];
+        assert_eq!(decompress(&literals, &mut dst), Err(Error::LookbehindOverrun));
+        let trailing = [4, b'a', b'b', b'c', b'd', 0xe3, 0x00, 0x21, 0, 0, 0];
+        assert_eq!(decompress(&trailing, &mut dst), Err(Error::InputNotConsumed));
+    }
+}
@@ -0,0 +1,145 @@
+//! # LZO1F routines
+//!
+//! Available with features `lzo1f` and `decompress`.
+//!
+//! LZO1F is an older member of the LZO family that still turns up in archived data. Only
+//! decompression is supported. The instruction set is a simpler version of LZO1X:
+//!
+//! - `0 0 0 L L L L L` (0..31): a literal run of L bytes, or
+//!   31 + (zero_bytes * 255) + non_zero_byte bytes when L == 0.
+//!   Only valid when the previous instruction did not copy any trailing literals.
+//! - `0 0 0 D D D S S` (0..31): after a literal copy, copy 3 bytes from 2..4 kB distance.
+//! - `L L L D D D S S` (32..223): copy L + 2 (3..8) bytes from within 2 kB distance.
+//!   - Both are followed by exactly one byte : H H H H H H H H,
+//!     distance = (H << 3) + D + 1 (+ 2048 for the 3-byte copy).
+//! - `1 1 1 L L L L L` (224..255): copy L + 2 bytes, or
+//!   33 + (zero_bytes * 255) + non_zero_byte bytes when L == 0.
+//!   - Always followed by exactly one LE16 : D D D D D D S S : D D D D D D D D.
+//!     End of stream is reached if D == 0.
+//!
+//! In each match, S is the number of literals (0..3) copied after it.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::lzo1f;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 10] = [4, b'a', b'b', b'c', b'd', 0xcc, 0, 0xe1, 0, 0];
+//!
+//! let mut dst = [0u8; 12];
+//! let size = lzo1f::decompress(&input, &mut dst)?;
+//! # assert_eq!(&dst[..size], b"abcdabcdabcd");
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+use crate::{
+    decompress::{consume_zero_byte_length, copy_literal, copy_lookbehind, needs_in},
+    Error,
+};
+
+const M2_MAX_OFFSET: usize = 0x0800;
+const M3_MARKER: u8 = 0xe0;
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut inp = 0usize;
+    let mut outp = 0usize;
+    'stream: loop {
+        needs_in(src, inp, 1)?;
+        let mut inst = src[inp];
+        inp += 1;
+        if inst < 32 {
+            // A literal run, which is always followed by a match
+            let mut len = inst as usize;
+            if len == 0 {
+                let offset = consume_zero_byte_length(src, &mut inp, dst.len() - outp)?;
+                needs_in(src, inp, 1)?;
+                len = offset * 255 + 31 + src[inp] as usize;
+                inp += 1;
+            }
+            copy_literal(src, &mut inp, dst, &mut outp, len)?;
+            needs_in(src, inp, 1)?;
+            inst = src[inp];
+            inp += 1;
+        }
+        loop {
+            let lbdist;
+            let lblen;
+            if inst < M3_MARKER {
+                // [M1] and [M2]
+                needs_in(src, inp, 1)?;
+                let dist = ((src[inp] as usize) << 3) + ((inst as usize >> 2) & 0x7) + 1;
+                inp += 1;
+                if inst < 32 {
+                    lbdist = dist + M2_MAX_OFFSET;
+                    lblen = 3;
+                } else {
+                    lbdist = dist;
+                    lblen = (inst as usize >> 5) + 2;
+                }
+            } else {
+                // [M3]
+                let mut len = (inst & 0x1f) as usize;
+                if len == 0 {
+                    let offset = consume_zero_byte_length(src, &mut inp, dst.len() - outp)?;
+                    needs_in(src, inp, 1)?;
+                    len = offset * 255 + 31 + src[inp] as usize;
+                    inp += 1;
+                }
+                needs_in(src, inp, 2)?;
+                lbdist = (src[inp] as usize >> 2) + ((src[inp + 1] as usize) << 6);
+                inp += 2;
+                if lbdist == 0 {
+                    // Stream finished
+                    break 'stream;
+                }
+                lblen = len + 2;
+            }
+            copy_lookbehind(dst, &mut outp, lbdist, lblen)?;
+            // The literal count is stored in the byte two positions back in every encoding
+            let state = (src[inp - 2] & 0x3) as usize;
+            if state == 0 {
+                break;
+            }
+            copy_literal(src, &mut inp, dst, &mut outp, state)?;
+            needs_in(src, inp, 1)?;
+            inst = src[inp];
+            inp += 1;
+        }
+    }
+    if inp == src.len() {
+        Ok(outp)
+    } else {
+        Err(Error::InputNotConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lzo1f::decompress, Error};
+
+    /// "abcd", then an M2 match of 8 bytes at distance 4.
+    const M2_STREAM: &[u8] = &[4, b'a', b'b', b'c', b'd', 0xcc, 0x00, 0xe1, 0, 0];
+    /// "abcd", then an M3 match of 8 bytes at distance 4 followed by one literal.
+    const M3_STREAM: &[u8] = &[4, b'a', b'b', b'c', b'd', 0xe6, 0x11, 0x00, b'x', 0xe1, 0, 0];
+
+    #[test]
+    fn test_decompress() {
+        let mut dst = [0u8; 13];
+        let size = decompress(M2_STREAM, &mut dst).expect("Failed to decompress (1)");
+        assert_eq!(&dst[..size], b"abcdabcdabcd");
+        let size = decompress(M3_STREAM, &mut dst).expect("Failed to decompress (2)");
+        assert_eq!(&dst[..size], b"abcdabcdabcdx");
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let mut dst = [0u8; 13];
+        assert_eq!(decompress(&M2_STREAM[..9], &mut dst), Err(Error::InputOverrun));
+        assert_eq!(decompress(M2_STREAM, &mut dst[..11]), Err(Error::OutputOverrun));
+        // M2 match reaching before the start of the output
+        let lookbehind = [4, 1, 2, 3, 4, 0xcc, 0xff, 0xe1, 0, 0];
+        assert_eq!(decompress(&lookbehind, &mut dst), Err(Error::LookbehindOverrun));
+    }
+}
@@ -0,0 +1,116 @@
+//! # LZO1Y routines
+//!
+//! Available with feature `lzo1y`.
+//!
+//! LZO1Y shares the LZO1X instruction set, but trades M2 match distance (1 kB instead of 2 kB)
+//! for longer M2 matches (up to 14 bytes). Streams of the two formats are not interchangeable.
+//!
+//! Compression routines require feature `compress`, and share their dictionary type with the
+//! [`compress`](crate::compress) module. Decompression routines require feature `decompress`.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))] {
+//! use lzokay::lzo1y;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! let compressed = lzo1y::compress(&input)?;
+//! let mut dst = [0u8; 512];
+//! let size = lzo1y::decompress(&compressed, &mut dst)?;
+//! # assert_eq!(size, input.len());
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+extern crate alloc;
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "compress")]
+pub use crate::compress::compress_worst_size;
+#[cfg(feature = "compress")]
+use crate::compress::{compress_impl, Dict};
+#[cfg(all(feature = "compress", feature = "alloc"))]
+use crate::compress::{compress_vec_impl, new_dict};
+#[cfg(feature = "decompress")]
+use crate::decompress::decompress_impl;
+use crate::{variant::Lzo1y, Error};
+
+/// Compress the supplied buffer into a heap-allocated vector.
+///
+/// Creates a new dictionary for each invocation.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress(src: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    compress_with_dict(src, &mut new_dict())
+}
+
+/// Compress the supplied buffer into a heap-allocated vector,
+/// with the supplied pre-allocated dictionary.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress_with_dict(src: impl AsRef<[u8]>, dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    compress_vec_impl::<Lzo1y>(src.as_ref(), dict)
+}
+
+/// Compress the supplied buffer.
+///
+/// For sizing `dst`, use [`compress_worst_size`].
+#[cfg(feature = "compress")]
+pub fn compress_no_alloc(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+    compress_impl::<Lzo1y>(src, dst, dict)
+}
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+#[cfg(feature = "decompress")]
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    decompress_impl::<Lzo1y>(src, dst)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "decompress")]
+    use crate::Error;
+
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+
+    /// "abcd", then an M2 match of 8 bytes at distance 4, which is out of bounds in LZO1X.
+    #[cfg(feature = "decompress")]
+    const M2_STREAM: &[u8] = &[0x15, b'a', b'b', b'c', b'd', 0x9c, 0x00, 0x11, 0, 0];
+
+    #[test]
+    #[cfg(feature = "decompress")]
+    fn test_decompress_m2() {
+        let mut dst = [0u8; 12];
+        let size = super::decompress(M2_STREAM, &mut dst).expect("Failed to decompress");
+        assert_eq!(&dst[..size], b"abcdabcdabcd");
+        assert_eq!(
+            crate::decompress::decompress(M2_STREAM, &mut dst),
+            Err(Error::LookbehindOverrun)
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    fn test_round_trip() {
+        use crate::compress::{dict_from_storage, dict_storage_size};
+
+        let mut storage = [0u8; dict_storage_size()];
+        let mut dict = dict_from_storage(&mut storage);
+        let mut compressed = [0u8; super::compress_worst_size(INPUT_1.len())];
+        let mut dst = [0u8; INPUT_1.len()];
+        for input in [INPUT_1, INPUT_2].iter() {
+            let size = super::compress_no_alloc(input, &mut compressed, &mut dict)
+                .expect("Failed to compress");
+            let size =
+                super::decompress(&compressed[..size], &mut dst).expect("Failed to decompress");
+            assert_eq!(&dst[..size], *input);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! # LZO1Z routines
+//!
+//! Available with feature `lzo1z`.
+//!
+//! LZO1Z shares the LZO1X instruction set, but stores match distances most-significant bits
+//! first, limits M2 matches to a 1792-byte window, and lets M2 matches reuse the previous
+//! match distance. Streams of the two formats are not interchangeable.
+//!
+//! Compression routines require feature `compress`, and share their dictionary type with the
+//! [`compress`](crate::compress) module. Decompression routines require feature `decompress`.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))] {
+//! use lzokay::lzo1z;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! let compressed = lzo1z::compress(&input)?;
+//! let mut dst = [0u8; 512];
+//! let size = lzo1z::decompress(&compressed, &mut dst)?;
+//! # assert_eq!(size, input.len());
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+extern crate alloc;
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "compress")]
+pub use crate::compress::compress_worst_size;
+#[cfg(feature = "compress")]
+use crate::compress::{compress_impl, Dict};
+#[cfg(all(feature = "compress", feature = "alloc"))]
+use crate::compress::{compress_vec_impl, new_dict};
+#[cfg(feature = "decompress")]
+use crate::decompress::decompress_impl;
+use crate::{variant::Lzo1z, Error};
+
+/// Compress the supplied buffer into a heap-allocated vector.
+///
+/// Creates a new dictionary for each invocation.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress(src: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    compress_with_dict(src, &mut new_dict())
+}
+
+/// Compress the supplied buffer into a heap-allocated vector,
+/// with the supplied pre-allocated dictionary.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress_with_dict(src: impl AsRef<[u8]>, dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    compress_vec_impl::<Lzo1z>(src.as_ref(), dict)
+}
+
+/// Compress the supplied buffer.
+///
+/// For sizing `dst`, use [`compress_worst_size`].
+#[cfg(feature = "compress")]
+pub fn compress_no_alloc(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+    compress_impl::<Lzo1z>(src, dst, dict)
+}
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+#[cfg(feature = "decompress")]
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    decompress_impl::<Lzo1z>(src, dst)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "decompress")]
+    use crate::Error;
+
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+
+    /// "abcd", an M2 match of 4 bytes at distance 4 followed by one literal, then an M2 match
+    /// reusing the previous distance.
+    #[cfg(feature = "decompress")]
+    const M2_STREAM: &[u8] = &[0x15, b'a', b'b', b'c', b'd', 0x60, 0x0d, b'x', 0x5c, 0x11, 0, 0];
+
+    #[test]
+    #[cfg(feature = "decompress")]
+    fn test_decompress_m2() {
+        let mut dst = [0u8; 12];
+        let size = super::decompress(M2_STREAM, &mut dst).expect("Failed to decompress");
+        assert_eq!(&dst[..size], b"abcdabcdxbcd");
+        assert_eq!(
+            crate::decompress::decompress(M2_STREAM, &mut dst),
+            Err(Error::LookbehindOverrun)
+        );
+        // Distance reuse before any match
+        let reuse = [0x15, b'a', b'b', b'c', b'd', 0x5c, 0x11, 0, 0];
+        assert_eq!(super::decompress(&reuse, &mut dst), Err(Error::LookbehindOverrun));
+    }
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    fn test_round_trip() {
+        use crate::compress::{dict_from_storage, dict_storage_size};
+
+        let mut storage = [0u8; dict_storage_size()];
+        let mut dict = dict_from_storage(&mut storage);
+        let mut compressed = [0u8; super::compress_worst_size(INPUT_1.len())];
+        let mut dst = [0u8; INPUT_1.len()];
+        for input in [INPUT_1, INPUT_2].iter() {
+            let size = super::compress_no_alloc(input, &mut compressed, &mut dict)
+                .expect("Failed to compress");
+            let size =
+                super::decompress(&compressed[..size], &mut dst).expect("Failed to decompress");
+            assert_eq!(&dst[..size], *input);
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! # LZO2A routines
+//!
+//! Available with feature `lzo2a`.
+//!
+//! LZO2A does not share the LZO1X instruction set. Each token is introduced by one or two flag
+//! bits, packed least-significant bit first into bytes interleaved with the token data:
+//!
+//! - `0`: a literal byte follows.
+//! - `1 0 L L`: copy 2 + L (2..5) bytes from within 256 bytes.
+//!   Always followed by exactly one byte : distance - 1.
+//! - `1 1`: always followed by two bytes : `L L L D D D D D : D D D D D D D D`
+//!   - L != 0: copy L + 2 (3..9) bytes from within 8 kB, at distance D.
+//!     End of stream is reached if D == 0.
+//!   - L == 0: followed by one flag bit M, then a length of
+//!     (M ? 2 : 9) + (zero_bytes * 255) + non_zero_byte bytes, at distance D + M * 8192.
+//!
+//! Compression routines require feature `compress`, and share their dictionary type with the
+//! [`compress`](crate::compress) module. Decompression routines require feature `decompress`.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))] {
+//! use lzokay::lzo2a;
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! let compressed = lzo2a::compress(&input)?;
+//! let mut dst = [0u8; 512];
+//! let size = lzo2a::decompress(&compressed, &mut dst)?;
+//! # assert_eq!(size, input.len());
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+extern crate alloc;
+
+#[cfg(all(not(feature = "std"), feature = "alloc", feature = "compress"))]
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "compress")]
+use core::cmp::min;
+
+#[cfg(all(feature = "compress", feature = "alloc"))]
+use crate::compress::new_dict;
+#[cfg(feature = "compress")]
+use crate::compress::{Dict, MAX_MATCH_BY_LENGTH_LEN};
+#[cfg(feature = "decompress")]
+use crate::decompress::{consume_zero_byte_length, copy_lookbehind, needs_in, needs_out};
+use crate::Error;
+
+#[cfg(feature = "compress")]
+const M1_MAX_LEN: u32 = 5;
+#[cfg(feature = "compress")]
+const M1_MAX_OFFSET: u32 = 0x100;
+#[cfg(feature = "compress")]
+const M2_MAX_LEN: u32 = 9;
+#[cfg(feature = "compress")]
+const M2_MAX_OFFSET: u32 = 0x1fff;
+#[cfg(feature = "compress")]
+const M3_MAX_OFFSET: u32 = 0x3fff;
+
+/// Worst-case compression size.
+#[cfg(feature = "compress")]
+pub const fn compress_worst_size(s: usize) -> usize { s + s / 8 + 8 }
+
+/// Compress the supplied buffer into a heap-allocated vector.
+///
+/// Creates a new dictionary for each invocation.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress(src: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    compress_with_dict(src, &mut new_dict())
+}
+
+/// Compress the supplied buffer into a heap-allocated vector,
+/// with the supplied pre-allocated dictionary.
+#[cfg(all(feature = "compress", feature = "alloc"))]
+pub fn compress_with_dict(src: impl AsRef<[u8]>, dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    let src = src.as_ref();
+    let mut dst = vec![0u8; compress_worst_size(src.len())];
+    let size = compress_no_alloc(src, &mut dst, dict)?;
+    dst.truncate(size);
+    Ok(dst)
+}
+
+/// Bit and byte output, reserving a byte for the bit buffer whenever it is started.
+#[cfg(feature = "compress")]
+struct Writer<'a> {
+    dst: &'a mut [u8],
+    op: usize,
+    bitp: usize,
+    b: u32,
+    k: u32,
+}
+
+#[cfg(feature = "compress")]
+impl Writer<'_> {
+    fn put_byte(&mut self, v: u8) -> Result<(), Error> {
+        if self.op >= self.dst.len() {
+            return Err(Error::OutputOverrun);
+        }
+        self.dst[self.op] = v;
+        self.op += 1;
+        Ok(())
+    }
+
+    fn put_bits(&mut self, j: u32, x: u32) -> Result<(), Error> {
+        if self.k == 0 {
+            self.bitp = self.op;
+            self.put_byte(0)?;
+        }
+        self.b |= x << self.k;
+        self.k += j;
+        if self.k >= 8 {
+            self.dst[self.bitp] = self.b as u8;
+            self.b >>= 8;
+            self.k -= 8;
+            if self.k > 0 {
+                self.bitp = self.op;
+                self.put_byte(0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_length(&mut self, mut len: u32) -> Result<(), Error> {
+        while len > 255 {
+            self.put_byte(0)?;
+            len -= 255;
+        }
+        self.put_byte(len as u8)
+    }
+
+    fn put_distance(&mut self, l: u32, off: u32) -> Result<(), Error> {
+        self.put_bits(2, 0x3)?;
+        self.put_byte((l << 5 | (off & 0x1f)) as u8)?;
+        self.put_byte((off >> 5) as u8)
+    }
+
+    fn flush(&mut self) {
+        if self.k > 0 {
+            self.dst[self.bitp] = self.b as u8;
+        }
+    }
+}
+
+/// Compress the supplied buffer.
+///
+/// For sizing `dst`, use [`compress_worst_size`].
+#[cfg(feature = "compress")]
+pub fn compress_no_alloc(src: &[u8], dst: &mut [u8], dict: &mut Dict) -> Result<usize, Error> {
+    let (search, seed) = (dict.search(), dict.seed());
+    let d = dict.storage();
+    let mut w = Writer { dst, op: 0, bitp: 0, b: 0, k: 0 };
+    let mut lb_off = 0u32;
+    let mut lb_len = 0u32;
+    let mut best_off = [0u32; MAX_MATCH_BY_LENGTH_LEN];
+    let mut s = d.init(src, seed);
+    d.advance(&mut s, &mut lb_off, &mut lb_len, &mut best_off, false, search);
+    while s.buf_sz > 0 {
+        if lb_len >= 2 && lb_off > M3_MAX_OFFSET {
+            // Out of range: fall back to the longest shorter match that is in range
+            let max_len = min(lb_len as usize, MAX_MATCH_BY_LENGTH_LEN - 1);
+            let found = (2..=max_len).rev().find(|&len| {
+                let off = best_off[len];
+                off != 0 && off <= if len == 2 { M1_MAX_OFFSET } else { M3_MAX_OFFSET }
+            });
+            match found {
+                Some(len) => {
+                    lb_len = len as u32;
+                    lb_off = best_off[len];
+                }
+                None => lb_len = 0,
+            }
+        }
+        if lb_len < 2 || (lb_len == 2 && lb_off > M1_MAX_OFFSET) {
+            w.put_bits(1, 0)?;
+            w.put_byte(src[s.bufp])?;
+            d.advance(&mut s, &mut lb_off, &mut lb_len, &mut best_off, false, search);
+            continue;
+        }
+        if lb_len <= M1_MAX_LEN && lb_off <= M1_MAX_OFFSET {
+            w.put_bits(1, 1)?;
+            w.put_bits(1, 0)?;
+            w.put_bits(2, lb_len - 2)?;
+            w.put_byte((lb_off - 1) as u8)?;
+        } else if lb_off <= M2_MAX_OFFSET {
+            if lb_len <= M2_MAX_LEN {
+                w.put_distance(lb_len - 2, lb_off)?;
+            } else {
+                w.put_distance(0, lb_off)?;
+                w.put_bits(1, 0)?;
+                w.put_length(lb_len - 9)?;
+            }
+        } else {
+            w.put_distance(0, lb_off - (M2_MAX_OFFSET + 1))?;
+            w.put_bits(1, 1)?;
+            w.put_length(lb_len - 2)?;
+        }
+        d.advance(&mut s, &mut lb_off, &mut lb_len, &mut best_off, true, search);
+    }
+
+    // End of stream
+    w.put_distance(1, 0)?;
+    w.flush();
+    Ok(w.op)
+}
+
+/// Bit input, refilled one byte at a time from the token stream.
+#[cfg(feature = "decompress")]
+struct Reader<'a> {
+    src: &'a [u8],
+    ip: usize,
+    b: u32,
+    k: u32,
+}
+
+#[cfg(feature = "decompress")]
+impl Reader<'_> {
+    fn bits(&mut self, j: u32) -> Result<u32, Error> {
+        if self.k < j {
+            needs_in(self.src, self.ip, 1)?;
+            self.b |= (self.src[self.ip] as u32) << self.k;
+            self.ip += 1;
+            self.k += 8;
+        }
+        let v = self.b & ((1 << j) - 1);
+        self.b >>= j;
+        self.k -= j;
+        Ok(v)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        needs_in(self.src, self.ip, 1)?;
+        self.ip += 1;
+        Ok(self.src[self.ip - 1])
+    }
+}
+
+/// Decompress `src` into `dst`.
+///
+/// `dst` must be large enough to hold the entire decompressed output.
+#[cfg(feature = "decompress")]
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut r = Reader { src, ip: 0, b: 0, k: 0 };
+    let mut op = 0usize;
+    loop {
+        if r.bits(1)? == 0 {
+            // a literal
+            let c = r.byte()?;
+            needs_out(dst, op, 1)?;
+            dst[op] = c;
+            op += 1;
+            continue;
+        }
+        let len;
+        let dist;
+        if r.bits(1)? == 0 {
+            // a M1 match
+            len = 2 + r.bits(2)? as usize;
+            dist = r.byte()? as usize + 1;
+        } else {
+            let t = r.byte()? as usize;
+            let h = r.byte()? as usize;
+            let l = t >> 5;
+            if l == 0 {
+                let (base, high) = if r.bits(1)? == 0 { (9, 0) } else { (2, 8192) };
+                let offset = consume_zero_byte_length(src, &mut r.ip, dst.len() - op)?;
+                len = base + offset * 255 + r.byte()? as usize;
+                dist = ((t & 0x1f) | h << 5) + high;
+            } else {
+                dist = (t & 0x1f) | h << 5;
+                if dist == 0 {
+                    // Stream finished
+                    break;
+                }
+                len = l + 2;
+            }
+        }
+        copy_lookbehind(dst, &mut op, dist, len)?;
+    }
+    if r.ip == src.len() {
+        Ok(op)
+    } else {
+        Err(Error::InputNotConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "decompress")]
+    use crate::Error;
+
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+
+    /// Three literals, an M2 match of 6 bytes at distance 3, then one literal.
+    #[cfg(feature = "decompress")]
+    const M2_STREAM: &[u8] = &[0xd8, b'a', b'b', b'c', 0x83, 0x00, b'x', 0x20, 0x00];
+    /// Two literals, then an M1 match of 2 bytes at distance 2.
+    #[cfg(feature = "decompress")]
+    const M1_STREAM: &[u8] = &[0xc4, b'a', b'b', 0x01, 0x20, 0x00];
+
+    #[test]
+    #[cfg(feature = "decompress")]
+    fn test_decompress() {
+        let mut dst = [0u8; 10];
+        let size = super::decompress(M2_STREAM, &mut dst).expect("Failed to decompress (1)");
+        assert_eq!(&dst[..size], b"abcabcabcx");
+        let size = super::decompress(M1_STREAM, &mut dst).expect("Failed to decompress (2)");
+        assert_eq!(&dst[..size], b"abab");
+        assert_eq!(super::decompress(&M2_STREAM[..8], &mut dst), Err(Error::InputOverrun));
+        assert_eq!(super::decompress(M2_STREAM, &mut dst[..9]), Err(Error::OutputOverrun));
+    }
+
+    #[test]
+    #[cfg(all(feature = "compress", feature = "decompress", not(feature = "forbid-unsafe")))]
+    fn test_round_trip() {
+        use crate::compress::{dict_from_storage, dict_storage_size};
+
+        let mut storage = [0u8; dict_storage_size()];
+        let mut dict = dict_from_storage(&mut storage);
+        let mut compressed = [0u8; super::compress_worst_size(INPUT_1.len())];
+        let mut dst = [0u8; INPUT_1.len()];
+        for input in [INPUT_1, INPUT_2].iter() {
+            let size = super::compress_no_alloc(input, &mut compressed, &mut dict)
+                .expect("Failed to compress");
+            let size =
+                super::decompress(&compressed[..size], &mut dst).expect("Failed to decompress");
+            assert_eq!(&dst[..size], *input);
+        }
+    }
+}
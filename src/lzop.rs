@@ -0,0 +1,1436 @@
+//! # lzop container
+//!
+//! Available with feature `lzop`.
+//!
+//! Reading and writing files in the format of the [lzop](https://www.lzop.org/) utility: a
+//! header with file metadata, followed by LZO1X-compressed blocks with optional checksums.
+//!
+//! To salvage damaged files, [`Reader::set_recovery`] skips corrupt blocks instead of failing,
+//! reporting what was lost. [`Writer::set_rsyncable`] ends blocks at content-defined
+//! boundaries, so edits to the input only change the blocks around them. Blocks of zeros
+//! compress without a search for matches, and [`SparseWriter`] leaves them as holes on output.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Read, Write};
+//!
+//! use lzokay::lzop::{Reader, Writer};
+//! # #[allow(non_upper_case_globals)] const input: [u8; 512] = [0u8; 512];
+//!
+//! let mut writer = Writer::new(Vec::new())?;
+//! writer.write_all(&input)?;
+//! let file = writer.finish()?;
+//!
+//! let mut reader = Reader::new(file.as_slice())?;
+//! let mut output = Vec::new();
+//! reader.read_to_end(&mut output)?;
+//! # assert_eq!(output, input);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    num::{NonZeroU64, NonZeroUsize},
+    ops::Range,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::{
+    checksum::{adler32, crc32, xxh32, xxh64, ADLER32_INIT, CRC32_INIT},
+    compress::{compress_no_alloc, compress_worst_size, new_dict, Dict, TokenWriter},
+    decompress::decompress,
+    Error, Progress,
+};
+
+/// File signature.
+pub const MAGIC: [u8; 9] = [0x89, b'L', b'Z', b'O', 0x00, 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Header flag: blocks include an Adler-32 checksum of the decompressed data.
+pub const F_ADLER32_D: u32 = 0x1;
+/// Header flag: compressed blocks include an Adler-32 checksum of the compressed data.
+pub const F_ADLER32_C: u32 = 0x2;
+/// Header flag: an extra field follows the header.
+pub const F_H_EXTRA_FIELD: u32 = 0x40;
+/// Header flag: blocks include a CRC-32 checksum of the decompressed data.
+pub const F_CRC32_D: u32 = 0x100;
+/// Header flag: compressed blocks include a CRC-32 checksum of the compressed data.
+pub const F_CRC32_C: u32 = 0x200;
+/// Header flag: the data was filtered before compression. Unsupported.
+pub const F_H_FILTER: u32 = 0x800;
+/// Header flag: the header checksum is CRC-32 rather than Adler-32.
+pub const F_H_CRC32: u32 = 0x1000;
+/// Header flag: blocks include an XXH32 checksum of the decompressed data. An lzokay
+/// extension, so lzop can't read the file.
+pub const F_XXH32_D: u32 = 0x1_0000;
+/// Header flag: compressed blocks include an XXH32 checksum of the compressed data. An lzokay
+/// extension, so lzop can't read the file.
+pub const F_XXH32_C: u32 = 0x2_0000;
+/// Header flag: blocks include an XXH64 checksum of the decompressed data. An lzokay
+/// extension, so lzop can't read the file.
+pub const F_XXH64_D: u32 = 0x4_0000;
+/// Header flag: compressed blocks include an XXH64 checksum of the compressed data. An lzokay
+/// extension, so lzop can't read the file.
+pub const F_XXH64_C: u32 = 0x8_0000;
+/// Header flags: operating system, in the top byte.
+pub const F_OS_UNIX: u32 = 0x0300_0000;
+
+/// Compression method: LZO1X-1.
+pub const M_LZO1X_1: u8 = 1;
+/// Compression method: LZO1X-1(15).
+pub const M_LZO1X_1_15: u8 = 2;
+/// Compression method: LZO1X-999.
+pub const M_LZO1X_999: u8 = 3;
+
+/// Block size written by lzop.
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+/// Largest block size accepted by lzop.
+pub const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Format version written, matching lzop 1.03.
+const VERSION: u16 = 0x1030;
+/// Library version written, matching liblzo2 2.08.
+const LIB_VERSION: u16 = 0x2080;
+/// Format version needed to extract the files written.
+const VERSION_NEEDED: u16 = 0x0940;
+/// Oldest format version with the current header layout.
+const MIN_VERSION: u16 = 0x0940;
+
+/// File metadata.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Header {
+    /// Format version of the writer.
+    pub version: u16,
+    /// liblzo2 version of the writer.
+    pub lib_version: u16,
+    /// Format version needed to extract the file.
+    pub version_needed: u16,
+    /// Compression method, one of the `M_*` constants.
+    pub method: u8,
+    /// Compression level.
+    pub level: u8,
+    /// `F_*` flags.
+    pub flags: u32,
+    /// Unix file mode.
+    pub mode: u32,
+    /// Modification time, in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// Original file name.
+    pub name: Vec<u8>,
+    /// Contents of the extra field, present with [`F_H_EXTRA_FIELD`].
+    pub extra: Option<Vec<u8>>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            version: VERSION,
+            lib_version: LIB_VERSION,
+            version_needed: VERSION_NEEDED,
+            method: M_LZO1X_1,
+            level: 5,
+            flags: F_OS_UNIX | F_ADLER32_D | F_ADLER32_C,
+            mode: 0o100644,
+            mtime: 0,
+            name: Vec::new(),
+            extra: None,
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+fn lzo_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// Reads header fields, retaining the raw bytes for the header checksum.
+struct FieldReader<'a, R> {
+    inner: &'a mut R,
+    raw: Vec<u8>,
+}
+
+impl<R: Read> FieldReader<'_, R> {
+    fn bytes(&mut self, len: usize) -> io::Result<&[u8]> {
+        let start = self.raw.len();
+        self.raw.resize(start + len, 0);
+        self.inner.read_exact(&mut self.raw[start..])?;
+        Ok(&self.raw[start..])
+    }
+
+    fn u8(&mut self) -> io::Result<u8> { Ok(self.bytes(1)?[0]) }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Verifies the checksum following the fields read so far, then starts a new checksum.
+    fn verify(&mut self, flags: u32) -> io::Result<()> {
+        let expected = if flags & F_H_CRC32 != 0 {
+            crc32(CRC32_INIT, &self.raw)
+        } else {
+            adler32(ADLER32_INIT, &self.raw)
+        };
+        if read_u32(self.inner)? != expected {
+            return Err(invalid_data("header checksum mismatch"));
+        }
+        self.raw.clear();
+        Ok(())
+    }
+}
+
+impl Header {
+    fn read(r: &mut impl Read) -> io::Result<Header> {
+        let mut magic = [0u8; 9];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not an lzop file"));
+        }
+        let mut f = FieldReader { inner: r, raw: Vec::new() };
+        let version = f.u16()?;
+        if version < MIN_VERSION {
+            return Err(invalid_data("unsupported lzop version"));
+        }
+        let lib_version = f.u16()?;
+        let version_needed = f.u16()?;
+        if version_needed > VERSION {
+            return Err(invalid_data("unsupported lzop version"));
+        }
+        let method = f.u8()?;
+        if !(M_LZO1X_1..=M_LZO1X_999).contains(&method) {
+            return Err(invalid_data("unsupported compression method"));
+        }
+        let level = f.u8()?;
+        let flags = f.u32()?;
+        if flags & F_H_FILTER != 0 {
+            return Err(invalid_data("filters are unsupported"));
+        }
+        let mode = f.u32()?;
+        let mtime_low = f.u32()?;
+        let mtime_high = f.u32()?;
+        let name_len = f.u8()? as usize;
+        let name = f.bytes(name_len)?.to_vec();
+        f.verify(flags)?;
+        let extra = if flags & F_H_EXTRA_FIELD != 0 {
+            let len = f.u32()? as usize;
+            if len > MAX_BLOCK_SIZE {
+                return Err(invalid_data("extra field too large"));
+            }
+            let extra = f.bytes(len)?.to_vec();
+            f.verify(flags)?;
+            Some(extra)
+        } else {
+            None
+        };
+        Ok(Header {
+            version,
+            lib_version,
+            version_needed,
+            method,
+            level,
+            flags,
+            mode,
+            mtime: (mtime_high as u64) << 32 | mtime_low as u64,
+            name,
+            extra,
+        })
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        if self.version < MIN_VERSION || self.flags & F_H_FILTER != 0 || self.name.len() > 255 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported header"));
+        }
+        let mut flags = self.flags & !F_H_EXTRA_FIELD;
+        if self.extra.is_some() {
+            flags |= F_H_EXTRA_FIELD;
+        }
+        let checksum = |raw: &[u8]| {
+            if flags & F_H_CRC32 != 0 {
+                crc32(CRC32_INIT, raw)
+            } else {
+                adler32(ADLER32_INIT, raw)
+            }
+        };
+        let mut raw = Vec::with_capacity(34 + self.name.len());
+        raw.extend_from_slice(&self.version.to_be_bytes());
+        raw.extend_from_slice(&self.lib_version.to_be_bytes());
+        raw.extend_from_slice(&self.version_needed.to_be_bytes());
+        raw.push(self.method);
+        raw.push(self.level);
+        raw.extend_from_slice(&flags.to_be_bytes());
+        raw.extend_from_slice(&self.mode.to_be_bytes());
+        raw.extend_from_slice(&(self.mtime as u32).to_be_bytes());
+        raw.extend_from_slice(&((self.mtime >> 32) as u32).to_be_bytes());
+        raw.push(self.name.len() as u8);
+        raw.extend_from_slice(&self.name);
+        w.write_all(&MAGIC)?;
+        w.write_all(&raw)?;
+        w.write_all(&checksum(&raw).to_be_bytes())?;
+        if let Some(extra) = &self.extra {
+            raw.clear();
+            raw.extend_from_slice(&(extra.len() as u32).to_be_bytes());
+            raw.extend_from_slice(extra);
+            w.write_all(&raw)?;
+            w.write_all(&checksum(&raw).to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// A progress callback for [`Reader`] and [`Writer`].
+type ProgressFn = Box<dyn FnMut(Progress) + Send + Sync>;
+
+/// Caps a rate of bytes per second by sleeping after each block.
+struct Throttle {
+    bytes_per_sec: NonZeroU64,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: NonZeroU64) -> Self {
+        Throttle { bytes_per_sec, start: Instant::now(), bytes: 0 }
+    }
+
+    /// Counts `bytes` as processed, sleeping until they're due at the capped rate.
+    fn consume(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        let due = Duration::from_secs_f64(self.bytes as f64 / self.bytes_per_sec.get() as f64);
+        let elapsed = self.start.elapsed();
+        if let Some(wait) = due.checked_sub(elapsed) {
+            thread::sleep(wait);
+        } else if elapsed - due > Duration::from_secs(1) {
+            // Idle for a while, such as waiting on the caller: don't allow a burst to catch up
+            *self = Throttle::new(self.bytes_per_sec);
+        }
+    }
+}
+
+/// Smallest block ended at a content-defined boundary, see [`Writer::set_rsyncable`].
+const RSYNC_MIN_BLOCK: usize = 16 * 1024;
+/// A boundary follows a byte where the hash's top bits are zero, on average every 64 KiB.
+const RSYNC_MASK: u64 = 0xffff << 48;
+
+/// Random values for the bytes in [`Chunker`]'s hash, from SplitMix64.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Finds content-defined block boundaries with a Gear rolling hash, which depends only on the
+/// last 64 bytes: after an edit, boundaries fall in the same places once past it.
+struct Chunker {
+    hash: u64,
+}
+
+impl Chunker {
+    /// Hashes `data`, which follows `filled` bytes of the current block, up to and including
+    /// the first byte ending a block. Returns the number of bytes hashed, if a block ends.
+    fn boundary(&mut self, data: &[u8], filled: usize) -> Option<usize> {
+        for (i, &byte) in data.iter().enumerate() {
+            self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+            if self.hash & RSYNC_MASK == 0 && filled + i + 1 >= RSYNC_MIN_BLOCK {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+}
+
+/// A part of an lzop file skipped by a [`Reader`] in recovery mode.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lost {
+    /// Offsets of the bytes skipped, counted from the first block like
+    /// [`Progress::bytes_in`].
+    pub input: Range<u64>,
+    /// Offsets of the zeros output in place of a corrupt block. Empty when the data skipped
+    /// wasn't a block of a known size.
+    pub output: Range<u64>,
+}
+
+/// Reads from an underlying reader, buffering what's read after a mark so it can be read
+/// again.
+struct Rewind<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    mark: Option<usize>,
+}
+
+impl<R: Read> Rewind<R> {
+    /// Starts buffering at the current position.
+    fn mark(&mut self) {
+        if self.pos > self.buf.len() / 2 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.mark = Some(self.pos);
+    }
+
+    /// The bytes read since the mark.
+    fn marked(&self) -> &[u8] { &self.buf[self.mark.unwrap_or(self.pos)..self.pos] }
+
+    /// Moves back to `offset` bytes after the mark, and stops buffering.
+    fn rewind(&mut self, offset: usize) { self.pos = self.mark.take().unwrap() + offset; }
+
+    /// Whether the underlying reader is exhausted.
+    fn at_end(&mut self) -> io::Result<bool> {
+        if self.pos < self.buf.len() {
+            return Ok(false);
+        }
+        let mut byte = [0u8];
+        if self.inner.read(&mut byte)? == 0 {
+            return Ok(true);
+        }
+        self.buf.push(byte[0]);
+        Ok(false)
+    }
+}
+
+impl<R: Read> Read for Rewind<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            if self.mark.is_none() {
+                return self.inner.read(buf);
+            }
+            let start = self.buf.len();
+            self.buf.resize(start + buf.len(), 0);
+            match self.inner.read(&mut self.buf[start..]) {
+                Ok(len) => self.buf.truncate(start + len),
+                Err(e) => {
+                    self.buf.truncate(start);
+                    return Err(e);
+                }
+            }
+        }
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// A block's sizes and checksums.
+struct BlockHeader {
+    dst_len: usize,
+    src_len: usize,
+    /// Checksums of the decompressed data.
+    d: Checksums,
+    /// Checksums of the compressed data.
+    c: Checksums,
+    /// Size of the block header itself.
+    len: usize,
+}
+
+/// The checksums of a block's data, decompressed or compressed, in the order they're stored.
+#[derive(Default)]
+struct Checksums {
+    adler32: Option<u32>,
+    crc32: Option<u32>,
+    xxh32: Option<u32>,
+    xxh64: Option<u64>,
+}
+
+impl Checksums {
+    /// Flags of the compressed data's checksums, in the order of the fields.
+    const COMPRESSED: [u32; 4] = [F_ADLER32_C, F_CRC32_C, F_XXH32_C, F_XXH64_C];
+    /// Flags of the decompressed data's checksums, in the order of the fields.
+    const DECOMPRESSED: [u32; 4] = [F_ADLER32_D, F_CRC32_D, F_XXH32_D, F_XXH64_D];
+
+    /// Reads the checksums enabled in `flags`, of the kinds in `kinds`.
+    fn read(r: &mut impl Read, flags: u32, kinds: [u32; 4]) -> io::Result<Checksums> {
+        let mut u32_if = |kind: u32| match flags & kind {
+            0 => Ok(None),
+            _ => read_u32(r).map(Some),
+        };
+        let (adler32, crc32, xxh32) = (u32_if(kinds[0])?, u32_if(kinds[1])?, u32_if(kinds[2])?);
+        let xxh64 = match flags & kinds[3] {
+            0 => None,
+            _ => Some((read_u32(r)? as u64) << 32 | read_u32(r)? as u64),
+        };
+        Ok(Checksums { adler32, crc32, xxh32, xxh64 })
+    }
+
+    /// Computes the checksums of `data` enabled in `flags`, of the kinds in `kinds`.
+    fn compute(data: &[u8], flags: u32, kinds: [u32; 4]) -> Checksums {
+        let enabled = |kind: u32| flags & kind != 0;
+        Checksums {
+            adler32: if enabled(kinds[0]) { Some(adler32(ADLER32_INIT, data)) } else { None },
+            crc32: if enabled(kinds[1]) { Some(crc32(CRC32_INIT, data)) } else { None },
+            xxh32: if enabled(kinds[2]) { Some(xxh32(0, data)) } else { None },
+            xxh64: if enabled(kinds[3]) { Some(xxh64(0, data)) } else { None },
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        for value in [self.adler32, self.crc32, self.xxh32].iter().flatten() {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        if let Some(value) = self.xxh64 {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    /// Size of the checksums as stored.
+    fn len(&self) -> usize {
+        let count = [self.adler32, self.crc32, self.xxh32].iter().flatten().count();
+        4 * count + if self.xxh64.is_some() { 8 } else { 0 }
+    }
+
+    /// Whether `data` matches every checksum present.
+    fn matches(&self, data: &[u8]) -> bool {
+        !(matches!(self.adler32, Some(v) if v != adler32(ADLER32_INIT, data))
+            || matches!(self.crc32, Some(v) if v != crc32(CRC32_INIT, data))
+            || matches!(self.xxh32, Some(v) if v != xxh32(0, data))
+            || matches!(self.xxh64, Some(v) if v != xxh64(0, data)))
+    }
+}
+
+/// Decompresses an lzop file from an underlying reader.
+///
+/// Checksums are verified by default.
+///
+/// `Send` and `Sync` when the underlying reader is.
+pub struct Reader<R> {
+    inner: Rewind<R>,
+    header: Header,
+    verify_checksums: bool,
+    recover: bool,
+    lost: Vec<Lost>,
+    src: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+    progress: Option<ProgressFn>,
+    /// Block bytes read, excluding the file header.
+    bytes_in: u64,
+    bytes_out: u64,
+    throttle: Option<Throttle>,
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads the file header from `inner`.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let header = Header::read(&mut inner)?;
+        Ok(Reader {
+            inner: Rewind { inner, buf: Vec::new(), pos: 0, mark: None },
+            header,
+            verify_checksums: true,
+            recover: false,
+            lost: Vec::new(),
+            src: Vec::new(),
+            buf: Vec::new(),
+            pos: 0,
+            done: false,
+            progress: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            throttle: None,
+        })
+    }
+
+    /// The file header.
+    pub fn header(&self) -> &Header { &self.header }
+
+    /// Sets whether block checksums are verified.
+    pub fn set_verify_checksums(&mut self, verify: bool) { self.verify_checksums = verify; }
+
+    /// Sets whether to recover from corrupt blocks rather than failing, for salvaging what's
+    /// left of a damaged file.
+    ///
+    /// A block that fails to decompress or verify is output as zeros, keeping the following
+    /// data at its offset, and reading continues after it. When a block's sizes are invalid,
+    /// the reader searches for the next valid block a byte at a time, outputting nothing for
+    /// the bytes it skips. A truncated file ends at the last complete block. Each part skipped
+    /// is reported by [`lost`](Reader::lost).
+    ///
+    /// The search buffers the data it reads, up to the largest block size for each candidate.
+    /// Without block checksums, corrupt data that happens to decompress is output unnoticed.
+    pub fn set_recovery(&mut self, recover: bool) { self.recover = recover; }
+
+    /// The parts of the file skipped in recovery mode so far, in order.
+    pub fn lost(&self) -> &[Lost] { &self.lost }
+
+    /// Sets a callback called after each block is decompressed.
+    ///
+    /// [`Progress::bytes_in`] counts the blocks read from the underlying reader, but not the
+    /// file header. The total isn't known.
+    pub fn set_progress(&mut self, progress: impl FnMut(Progress) + Send + Sync + 'static) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    /// Caps decompression at `bytes_per_sec` of decompressed data, or removes the cap.
+    ///
+    /// Sleeps after decompressing each block until the block is due at the capped rate. Time
+    /// spent idle isn't saved up for a burst later.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: Option<NonZeroU64>) {
+        self.throttle = bytes_per_sec.map(Throttle::new);
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner.inner }
+
+    /// The underlying reader, mutably. Reading from it will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner.inner }
+
+    /// Unwraps the underlying reader.
+    pub fn into_inner(self) -> R { self.inner.inner }
+
+    /// Reads the next block into `buf`, returning `false` at the end of the file.
+    fn next_block(&mut self) -> io::Result<bool> {
+        if self.recover {
+            return self.recover_block();
+        }
+        let header = match self.read_block_header()? {
+            Some(header) => header,
+            None => {
+                self.done = true;
+                return Ok(false);
+            }
+        };
+        self.read_block(&header)?;
+        self.finish_block(header.len + header.src_len, header.dst_len);
+        Ok(true)
+    }
+
+    /// Like [`next_block`](Reader::next_block), skipping corrupt data.
+    fn recover_block(&mut self) -> io::Result<bool> {
+        // Set while searching for a valid block, as block sizes can't be trusted
+        let mut searching = false;
+        loop {
+            self.inner.mark();
+            let result = match self.read_block_header() {
+                Ok(Some(header)) => self.read_block(&header).map(|()| Some(header)),
+                Ok(None) if searching && !self.inner.at_end()? => Err(invalid_data("end marker")),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            let consumed = self.inner.marked().len();
+            match result {
+                Ok(Some(header)) => {
+                    self.inner.rewind(consumed);
+                    self.finish_block(consumed, header.dst_len);
+                    return Ok(true);
+                }
+                Ok(None) => {
+                    self.inner.rewind(consumed);
+                    self.done = true;
+                    return Ok(false);
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !searching => {
+                    // Truncated: skip the rest
+                    self.inner.rewind(consumed);
+                    self.skip(consumed as u64, 0);
+                    self.done = true;
+                    return Ok(false);
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::InvalidData
+                        || e.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    if let (false, Some(dst_len)) = (searching, self.block_dst_len(consumed)) {
+                        // A corrupt block of a known size: output zeros in its place
+                        self.inner.rewind(consumed);
+                        self.buf.clear();
+                        self.buf.resize(dst_len, 0);
+                        self.skip(consumed as u64, dst_len as u64);
+                        self.finish_block(0, dst_len);
+                        return Ok(true);
+                    }
+                    if consumed == 0 {
+                        self.inner.rewind(0);
+                        self.done = true;
+                        return Ok(false);
+                    }
+                    // Look for a block at the next byte
+                    self.inner.rewind(1);
+                    self.skip(1, 0);
+                    searching = true;
+                }
+                Err(e) => {
+                    // Read the block again if called again
+                    self.inner.rewind(0);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// The decompressed size of the block read since the mark, if its header was read and is
+    /// valid.
+    fn block_dst_len(&self, consumed: usize) -> Option<usize> {
+        let raw = self.inner.marked();
+        if consumed < 8 {
+            return None;
+        }
+        let dst_len = u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize;
+        let src_len = u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]) as usize;
+        if dst_len == 0 || dst_len > MAX_BLOCK_SIZE || src_len == 0 || src_len > dst_len {
+            return None;
+        }
+        Some(dst_len)
+    }
+
+    /// Records `input_len` bytes as skipped, replaced by `output_len` zeros, merging with the
+    /// last part skipped if they're adjacent.
+    fn skip(&mut self, input_len: u64, output_len: u64) {
+        let input = self.bytes_in..self.bytes_in + input_len;
+        let output = self.bytes_out..self.bytes_out + output_len;
+        self.bytes_in += input_len;
+        if input.is_empty() {
+            return;
+        }
+        match self.lost.last_mut() {
+            Some(last) if last.input.end == input.start && last.output.is_empty() => {
+                last.input.end = input.end;
+                last.output = output;
+            }
+            _ => self.lost.push(Lost { input, output }),
+        }
+    }
+
+    /// Reads a block header, returning `None` for the end marker.
+    fn read_block_header(&mut self) -> io::Result<Option<BlockHeader>> {
+        let flags = self.header.flags;
+        let dst_len = read_u32(&mut self.inner)? as usize;
+        if dst_len == 0 {
+            return Ok(None);
+        }
+        if dst_len > MAX_BLOCK_SIZE {
+            return Err(invalid_data("block too large"));
+        }
+        let src_len = read_u32(&mut self.inner)? as usize;
+        if src_len == 0 || src_len > dst_len {
+            return Err(invalid_data("invalid block size"));
+        }
+        let d = Checksums::read(&mut self.inner, flags, Checksums::DECOMPRESSED)?;
+        let c = if src_len < dst_len {
+            Checksums::read(&mut self.inner, flags, Checksums::COMPRESSED)?
+        } else {
+            Checksums::default()
+        };
+        let len = 8 + d.len() + c.len();
+        Ok(Some(BlockHeader { dst_len, src_len, d, c, len }))
+    }
+
+    /// Reads and decompresses a block's data into `buf`.
+    fn read_block(&mut self, header: &BlockHeader) -> io::Result<()> {
+        self.src.resize(header.src_len, 0);
+        self.inner.read_exact(&mut self.src)?;
+        if header.src_len < header.dst_len {
+            self.verify(&self.src, &header.c)?;
+            self.buf.resize(header.dst_len, 0);
+            if decompress(&self.src, &mut self.buf).map_err(lzo_error)? != header.dst_len {
+                return Err(invalid_data("block size mismatch"));
+            }
+        } else {
+            core::mem::swap(&mut self.src, &mut self.buf);
+        }
+        self.verify(&self.buf, &header.d)
+    }
+
+    /// Counts a block of `src_len` bytes decompressed into `dst_len` bytes in `buf`.
+    fn finish_block(&mut self, src_len: usize, dst_len: usize) {
+        self.pos = 0;
+        self.bytes_in += src_len as u64;
+        self.bytes_out += dst_len as u64;
+        if let Some(throttle) = &mut self.throttle {
+            throttle.consume(dst_len);
+        }
+        if let Some(progress) = &mut self.progress {
+            progress(Progress {
+                bytes_in: self.bytes_in,
+                bytes_out: self.bytes_out,
+                total_in: None,
+            });
+        }
+    }
+
+    fn verify(&self, data: &[u8], checksums: &Checksums) -> io::Result<()> {
+        if self.verify_checksums && !checksums.matches(data) {
+            return Err(invalid_data("block checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done || buf.is_empty() || !self.next_block()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Wipes the compressed and decompressed data buffered, discarding decompressed data not yet
+/// read. Reading continues with the next block.
+#[cfg(feature = "zeroize")]
+impl<R> Zeroize for Reader<R> {
+    fn zeroize(&mut self) {
+        self.src.zeroize();
+        self.buf.zeroize();
+        self.pos = 0;
+        // Data before the position is never read again, even when recovering
+        let pos = self.inner.pos;
+        self.inner.buf[..pos].zeroize();
+    }
+}
+
+/// Fails to compile if the reader and writer stop being `Send` and `Sync`, as documented.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<Reader<&[u8]>>();
+    check::<Writer<Vec<u8>>>();
+}
+
+/// Compresses an lzop file into an underlying writer.
+///
+/// Call [`finish`](Writer::finish) to write the end of the file. Dropping the writer finishes
+/// it too, ignoring errors.
+///
+/// `Send` and `Sync` when the underlying writer is.
+pub struct Writer<W: Write> {
+    inner: Option<W>,
+    flags: u32,
+    block_size: usize,
+    flush_interval: Option<NonZeroUsize>,
+    buf: Vec<u8>,
+    dst: Vec<u8>,
+    dict: Dict<'static>,
+    progress: Option<ProgressFn>,
+    bytes_in: u64,
+    /// Block bytes written, excluding the file header.
+    bytes_out: u64,
+    throttle: Option<Throttle>,
+    chunker: Option<Chunker>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes a default header to `inner`.
+    pub fn new(inner: W) -> io::Result<Self> { Self::with_header(inner, &Header::default()) }
+
+    /// Writes `header` to `inner`.
+    pub fn with_header(mut inner: W, header: &Header) -> io::Result<Self> {
+        header.write(&mut inner)?;
+        Ok(Writer {
+            inner: Some(inner),
+            flags: header.flags,
+            block_size: DEFAULT_BLOCK_SIZE,
+            flush_interval: None,
+            buf: Vec::new(),
+            dst: Vec::new(),
+            dict: new_dict(),
+            progress: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            throttle: None,
+            chunker: None,
+        })
+    }
+
+    /// Sets the uncompressed size of each block, at most [`MAX_BLOCK_SIZE`].
+    pub fn set_block_size(&mut self, block_size: usize) -> io::Result<()> {
+        if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid block size"));
+        }
+        self.write_block()?;
+        self.block_size = block_size;
+        Ok(())
+    }
+
+    /// Flushes after every `interval` bytes of input, or only when asked.
+    ///
+    /// For interactive protocols, where data buffered for a full block would wait on the peer's
+    /// next message: with an interval, no byte waits in the writer for more than `interval`
+    /// bytes of input after it, whatever the block size or how long the search for matches
+    /// takes. Once that many bytes are buffered, they're written as a block, stored
+    /// uncompressed if they don't compress, and the underlying writer is flushed. Smaller
+    /// intervals bound latency more tightly at the cost of compression, as blocks are
+    /// compressed independently.
+    pub fn set_flush_interval(&mut self, interval: Option<NonZeroUsize>) -> io::Result<()> {
+        self.write_block()?;
+        self.flush_interval = interval;
+        Ok(())
+    }
+
+    /// Ends blocks at boundaries chosen by the content, as well as at the block size.
+    ///
+    /// Blocks are compressed independently, so once blocks end in the same places, the same
+    /// data compresses to the same bytes. With content-defined boundaries, inserting or
+    /// removing data only changes the blocks around the edit, rather than shifting every block
+    /// after it: rsync and deduplicating backups then transfer or store little more than the
+    /// edit. Like gzip's `--rsyncable`.
+    ///
+    /// Blocks average about 64 KiB, and are at least 16 KiB unless the block size is smaller,
+    /// so files compress slightly worse than with whole blocks.
+    pub fn set_rsyncable(&mut self, rsyncable: bool) {
+        self.chunker = if rsyncable { Some(Chunker { hash: 0 }) } else { None };
+    }
+
+    /// Sets a callback called after each block is compressed and written.
+    ///
+    /// [`Progress::bytes_out`] counts the blocks written to the underlying writer, but not the
+    /// file header. The total isn't known.
+    pub fn set_progress(&mut self, progress: impl FnMut(Progress) + Send + Sync + 'static) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    /// Caps compression at `bytes_per_sec` of uncompressed data, or removes the cap.
+    ///
+    /// Sleeps after writing each block until the block is due at the capped rate, so `write`
+    /// and `flush` only block when they write a block. Time spent idle isn't saved up for a
+    /// burst later.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: Option<NonZeroU64>) {
+        self.throttle = bytes_per_sec.map(Throttle::new);
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W { self.inner.as_ref().unwrap() }
+
+    /// The underlying writer, mutably. Writing to it will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut W { self.inner.as_mut().unwrap() }
+
+    /// Writes any buffered data and the end of the file, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_end()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_end(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&0u32.to_be_bytes())?;
+        inner.flush()
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.dst.resize(compress_worst_size(self.buf.len()), 0);
+        let size = if self.buf.iter().all(|&b| b == 0) {
+            // Zero-filled, as in the unused parts of disk images: skip searching for matches
+            let mut writer = TokenWriter::new(&mut self.dst);
+            writer.write_literal(&[0]).map_err(lzo_error)?;
+            if self.buf.len() > 3 {
+                writer.write_match(self.buf.len() - 1, 1).map_err(lzo_error)?;
+            } else {
+                writer.write_literal(&self.buf[1..]).map_err(lzo_error)?;
+            }
+            writer.finish().map_err(lzo_error)?
+        } else {
+            compress_no_alloc(&self.buf, &mut self.dst, &mut self.dict).map_err(lzo_error)?
+        };
+        let compressed = size < self.buf.len();
+        let data = if compressed { &self.dst[..size] } else { &self.buf[..] };
+        let mut header = Vec::with_capacity(48);
+        header.extend_from_slice(&(self.buf.len() as u32).to_be_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        Checksums::compute(&self.buf, self.flags, Checksums::DECOMPRESSED).write(&mut header);
+        if compressed {
+            Checksums::compute(data, self.flags, Checksums::COMPRESSED).write(&mut header);
+        }
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&header)?;
+        inner.write_all(data)?;
+        self.bytes_in += self.buf.len() as u64;
+        self.bytes_out += (header.len() + data.len()) as u64;
+        if let Some(throttle) = &mut self.throttle {
+            throttle.consume(self.buf.len());
+        }
+        self.buf.clear();
+        if let Some(progress) = &mut self.progress {
+            progress(Progress {
+                bytes_in: self.bytes_in,
+                bytes_out: self.bytes_out,
+                total_in: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() == self.block_size {
+            self.write_block()?;
+        }
+        let mut len = buf.len().min(self.block_size - self.buf.len());
+        // Data is flushed as soon as the interval is reached, so less is buffered here
+        let interval = self.flush_interval.map_or(usize::MAX, NonZeroUsize::get);
+        len = len.min(interval - self.buf.len());
+        let boundary = match &mut self.chunker {
+            Some(chunker) => chunker.boundary(&buf[..len], self.buf.len()),
+            None => None,
+        };
+        if let Some(end) = boundary {
+            len = end;
+        }
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == interval {
+            self.flush()?;
+        } else if boundary.is_some() {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Writes any buffered data as a block, and flushes the underlying writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_end();
+        }
+    }
+}
+
+/// Wipes the data buffered and the dictionary, discarding data not yet written as a block:
+/// [`flush`](Write::flush) first.
+#[cfg(feature = "zeroize")]
+impl<W: Write> Zeroize for Writer<W> {
+    fn zeroize(&mut self) {
+        self.buf.zeroize();
+        self.dst.zeroize();
+        self.dict.zeroize();
+    }
+}
+
+/// Size of the aligned blocks of zeros that [`SparseWriter`] skips.
+const SPARSE_BLOCK_SIZE: u64 = 4096;
+
+/// Writes a file, seeking over blocks of zeros rather than writing them, which leaves holes
+/// in file systems supporting sparse files.
+///
+/// For decompressing disk images and other files that are mostly zeros, without allocating
+/// disk space for the zeros. Aligned 4 KiB blocks of zeros are skipped; elsewhere, file systems
+/// without sparse files fill the gaps with zeros, so the contents are the same either way.
+///
+/// The file must be empty, as skipped blocks keep what they held before. Call
+/// [`finish`](SparseWriter::finish) to write the last incomplete block and set the file's
+/// length, which trailing zeros don't extend otherwise.
+///
+/// ```no_run
+/// use std::{fs::File, io};
+///
+/// use lzokay::lzop::{Reader, SparseWriter};
+///
+/// let mut reader = Reader::new(io::BufReader::new(File::open("disk.img.lzo")?))?;
+/// let mut writer = SparseWriter::new(File::create("disk.img")?);
+/// io::copy(&mut reader, &mut writer)?;
+/// writer.finish()?;
+/// # Ok::<(), io::Error>(())
+/// ```
+pub struct SparseWriter {
+    file: File,
+    /// Bytes written or skipped, excluding `block`.
+    pos: u64,
+    /// Whether `pos` is past the file's write position, after skipping zeros.
+    skipped: bool,
+    /// The start of the block at `pos`, until it's complete.
+    block: Vec<u8>,
+}
+
+impl SparseWriter {
+    /// Writes to `file` from its start.
+    pub fn new(file: File) -> Self {
+        SparseWriter { file, pos: 0, skipped: false, block: Vec::new() }
+    }
+
+    /// Writes any incomplete last block, and sets the file's length to the bytes written,
+    /// including trailing zeros, returning it.
+    pub fn finish(mut self) -> io::Result<File> {
+        let block = std::mem::take(&mut self.block);
+        self.write_blocks(&block)?;
+        self.file.set_len(self.pos)?;
+        Ok(self.file)
+    }
+
+    /// Writes `data` at `pos`, skipping it if it's all zeros.
+    fn write_blocks(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.iter().all(|&b| b == 0) {
+            self.skipped = true;
+        } else {
+            if self.skipped {
+                self.file.seek(SeekFrom::Start(self.pos))?;
+                self.skipped = false;
+            }
+            self.file.write_all(data)?;
+        }
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+}
+
+impl Write for SparseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        const BLOCK: usize = SPARSE_BLOCK_SIZE as usize;
+        if self.block.is_empty() && buf.len() >= BLOCK {
+            // Whole blocks, up to a change between zeros and data
+            let zero = buf[..BLOCK].iter().all(|&b| b == 0);
+            let len = buf[..buf.len() - buf.len() % BLOCK]
+                .chunks(BLOCK)
+                .take_while(|block| block.iter().all(|&b| b == 0) == zero)
+                .count()
+                * BLOCK;
+            self.write_blocks(&buf[..len])?;
+            return Ok(len);
+        }
+        let len = buf.len().min(BLOCK - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        if self.block.len() == BLOCK {
+            let block = std::mem::take(&mut self.block);
+            self.write_blocks(&block)?;
+            self.block = block;
+            self.block.clear();
+        }
+        Ok(len)
+    }
+
+    /// Flushes the file. An incomplete last block is only written by
+    /// [`finish`](SparseWriter::finish).
+    fn flush(&mut self) -> io::Result<()> { self.file.flush() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        num::{NonZeroU64, NonZeroUsize},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        lzop::{
+            Header, Lost, Reader, SparseWriter, Writer, F_ADLER32_D, F_CRC32_C, F_CRC32_D,
+            F_H_CRC32, F_H_EXTRA_FIELD, F_XXH32_C, F_XXH32_D, F_XXH64_C, F_XXH64_D, MAGIC,
+        },
+        Progress,
+    };
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    fn write(header: &Header, block_size: usize) -> Vec<u8> {
+        let mut writer = Writer::with_header(Vec::new(), header).unwrap();
+        writer.set_block_size(block_size).unwrap();
+        for chunk in INPUT.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read(file: &[u8]) -> std::io::Result<(Header, Vec<u8>)> {
+        let mut reader = Reader::new(file)?;
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok((reader.header().clone(), output))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let header = Header { name: b"test1.txt".to_vec(), mtime: 1 << 33, ..Header::default() };
+        let file = write(&header, 4096);
+        assert_eq!(&file[..MAGIC.len()], &MAGIC);
+        assert_eq!(read(&file).unwrap(), (header, INPUT.to_vec()));
+
+        let header = Header {
+            flags: F_CRC32_D | F_CRC32_C | F_H_CRC32 | F_H_EXTRA_FIELD,
+            extra: Some(b"extra".to_vec()),
+            ..Header::default()
+        };
+        let file = write(&header, INPUT.len());
+        assert_eq!(read(&file).unwrap(), (header, INPUT.to_vec()));
+    }
+
+    #[test]
+    fn test_xxhash() {
+        let header = Header {
+            flags: F_ADLER32_D | F_XXH32_D | F_XXH32_C | F_XXH64_D | F_XXH64_C,
+            ..Header::default()
+        };
+        let file = write(&header, 4096);
+        assert_eq!(read(&file).unwrap(), (header, INPUT.to_vec()));
+        // Each checksum of the first block, after the 29-byte header and the two sizes
+        for &offset in &[0, 4, 8, 12, 16, 20] {
+            let mut corrupt = file.clone();
+            corrupt[MAGIC.len() + 29 + 8 + offset] ^= 1;
+            assert!(read(&corrupt).is_err(), "{}", offset);
+        }
+    }
+
+    #[test]
+    fn test_rsyncable() {
+        // Words from the test file in a pseudo-random order, compressible but not repetitive
+        let words: Vec<&[u8]> = INPUT.split(|&b| b == b' ').collect();
+        let mut seed = 1u32;
+        let mut data = Vec::new();
+        while data.len() < 2 << 20 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.extend_from_slice(words[(seed >> 8) as usize % words.len()]);
+            data.push(b' ');
+        }
+        let mut edited = data.clone();
+        edited.insert(data.len() / 3, b'!');
+
+        let compress = |data: &[u8], rsyncable: bool| {
+            let mut writer = Writer::new(Vec::new()).unwrap();
+            writer.set_rsyncable(rsyncable);
+            for chunk in data.chunks(10000) {
+                writer.write_all(chunk).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let common = |a: &[u8], b: &[u8]| {
+            let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+            let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+            prefix + suffix
+        };
+
+        // Only the blocks around the edit change
+        let (file, edited_file) = (compress(&data, true), compress(&edited, true));
+        assert_eq!(read(&file).unwrap().1, data);
+        assert_eq!(read(&edited_file).unwrap().1, edited);
+        assert!(file.len() - common(&file, &edited_file) < 128 * 1024);
+        // Whereas fixed-size blocks all shift after it
+        let (file, edited_file) = (compress(&data, false), compress(&edited, false));
+        assert!(file.len() - common(&file, &edited_file) > file.len() / 2);
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut data = vec![0u8; 100_000];
+        data.extend_from_slice(INPUT);
+        data.resize(data.len() + 3 * 4096 + 100, 0);
+        data.extend_from_slice(&INPUT[..1000]);
+        data.resize(data.len() + 50_000, 0);
+
+        // Zero blocks compress to a few hundred bytes
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.set_block_size(100_000).unwrap();
+        writer.write_all(&data).unwrap();
+        let file = writer.finish().unwrap();
+        assert!(file.len() < 1000 + INPUT.len());
+        assert_eq!(read(&file).unwrap().1, data);
+
+        let path = std::env::temp_dir().join(format!("lzokay-sparse-{}", std::process::id()));
+        for &chunk in &[1, 1000, 4096, 10_000, data.len()] {
+            let mut writer = SparseWriter::new(std::fs::File::create(&path).unwrap());
+            for piece in data.chunks(chunk) {
+                writer.write_all(piece).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), data);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_progress() {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.set_block_size(1000).unwrap();
+        let sink = reports.clone();
+        writer.set_progress(move |progress| sink.lock().unwrap().push(progress));
+        writer.write_all(INPUT).unwrap();
+        let file = writer.finish().unwrap();
+        let written = std::mem::take(&mut *reports.lock().unwrap());
+        assert_eq!(written.len(), INPUT.len().div_ceil(1000));
+        let last = written.last().unwrap();
+        assert_eq!(last.bytes_in, INPUT.len() as u64);
+        // The file header and the 4-byte end marker aren't counted
+        let header_len = file.len() - last.bytes_out as usize - 4;
+        assert_eq!(header_len, MAGIC.len() + 29);
+
+        let mut reader = Reader::new(file.as_slice()).unwrap();
+        let sink = reports.clone();
+        reader.set_progress(move |progress| sink.lock().unwrap().push(progress));
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        let read = reports.lock().unwrap();
+        let swapped: Vec<_> = written
+            .iter()
+            .map(|p| Progress { bytes_in: p.bytes_out, bytes_out: p.bytes_in, total_in: None })
+            .collect();
+        assert_eq!(*read, swapped);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        // 1000-byte blocks at 100 KB/s, about 36 ms for the whole input in each direction
+        let rate = NonZeroU64::new(100_000);
+        let start = Instant::now();
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.set_block_size(1000).unwrap();
+        writer.set_rate_limit(rate);
+        writer.write_all(INPUT).unwrap();
+        let file = writer.finish().unwrap();
+        let expected = Duration::from_secs_f64(INPUT.len() as f64 / 100_000.0);
+        assert!(start.elapsed() >= expected);
+
+        let start = Instant::now();
+        let mut reader = Reader::new(file.as_slice()).unwrap();
+        reader.set_rate_limit(rate);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert!(start.elapsed() >= expected);
+        assert_eq!(output, INPUT);
+    }
+
+    #[test]
+    fn test_flush_interval() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.set_flush_interval(NonZeroUsize::new(100)).unwrap();
+        let header_len = writer.get_ref().len();
+        writer.write_all(&INPUT[..99]).unwrap();
+        assert_eq!(writer.get_ref().len(), header_len);
+        writer.write_all(&INPUT[99..101]).unwrap();
+        assert!(writer.get_ref().len() > header_len);
+        // A write spanning several intervals returns at each
+        assert_eq!(writer.write(&INPUT[101..1000]).unwrap(), 99);
+        writer.write_all(&INPUT[200..]).unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(read(&file).unwrap().1, INPUT);
+    }
+
+    #[test]
+    fn test_incompressible() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.write_all(b"abc").unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(read(&file).unwrap().1, b"abc");
+    }
+
+    #[test]
+    fn test_errors() {
+        let file = write(&Header::default(), 4096);
+        assert!(read(&file[1..]).is_err());
+        assert!(read(&file[..file.len() - 1]).is_err());
+        // Header checksum
+        let mut corrupt = file.clone();
+        corrupt[MAGIC.len() + 8] ^= 1;
+        assert!(read(&corrupt).is_err());
+        // Decompressed checksum of the first block, after the 29-byte header
+        let mut corrupt = file.clone();
+        corrupt[MAGIC.len() + 29 + 8] ^= 1;
+        assert!(read(&corrupt).is_err());
+        let mut reader = Reader::new(corrupt.as_slice()).unwrap();
+        reader.set_verify_checksums(false);
+        assert!(reader.read_to_end(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_recovery() {
+        use std::convert::TryInto;
+
+        let file = write(&Header::default(), 1000);
+        // Offsets of the blocks, after the 29-byte header
+        let mut blocks = vec![MAGIC.len() + 29];
+        loop {
+            let at = *blocks.last().unwrap();
+            let field = |i: usize| u32::from_be_bytes(file[at + i..at + i + 4].try_into().unwrap());
+            if field(0) == 0 {
+                break;
+            }
+            let (dst_len, src_len) = (field(0) as usize, field(4) as usize);
+            blocks.push(at + if src_len < dst_len { 16 } else { 12 } + src_len);
+        }
+        let start = blocks[0];
+        let recover = |file: &[u8]| {
+            let mut reader = Reader::new(file).unwrap();
+            reader.set_recovery(true);
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output).unwrap();
+            (output, reader.lost().to_vec())
+        };
+        assert_eq!(recover(&file), (INPUT.to_vec(), vec![]));
+
+        // Corrupt data in the second block, which is replaced by zeros
+        let mut corrupt = file.clone();
+        corrupt[blocks[1] + 20] ^= 0xff;
+        let (output, lost) = recover(&corrupt);
+        assert!(read(&corrupt).is_err());
+        let mut expected = INPUT.to_vec();
+        expected[1000..2000].copy_from_slice(&[0; 1000]);
+        assert_eq!(output, expected);
+        let input = (blocks[1] - start) as u64..(blocks[2] - start) as u64;
+        assert_eq!(lost, [Lost { input, output: 1000..2000 }]);
+
+        // An invalid size in the second block's header, skipped by searching for the third
+        let mut corrupt = file.clone();
+        corrupt[blocks[1]] = 0xff;
+        let (output, lost) = recover(&corrupt);
+        assert_eq!(output, [&INPUT[..1000], &INPUT[2000..]].concat());
+        let input = (blocks[1] - start) as u64..(blocks[2] - start) as u64;
+        assert_eq!(lost, [Lost { input, output: 1000..1000 }]);
+
+        // Truncated in the third block
+        let (output, lost) = recover(&file[..blocks[2] + 30]);
+        assert_eq!(output, &INPUT[..2000]);
+        let input = (blocks[2] - start) as u64..(blocks[2] + 30 - start) as u64;
+        assert_eq!(lost, [Lost { input, output: 2000..2000 }]);
+    }
+
+    #[test]
+    #[cfg(feature = "zeroize")]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.write_all(INPUT).unwrap();
+        writer.flush().unwrap();
+        writer.zeroize();
+        writer.write_all(INPUT).unwrap();
+        let file = writer.finish().unwrap();
+        let mut expected = INPUT.to_vec();
+        expected.extend_from_slice(INPUT);
+        assert_eq!(read(&file).unwrap().1, expected);
+
+        // The rest of the block read from is discarded
+        let mut reader = Reader::new(file.as_slice()).unwrap();
+        let mut output = [0u8; 100];
+        reader.read_exact(&mut output).unwrap();
+        reader.zeroize();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, INPUT);
+    }
+}
@@ -0,0 +1,93 @@
+//! # Swift and Kotlin bindings
+//!
+//! Available with feature `uniffi`.
+//!
+//! [UniFFI](https://mozilla.github.io/uniffi-rs/) exports for using this crate from Swift,
+//! Kotlin and Python. Build a shared library, then generate the foreign bindings from it:
+//!
+//! ```sh
+//! cargo rustc --release --features uniffi --crate-type cdylib
+//! uniffi-bindgen generate --library target/release/liblzokay.so --language swift --out-dir out
+//! uniffi-bindgen generate --library target/release/liblzokay.so --language kotlin --out-dir out
+//! ```
+//!
+//! Errors are raised as `LzokayException` (Kotlin) or `LzokayError` (Swift).
+
+use std::fmt;
+
+use crate::{compress, decompress, Error};
+
+/// Error result codes, mirroring [`Error`].
+#[derive(Debug, Eq, PartialEq, uniffi::Error)]
+pub enum LzokayError {
+    /// Likely indicates bad compressed LZO input.
+    LookbehindOverrun,
+    /// Output buffer was not large enough to store the compression/decompression result.
+    OutputOverrun,
+    /// Compressed input buffer is invalid or truncated.
+    InputOverrun,
+    /// Unknown error.
+    Error,
+    /// Decompression succeeded, but input buffer has remaining data.
+    InputNotConsumed,
+    /// The operation was cancelled.
+    Cancelled,
+    /// Compressed output didn't decompress to the input.
+    VerificationFailed,
+}
+
+impl From<Error> for LzokayError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::LookbehindOverrun => LzokayError::LookbehindOverrun,
+            Error::OutputOverrun => LzokayError::OutputOverrun,
+            Error::InputOverrun => LzokayError::InputOverrun,
+            Error::Error => LzokayError::Error,
+            Error::InputNotConsumed => LzokayError::InputNotConsumed,
+            Error::Cancelled => LzokayError::Cancelled,
+            Error::VerificationFailed => LzokayError::VerificationFailed,
+        }
+    }
+}
+
+impl fmt::Display for LzokayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Debug::fmt(self, f) }
+}
+
+impl std::error::Error for LzokayError {}
+
+/// Worst-case compression size of `src_size` bytes.
+#[uniffi::export]
+pub fn compress_worst_size(src_size: u64) -> u64 {
+    compress::compress_worst_size(src_size as usize) as u64
+}
+
+/// Compresses `src`, returning the compressed bytes.
+#[uniffi::export]
+pub fn compress(src: Vec<u8>) -> Result<Vec<u8>, LzokayError> { Ok(compress::compress(&src)?) }
+
+/// Decompresses `src`, which must decompress to exactly `expected_size` bytes.
+#[uniffi::export]
+pub fn decompress(src: Vec<u8>, expected_size: u64) -> Result<Vec<u8>, LzokayError> {
+    let mut dst = vec![0u8; expected_size as usize];
+    let size = decompress::decompress(&src, &mut dst)?;
+    if size != dst.len() {
+        return Err(LzokayError::InputOverrun);
+    }
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_round_trip() {
+        let compressed = compress(INPUT.to_vec()).unwrap();
+        assert!(compressed.len() as u64 <= compress_worst_size(INPUT.len() as u64));
+        assert_eq!(decompress(compressed.clone(), INPUT.len() as u64).unwrap(), INPUT);
+        assert_eq!(decompress(compressed, 100), Err(LzokayError::OutputOverrun));
+    }
+}
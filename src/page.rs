@@ -0,0 +1,139 @@
+//! # Page compression
+//!
+//! Available with feature `page`.
+//!
+//! Compression of the fixed-size pages of storage engines, on the write path: 4, 8, 16 or 32
+//! KiB, checked at compile time. A compressed page is only useful if it's smaller than the
+//! page, so [`compress_page`] writes into a buffer of the page's size rather than the worst
+//! case, and reports [`PageCompression::Incompressible`] for pages to store as they are.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "alloc")] {
+//! use lzokay::{
+//!     compress::new_dict,
+//!     page::{compress_page, decompress_page, PageCompression},
+//! };
+//!
+//! let page = [0u8; 4096];
+//! let mut dict = new_dict();
+//! let mut dst = [0u8; 4096];
+//! let stored = match compress_page(&page, &mut dst, &mut dict)? {
+//!     PageCompression::Compressed(size) => &dst[..size],
+//!     PageCompression::Incompressible => &page[..],
+//! };
+//! # assert!(stored.len() < page.len());
+//!
+//! let mut output = [0u8; 4096];
+//! decompress_page(stored, &mut output)?;
+//! assert_eq!(output, page);
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+use crate::{
+    compress::{compress_no_alloc, Dict},
+    decompress::decompress,
+    Error,
+};
+
+/// A page of `N` bytes, for checking the page size is supported.
+pub struct Page<const N: usize>;
+
+/// Implemented for the [`Page`] sizes supported: 4, 8, 16 and 32 KiB.
+pub trait SupportedPage: private::Sealed {}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! supported_pages {
+    ($($size:expr),*) => {$(
+        impl private::Sealed for Page<$size> {}
+        impl SupportedPage for Page<$size> {}
+    )*};
+}
+
+supported_pages!(4096, 8192, 16384, 32768);
+
+/// Result of [`compress_page`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PageCompression {
+    /// The page compressed to this many bytes, fewer than the page size.
+    Compressed(usize),
+    /// The page doesn't compress to fewer bytes, and should be stored as it is.
+    Incompressible,
+}
+
+/// Compress `page` into `dst`, with the supplied dictionary.
+///
+/// Compression stops as soon as the output would fill `dst`, reporting the page as
+/// incompressible; `dst`'s contents are then unspecified.
+pub fn compress_page<const N: usize>(
+    page: &[u8; N],
+    dst: &mut [u8; N],
+    dict: &mut Dict,
+) -> Result<PageCompression, Error>
+where
+    Page<N>: SupportedPage,
+{
+    match compress_no_alloc(page, dst, dict) {
+        Ok(size) if size < N => Ok(PageCompression::Compressed(size)),
+        Ok(_) | Err(Error::OutputOverrun) => Ok(PageCompression::Incompressible),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decompress a page compressed by [`compress_page`].
+///
+/// The page must decompress to exactly `N` bytes; a shorter output fails with
+/// [`Error::InputOverrun`].
+pub fn decompress_page<const N: usize>(src: &[u8], page: &mut [u8; N]) -> Result<(), Error>
+where Page<N>: SupportedPage {
+    match decompress(src, page)? {
+        size if size == N => Ok(()),
+        _ => Err(Error::InputOverrun),
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{compress_page, decompress_page, PageCompression};
+    use crate::{compress::new_dict, Error};
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_page() {
+        let mut dict = new_dict();
+        let mut page = [0u8; 8192];
+        for (i, byte) in page.iter_mut().enumerate() {
+            *byte = INPUT[i % INPUT.len()];
+        }
+        let mut dst = [0u8; 8192];
+        let size = match compress_page(&page, &mut dst, &mut dict) {
+            Ok(PageCompression::Compressed(size)) => size,
+            other => panic!("{:?}", other),
+        };
+        let mut output = [0u8; 8192];
+        assert_eq!(decompress_page(&dst[..size], &mut output), Ok(()));
+        assert_eq!(output[..], page[..]);
+        // Other page sizes fail
+        let mut small = [0u8; 4096];
+        assert_eq!(decompress_page(&dst[..size], &mut small), Err(Error::OutputOverrun));
+        let mut large = [0u8; 16384];
+        assert_eq!(decompress_page(&dst[..size], &mut large), Err(Error::InputOverrun));
+
+        // Random data doesn't compress
+        let mut seed = 1u32;
+        let mut random = [0u8; 32768];
+        for byte in random.iter_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            *byte = (seed >> 16) as u8;
+        }
+        let mut dst = [0u8; 32768];
+        let result = compress_page(&random, &mut dst, &mut dict);
+        assert_eq!(result, Ok(PageCompression::Incompressible));
+    }
+}
@@ -0,0 +1,207 @@
+//! # Preset dictionary selection
+//!
+//! Available with features `compress`, `decompress` and `alloc`.
+//!
+//! Small blocks compress poorly on their own, with little earlier input for matches to copy
+//! from. A preset dictionary, data resembling the blocks, gives them some: see
+//! [`compress_with_preset`]. A store mixing kinds of data, such as JSON documents and binary
+//! telemetry, compresses best with a preset for each kind. [`compress`] picks one for each
+//! block by compressing a sample of it against each preset, and [`compress_with`] lets a
+//! classifier of your own pick it, for when the kind of a block is known or cheap to tell.
+//!
+//! Both write a frame: an 8-byte header, then the LZO1X stream. The header holds the
+//! [`id`](Preset::id) of the preset chosen, or 0 for none, then the decompressed size, as
+//! big-endian 32-bit integers. [`decompress`] finds the preset among its own by that id, so
+//! presets may be added and reordered between compressing and decompressing, but not changed.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::{
+//!     compress::new_dict,
+//!     preset::{self, Preset},
+//! };
+//!
+//! let presets = [
+//!     Preset::new("json", br#"{"device": "sensor-1", "temperature": 21.5, "humidity": 40}"#),
+//!     Preset::new("telemetry", &[0x7e, 0x01, 0x00, 0x10, 0xff, 0xff, 0x00, 0x00, 0x7e]),
+//! ];
+//! let block = br#"{"device": "sensor-7", "temperature": 19.0, "humidity": 52}"#;
+//! let frame = preset::compress(block, &presets, &mut new_dict())?;
+//! assert_eq!(preset::frame_preset(&frame, &presets)?.map(|p| p.name), Some("json"));
+//! assert_eq!(preset::decompress(&frame, &presets)?, block);
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    checksum::{crc32, CRC32_INIT},
+    compress::{compress_with_preset, Dict},
+    decompress::decompress_with_dict,
+    Error,
+};
+
+/// Size of a frame's header.
+pub const HEADER_SIZE: usize = 8;
+/// Largest block compressed into a frame, as lzop's.
+pub const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
+/// Input of a block compressed against each preset by [`compress`], to choose between them.
+const SAMPLE_SIZE: usize = 4096;
+
+/// A named preset dictionary.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Preset<'a> {
+    /// The name, for the application: frames record the [`id`](Preset::id) instead.
+    pub name: &'a str,
+    /// The data matches may copy from. Only the last 48 KiB are used.
+    pub data: &'a [u8],
+}
+
+impl<'a> Preset<'a> {
+    /// Creates a preset named `name`, from `data`.
+    pub const fn new(name: &'a str, data: &'a [u8]) -> Self { Preset { name, data } }
+
+    /// Identifies the preset in frames: the CRC-32 of its data, or 1 if that's 0, which frames
+    /// use for no preset.
+    pub fn id(&self) -> u32 {
+        match crc32(CRC32_INIT, self.data) {
+            0 => 1,
+            id => id,
+        }
+    }
+}
+
+/// Compresses `block` into a frame, against the preset that compresses a sample of it best, or
+/// without one if none helps.
+///
+/// The first 4 KiB of `block` are compressed against each preset and without one, then the
+/// whole block against the best. Fails with [`Error::Error`] if `block` is larger than
+/// [`MAX_BLOCK_SIZE`].
+pub fn compress(block: &[u8], presets: &[Preset], dict: &mut Dict) -> Result<Vec<u8>, Error> {
+    let sample = &block[..block.len().min(SAMPLE_SIZE)];
+    let mut best = (compress_with_preset(sample, &[], dict)?.len(), None);
+    for (i, preset) in presets.iter().enumerate() {
+        let len = compress_with_preset(sample, preset.data, dict)?.len();
+        if len < best.0 {
+            best = (len, Some(i));
+        }
+    }
+    compress_with(block, presets, dict, |_| best.1)
+}
+
+/// Compresses `block` into a frame, against the preset at the index of `presets` that `select`
+/// returns for the block, or without one if it returns `None`.
+///
+/// Fails with [`Error::Error`] if `block` is larger than [`MAX_BLOCK_SIZE`].
+///
+/// # Panics
+///
+/// If `select` returns an index out of range.
+pub fn compress_with(
+    block: &[u8],
+    presets: &[Preset],
+    dict: &mut Dict,
+    select: impl FnOnce(&[u8]) -> Option<usize>,
+) -> Result<Vec<u8>, Error> {
+    if block.len() > MAX_BLOCK_SIZE {
+        return Err(Error::Error);
+    }
+    let (id, data) = match select(block) {
+        Some(i) => (presets[i].id(), presets[i].data),
+        None => (0, &[][..]),
+    };
+    let stream = compress_with_preset(block, data, dict)?;
+    let mut frame = Vec::with_capacity(HEADER_SIZE + stream.len());
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(&(block.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&stream);
+    Ok(frame)
+}
+
+/// The preset of `presets` that `frame` was compressed against, or `None` if it wasn't.
+///
+/// Fails with [`Error::InputOverrun`] if the header is truncated, and [`Error::Error`] if the
+/// preset isn't among `presets`.
+pub fn frame_preset<'p, 'a>(
+    frame: &[u8],
+    presets: &'p [Preset<'a>],
+) -> Result<Option<&'p Preset<'a>>, Error> {
+    if frame.len() < HEADER_SIZE {
+        return Err(Error::InputOverrun);
+    }
+    match u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) {
+        0 => Ok(None),
+        id => presets.iter().find(|p| p.id() == id).map(Some).ok_or(Error::Error),
+    }
+}
+
+/// Decompresses a frame from [`compress`] or [`compress_with`], finding the preset it was
+/// compressed against among `presets`.
+///
+/// Fails as [`frame_preset`] does, and with [`Error::Error`] if the stream doesn't decompress
+/// to the size in the header.
+pub fn decompress(frame: &[u8], presets: &[Preset]) -> Result<Vec<u8>, Error> {
+    let data = frame_preset(frame, presets)?.map_or(&[][..], |p| p.data);
+    let len = u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]) as usize;
+    if len > MAX_BLOCK_SIZE {
+        return Err(Error::Error);
+    }
+    let mut dst = vec![0u8; data.len() + len];
+    dst[..data.len()].copy_from_slice(data);
+    if decompress_with_dict(&frame[HEADER_SIZE..], &mut dst, data.len())? != len {
+        return Err(Error::Error);
+    }
+    dst.drain(..data.len());
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "std"))]
+    use super::alloc::vec::Vec;
+
+    use super::{compress, compress_with, decompress, frame_preset, Preset, HEADER_SIZE};
+    use crate::{compress::new_dict, Error};
+
+    const INPUT_1: &[u8] = include_bytes!("test1.txt");
+    const INPUT_2: &[u8] = include_bytes!("test2.txt");
+
+    #[test]
+    fn test_preset_selection() {
+        let presets = [Preset::new("one", &INPUT_1[..1536]), Preset::new("two", &INPUT_2[..1536])];
+        let mut dict = new_dict();
+        for (input, name) in [(INPUT_1, "one"), (INPUT_2, "two")].iter() {
+            let block = &input[1536..];
+            let frame = compress(block, &presets, &mut dict).unwrap();
+            assert_eq!(frame_preset(&frame, &presets).unwrap().map(|p| p.name), Some(*name));
+            assert_eq!(decompress(&frame, &presets).unwrap(), block);
+            // Reordered presets are found by id
+            let reversed: Vec<Preset> = presets.iter().rev().copied().collect();
+            assert_eq!(decompress(&frame, &reversed).unwrap(), block);
+
+            let plain = compress_with(block, &presets, &mut dict, |_| None).unwrap();
+            assert!(frame.len() < plain.len());
+            assert_eq!(frame_preset(&plain, &[]), Ok(None));
+            assert_eq!(decompress(&plain, &[]).unwrap(), block);
+            assert_eq!(decompress(&frame, &[]), Err(Error::Error));
+        }
+
+        // Nothing for a preset to improve
+        let frame = compress(&[], &presets, &mut dict).unwrap();
+        assert_eq!(frame_preset(&frame, &presets), Ok(None));
+        assert_eq!(decompress(&frame, &presets), Ok(Vec::new()));
+
+        let frame = compress_with(INPUT_1, &presets, &mut dict, |_| Some(1)).unwrap();
+        assert_eq!(frame_preset(&frame, &presets), Ok(Some(&presets[1])));
+        assert_eq!(decompress(&frame, &presets).unwrap(), INPUT_1);
+        assert_eq!(decompress(&frame[..HEADER_SIZE - 1], &presets), Err(Error::InputOverrun));
+        let mut wrong_size = frame.clone();
+        wrong_size[7] ^= 1;
+        assert!(decompress(&wrong_size, &presets).is_err());
+    }
+}
@@ -0,0 +1,971 @@
+//! # Python bindings
+//!
+//! Available with feature `python`.
+//!
+//! A [PyO3](https://pyo3.rs) extension module, built with [maturin](https://www.maturin.rs)
+//! using the `pyproject.toml` in the repository:
+//!
+//! ```sh
+//! maturin develop --release
+//! ```
+//!
+//! `pyproject.toml` enables feature `abi3`, building against the stable ABI: one wheel covers
+//! CPython 3.11 and later, the first version whose stable ABI includes the buffer protocol.
+//! Free-threaded builds have no stable ABI, so get wheels of their own. Without `abi3`, the
+//! module is tied to the interpreter it was built for, but `compress()` shrinks its output in
+//! place rather than copying it.
+//!
+//! ```python
+//! import lzokay
+//!
+//! compressed = lzokay.compress(data)
+//! assert lzokay.decompress(compressed, len(data)) == data
+//! fast = lzokay.compress(data, level=1)
+//! assert lzokay.decompress(compressed, max_size=1 << 20) == data
+//!
+//! for chunk in lzokay.decompress_iter(compressed, chunk_size=65536):
+//!     process(chunk)
+//!
+//! out = bytearray(len(data))
+//! size = lzokay.decompress_into(compressed, out)
+//!
+//! # Compress a batch in parallel, without holding the GIL
+//! batch = lzokay.compress_many([data, data], threads=2)
+//! ```
+//!
+//! For streaming, `LZOCompressor` and `LZODecompressor` mirror `zlib.compressobj()` and
+//! `zlib.decompressobj()`. As an LZO1X stream can't be produced or decoded incrementally,
+//! input is buffered until the stream is complete.
+//!
+//! `lzokay.lzo_compat` is a drop-in replacement for python-lzo's `lzo` module, including its
+//! header format and checksum functions:
+//!
+//! ```python
+//! import lzokay.lzo_compat as lzo
+//! ```
+//!
+//! `LzoFile` and `open()` read and write lzop files, like `gzip.GzipFile` and `gzip.open()`:
+//!
+//! ```python
+//! with lzokay.open("data.lzo", "wb") as f:
+//!     f.write(data)
+//! with lzokay.open("data.lzo") as f:
+//!     assert f.read() == data
+//! ```
+//!
+//! `compress_file()` and `decompress_file()` convert whole files to and from lzop files, taking
+//! a `str` or `os.PathLike`. They stream in blocks with the GIL released, so memory use doesn't
+//! depend on the file size. Decompressed zeros are left as holes in sparse files:
+//!
+//! ```python
+//! lzokay.compress_file("data.bin", "data.bin.lzo")
+//! lzokay.decompress_file(pathlib.Path("data.bin.lzo"), "restored.bin")
+//! ```
+//!
+//! `lzokay.lzop` replaces the `lzop` utility, with file metadata and checksum verification:
+//!
+//! ```python
+//! import lzokay.lzop
+//!
+//! lzokay.lzop.compress_file("data.bin")  # writes data.bin.lzo
+//! header = lzokay.lzop.decompress_file("data.bin.lzo")
+//! print(header.name, header.mtime)
+//! ```
+//!
+//! With feature `numpy`, `compress_array()` and `decompress_array()` compress numpy arrays,
+//! preserving their dtype and shape:
+//!
+//! ```python
+//! restored = lzokay.decompress_array(lzokay.compress_array(array))
+//! assert restored.dtype == array.dtype and (restored == array).all()
+//! ```
+//!
+//! `features()` reports the crate features the module was built with:
+//!
+//! ```python
+//! if lzokay.features()["lzo1y"]:
+//!     ...
+//! ```
+//!
+//! Errors are raised as subclasses of `lzokay.LzokayError`. Decompression errors carry where
+//! they occurred, for retry logic and error reporting:
+//!
+//! - `input_offset`: the offset into the compressed input.
+//! - `output_offset`, or `bytes_written`: the number of bytes written to the output.
+//! - `required_size`: for `OutputOverrunError` with a fixed-size output, the size needed.
+//!
+//! These are `None` for errors from other operations.
+//!
+//! The module supports free-threaded CPython (3.13t and later) without re-enabling the GIL.
+//! Functions may be called from any number of threads. Using one `LZOCompressor`,
+//! `LZODecompressor`, `LzoFile` or `decompress_iter()` iterator from several threads at once
+//! raises `RuntimeError` rather than racing.
+
+mod file;
+mod lzo_compat;
+mod lzop;
+#[cfg(feature = "numpy")]
+mod numpy;
+
+use std::{convert::TryFrom, slice};
+
+use pyo3::{
+    buffer::PyBuffer,
+    create_exception,
+    exceptions::{PyException, PyTypeError, PyValueError},
+    prelude::*,
+    pybacked::PyBackedBytes,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{
+    compress, decompress,
+    decompress::{stream::Decoder, Failure},
+    format::END_OF_STREAM,
+    variant::Lzo1x,
+    Error,
+};
+
+const DEFAULT_LEVEL: i32 = compress::DEFAULT_LEVEL as i32;
+
+create_exception!(lzokay, LzokayError, PyException, "Base class for lzokay errors.");
+create_exception!(
+    lzokay,
+    LookbehindOverrunError,
+    LzokayError,
+    "Likely indicates bad compressed LZO input."
+);
+create_exception!(
+    lzokay,
+    OutputOverrunError,
+    LzokayError,
+    "Output buffer was not large enough to store the compression/decompression result."
+);
+create_exception!(
+    lzokay,
+    InputOverrunError,
+    LzokayError,
+    "Compressed input buffer is invalid or truncated."
+);
+create_exception!(
+    lzokay,
+    InputNotConsumedError,
+    LzokayError,
+    "Decompression succeeded, but input buffer has remaining data."
+);
+
+fn to_py_err(e: Error) -> PyErr {
+    match e {
+        Error::LookbehindOverrun => LookbehindOverrunError::new_err("lookbehind overrun"),
+        Error::OutputOverrun => OutputOverrunError::new_err("output overrun"),
+        Error::InputOverrun => InputOverrunError::new_err("input overrun"),
+        Error::Error => LzokayError::new_err("unknown error"),
+        Error::InputNotConsumed => InputNotConsumedError::new_err("input not consumed"),
+        Error::Cancelled => LzokayError::new_err("cancelled"),
+        Error::VerificationFailed => LzokayError::new_err("verification failed"),
+    }
+}
+
+/// Converts a decompression failure, attaching where it occurred to the exception.
+///
+/// `required_size` is the output size needed, for `OutputOverrunError`s where it's known.
+fn failure_to_py_err(py: Python, failure: Failure, required_size: Option<usize>) -> PyErr {
+    let Failure { error, input_offset, output_offset } = failure;
+    let err = to_py_err(error);
+    let value = err.value(py);
+    let result = value
+        .setattr("input_offset", input_offset)
+        .and_then(|()| value.setattr("output_offset", output_offset))
+        .and_then(|()| value.setattr("bytes_written", output_offset))
+        .and_then(|()| value.setattr("required_size", required_size));
+    match result {
+        Ok(()) => err,
+        Err(e) => e,
+    }
+}
+
+/// Decompresses `data` into `dst`, measuring the required size if `dst` is too small.
+fn decompress_sized(py: Python, data: &[u8], dst: &mut [u8]) -> PyResult<usize> {
+    py.allow_threads(|| {
+        decompress::decompress_detailed::<Lzo1x>(data, dst).map_err(|failure| {
+            let mut required_size = None;
+            if failure.error == Error::OutputOverrun {
+                let mut output = Vec::new();
+                if decompress::decompress_growing(data, &mut output, usize::MAX).is_ok() {
+                    required_size = Some(output.len());
+                }
+            }
+            (failure, required_size)
+        })
+    })
+    .map_err(|(failure, required_size)| failure_to_py_err(py, failure, required_size))
+}
+
+/// Creates a dictionary compressing at `level`, from 1 (fastest) to 9 (best).
+fn new_dict(level: i32) -> PyResult<compress::Dict<'static>> {
+    let mut dict = compress::new_dict();
+    match u8::try_from(level).ok().map(|level| dict.set_level(level)) {
+        Some(Ok(())) => Ok(dict),
+        _ => Err(PyValueError::new_err(format!(
+            "level must be from {} to {}, got {}",
+            compress::MIN_LEVEL,
+            compress::MAX_LEVEL,
+            level
+        ))),
+    }
+}
+
+/// Compresses `data`, returning the compressed bytes.
+///
+/// `level` ranges from 1 (fastest) to 9 (best, the default).
+#[pyfunction]
+#[pyo3(name = "compress", signature = (data, *, level = DEFAULT_LEVEL))]
+fn py_compress<'py>(py: Python<'py>, data: &[u8], level: i32) -> PyResult<Bound<'py, PyBytes>> {
+    let mut dict = new_dict(level)?;
+    let mut len = 0;
+    // Compress straight into the bytes object, then shrink it, rather than copying from a Vec.
+    let bytes = PyBytes::new_with(py, compress::compress_worst_size(data.len()), |buf| {
+        len = py
+            .allow_threads(|| compress::compress_no_alloc(data, buf, &mut dict))
+            .map_err(to_py_err)?;
+        Ok(())
+    })?;
+    truncate_bytes(bytes, len)
+}
+
+/// Shrinks a newly created bytes object to `len` bytes, in place.
+#[cfg(not(feature = "abi3"))]
+fn truncate_bytes(bytes: Bound<'_, PyBytes>, len: usize) -> PyResult<Bound<'_, PyBytes>> {
+    use pyo3::ffi;
+
+    let py = bytes.py();
+    let mut ptr = bytes.into_ptr();
+    // SAFETY: `bytes` was just created, so nothing else holds a reference to it. On failure,
+    // `_PyBytes_Resize` releases it and sets `ptr` to null.
+    unsafe {
+        if ffi::_PyBytes_Resize(&mut ptr, len as ffi::Py_ssize_t) != 0 {
+            return Err(PyErr::fetch(py));
+        }
+        Ok(Bound::from_owned_ptr(py, ptr).downcast_into_unchecked())
+    }
+}
+
+/// Shrinks a newly created bytes object to `len` bytes, by copying: resizing in place isn't
+/// part of the stable ABI.
+#[cfg(feature = "abi3")]
+fn truncate_bytes(bytes: Bound<'_, PyBytes>, len: usize) -> PyResult<Bound<'_, PyBytes>> {
+    Ok(PyBytes::new(bytes.py(), &bytes.as_bytes()[..len]))
+}
+
+/// Compresses each buffer in `buffers`, returning a list of the compressed bytes.
+///
+/// The batch is split across `threads` threads, by default one per CPU, with the GIL released.
+/// `level` is as for `compress()`.
+#[pyfunction]
+#[pyo3(signature = (buffers, threads = None, *, level = DEFAULT_LEVEL))]
+fn compress_many<'py>(
+    py: Python<'py>,
+    buffers: Vec<PyBackedBytes>,
+    threads: Option<usize>,
+    level: i32,
+) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+    let dict = new_dict(level)?;
+    let threads = match threads {
+        Some(0) => return Err(PyValueError::new_err("threads must be at least 1")),
+        Some(threads) => threads,
+        // One per CPU
+        None => 0,
+    };
+    let buffers: Vec<&[u8]> = buffers.iter().map(|buffer| &buffer[..]).collect();
+    let results = py.allow_threads(|| compress::compress_parallel(&buffers, threads, &dict));
+    match results {
+        Ok(results) => Ok(results.iter().map(|dst| PyBytes::new(py, dst)).collect()),
+        Err(e) => Err(to_py_err(e)),
+    }
+}
+
+/// Decompresses `data` into a buffer of `buffer_size` bytes, returning the decompressed bytes.
+///
+/// Without `buffer_size`, the buffer grows until the output fits. `max_size` then limits the
+/// output size, raising `OutputOverrunError` beyond it, to guard against decompression bombs.
+#[pyfunction]
+#[pyo3(name = "decompress", signature = (data, buffer_size = None, max_size = None))]
+fn py_decompress<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    buffer_size: Option<usize>,
+    max_size: Option<usize>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let dst = match buffer_size {
+        Some(buffer_size) => {
+            let mut dst = vec![0u8; buffer_size];
+            let size = decompress_sized(py, data, &mut dst)?;
+            dst.truncate(size);
+            dst
+        }
+        None => {
+            let mut dst = Vec::new();
+            py.allow_threads(|| {
+                decompress::decompress_growing(data, &mut dst, max_size.unwrap_or(usize::MAX))
+            })
+            .map_err(|failure| failure_to_py_err(py, failure, None))?;
+            dst
+        }
+    };
+    Ok(PyBytes::new(py, &dst))
+}
+
+/// Decompresses `data` into the writable buffer `out`, such as a `bytearray` or `memoryview`,
+/// returning the number of bytes written.
+///
+/// Useful for reusing a preallocated buffer across many calls.
+#[pyfunction]
+fn decompress_into(py: Python, data: &[u8], out: PyBuffer<u8>) -> PyResult<usize> {
+    let dst = writable_buffer(&out, "out")?;
+    decompress_sized(py, data, dst)
+}
+
+/// Borrows a writable, contiguous buffer as a slice.
+#[allow(clippy::mut_from_ref)]
+fn writable_buffer<'a>(buf: &'a PyBuffer<u8>, name: &str) -> PyResult<&'a mut [u8]> {
+    if buf.readonly() {
+        return Err(PyTypeError::new_err(format!("{} must be a writable buffer", name)));
+    }
+    if !buf.is_c_contiguous() {
+        return Err(PyValueError::new_err(format!("{} must be contiguous", name)));
+    }
+    // SAFETY: The buffer is writable and contiguous, and the exported buffer can't be resized
+    // or freed until `buf` is released, which happens after the slice's lifetime ends.
+    Ok(unsafe { slice::from_raw_parts_mut(buf.buf_ptr() as *mut u8, buf.len_bytes()) })
+}
+
+/// Decompresses `data` lazily, returning an iterator over chunks of at most `chunk_size` bytes.
+///
+/// Only one chunk of output is held at a time, so large payloads can be processed without
+/// materializing the whole output.
+#[pyfunction]
+#[pyo3(signature = (data, chunk_size = 65536))]
+fn decompress_iter(data: PyBackedBytes, chunk_size: usize) -> PyResult<DecompressIter> {
+    if chunk_size == 0 {
+        return Err(PyValueError::new_err("chunk_size must be at least 1"));
+    }
+    Ok(DecompressIter { data, pos: 0, decoder: Decoder::new(), chunk_size, done: false })
+}
+
+/// Returns a dict of the crate features compiled into the module, such as `"lzo1y"` and
+/// `"cpp-backend"`, mapped to whether they're enabled.
+#[pyfunction]
+#[pyo3(name = "features")]
+fn py_features(py: Python) -> PyResult<Bound<PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, enabled) in crate::features().flags() {
+        dict.set_item(name, enabled)?;
+    }
+    Ok(dict)
+}
+
+/// Iterator over decompressed chunks, returned by `decompress_iter()`.
+#[pyclass(module = "lzokay")]
+struct DecompressIter {
+    data: PyBackedBytes,
+    pos: usize,
+    decoder: Decoder,
+    chunk_size: usize,
+    done: bool,
+}
+
+#[pymethods]
+impl DecompressIter {
+    fn __iter__(slf: Py<Self>) -> Py<Self> { slf }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let DecompressIter { data, pos, decoder, chunk_size, .. } = self;
+        let mut chunk = vec![0u8; *chunk_size];
+        let result = py.allow_threads(|| {
+            let mut len = 0;
+            loop {
+                let (consumed, produced) = decoder.decode(&data[*pos..], &mut chunk[len..])?;
+                *pos += consumed;
+                len += produced;
+                if decoder.is_finished() {
+                    if *pos < data.len() {
+                        return Err(Error::InputNotConsumed);
+                    }
+                    return Ok(len);
+                } else if len == chunk.len() {
+                    return Ok(len);
+                } else if consumed == 0 && produced == 0 {
+                    decoder.finish()?;
+                }
+            }
+        });
+        match result {
+            Ok(0) => {
+                self.done = true;
+                Ok(None)
+            }
+            Ok(len) => Ok(Some(PyBytes::new(py, &chunk[..len]))),
+            Err(error) => {
+                self.done = true;
+                let failure = Failure {
+                    error,
+                    input_offset: self.decoder.total_in() as usize,
+                    output_offset: self.decoder.total_out() as usize,
+                };
+                Err(failure_to_py_err(py, failure, None))
+            }
+        }
+    }
+}
+
+/// Streaming compressor, mirroring `zlib.compressobj()`.
+///
+/// `compress()` buffers its input, and `flush()` returns the complete stream. `level` is as
+/// for `lzokay.compress()`.
+#[pyclass(module = "lzokay")]
+struct LZOCompressor {
+    input: Vec<u8>,
+    level: i32,
+    flushed: bool,
+}
+
+#[pymethods]
+impl LZOCompressor {
+    #[new]
+    #[pyo3(signature = (level = DEFAULT_LEVEL))]
+    fn new(level: i32) -> PyResult<Self> {
+        new_dict(level)?;
+        Ok(LZOCompressor { input: Vec::new(), level, flushed: false })
+    }
+
+    /// Adds `data` to the stream, returning any compressed output available so far.
+    fn compress<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        if self.flushed {
+            return Err(PyValueError::new_err("compressor already flushed"));
+        }
+        self.input.extend_from_slice(data);
+        Ok(PyBytes::new(py, &[]))
+    }
+
+    /// Finishes the stream, returning the remaining compressed output.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        if self.flushed {
+            return Err(PyValueError::new_err("compressor already flushed"));
+        }
+        self.flushed = true;
+        let input = std::mem::take(&mut self.input);
+        py_compress(py, &input, self.level)
+    }
+}
+
+/// Streaming decompressor, mirroring `zlib.decompressobj()`.
+#[pyclass(module = "lzokay")]
+#[derive(Default)]
+struct LZODecompressor {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    /// Position of the next byte of `output` to return.
+    pos: usize,
+    eof: bool,
+}
+
+#[pymethods]
+impl LZODecompressor {
+    #[new]
+    fn new() -> Self { Self::default() }
+
+    /// Whether the end of the stream has been reached.
+    #[getter]
+    fn eof(&self) -> bool { self.eof }
+
+    /// Adds `data` to the stream, returning at most `max_length` bytes of decompressed output,
+    /// or all available output if `max_length` is 0.
+    ///
+    /// Output beyond `max_length` is returned by subsequent calls, which may pass empty `data`.
+    #[pyo3(signature = (data, max_length = 0))]
+    fn decompress<'py>(
+        &mut self,
+        py: Python<'py>,
+        data: &[u8],
+        max_length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        if self.eof {
+            if !data.is_empty() {
+                return Err(InputNotConsumedError::new_err("input not consumed"));
+            }
+        } else {
+            self.input.extend_from_slice(data);
+            if self.input.ends_with(&END_OF_STREAM) {
+                let (input, output) = (&self.input, &mut self.output);
+                match py.allow_threads(|| decompress::decompress_growing(input, output, usize::MAX))
+                {
+                    Ok(()) => {
+                        self.eof = true;
+                        self.input = Vec::new();
+                    }
+                    // The marker may also appear within the stream
+                    Err(Failure { error: Error::InputOverrun, .. }) => {}
+                    Err(failure) => return Err(failure_to_py_err(py, failure, None)),
+                }
+            }
+        }
+        Ok(PyBytes::new(py, self.take_output(max_length)))
+    }
+
+    /// Returns all remaining decompressed output.
+    ///
+    /// Raises `InputOverrunError` if the stream is incomplete.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        if !self.eof {
+            return Err(InputOverrunError::new_err("input overrun"));
+        }
+        Ok(PyBytes::new(py, self.take_output(0)))
+    }
+}
+
+impl LZODecompressor {
+    fn take_output(&mut self, max_length: usize) -> &[u8] {
+        let start = self.pos;
+        let remaining = self.output.len() - start;
+        let len = if max_length == 0 { remaining } else { remaining.min(max_length) };
+        self.pos += len;
+        &self.output[start..self.pos]
+    }
+}
+
+// The module has no global state, and objects with mutable state are guarded by PyO3's borrow
+// checking, so the GIL isn't needed on free-threaded builds.
+#[pymodule(gil_used = false)]
+#[pyo3(name = "lzokay")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_function(wrap_pyfunction!(py_compress, m)?)?;
+    m.add_function(wrap_pyfunction!(compress_many, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress_into, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(py_features, m)?)?;
+    #[cfg(feature = "numpy")]
+    m.add_function(wrap_pyfunction!(numpy::compress_array, m)?)?;
+    #[cfg(feature = "numpy")]
+    m.add_function(wrap_pyfunction!(numpy::decompress_array, m)?)?;
+    m.add_class::<LZOCompressor>()?;
+    m.add_class::<LZODecompressor>()?;
+    m.add_class::<file::LzoFile>()?;
+    m.add_function(wrap_pyfunction!(file::open, m)?)?;
+    m.add_function(wrap_pyfunction!(lzop::compress_file, m)?)?;
+    m.add_function(wrap_pyfunction!(lzop::decompress_file, m)?)?;
+    let base = py.get_type::<LzokayError>();
+    for attr in ["input_offset", "output_offset", "bytes_written", "required_size"].iter() {
+        base.setattr(*attr, py.None())?;
+    }
+    m.add("LzokayError", base)?;
+    m.add("LookbehindOverrunError", py.get_type::<LookbehindOverrunError>())?;
+    m.add("OutputOverrunError", py.get_type::<OutputOverrunError>())?;
+    m.add("InputOverrunError", py.get_type::<InputOverrunError>())?;
+    m.add("InputNotConsumedError", py.get_type::<InputNotConsumedError>())?;
+    let lzo_compat = lzo_compat::module(py)?;
+    lzo_compat.gil_used(false)?;
+    m.add_submodule(&lzo_compat)?;
+    let lzop = lzop::module(py)?;
+    lzop.gil_used(false)?;
+    m.add_submodule(&lzop)?;
+    // Allow `import lzokay.lzo_compat` and `import lzokay.lzop`
+    let modules = py.import("sys")?.getattr("modules")?;
+    modules.set_item("lzokay.lzo_compat", lzo_compat)?;
+    modules.set_item("lzokay.lzop", lzop)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{prelude::*, types::PyDict};
+
+    use super::python_module;
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    fn run(script: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "lzokay").unwrap();
+            python_module(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("lzokay", module).unwrap();
+            globals.set_item("data", pyo3::types::PyBytes::new(py, INPUT)).unwrap();
+            let script = std::ffi::CString::new(script).unwrap();
+            py.run(&script, Some(&globals), None).map_err(|e| e.display(py)).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_threads() {
+        run(r#"
+import threading
+results = []
+def work():
+    compressed = lzokay.compress(data, level=5)
+    results.append(lzokay.decompress(compressed, len(data)) == data)
+threads = [threading.Thread(target=work) for _ in range(8)]
+for t in threads:
+    t.start()
+for t in threads:
+    t.join()
+assert results == [True] * 8
+"#);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        run(r#"
+compressed = lzokay.compress(data)
+assert lzokay.decompress(compressed, len(data)) == data
+assert lzokay.decompress(lzokay.compress(b""), 0) == b""
+try:
+    lzokay.decompress(compressed, 10)
+    assert False
+except lzokay.OutputOverrunError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_features() {
+        run(r#"
+features = lzokay.features()
+assert features["compress"] and features["decompress"] and features["lzop"]
+assert features["python"] and features["parallel"]
+assert "cpp-backend" in features
+"#);
+    }
+
+    #[test]
+    fn test_levels() {
+        run(r#"
+sizes = [len(lzokay.compress(data, level=level)) for level in range(1, 10)]
+assert sizes == sorted(sizes, reverse=True) and sizes[0] > sizes[-1]
+assert lzokay.compress(data, level=9) == lzokay.compress(data)
+fast = lzokay.compress(data, level=1)
+assert lzokay.decompress(fast) == data
+assert lzokay.compress_many([data], level=1) == [fast]
+c = lzokay.LZOCompressor(1)
+assert c.compress(data) + c.flush() == fast
+for level in (0, 10, -1):
+    for f in (
+        lambda: lzokay.compress(data, level=level),
+        lambda: lzokay.compress_many([data], level=level),
+        lambda: lzokay.LZOCompressor(level),
+    ):
+        try:
+            f()
+            assert False
+        except ValueError:
+            pass
+"#);
+    }
+
+    #[test]
+    fn test_compress_many() {
+        run(r#"
+buffers = [data[i:] for i in range(0, len(data), len(data) // 10)] + [b"", bytearray(data)]
+expected = [lzokay.compress(bytes(b)) for b in buffers]
+assert lzokay.compress_many(buffers) == expected
+assert lzokay.compress_many(buffers, threads=1) == expected
+assert lzokay.compress_many(buffers, threads=100) == expected
+assert lzokay.compress_many([]) == []
+try:
+    lzokay.compress_many(buffers, threads=0)
+    assert False
+except ValueError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_decompress_auto_sized() {
+        run(r#"
+compressed = lzokay.compress(data)
+assert lzokay.decompress(compressed) == data
+assert lzokay.decompress(compressed, max_size=len(data)) == data
+try:
+    lzokay.decompress(compressed, max_size=len(data) - 1)
+    assert False
+except lzokay.OutputOverrunError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_error_attributes() {
+        run(r#"
+compressed = lzokay.compress(data)
+try:
+    lzokay.decompress(compressed, 10)
+    assert False
+except lzokay.OutputOverrunError as e:
+    assert 0 < e.input_offset < len(compressed)
+    assert e.output_offset == e.bytes_written <= 10
+    assert e.required_size == len(data)
+out = bytearray(100)
+try:
+    lzokay.decompress_into(compressed, out)
+    assert False
+except lzokay.OutputOverrunError as e:
+    assert e.required_size == len(data)
+    assert out[:e.bytes_written] == data[:e.bytes_written]
+try:
+    lzokay.decompress(compressed, max_size=100)
+    assert False
+except lzokay.OutputOverrunError as e:
+    assert e.required_size is None
+try:
+    lzokay.decompress(compressed[:-10])
+    assert False
+except lzokay.InputOverrunError as e:
+    assert e.input_offset <= len(compressed) - 10
+    assert e.output_offset > 0 and e.required_size is None
+try:
+    lzokay.LZODecompressor().flush()
+    assert False
+except lzokay.LzokayError as e:
+    assert e.input_offset is None
+"#);
+    }
+
+    #[test]
+    #[cfg(feature = "numpy")]
+    fn test_numpy() {
+        run(r#"
+try:
+    lzokay.decompress_array(b"LZNP\x02")
+    assert False
+except lzokay.LzokayError:
+    pass
+try:
+    import numpy
+except ImportError:
+    numpy = None
+if numpy is not None:
+    for array in (
+        numpy.arange(1000, dtype=">f8").reshape(10, 100),
+        numpy.frombuffer(data, dtype=numpy.uint8)[::2],
+        numpy.zeros((0, 3), dtype=numpy.int16),
+        numpy.float32(1.5),
+    ):
+        restored = lzokay.decompress_array(lzokay.compress_array(array))
+        assert restored.dtype == array.dtype and restored.shape == array.shape
+        assert (restored == array).all()
+    compressed = lzokay.compress_array(numpy.arange(100, dtype="u1"))
+    assert compressed.endswith(lzokay.compress(bytes(range(100))))
+"#);
+    }
+
+    #[test]
+    fn test_decompress_iter() {
+        run(r#"
+compressed = lzokay.compress(data)
+chunks = list(lzokay.decompress_iter(compressed, chunk_size=1000))
+assert b"".join(chunks) == data
+assert all(len(chunk) == 1000 for chunk in chunks[:-1])
+assert b"".join(lzokay.decompress_iter(compressed)) == data
+it = lzokay.decompress_iter(compressed[:-10], chunk_size=100)
+try:
+    for chunk in it:
+        pass
+    assert False
+except lzokay.InputOverrunError as e:
+    assert e.output_offset > 0
+assert list(it) == []
+try:
+    list(lzokay.decompress_iter(compressed + b"\x00"))
+    assert False
+except lzokay.InputNotConsumedError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_decompress_into() {
+        run(r#"
+compressed = lzokay.compress(data)
+out = bytearray(len(data) + 10)
+assert lzokay.decompress_into(compressed, out) == len(data)
+assert out[:len(data)] == data
+view = memoryview(out)[5:]
+assert lzokay.decompress_into(compressed, view) == len(data)
+assert out[5:len(data) + 5] == data
+try:
+    lzokay.decompress_into(compressed, memoryview(out)[:10])
+    assert False
+except lzokay.OutputOverrunError:
+    pass
+try:
+    lzokay.decompress_into(compressed, bytes(10))
+    assert False
+except TypeError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_lzo_compat() {
+        run(r#"
+import zlib
+lzo = lzokay.lzo_compat
+compressed = lzo.compress(data)
+assert compressed[0] == 0xf0
+assert int.from_bytes(compressed[1:5], "big") == len(data)
+assert lzo.decompress(compressed) == data
+assert lzo.optimize(compressed) == compressed
+raw = lzo.compress(data, 9, False)
+assert raw == compressed[5:]
+assert lzo.decompress(raw, False, len(data) + 10) == data
+for bad in (compressed[:4], b"\x00" + compressed[1:], compressed[:5] + b"\x11\x00\x00"):
+    try:
+        lzo.decompress(bad)
+        assert False
+    except lzo.error:
+        pass
+try:
+    lzo.decompress(raw, False)
+    assert False
+except lzo.error:
+    pass
+assert lzo.adler32(data) == zlib.adler32(data)
+assert lzo.adler32(data[100:], lzo.adler32(data[:100])) == zlib.adler32(data)
+assert lzo.crc32(data) == zlib.crc32(data)
+assert lzo.crc32(data[100:], lzo.crc32(data[:100])) == zlib.crc32(data)
+"#);
+    }
+
+    #[test]
+    fn test_lzo_file() {
+        run(r#"
+import io
+buf = io.BytesIO()
+with lzokay.LzoFile(fileobj=buf, mode="wb") as f:
+    assert f.write(data[:100]) == 100
+    f.flush()
+    f.write(data[100:])
+    assert f.tell() == len(data)
+assert f.closed and not buf.closed
+assert buf.getvalue().startswith(b"\x89LZO\x00\r\n\x1a\n")
+
+buf.seek(0)
+f = lzokay.LzoFile(fileobj=buf)
+assert f.readable() and not f.writable()
+assert f.read(10) == data[:10]
+assert f.seek(100) == 100
+assert f.read(10) == data[100:110]
+assert f.seek(5) == 5
+out = bytearray(10)
+assert f.readinto(out) == 10 and out == data[5:15]
+assert f.seek(-5, 1) == 10
+assert f.read() == data[10:]
+assert f.read() == b""
+f.close()
+try:
+    f.read()
+    assert False
+except ValueError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_compress_file() {
+        run(r#"
+import pathlib, tempfile
+with tempfile.TemporaryDirectory() as tmp:
+    tmp = pathlib.Path(tmp)
+    (tmp / "data.txt").write_bytes(data)
+    assert pathlib.Path(lzokay.compress_file(tmp / "data.txt", tmp / "data.lzo")) == tmp / "data.lzo"
+    header = lzokay.decompress_file(tmp / "data.lzo", str(tmp / "out.txt"))
+    assert header.name == b"data.txt"
+    assert (tmp / "out.txt").read_bytes() == data
+    # Zeros are skipped, and trailing zeros still extend the file
+    sparse = b"\0" * 100000 + data + b"\0" * 50000
+    (tmp / "sparse.bin").write_bytes(sparse)
+    lzokay.compress_file(tmp / "sparse.bin", tmp / "sparse.lzo")
+    lzokay.decompress_file(tmp / "sparse.lzo", tmp / "sparse.out")
+    assert (tmp / "sparse.out").read_bytes() == sparse
+    lzokay.decompress_file(tmp / "sparse.lzo", tmp / "dense.out", sparse=False)
+    assert (tmp / "dense.out").read_bytes() == sparse
+    try:
+        lzokay.decompress_file(tmp / "missing.lzo", tmp / "out.txt")
+        assert False
+    except FileNotFoundError:
+        pass
+"#);
+    }
+
+    #[test]
+    fn test_lzop() {
+        run(r#"
+import os, tempfile
+lzop = lzokay.lzop
+with tempfile.TemporaryDirectory() as tmp:
+    src = os.path.join(tmp, "data.txt")
+    with open(src, "wb") as f:
+        f.write(data)
+    os.chmod(src, 0o640)
+    os.utime(src, (1500000000, 1500000000))
+    dst = lzop.compress_file(src)
+    assert dst == src + ".lzo"
+    os.remove(src)
+
+    with lzop.open(dst) as f:
+        assert f.header.name == b"data.txt" and f.header.mtime == 1500000000
+        assert f.read() == data
+    header = lzop.decompress_file(dst)
+    assert header.name == b"data.txt"
+    with open(src, "rb") as f:
+        assert f.read() == data
+    assert os.stat(src).st_mtime == 1500000000
+    if os.name == "posix":
+        assert os.stat(src).st_mode & 0o777 == 0o640
+
+    # Corrupt the first block's checksum
+    with open(dst, "rb") as f:
+        compressed = bytearray(f.read())
+    compressed[9 + 29 + len(b"data.txt") + 8] ^= 0xff
+    with open(dst, "wb") as f:
+        f.write(compressed)
+    out = os.path.join(tmp, "out.txt")
+    try:
+        lzop.decompress_file(dst, out)
+        assert False
+    except lzokay.LzokayError:
+        pass
+    lzop.decompress_file(dst, out, verify_checksums=False)
+    with lzop.open(dst, verify_checksums=False) as f:
+        assert f.read() == data
+    try:
+        lzop.decompress_file(out)
+        assert False
+    except ValueError:
+        pass
+"#);
+    }
+
+    #[test]
+    fn test_streaming() {
+        run(r#"
+c = lzokay.LZOCompressor()
+compressed = c.compress(data[:100]) + c.compress(data[100:]) + c.flush()
+assert compressed == lzokay.compress(data)
+
+d = lzokay.LZODecompressor()
+out = d.decompress(compressed[:10])
+assert out == b"" and not d.eof
+out += d.decompress(compressed[10:], 100)
+assert d.eof and len(out) == 100
+out += d.decompress(b"", 100)
+out += d.flush()
+assert out == data
+"#);
+    }
+}
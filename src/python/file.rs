@@ -0,0 +1,320 @@
+//! `lzokay.LzoFile` and `lzokay.open()`, modeled on `gzip.GzipFile` and `gzip.open()`.
+
+use std::io::{self, Read, Write};
+
+use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyOSError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+use super::{
+    lzop::{io_err, Header},
+    writable_buffer,
+};
+use crate::lzop::{Reader, Writer};
+
+/// Adapts a Python file object to `Read` and `Write`.
+struct PyFile {
+    file: PyObject,
+}
+
+impl Read for PyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let data = self.file.call_method1(py, "read", (buf.len(),))?;
+            let data = data.downcast_bound::<PyBytes>(py).map_err(PyErr::from)?.as_bytes();
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        })
+    }
+}
+
+impl Write for PyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            self.file.call_method1(py, "write", (PyBytes::new(py, buf),))?;
+            Ok(buf.len())
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| {
+            self.file.call_method0(py, "flush")?;
+            Ok(())
+        })
+    }
+}
+
+enum State {
+    Read(Box<Reader<PyFile>>),
+    Write(Box<Writer<PyFile>>),
+    Closed,
+}
+
+/// A file object reading or writing an lzop file, modeled on `gzip.GzipFile`.
+///
+/// In read mode, seeking backwards rewinds to the start of the file and decompresses up to the
+/// new position, so it's slow. In write mode, only forward seeks are supported, writing zeros.
+#[pyclass(module = "lzokay")]
+pub(super) struct LzoFile {
+    state: State,
+    /// The underlying file, if opened by this object.
+    owned: Option<PyObject>,
+    /// Position of the lzop header in the underlying file, for rewinding.
+    start: u64,
+    /// Position in the decompressed data.
+    pos: u64,
+    #[pyo3(get)]
+    mode: String,
+    verify_checksums: bool,
+}
+
+fn closed_error() -> PyErr { PyValueError::new_err("I/O operation on closed file") }
+
+#[pymethods]
+impl LzoFile {
+    /// Opens `filename`, or wraps `fileobj`, in mode `"rb"` (the default), `"wb"` or `"xb"`.
+    ///
+    /// In read mode, block checksums are verified unless `verify_checksums` is false.
+    #[new]
+    #[pyo3(signature = (filename = None, mode = None, fileobj = None, verify_checksums = true))]
+    pub(super) fn new(
+        py: Python,
+        filename: Option<PyObject>,
+        mode: Option<&str>,
+        fileobj: Option<PyObject>,
+        verify_checksums: bool,
+    ) -> PyResult<Self> {
+        let mode = match mode.unwrap_or("rb") {
+            "r" | "rb" => "rb",
+            "w" | "wb" => "wb",
+            "x" | "xb" => "xb",
+            mode => return Err(PyValueError::new_err(format!("invalid mode: {:?}", mode))),
+        };
+        let (file, owned) = match (fileobj, filename) {
+            (Some(file), _) => (file, None),
+            (None, Some(filename)) => {
+                let file = py.import("builtins")?.call_method1("open", (filename, mode))?.unbind();
+                (file.clone_ref(py), Some(file))
+            }
+            (None, None) => {
+                return Err(PyValueError::new_err("either filename or fileobj is required"))
+            }
+        };
+        let start = file.call_method0(py, "tell").and_then(|pos| pos.extract(py)).unwrap_or(0);
+        let file = PyFile { file };
+        let state = if mode == "rb" {
+            let mut reader = Reader::new(file).map_err(io_err)?;
+            reader.set_verify_checksums(verify_checksums);
+            State::Read(Box::new(reader))
+        } else {
+            State::Write(Box::new(Writer::new(file).map_err(io_err)?))
+        };
+        Ok(LzoFile { state, owned, start, pos: 0, mode: mode.to_owned(), verify_checksums })
+    }
+
+    /// The file's metadata, in read mode.
+    #[getter]
+    fn header(&self) -> Option<Header> {
+        match &self.state {
+            State::Read(reader) => Some(Header::from(reader.header())),
+            _ => None,
+        }
+    }
+
+    /// Reads up to `size` bytes, or until the end of the file if `size` is negative.
+    #[pyo3(signature = (size = -1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut buf = Vec::new();
+        if size < 0 {
+            reader.read_to_end(&mut buf).map_err(io_err)?;
+        } else {
+            reader.take(size as u64).read_to_end(&mut buf).map_err(io_err)?;
+        }
+        self.pos += buf.len() as u64;
+        Ok(PyBytes::new(py, &buf))
+    }
+
+    /// Reads up to `size` bytes. Equivalent to `read()`.
+    #[pyo3(signature = (size = -1))]
+    fn read1<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        self.read(py, size)
+    }
+
+    /// Reads into the writable buffer `b`, returning the number of bytes read.
+    fn readinto(&mut self, b: PyBuffer<u8>) -> PyResult<usize> {
+        let buf = writable_buffer(&b, "b")?;
+        let reader = self.reader()?;
+        let mut len = 0;
+        while len < buf.len() {
+            match reader.read(&mut buf[len..]).map_err(io_err)? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    /// Reads a line, including the trailing newline.
+    #[pyo3(signature = (size = -1))]
+    fn readline<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while size < 0 || (line.len() as i64) < size {
+            if reader.read(&mut byte).map_err(io_err)? == 0 {
+                break;
+            }
+            line.push(byte[0]);
+            if byte[0] == b'\n' {
+                break;
+            }
+        }
+        self.pos += line.len() as u64;
+        Ok(PyBytes::new(py, &line))
+    }
+
+    /// Compresses and writes `data`, returning its length.
+    fn write(&mut self, data: &[u8]) -> PyResult<usize> {
+        self.writer()?.write_all(data).map_err(io_err)?;
+        self.pos += data.len() as u64;
+        Ok(data.len())
+    }
+
+    /// Moves to `offset` in the decompressed data, relative to the start (`whence` 0) or the
+    /// current position (`whence` 1), returning the new position.
+    #[pyo3(signature = (offset, whence = 0))]
+    fn seek(&mut self, py: Python, offset: i64, whence: i32) -> PyResult<u64> {
+        let target = match whence {
+            0 => offset,
+            1 => self.pos as i64 + offset,
+            _ => return Err(PyValueError::new_err("whence must be 0 or 1")),
+        };
+        if target < 0 {
+            return Err(PyValueError::new_err("negative seek position"));
+        }
+        let target = target as u64;
+        match &mut self.state {
+            State::Read(_) => {
+                if target < self.pos {
+                    self.rewind(py)?;
+                }
+                let remaining = target - self.pos;
+                let skipped = io::copy(&mut self.reader()?.take(remaining), &mut io::sink())
+                    .map_err(io_err)?;
+                self.pos += skipped;
+            }
+            State::Write(writer) => {
+                if target < self.pos {
+                    return Err(PyOSError::new_err("negative seek in write mode"));
+                }
+                io::copy(&mut io::repeat(0).take(target - self.pos), writer.as_mut())
+                    .map_err(io_err)?;
+                self.pos = target;
+            }
+            State::Closed => return Err(closed_error()),
+        }
+        Ok(self.pos)
+    }
+
+    /// Returns the position in the decompressed data.
+    fn tell(&self) -> PyResult<u64> {
+        match self.state {
+            State::Closed => Err(closed_error()),
+            _ => Ok(self.pos),
+        }
+    }
+
+    /// Writes any buffered data as a block.
+    fn flush(&mut self) -> PyResult<()> {
+        if let State::Write(writer) = &mut self.state {
+            writer.flush().map_err(io_err)?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the file, and closes the underlying file if it was opened by this object.
+    fn close(&mut self, py: Python) -> PyResult<()> {
+        let state = std::mem::replace(&mut self.state, State::Closed);
+        let result = match state {
+            State::Write(writer) => writer.finish().map(drop),
+            _ => Ok(()),
+        };
+        if let Some(file) = self.owned.take() {
+            file.call_method0(py, "close")?;
+        }
+        result.map_err(io_err)
+    }
+
+    #[getter]
+    fn closed(&self) -> bool { matches!(self.state, State::Closed) }
+
+    fn readable(&self) -> bool { matches!(self.state, State::Read(_)) }
+
+    fn writable(&self) -> bool { matches!(self.state, State::Write(_)) }
+
+    fn seekable(&self) -> bool { true }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> { slf }
+
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<()> {
+        self.close(py)
+    }
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> { slf }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let line = self.readline(py, -1)?;
+        Ok(if line.as_bytes().is_empty() { None } else { Some(line) })
+    }
+}
+
+impl LzoFile {
+    fn reader(&mut self) -> PyResult<&mut Reader<PyFile>> {
+        match &mut self.state {
+            State::Read(reader) => Ok(reader),
+            State::Write(_) => Err(PyOSError::new_err("read() on write-only LzoFile object")),
+            State::Closed => Err(closed_error()),
+        }
+    }
+
+    fn writer(&mut self) -> PyResult<&mut Writer<PyFile>> {
+        match &mut self.state {
+            State::Write(writer) => Ok(writer),
+            State::Read(_) => Err(PyOSError::new_err("write() on read-only LzoFile object")),
+            State::Closed => Err(closed_error()),
+        }
+    }
+
+    /// Seeks the underlying file back to the header, and starts reading again.
+    fn rewind(&mut self, py: Python) -> PyResult<()> {
+        let state = std::mem::replace(&mut self.state, State::Closed);
+        if let State::Read(reader) = state {
+            let file = reader.into_inner();
+            file.file.call_method1(py, "seek", (self.start,))?;
+            let mut reader = Reader::new(file).map_err(io_err)?;
+            reader.set_verify_checksums(self.verify_checksums);
+            self.state = State::Read(Box::new(reader));
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Opens an lzop file in binary mode, returning an `LzoFile`.
+#[pyfunction]
+#[pyo3(signature = (filename, mode = "rb"))]
+pub(super) fn open(py: Python, filename: PyObject, mode: &str) -> PyResult<LzoFile> {
+    LzoFile::new(py, Some(filename), Some(mode), None, true)
+}
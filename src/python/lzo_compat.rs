@@ -0,0 +1,122 @@
+//! `lzokay.lzo_compat`: a drop-in replacement for the python-lzo `lzo` module.
+//!
+//! Streams use python-lzo's header: a method byte (`0xf0`, or `0xf1` from `lzo1x_999`),
+//! followed by the decompressed size as a big-endian `u32`.
+
+use std::convert::TryFrom;
+
+use pyo3::{prelude::*, types::PyBytes};
+
+use super::{decompress_sized, to_py_err, LzokayError};
+use crate::{checksum, compress, Error};
+
+const HEADER_SIZE: usize = 5;
+const METHOD_LZO1X_1: u8 = 0xf0;
+const METHOD_LZO1X_999: u8 = 0xf1;
+
+fn header_error() -> PyErr { LzokayError::new_err("Header error - invalid compressed data") }
+
+/// Compresses `string`, prefixed with a header unless `header` is false.
+///
+/// All levels use the same compressor.
+#[pyfunction]
+#[pyo3(name = "compress", signature = (string, level = 1, header = true))]
+fn py_compress<'py>(
+    py: Python<'py>,
+    string: &[u8],
+    level: i32,
+    header: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let _ = level;
+    let size = u32::try_from(string.len()).map_err(|_| to_py_err(Error::Error))?;
+    let dst = py
+        .allow_threads(|| {
+            let offset = if header { HEADER_SIZE } else { 0 };
+            let mut dst = vec![0u8; offset + compress::compress_worst_size(string.len())];
+            let len =
+                compress::compress_no_alloc(string, &mut dst[offset..], &mut compress::new_dict())?;
+            dst.truncate(offset + len);
+            if header {
+                dst[0] = METHOD_LZO1X_1;
+                dst[1..HEADER_SIZE].copy_from_slice(&size.to_be_bytes());
+            }
+            Ok(dst)
+        })
+        .map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &dst))
+}
+
+/// Splits `string` into its payload and expected decompressed size.
+///
+/// Without a header, `buflen` is the maximum decompressed size, and need not be exact.
+fn split_header(string: &[u8], header: bool, buflen: i64) -> PyResult<(&[u8], usize, bool)> {
+    if header {
+        if string.len() < HEADER_SIZE
+            || (string[0] != METHOD_LZO1X_1 && string[0] != METHOD_LZO1X_999)
+        {
+            return Err(header_error());
+        }
+        let mut size = [0u8; 4];
+        size.copy_from_slice(&string[1..HEADER_SIZE]);
+        Ok((&string[HEADER_SIZE..], u32::from_be_bytes(size) as usize, true))
+    } else if buflen < 0 {
+        Err(LzokayError::new_err("Argument buflen required for headerless decompression"))
+    } else {
+        Ok((string, buflen as usize, false))
+    }
+}
+
+/// Decompresses `string`. Headerless streams require `buflen`, the maximum output size.
+#[pyfunction]
+#[pyo3(name = "decompress", signature = (string, header = true, buflen = -1))]
+fn py_decompress<'py>(
+    py: Python<'py>,
+    string: &[u8],
+    header: bool,
+    buflen: i64,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let (src, size, exact) = split_header(string, header, buflen)?;
+    let mut dst = vec![0u8; size];
+    let len = decompress_sized(py, src, &mut dst)?;
+    if exact && len != size {
+        return Err(LzokayError::new_err("Compressed data violation"));
+    }
+    Ok(PyBytes::new(py, &dst[..len]))
+}
+
+/// Validates `string`, returning it unchanged.
+///
+/// python-lzo rearranges the stream for faster decompression; this crate's streams have
+/// nothing to gain from that, so only the validation is performed.
+#[pyfunction]
+#[pyo3(signature = (string, header = true, buflen = -1))]
+fn optimize<'py>(
+    py: Python<'py>,
+    string: &Bound<'py, PyBytes>,
+    header: bool,
+    buflen: i64,
+) -> PyResult<Bound<'py, PyBytes>> {
+    py_decompress(py, string.as_bytes(), header, buflen)?;
+    Ok(string.clone())
+}
+
+/// Updates the Adler-32 checksum `value` with `string`.
+#[pyfunction]
+#[pyo3(signature = (string, value = checksum::ADLER32_INIT))]
+fn adler32(string: &[u8], value: u32) -> u32 { checksum::adler32(value, string) }
+
+/// Updates the CRC-32 checksum `value` with `string`.
+#[pyfunction]
+#[pyo3(signature = (string, value = checksum::CRC32_INIT))]
+fn crc32(string: &[u8], value: u32) -> u32 { checksum::crc32(value, string) }
+
+pub(super) fn module<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyModule>> {
+    let m = PyModule::new(py, "lzo_compat")?;
+    m.add_function(wrap_pyfunction!(py_compress, &m)?)?;
+    m.add_function(wrap_pyfunction!(py_decompress, &m)?)?;
+    m.add_function(wrap_pyfunction!(optimize, &m)?)?;
+    m.add_function(wrap_pyfunction!(adler32, &m)?)?;
+    m.add_function(wrap_pyfunction!(crc32, &m)?)?;
+    m.add("error", py.get_type::<LzokayError>())?;
+    Ok(m)
+}
@@ -0,0 +1,203 @@
+//! `lzokay.lzop`: reading and writing lzop files, in place of the `lzop` utility.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use super::{file::LzoFile, LzokayError};
+use crate::lzop::{self, Reader, SparseWriter, Writer};
+
+/// Converts an I/O error, raising invalid lzop data as `LzokayError` rather than `OSError`.
+pub(super) fn io_err(e: io::Error) -> PyErr {
+    if e.kind() == io::ErrorKind::InvalidData && !e.get_ref().is_some_and(|e| e.is::<PyErr>()) {
+        LzokayError::new_err(e.to_string())
+    } else {
+        PyErr::from(e)
+    }
+}
+
+/// lzop file metadata.
+#[pyclass(module = "lzokay.lzop", name = "Header", frozen)]
+pub(super) struct Header {
+    inner: lzop::Header,
+}
+
+impl From<&lzop::Header> for Header {
+    fn from(header: &lzop::Header) -> Self { Header { inner: header.clone() } }
+}
+
+#[pymethods]
+impl Header {
+    /// Format version of the writer.
+    #[getter]
+    fn version(&self) -> u16 { self.inner.version }
+
+    /// liblzo2 version of the writer.
+    #[getter]
+    fn lib_version(&self) -> u16 { self.inner.lib_version }
+
+    /// Format version needed to extract the file.
+    #[getter]
+    fn version_needed(&self) -> u16 { self.inner.version_needed }
+
+    /// Compression method: 1 for LZO1X-1, 2 for LZO1X-1(15) or 3 for LZO1X-999.
+    #[getter]
+    fn method(&self) -> u8 { self.inner.method }
+
+    /// Compression level.
+    #[getter]
+    fn level(&self) -> u8 { self.inner.level }
+
+    /// Header flags, including which checksums are present.
+    #[getter]
+    fn flags(&self) -> u32 { self.inner.flags }
+
+    /// Unix file mode of the original file.
+    #[getter]
+    fn mode(&self) -> u32 { self.inner.mode }
+
+    /// Modification time of the original file, in seconds since the Unix epoch.
+    #[getter]
+    fn mtime(&self) -> u64 { self.inner.mtime }
+
+    /// Name of the original file.
+    #[getter]
+    fn name(&self) -> &[u8] { &self.inner.name }
+
+    /// Contents of the extra field, if present.
+    #[getter]
+    fn extra(&self) -> Option<&[u8]> { self.inner.extra.as_deref() }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Header(name={:?}, mode={:#o}, mtime={}, method={}, level={})",
+            String::from_utf8_lossy(&self.inner.name),
+            self.inner.mode,
+            self.inner.mtime,
+            self.inner.method,
+            self.inner.level
+        )
+    }
+}
+
+/// Opens an lzop file in binary mode, returning an `LzoFile`.
+#[pyfunction]
+#[pyo3(signature = (filename, mode = "rb", verify_checksums = true))]
+fn open(py: Python, filename: PyObject, mode: &str, verify_checksums: bool) -> PyResult<LzoFile> {
+    LzoFile::new(py, Some(filename), Some(mode), None, verify_checksums)
+}
+
+/// Creates a header recording `path`'s name, mode and modification time, as lzop does.
+fn file_header(path: &Path, file: &File) -> io::Result<lzop::Header> {
+    let metadata = file.metadata()?;
+    let mut header = lzop::Header::default();
+    if let Some(name) = path.file_name() {
+        #[cfg(unix)]
+        {
+            header.name = std::os::unix::ffi::OsStrExt::as_bytes(name).to_vec();
+        }
+        #[cfg(not(unix))]
+        {
+            header.name = name.to_string_lossy().into_owned().into_bytes();
+        }
+    }
+    #[cfg(unix)]
+    {
+        header.mode = std::os::unix::fs::MetadataExt::mode(&metadata);
+    }
+    if let Some(mtime) =
+        metadata.modified().ok().and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+    {
+        header.mtime = mtime.as_secs();
+    }
+    Ok(header)
+}
+
+/// Compresses the file at `src` to an lzop file at `dst`, by default `src` with a `.lzo`
+/// suffix, returning `dst`.
+///
+/// Like lzop, the file's name, mode and modification time are recorded in the header.
+#[pyfunction]
+#[pyo3(signature = (src, dst = None))]
+pub(super) fn compress_file(py: Python, src: PathBuf, dst: Option<PathBuf>) -> PyResult<PathBuf> {
+    let dst = dst.unwrap_or_else(|| {
+        let mut dst = src.clone().into_os_string();
+        dst.push(".lzo");
+        dst.into()
+    });
+    py.allow_threads(|| -> io::Result<()> {
+        let mut input = File::open(&src)?;
+        let header = file_header(&src, &input)?;
+        let output = BufWriter::new(File::create(&dst)?);
+        let mut writer = Writer::with_header(output, &header)?;
+        io::copy(&mut input, &mut writer)?;
+        writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    })
+    .map_err(io_err)?;
+    Ok(dst)
+}
+
+/// Decompresses the lzop file at `src` to `dst`, by default `src` without its `.lzo` suffix,
+/// returning the file's `Header`.
+///
+/// Like lzop, the original file's mode and modification time are restored. Block checksums are
+/// verified unless `verify_checksums` is false. Unless `sparse` is false, blocks of zeros are
+/// left as holes in file systems supporting sparse files, as for disk images.
+#[pyfunction]
+#[pyo3(signature = (src, dst = None, verify_checksums = true, sparse = true))]
+pub(super) fn decompress_file(
+    py: Python,
+    src: PathBuf,
+    dst: Option<PathBuf>,
+    verify_checksums: bool,
+    sparse: bool,
+) -> PyResult<Header> {
+    let dst = match dst {
+        Some(dst) => dst,
+        None if src.extension().is_some_and(|ext| ext == "lzo") => src.with_extension(""),
+        None => return Err(PyValueError::new_err("dst is required without a .lzo suffix")),
+    };
+    let header = py
+        .allow_threads(|| -> io::Result<lzop::Header> {
+            let mut reader = Reader::new(BufReader::new(File::open(&src)?))?;
+            reader.set_verify_checksums(verify_checksums);
+            let output = if sparse {
+                let mut output = SparseWriter::new(File::create(&dst)?);
+                io::copy(&mut reader, &mut output)?;
+                output.finish()?
+            } else {
+                let mut output = BufWriter::new(File::create(&dst)?);
+                io::copy(&mut reader, &mut output)?;
+                output.flush()?;
+                output.into_inner().map_err(|e| e.into_error())?
+            };
+            let header = reader.header();
+            if header.mtime != 0 {
+                output.set_modified(UNIX_EPOCH + Duration::from_secs(header.mtime))?;
+            }
+            #[cfg(unix)]
+            if header.mode & 0o7777 != 0 {
+                use std::os::unix::fs::PermissionsExt;
+                output.set_permissions(std::fs::Permissions::from_mode(header.mode & 0o7777))?;
+            }
+            Ok(header.clone())
+        })
+        .map_err(io_err)?;
+    Ok(Header { inner: header })
+}
+
+pub(super) fn module<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyModule>> {
+    let m = PyModule::new(py, "lzop")?;
+    m.add_function(wrap_pyfunction!(open, &m)?)?;
+    m.add_function(wrap_pyfunction!(compress_file, &m)?)?;
+    m.add_function(wrap_pyfunction!(decompress_file, &m)?)?;
+    m.add_class::<LzoFile>()?;
+    m.add_class::<Header>()?;
+    Ok(m)
+}
@@ -0,0 +1,121 @@
+//! `lzokay.compress_array()` and `lzokay.decompress_array()`, for numpy arrays.
+//!
+//! numpy is imported at runtime, so it isn't needed to build the extension. Compressed arrays
+//! are prefixed with a header recording the dtype and shape:
+//!
+//! - The magic `b"LZNP"` and a version byte, 1.
+//! - The length of the dtype string, as a `u8`, followed by the dtype string, such as `<f8`.
+//! - The number of dimensions, as a `u8`, followed by each dimension as a little-endian `u64`.
+//!
+//! The rest is an LZO1X stream of the array's bytes in C order.
+
+use std::{convert::TryFrom, slice};
+
+use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::{IntoPyDict, PyBytes},
+};
+
+use super::{decompress_sized, to_py_err, writable_buffer, LzokayError};
+use crate::compress;
+
+const MAGIC: &[u8; 4] = b"LZNP";
+const VERSION: u8 = 1;
+
+fn header_error() -> PyErr { LzokayError::new_err("invalid compressed array header") }
+
+/// Views `array`'s data as a flat `uint8` buffer.
+fn byte_buffer(array: &Bound<'_, PyAny>) -> PyResult<PyBuffer<u8>> {
+    PyBuffer::get(&array.call_method1("reshape", (-1,))?.call_method1("view", ("u1",))?)
+}
+
+/// Compresses `array`, which may be anything `numpy.asarray()` accepts, returning bytes that
+/// `decompress_array()` restores with the same dtype and shape.
+#[pyfunction]
+pub(super) fn compress_array<'py>(
+    py: Python<'py>,
+    array: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let array = py.import("numpy")?.call_method(
+        "asarray",
+        (array,),
+        Some(&[("order", "C")].into_py_dict(py)?),
+    )?;
+    let dtype = array.getattr("dtype")?;
+    if dtype.getattr("hasobject")?.extract()? {
+        return Err(PyTypeError::new_err("arrays of Python objects can't be compressed"));
+    }
+    let dtype: String = dtype.getattr("str")?.extract()?;
+    let shape: Vec<u64> = array.getattr("shape")?.extract()?;
+    let dtype_len =
+        u8::try_from(dtype.len()).map_err(|_| PyValueError::new_err("dtype string is too long"))?;
+    let ndim =
+        u8::try_from(shape.len()).map_err(|_| PyValueError::new_err("too many dimensions"))?;
+
+    let mut header = Vec::with_capacity(MAGIC.len() + 3 + dtype.len() + shape.len() * 8);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.push(dtype_len);
+    header.extend_from_slice(dtype.as_bytes());
+    header.push(ndim);
+    for dim in &shape {
+        header.extend_from_slice(&dim.to_le_bytes());
+    }
+
+    let buffer = byte_buffer(&array)?;
+    // SAFETY: The buffer is contiguous, as the array is, and `buffer` keeps it alive.
+    let src = unsafe { slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes()) };
+    let dst = py
+        .allow_threads(|| {
+            let offset = header.len();
+            let mut dst = header;
+            dst.resize(offset + compress::compress_worst_size(src.len()), 0);
+            let len =
+                compress::compress_no_alloc(src, &mut dst[offset..], &mut compress::new_dict())?;
+            dst.truncate(offset + len);
+            Ok(dst)
+        })
+        .map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &dst))
+}
+
+/// Splits a compressed array into its dtype, shape and LZO1X stream.
+fn parse_header(data: &[u8]) -> PyResult<(&str, Vec<u64>, &[u8])> {
+    let mut pos = MAGIC.len() + 1;
+    if data.len() < pos + 1 || &data[..MAGIC.len()] != MAGIC || data[MAGIC.len()] != VERSION {
+        return Err(header_error());
+    }
+    let dtype_len = data[pos] as usize;
+    pos += 1;
+    let dtype = data.get(pos..pos + dtype_len).ok_or_else(header_error)?;
+    let dtype = std::str::from_utf8(dtype).map_err(|_| header_error())?;
+    pos += dtype_len;
+    let ndim = *data.get(pos).ok_or_else(header_error)? as usize;
+    pos += 1;
+    let mut shape = Vec::with_capacity(ndim);
+    for _ in 0..ndim {
+        let dim = data.get(pos..pos + 8).ok_or_else(header_error)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(dim);
+        shape.push(u64::from_le_bytes(bytes));
+        pos += 8;
+    }
+    Ok((dtype, shape, &data[pos..]))
+}
+
+/// Decompresses the output of `compress_array()` into a new array with its original dtype and
+/// shape.
+#[pyfunction]
+pub(super) fn decompress_array<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyAny>> {
+    let (dtype, shape, src) = parse_header(data)?;
+    let array = py.import("numpy")?.call_method1("empty", (shape, dtype))?;
+    let buffer = byte_buffer(&array)?;
+    let dst = writable_buffer(&buffer, "array")?;
+    let expected = dst.len();
+    if decompress_sized(py, src, dst)? != expected {
+        return Err(LzokayError::new_err("decompressed size doesn't match the array shape"));
+    }
+    Ok(array)
+}
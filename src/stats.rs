@@ -0,0 +1,162 @@
+//! # Global statistics
+//!
+//! Available with feature `stats`.
+//!
+//! Process-wide counters of the calls made, bytes processed and failures, updated with relaxed
+//! atomic adds: a handful of instructions per call, without locks or allocation. For monitoring
+//! embedded devices and servers without a metrics library.
+//!
+//! The one-shot functions of LZO1X, LZO1Y and LZO1Z are counted, as is [`Compressor`] once it
+//! finishes. Counters wrap on overflow, so long-running processes should compare snapshots
+//! rather than read totals.
+//!
+//! Requires atomic adds on `usize`, which some targets, such as `thumbv6m`, lack.
+//!
+//! [`Compressor`]: crate::compress::Compressor
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(all(feature = "compress", feature = "alloc"))] {
+//! use lzokay::{compress::compress, stats};
+//!
+//! let before = stats::snapshot();
+//! compress(&[0u8; 512])?;
+//! let after = stats::snapshot();
+//! assert!(after.compress_calls > before.compress_calls);
+//! # }
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+use crate::Error;
+
+/// Counters for one direction.
+struct Counters {
+    calls: AtomicUsize,
+    failures: AtomicUsize,
+    input_bytes: AtomicUsize,
+    output_bytes: AtomicUsize,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Counters {
+            calls: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            input_bytes: AtomicUsize::new(0),
+            output_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn record(&self, input_len: usize, result: &Result<usize, Error>) {
+        self.calls.fetch_add(1, Relaxed);
+        match result {
+            Ok(output_len) => {
+                self.input_bytes.fetch_add(input_len, Relaxed);
+                self.output_bytes.fetch_add(*output_len, Relaxed);
+            }
+            Err(_) => {
+                self.failures.fetch_add(1, Relaxed);
+            }
+        }
+    }
+
+    fn reset(&self) {
+        self.calls.store(0, Relaxed);
+        self.failures.store(0, Relaxed);
+        self.input_bytes.store(0, Relaxed);
+        self.output_bytes.store(0, Relaxed);
+    }
+}
+
+static COMPRESS: Counters = Counters::new();
+static DECOMPRESS: Counters = Counters::new();
+
+/// Values of the counters at one point in time.
+///
+/// Byte counts only include successful calls. The counters are read one at a time, so a
+/// snapshot taken during a call may include some of its counts but not others.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    /// Number of compressions.
+    pub compress_calls: usize,
+    /// Number of compressions that failed.
+    pub compress_failures: usize,
+    /// Number of bytes compressed.
+    pub compress_input_bytes: usize,
+    /// Number of compressed bytes written.
+    pub compress_output_bytes: usize,
+    /// Number of decompressions.
+    pub decompress_calls: usize,
+    /// Number of decompressions that failed.
+    pub decompress_failures: usize,
+    /// Number of compressed bytes decompressed.
+    pub decompress_input_bytes: usize,
+    /// Number of decompressed bytes written.
+    pub decompress_output_bytes: usize,
+}
+
+/// Reads the counters.
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        compress_calls: COMPRESS.calls.load(Relaxed),
+        compress_failures: COMPRESS.failures.load(Relaxed),
+        compress_input_bytes: COMPRESS.input_bytes.load(Relaxed),
+        compress_output_bytes: COMPRESS.output_bytes.load(Relaxed),
+        decompress_calls: DECOMPRESS.calls.load(Relaxed),
+        decompress_failures: DECOMPRESS.failures.load(Relaxed),
+        decompress_input_bytes: DECOMPRESS.input_bytes.load(Relaxed),
+        decompress_output_bytes: DECOMPRESS.output_bytes.load(Relaxed),
+    }
+}
+
+/// Sets every counter to zero.
+///
+/// Calls in progress on other threads may be partially counted afterwards.
+pub fn reset() {
+    COMPRESS.reset();
+    DECOMPRESS.reset();
+}
+
+#[cfg(feature = "compress")]
+#[inline]
+pub(crate) fn record_compress(input_len: usize, result: &Result<usize, Error>) {
+    COMPRESS.record(input_len, result);
+}
+
+#[cfg(feature = "decompress")]
+#[inline]
+pub(crate) fn record_decompress(input_len: usize, result: &Result<usize, Error>) {
+    DECOMPRESS.record(input_len, result);
+}
+
+#[cfg(all(test, feature = "compress", feature = "decompress", feature = "alloc"))]
+mod tests {
+    use super::snapshot;
+    use crate::{compress::compress, decompress::decompress, Error};
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    #[test]
+    fn test_stats() {
+        // Other tests run concurrently, so counters may grow by more than this test's calls
+        let before = snapshot();
+        let compressed = compress(INPUT).unwrap();
+        let mut dst = [0u8; INPUT.len()];
+        decompress(&compressed, &mut dst).unwrap();
+        let truncated = &compressed[..compressed.len() - 1];
+        assert_eq!(decompress(truncated, &mut dst), Err(Error::InputOverrun));
+        let after = snapshot();
+
+        assert!(after.compress_calls - before.compress_calls >= 1);
+        assert!(after.compress_input_bytes - before.compress_input_bytes >= INPUT.len());
+        assert!(after.compress_output_bytes - before.compress_output_bytes >= compressed.len());
+        assert!(after.decompress_calls - before.decompress_calls >= 2);
+        assert!(after.decompress_failures - before.decompress_failures >= 1);
+        assert!(after.decompress_input_bytes - before.decompress_input_bytes >= compressed.len());
+        assert!(after.decompress_output_bytes - before.decompress_output_bytes >= INPUT.len());
+    }
+}
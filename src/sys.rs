@@ -0,0 +1,49 @@
+//! # Raw C++ bindings
+//!
+//! Available with feature `sys`. Implies `cpp-backend`.
+//!
+//! The generated bindings to the original C++ implementation, as used by the [`cpp`](crate::cpp)
+//! module. Prefer the safe wrappers there; these are for calling upstream's entry points
+//! directly, e.g. to manage [`lzokay_DictBase`] storage manually.
+//!
+//! `lzokay_compress` and `lzokay_decompress` are thin `extern "C"` wrappers around
+//! `lzokay::compress` and `lzokay::decompress`, see `wrapper.hpp`.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::sys;
+//!
+//! let src = [0x14, b'a', b'b', b'c', 0x11, 0, 0];
+//! let mut dst = [0u8; 3];
+//! let mut out_size = 0;
+//! let result = unsafe {
+//!     sys::lzokay_decompress(src.as_ptr(), src.len(), dst.as_mut_ptr(), dst.len(), &mut out_size)
+//! };
+//! assert_eq!(result, sys::lzokay_EResult_Success);
+//! assert_eq!(&dst[..out_size], b"abc");
+//! ```
+
+#![allow(unknown_lints)]
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(deref_nullptr)]
+#![allow(dead_code)]
+
+#[cfg(not(feature = "std"))]
+mod types {
+    pub type c_uchar = u8;
+    pub type c_ushort = u16;
+    pub type c_uint = u32;
+    pub type c_int = i32;
+}
+#[cfg(feature = "std")]
+mod types {
+    pub type c_uchar = ::std::os::raw::c_uchar;
+    pub type c_ushort = ::std::os::raw::c_ushort;
+    pub type c_uint = ::std::os::raw::c_uint;
+    pub type c_int = ::std::os::raw::c_int;
+}
+
+include!("sys/bindings.rs");
@@ -0,0 +1,50 @@
+/* automatically generated by rust-bindgen 0.59.2 */
+
+pub const lzokay_EResult_LookbehindOverrun: lzokay_EResult = -4;
+pub const lzokay_EResult_OutputOverrun: lzokay_EResult = -3;
+pub const lzokay_EResult_InputOverrun: lzokay_EResult = -2;
+pub const lzokay_EResult_Error: lzokay_EResult = -1;
+pub const lzokay_EResult_Success: lzokay_EResult = 0;
+pub const lzokay_EResult_InputNotConsumed: lzokay_EResult = 1;
+pub type lzokay_EResult = types::c_int;
+#[repr(C)]
+pub struct lzokay_DictBase {
+    pub _storage: *mut lzokay_DictBase_storage_type,
+}
+#[repr(C)]
+pub struct lzokay_DictBase_Match3 {
+    pub head: [u16; 16384usize],
+    pub chain_sz: [u16; 16384usize],
+    pub chain: [u16; 51199usize],
+    pub best_len: [u16; 51199usize],
+}
+#[repr(C)]
+pub struct lzokay_DictBase_Match2 {
+    pub head: [u16; 65536usize],
+}
+#[repr(C)]
+pub struct lzokay_DictBase_Data {
+    pub match3: lzokay_DictBase_Match3,
+    pub match2: lzokay_DictBase_Match2,
+    pub buffer: [u8; 53247usize],
+}
+pub type lzokay_DictBase_storage_type = lzokay_DictBase_Data;
+extern "C" {
+    pub fn lzokay_compress(
+        src: *const u8,
+        src_size: usize,
+        dst: *mut u8,
+        dst_size: usize,
+        out_size: *mut usize,
+        dict: *mut lzokay_DictBase,
+    ) -> lzokay_EResult;
+}
+extern "C" {
+    pub fn lzokay_decompress(
+        src: *const u8,
+        src_size: usize,
+        dst: *mut u8,
+        dst_size: usize,
+        out_size: *mut usize,
+    ) -> lzokay_EResult;
+}
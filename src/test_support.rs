@@ -0,0 +1,240 @@
+//! # Test vectors
+//!
+//! Available with feature `test-support`.
+//!
+//! Known-good pairs of input and compressed stream, and generators of streams that exercise
+//! each instruction encoding or fail to decompress in a known way. For crates wrapping lzokay,
+//! to write integration tests without vendoring binary fixtures; enable the feature in
+//! `[dev-dependencies]` only.
+//!
+//! # Examples
+//!
+//! ```
+//! use lzokay::{decompress::decompress, test_support::{all_instructions, VECTORS}};
+//!
+//! for vector in VECTORS.iter() {
+//!     let mut dst = vec![0u8; vector.input.len()];
+//!     let result = decompress(vector.compressed, &mut dst);
+//!     assert_eq!(result, Ok(vector.input.len()), "{}", vector.name);
+//!     assert_eq!(dst, vector.input);
+//! }
+//!
+//! let stream = all_instructions();
+//! let mut dst = vec![0u8; stream.input.len()];
+//! decompress(&stream.compressed, &mut dst)?;
+//! assert_eq!(dst, stream.input);
+//! # Ok::<(), lzokay::Error>(())
+//! ```
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::{compress::TokenWriter, format, Error};
+
+/// A known-good input and its compressed stream.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Vector {
+    /// Short description, for assertion messages.
+    pub name: &'static str,
+    /// The decompressed data.
+    pub input: &'static [u8],
+    /// `input` compressed by this crate at the default level.
+    pub compressed: &'static [u8],
+}
+
+/// Known-good pairs of input and compressed stream: text, a run of one byte, and an empty
+/// input.
+pub const VECTORS: [Vector; 4] = [
+    Vector {
+        name: "text 1",
+        input: include_bytes!("test1.txt"),
+        compressed: include_bytes!("test1.bin"),
+    },
+    Vector {
+        name: "text 2",
+        input: include_bytes!("test2.txt"),
+        compressed: include_bytes!("test2.bin"),
+    },
+    Vector {
+        name: "run",
+        input: &[b'a'; 512],
+        compressed: &[0x12, b'a', 0x20, 0x00, 0xdf, 0x00, 0x00, 0x11, 0x00, 0x00],
+    },
+    Vector { name: "empty", input: &[], compressed: &format::END_OF_STREAM },
+];
+
+/// A generated input and a stream that decompresses to it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stream {
+    /// The decompressed data.
+    pub input: Vec<u8>,
+    /// A stream decompressing to `input`.
+    pub compressed: Vec<u8>,
+}
+
+/// A stream that fails to decompress.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Corrupt {
+    /// Short description, for assertion messages.
+    pub name: &'static str,
+    /// The stream.
+    pub compressed: Vec<u8>,
+    /// Size of the output buffer to decompress into.
+    pub dst_len: usize,
+    /// The error decompressing into `dst_len` bytes fails with.
+    pub error: Error,
+}
+
+/// A stream of one literal run of `len` bytes of noise. Runs longer than
+/// [`format::FIRST_LITERAL_MAX_LEN`] have an extended length.
+pub fn literal_run(len: usize) -> Stream { encode(&[Token::Literal(noise(len))]) }
+
+/// A stream of `dist` bytes of noise followed by a match copying `len` bytes from `dist` back,
+/// which overlaps its own output if `len` is greater.
+///
+/// `dist` must be from 1 to [`format::MAX_DISTANCE`], and `len` at least 3.
+pub fn repeated_match(len: usize, dist: usize) -> Stream {
+    encode(&[Token::Literal(noise(dist)), Token::Match(len, dist)])
+}
+
+/// A stream with every instruction encoding: M1 matches of both kinds, M2 matches, M3 and M4
+/// matches with and without extended lengths, reaching [`format::MAX_DISTANCE`], and literal
+/// runs stored in a match's state bits, as the first instruction, and with extended lengths.
+pub fn all_instructions() -> Stream {
+    use Token::{Literal as L, Match as M};
+
+    encode(&[
+        L(noise(format::FIRST_LITERAL_MAX_LEN)),
+        M(format::MIN_MATCH_LEN, 1),
+        L(noise(2)),
+        M(format::M1_LEN, 200),
+        L(noise(10)),
+        M(format::M3_MAX_SHORT_LEN + 7, 150),
+        L(noise(300)),
+        M(50_000, 1),
+        M(format::M4_MAX_SHORT_LEN, format::M4_MIN_DISTANCE),
+        L(noise(4)),
+        M(format::M1_LONG_LEN, format::M1_LONG_MIN_DISTANCE + 100),
+        M(format::MAX_MATCH_LEN, format::MAX_DISTANCE),
+        M(format::M2_MAX_LEN, format::M2_MAX_DISTANCE),
+    ])
+}
+
+/// Streams that fail to decompress, one for each error a decoder reports.
+pub fn corrupt_streams() -> Vec<Corrupt> {
+    let Stream { input, compressed } = all_instructions();
+    let mut trailing = compressed.clone();
+    trailing.push(0);
+    vec![
+        Corrupt {
+            name: "truncated",
+            compressed: compressed[..compressed.len() - 1].to_vec(),
+            dst_len: input.len(),
+            error: Error::InputOverrun,
+        },
+        Corrupt { name: "empty", compressed: vec![], dst_len: 0, error: Error::InputOverrun },
+        Corrupt {
+            name: "output too small",
+            compressed: compressed.clone(),
+            dst_len: input.len() - 1,
+            error: Error::OutputOverrun,
+        },
+        Corrupt {
+            name: "trailing data",
+            compressed: trailing,
+            dst_len: input.len(),
+            error: Error::InputNotConsumed,
+        },
+        Corrupt {
+            name: "match before the start",
+            // 4 literals, then an M2 match 2048 bytes back
+            compressed: vec![0x15, 1, 2, 3, 4, 0xfc, 0xff, 0x11, 0, 0],
+            dst_len: 4096,
+            error: Error::LookbehindOverrun,
+        },
+    ]
+}
+
+/// An element of a generated stream.
+enum Token {
+    Literal(Vec<u8>),
+    /// Length and distance.
+    Match(usize, usize),
+}
+
+/// Serializes `tokens`, which must form a valid stream.
+fn encode(tokens: &[Token]) -> Stream {
+    let mut input = Vec::new();
+    // Each token needs at most a few bytes of instruction, plus one per 255 of length
+    let bound = tokens.iter().fold(3, |bound, token| match token {
+        Token::Literal(lit) => bound + lit.len() + lit.len() / 255 + 4,
+        Token::Match(len, _) => bound + len / 255 + 5,
+    });
+    let mut compressed = vec![0u8; bound];
+    let mut writer = TokenWriter::new(&mut compressed);
+    for token in tokens {
+        let result = match *token {
+            Token::Literal(ref lit) => {
+                input.extend_from_slice(lit);
+                writer.write_literal(lit)
+            }
+            Token::Match(len, dist) => {
+                let start = input.len() - dist;
+                for i in 0..len {
+                    input.push(input[start + i]);
+                }
+                writer.write_match(len, dist)
+            }
+        };
+        result.expect("Generated an invalid token");
+    }
+    let size = writer.finish().expect("Generated stream is too large");
+    compressed.truncate(size);
+    Stream { input, compressed }
+}
+
+/// Deterministic, incompressible bytes.
+fn noise(len: usize) -> Vec<u8> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64 ^ len as u64;
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use super::{all_instructions, corrupt_streams, literal_run, repeated_match, Stream, VECTORS};
+    use crate::{compress::compress, decompress::decompress};
+
+    fn check(stream: &Stream) {
+        let mut dst = vec![0u8; stream.input.len()];
+        assert_eq!(decompress(&stream.compressed, &mut dst), Ok(stream.input.len()));
+        assert_eq!(dst, stream.input);
+    }
+
+    #[test]
+    fn test_vectors() {
+        for vector in VECTORS.iter() {
+            assert_eq!(compress(vector.input).as_deref(), Ok(vector.compressed), "{}", vector.name);
+        }
+        for &len in &[1, 3, 4, 238, 239, 255 + 18, 100_000] {
+            check(&literal_run(len));
+        }
+        check(&repeated_match(3, 4));
+        check(&repeated_match(100_000, 7));
+        check(&all_instructions());
+        for corrupt in corrupt_streams() {
+            let mut dst = vec![0u8; corrupt.dst_len];
+            let result = decompress(&corrupt.compressed, &mut dst);
+            assert_eq!(result, Err(corrupt.error), "{}", corrupt.name);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Opcode layout differences between the members of the LZO1X family.
+//!
+//! LZO1X and its siblings share the same instruction set (M1–M4 matches, literal runs and
+//! the two-bit literal state), but differ in how M1/M2 matches and their distances are packed.
+//! The compressor and decompressor are written once against [`Variant`] and monomorphized
+//! for each supported format.
+
+// Encoding and decoding halves are only used with their respective features.
+#![cfg_attr(not(all(feature = "compress", feature = "decompress")), allow(dead_code))]
+
+/// Bit layout of a single LZO1X-family format.
+pub(crate) trait Variant {
+    /// Maximum distance of an M2 match.
+    const M2_MAX_OFFSET: u32;
+    /// Maximum length of an M2 match.
+    const M2_MAX_LEN: u32;
+    /// Position, counted from the end of an encoded match, of the byte carrying the
+    /// trailing literal count.
+    const STATE_OFFSET: usize;
+
+    /// Encodes an M1 match distance, already reduced by its base distance.
+    fn encode_m1(off: u32) -> [u8; 2];
+    /// Decodes an M1 instruction and its trailing byte into (reduced distance, state).
+    fn decode_m1(inst: u8, next: u8) -> (usize, usize);
+    /// Encodes an M2 match of `len` bytes at distance `off + 1`.
+    fn encode_m2(len: u32, off: u32) -> [u8; 2];
+    /// Whether this M2 instruction reuses the previous match distance instead of being
+    /// followed by a distance byte.
+    #[inline]
+    fn m2_reuses_offset(_inst: u8) -> bool { false }
+    /// Decodes an M2 instruction and its trailing byte into (distance, length, state).
+    fn decode_m2(inst: u8, next: u8) -> (usize, usize, usize);
+    /// Encodes the 14-bit distance trailing M3 and M4 instructions.
+    fn encode_distance(off: u32) -> [u8; 2];
+    /// Decodes the 14-bit distance trailing M3 and M4 instructions into (distance, state).
+    fn decode_distance(b0: u8, b1: u8) -> (usize, usize);
+}
+
+/// The LZO1X format.
+pub struct Lzo1x;
+
+impl Variant for Lzo1x {
+    const M2_MAX_LEN: u32 = crate::format::M2_MAX_LEN as u32;
+    const M2_MAX_OFFSET: u32 = crate::format::M2_MAX_DISTANCE as u32;
+    const STATE_OFFSET: usize = 2;
+
+    #[inline]
+    fn encode_m1(off: u32) -> [u8; 2] { [((off & 0x3) << 2) as u8, (off >> 2) as u8] }
+
+    #[inline]
+    fn decode_m1(inst: u8, next: u8) -> (usize, usize) {
+        ((inst as usize >> 2) + ((next as usize) << 2), inst as usize & 0x3)
+    }
+
+    #[inline]
+    fn encode_m2(len: u32, off: u32) -> [u8; 2] {
+        [((len - 1) << 5 | (off & 0x7) << 2) as u8, (off >> 3) as u8]
+    }
+
+    #[inline]
+    fn decode_m2(inst: u8, next: u8) -> (usize, usize, usize) {
+        (
+            ((next as usize) << 3) + ((inst as usize >> 2) & 0x7) + 1,
+            (inst as usize >> 5) + 1,
+            inst as usize & 0x3,
+        )
+    }
+
+    #[inline]
+    fn encode_distance(off: u32) -> [u8; 2] { [(off << 2) as u8, (off >> 6) as u8] }
+
+    #[inline]
+    fn decode_distance(b0: u8, b1: u8) -> (usize, usize) {
+        let v = u16::from_le_bytes([b0, b1]) as usize;
+        (v >> 2, v & 0x3)
+    }
+}
+
+/// The LZO1Y format.
+///
+/// Identical to LZO1X, except that M2 matches cover a 1 KiB window
+/// with lengths of 3 to 14 bytes.
+#[cfg(feature = "lzo1y")]
+pub struct Lzo1y;
+
+#[cfg(feature = "lzo1y")]
+impl Variant for Lzo1y {
+    const M2_MAX_LEN: u32 = 14;
+    const M2_MAX_OFFSET: u32 = 0x0400;
+    const STATE_OFFSET: usize = 2;
+
+    #[inline]
+    fn encode_m1(off: u32) -> [u8; 2] { Lzo1x::encode_m1(off) }
+
+    #[inline]
+    fn decode_m1(inst: u8, next: u8) -> (usize, usize) { Lzo1x::decode_m1(inst, next) }
+
+    #[inline]
+    fn encode_m2(len: u32, off: u32) -> [u8; 2] {
+        [((len + 1) << 4 | (off & 0x3) << 2) as u8, (off >> 2) as u8]
+    }
+
+    #[inline]
+    fn decode_m2(inst: u8, next: u8) -> (usize, usize, usize) {
+        (
+            ((next as usize) << 2) + ((inst as usize >> 2) & 0x3) + 1,
+            (inst as usize >> 4) - 1,
+            inst as usize & 0x3,
+        )
+    }
+
+    #[inline]
+    fn encode_distance(off: u32) -> [u8; 2] { Lzo1x::encode_distance(off) }
+
+    #[inline]
+    fn decode_distance(b0: u8, b1: u8) -> (usize, usize) { Lzo1x::decode_distance(b0, b1) }
+}
+
+/// The LZO1Z format.
+///
+/// Distances are stored most-significant bits first, with the literal state in the final byte.
+/// M2 matches cover a 1792-byte window, and may reuse the distance of the previous match.
+#[cfg(feature = "lzo1z")]
+pub struct Lzo1z;
+
+#[cfg(feature = "lzo1z")]
+impl Variant for Lzo1z {
+    const M2_MAX_LEN: u32 = 8;
+    const M2_MAX_OFFSET: u32 = 0x0700;
+    const STATE_OFFSET: usize = 1;
+
+    #[inline]
+    fn encode_m1(off: u32) -> [u8; 2] { Self::encode_distance(off) }
+
+    #[inline]
+    fn decode_m1(inst: u8, next: u8) -> (usize, usize) { Self::decode_distance(inst, next) }
+
+    #[inline]
+    fn encode_m2(len: u32, off: u32) -> [u8; 2] {
+        [((len - 1) << 5 | off >> 6) as u8, ((off & 0x3f) << 2) as u8]
+    }
+
+    #[inline]
+    fn m2_reuses_offset(inst: u8) -> bool { inst & 0x1f >= 0x1c }
+
+    #[inline]
+    fn decode_m2(inst: u8, next: u8) -> (usize, usize, usize) {
+        let len = (inst as usize >> 5) + 1;
+        if Self::m2_reuses_offset(inst) {
+            (0, len, inst as usize & 0x3)
+        } else {
+            (((inst as usize & 0x1f) << 6) + (next as usize >> 2) + 1, len, next as usize & 0x3)
+        }
+    }
+
+    #[inline]
+    fn encode_distance(off: u32) -> [u8; 2] { [(off >> 6) as u8, ((off & 0x3f) << 2) as u8] }
+
+    #[inline]
+    fn decode_distance(b0: u8, b1: u8) -> (usize, usize) {
+        (((b0 as usize) << 6) + (b1 as usize >> 2), b1 as usize & 0x3)
+    }
+}
@@ -0,0 +1,191 @@
+//! # JavaScript bindings
+//!
+//! Available with feature `wasm`.
+//!
+//! [wasm-bindgen](https://rustwasm.github.io/docs/wasm-bindgen/) exports for using this crate
+//! from JavaScript and TypeScript. Build a package, including TypeScript definitions, with
+//! [wasm-pack](https://rustwasm.github.io/wasm-pack/):
+//!
+//! ```sh
+//! wasm-pack build --release -- --no-default-features --features wasm
+//! ```
+//!
+//! ```js
+//! import { compress, decompress } from "lzokay";
+//!
+//! const compressed = compress(input);
+//! const output = decompress(compressed, input.length);
+//! ```
+//!
+//! For streaming, `DecompressTransformer` and `CompressTransformer` are transformers for a
+//! [`TransformStream`](https://developer.mozilla.org/en-US/docs/Web/API/TransformStream), so a
+//! `fetch()` response body can be piped through decompression:
+//!
+//! ```js
+//! import { DecompressTransformer } from "lzokay";
+//!
+//! const response = await fetch("asset.lzo");
+//! const body = response.body.pipeThrough(new TransformStream(new DecompressTransformer()));
+//! ```
+//!
+//! Decompression only holds the last 48 KiB of output and an instruction's worth of input, and
+//! emits chunks of at most 64 KiB. As an LZO1X stream can't be produced incrementally,
+//! compression buffers its input until the stream is complete.
+//!
+//! Errors are thrown as JavaScript `Error`s.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{compress as c, decompress as d, decompress::stream::Decoder, Error};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The controller a `TransformStream` passes to its transformer.
+    pub type TransformStreamDefaultController;
+
+    #[wasm_bindgen(method)]
+    fn enqueue(this: &TransformStreamDefaultController, chunk: Vec<u8>);
+}
+
+fn js_error(e: Error) -> JsError { JsError::new(&format!("{:?}", e)) }
+
+/// Compresses `src`, returning the compressed bytes.
+#[wasm_bindgen]
+pub fn compress(src: &[u8]) -> Result<Vec<u8>, JsError> { c::compress(src).map_err(js_error) }
+
+/// Decompresses `src`, which must decompress to exactly `expectedSize` bytes.
+#[wasm_bindgen]
+pub fn decompress(
+    src: &[u8],
+    #[wasm_bindgen(js_name = expectedSize)] expected_size: usize,
+) -> Result<Vec<u8>, JsError> {
+    let mut dst = vec![0u8; expected_size];
+    let size = d::decompress(src, &mut dst).map_err(js_error)?;
+    if size != expected_size {
+        return Err(js_error(Error::InputOverrun));
+    }
+    Ok(dst)
+}
+
+/// A `TransformStream` transformer decompressing chunks of a stream as they arrive.
+#[wasm_bindgen]
+pub struct DecompressTransformer {
+    decoder: Decoder,
+    /// Input not yet consumed: the start of an incomplete instruction.
+    input: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl DecompressTransformer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self { DecompressTransformer { decoder: Decoder::new(), input: Vec::new() } }
+
+    /// Decompresses `chunk`, enqueueing the output.
+    pub fn transform(
+        &mut self,
+        chunk: &[u8],
+        controller: &TransformStreamDefaultController,
+    ) -> Result<(), JsError> {
+        self.push(chunk, |output| controller.enqueue(output.to_vec())).map_err(js_error)
+    }
+
+    /// Checks that the stream is complete.
+    pub fn flush(&mut self, _controller: &TransformStreamDefaultController) -> Result<(), JsError> {
+        self.decoder.finish().map_err(js_error)
+    }
+}
+
+impl Default for DecompressTransformer {
+    fn default() -> Self { Self::new() }
+}
+
+impl DecompressTransformer {
+    /// Decodes as much of the input so far as forms complete instructions, passing the output to
+    /// `emit` in chunks borrowed from the decoder's window.
+    fn push(&mut self, chunk: &[u8], mut emit: impl FnMut(&[u8])) -> Result<(), Error> {
+        self.input.extend_from_slice(chunk);
+        let mut inp = 0;
+        let result = loop {
+            match self.decoder.next_chunk(&self.input[inp..], usize::MAX) {
+                Ok((consumed, output)) => {
+                    inp += consumed;
+                    if output.is_empty() {
+                        break Ok(());
+                    }
+                    emit(output);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        self.input.drain(..inp);
+        result?;
+        if self.decoder.is_finished() && !self.input.is_empty() {
+            return Err(Error::InputNotConsumed);
+        }
+        Ok(())
+    }
+}
+
+/// A `TransformStream` transformer compressing the whole stream, enqueued as one chunk once the
+/// input ends.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct CompressTransformer {
+    input: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl CompressTransformer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self { Self::default() }
+
+    /// Buffers `chunk` until the input ends.
+    pub fn transform(&mut self, chunk: &[u8], _controller: &TransformStreamDefaultController) {
+        self.input.extend_from_slice(chunk);
+    }
+
+    /// Compresses the input, enqueueing the compressed stream.
+    pub fn flush(&mut self, controller: &TransformStreamDefaultController) -> Result<(), JsError> {
+        let input = core::mem::take(&mut self.input);
+        controller.enqueue(c::compress(&input).map_err(js_error)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecompressTransformer;
+    use crate::Error;
+
+    const INPUT: &[u8] = include_bytes!("test1.txt");
+
+    // Only the success paths are tested here, as errors need a JavaScript host
+    #[test]
+    fn test_round_trip() {
+        let compressed = super::compress(INPUT).unwrap();
+        let dst = super::decompress(&compressed, INPUT.len()).unwrap();
+        assert_eq!(dst, INPUT);
+    }
+
+    #[test]
+    fn test_decompress_transformer() {
+        let mut compressed = super::compress(INPUT).unwrap();
+        for &size in &[1, 7, 4096, compressed.len()] {
+            let mut transformer = DecompressTransformer::new();
+            let mut output = Vec::new();
+            for chunk in compressed.chunks(size) {
+                transformer.push(chunk, |out| output.extend_from_slice(out)).unwrap();
+            }
+            assert!(transformer.decoder.finish().is_ok());
+            assert_eq!(output, INPUT);
+        }
+
+        // Truncated and trailing input
+        let mut transformer = DecompressTransformer::new();
+        transformer.push(&compressed[..compressed.len() - 1], |_| {}).unwrap();
+        assert_eq!(transformer.decoder.finish(), Err(Error::InputOverrun));
+        compressed.push(0);
+        let mut transformer = DecompressTransformer::new();
+        assert_eq!(transformer.push(&compressed, |_| {}), Err(Error::InputNotConsumed));
+    }
+}
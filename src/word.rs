@@ -0,0 +1,80 @@
+//! Word-at-a-time helpers, with byte-at-a-time fallbacks.
+//!
+//! Words are always assembled with `from_le_bytes`, never by casting pointers, so alignment is
+//! never assumed. That's only faster where the CPU has cheap unaligned loads; elsewhere, such as
+//! Cortex-M0 (`thumbv6m`), each word would be built from single-byte loads, so the byte loops
+//! are used instead. Both paths are compiled on every target and tested against each other.
+
+use core::{cmp::min, convert::TryInto, mem::size_of};
+
+/// Whether the target has fast unaligned word loads.
+const WORD_AT_A_TIME: bool = cfg!(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    all(target_arch = "arm", target_feature = "v7", not(target_feature = "mclass")),
+    target_arch = "powerpc64",
+    target_arch = "s390x",
+));
+
+const WORD: usize = size_of::<usize>();
+
+/// Length of the common prefix of `a` and `b`.
+#[inline]
+pub(crate) fn match_len(a: &[u8], b: &[u8]) -> usize {
+    if WORD_AT_A_TIME {
+        match_len_words(a, b)
+    } else {
+        match_len_bytes(a, b)
+    }
+}
+
+#[inline]
+fn match_len_words(a: &[u8], b: &[u8]) -> usize {
+    let len = min(a.len(), b.len());
+    let mut i = 0;
+    while len - i >= WORD {
+        let x = usize::from_le_bytes(a[i..i + WORD].try_into().unwrap());
+        let y = usize::from_le_bytes(b[i..i + WORD].try_into().unwrap());
+        let diff = x ^ y;
+        if diff != 0 {
+            // The lowest differing bit is in the first differing byte
+            return i + diff.trailing_zeros() as usize / 8;
+        }
+        i += WORD;
+    }
+    i + match_len_bytes(&a[i..len], &b[i..len])
+}
+
+#[inline]
+fn match_len_bytes(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_len_bytes, match_len_words};
+
+    #[test]
+    fn test_match_len() {
+        // Starting at every offset, so words are read at every alignment
+        let mut a = [0u8; 64];
+        for (i, byte) in a.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        for start in 0..16 {
+            for len in 0..40 {
+                for diff in 0..=len {
+                    let mut b = a;
+                    if diff < len {
+                        b[start + diff] ^= 0x80;
+                    }
+                    let (a, b) = (&a[start..start + len], &b[start..start + len + 3]);
+                    assert_eq!(match_len_bytes(a, b), diff);
+                    assert_eq!(match_len_words(a, b), diff, "start {}, len {}", start, len);
+                    assert_eq!(match_len_words(b, a), diff);
+                }
+            }
+        }
+    }
+}
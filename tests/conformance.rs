@@ -0,0 +1,129 @@
+//! Decodes streams produced by liblzo2 at every compression level, and with a preset
+//! dictionary.
+//!
+//! The corpus in `tests/conformance/corpus` is generated from deterministic inputs with
+//! `tests/conformance/generate.sh`, which requires liblzo2. Run with
+//! `cargo test --features conformance --test conformance`, adding the format features (e.g.
+//! `lzo1y`) to cover their streams as well.
+//!
+//! With `LZOKAY_ENCODED_DIR` set, this crate's own encoding of each input is written there
+//! too, for `tests/conformance/check.sh` to decode with liblzo2.
+#![cfg(feature = "conformance")]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use lzokay::{
+    codec::Codec,
+    compress::{new_dict, MAX_LEVEL, MIN_LEVEL},
+    decompress::decompress_with_dict,
+};
+
+fn corpus_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance/corpus");
+    assert!(
+        dir.is_dir(),
+        "{}: corpus missing, generate it with tests/conformance/generate.sh and commit it",
+        dir.display()
+    );
+    dir
+}
+
+/// Maps a liblzo2 method name (e.g. `lzo1x_999_9`) to its decoder, if enabled.
+fn codec(method: &str) -> Option<Codec> {
+    match method.get(..5)? {
+        "lzo1x" => Some(Codec::Lzo1x),
+        #[cfg(feature = "lzo1b")]
+        "lzo1b" => Some(Codec::Lzo1b),
+        #[cfg(feature = "lzo1f")]
+        "lzo1f" => Some(Codec::Lzo1f),
+        #[cfg(feature = "lzo1y")]
+        "lzo1y" => Some(Codec::Lzo1y),
+        #[cfg(feature = "lzo1z")]
+        "lzo1z" => Some(Codec::Lzo1z),
+        #[cfg(feature = "lzo2a")]
+        "lzo2a" => Some(Codec::Lzo2a),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_liblzo2_corpus() {
+    let dir = corpus_dir();
+    let entries = fs::read_dir(&dir).expect("Failed to read corpus");
+    let mut decoded = 0;
+    for entry in entries {
+        let path = entry.expect("Failed to read corpus").path();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        // <input>.<method>.lzo
+        let mut parts = file_name.split('.');
+        let (input, method) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(input), Some(method), Some("lzo")) => (input, method),
+            _ => continue,
+        };
+        let codec = match codec(method) {
+            Some(codec) => codec,
+            None => continue,
+        };
+        let expected = fs::read(dir.join(format!("{}.raw", input))).expect("Failed to read input");
+        let compressed = fs::read(&path).expect("Failed to read stream");
+        let result = if method.contains("_dict") {
+            // Decompressed after the dictionary, which its matches may copy from
+            let mut dst = fs::read(dir.join("dictionary.dict")).expect("Failed to read dictionary");
+            let dict_len = dst.len();
+            dst.resize(dict_len + expected.len(), 0);
+            decompress_with_dict(&compressed, &mut dst, dict_len)
+                .map(|size| dst[dict_len..dict_len + size] == expected[..])
+        } else {
+            let mut dst = vec![0u8; expected.len()];
+            codec.decompress(&compressed, &mut dst).map(|size| dst[..size] == expected[..])
+        };
+        let matches = result.unwrap_or_else(|e| panic!("{}: {:?}", file_name, e));
+        assert!(matches, "{}: output mismatch", file_name);
+        decoded += 1;
+    }
+    assert!(decoded > 0, "{}: no streams found", dir.display());
+}
+
+#[test]
+fn test_encode_for_liblzo2() {
+    let out = match env::var_os("LZOKAY_ENCODED_DIR") {
+        Some(out) => PathBuf::from(out),
+        None => return,
+    };
+    fs::create_dir_all(&out).expect("Failed to create output directory");
+    let codecs = [
+        Codec::Lzo1x,
+        #[cfg(feature = "lzo1y")]
+        Codec::Lzo1y,
+        #[cfg(feature = "lzo1z")]
+        Codec::Lzo1z,
+        #[cfg(feature = "lzo2a")]
+        Codec::Lzo2a,
+    ];
+    let dir = corpus_dir();
+    for entry in fs::read_dir(&dir).expect("Failed to read corpus") {
+        let path = entry.expect("Failed to read corpus").path();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let input = match file_name.strip_suffix(".raw") {
+            Some(input) => input,
+            None => continue,
+        };
+        let raw = fs::read(&path).expect("Failed to read input");
+        fs::write(out.join(file_name), &raw).expect("Failed to write input");
+        // <input>.<method>_<level>.lzo, as liblzo2's methods are named
+        for &codec in &codecs {
+            for level in MIN_LEVEL..=MAX_LEVEL {
+                let mut dict = new_dict();
+                dict.set_level(level).expect("Failed to set level");
+                let mut dst = vec![0u8; codec.worst_size(raw.len())];
+                let size = codec.compress(&raw, &mut dst, &mut dict).expect("Failed to compress");
+                let method = format!("{}_{}", codec.name().to_ascii_lowercase(), level);
+                fs::write(out.join(format!("{}.{}.lzo", input, method)), &dst[..size])
+                    .expect("Failed to write stream");
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Decodes the streams lzokay's encoders wrote to the given directory with liblzo2, checking
+# that liblzo2 reads them back as their inputs. The directory is written by the conformance
+# test when LZOKAY_ENCODED_DIR is set. Requires liblzo2 headers and library (e.g. liblzo2-dev).
+set -e
+dir=$(cd "$(dirname "$0")" && pwd)
+encoded="$1"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+${CC:-cc} -O2 -o "$tmp/generate" "$dir/generate.c" -llzo2
+for raw in "$encoded"/*.raw; do
+    "$tmp/generate" --check "$raw" "${raw%.raw}".*.lzo
+done
@@ -0,0 +1,248 @@
+/*
+ * Generates the liblzo2 conformance corpus for tests/conformance.rs.
+ *
+ * Usage: generate <output dir> <dictionary> <input file>...
+ *        generate --random <output file> <size>
+ *        generate --check <input file> <stream>...
+ *
+ * For each input, writes <name>.raw with the input itself, and <name>.<method>.lzo
+ * for every liblzo2 compressor and level below. LZO1X-999 also compresses each input
+ * against the dictionary, copied to dictionary.dict, as <name>.lzo1x_999_dict[_<level>].lzo.
+ *
+ * With --random, writes <size> pseudo-random bytes instead, the same on every run.
+ *
+ * With --check, decodes streams named <name>.<method>.lzo, as written by lzokay's own
+ * encoders, with the liblzo2 decoder of their format, and compares them to the input.
+ */
+#include <lzo/lzo1b.h>
+#include <lzo/lzo1c.h>
+#include <lzo/lzo1f.h>
+#include <lzo/lzo1x.h>
+#include <lzo/lzo1y.h>
+#include <lzo/lzo1z.h>
+#include <lzo/lzo2a.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int (*compress_fn)(const lzo_bytep, lzo_uint, lzo_bytep, lzo_uintp, lzo_voidp);
+typedef int (*compress_level_fn)(const lzo_bytep, lzo_uint, lzo_bytep, lzo_uintp, lzo_voidp,
+                                 const lzo_bytep, lzo_uint, lzo_callback_p, int);
+typedef int (*decompress_fn)(const lzo_bytep, lzo_uint, lzo_bytep, lzo_uintp, lzo_voidp);
+
+static const struct {
+    const char *name;
+    compress_fn fn;
+    lzo_uint mem;
+} methods[] = {
+    {"lzo1x_1", lzo1x_1_compress, LZO1X_1_MEM_COMPRESS},
+    {"lzo1x_1_11", lzo1x_1_11_compress, LZO1X_1_11_MEM_COMPRESS},
+    {"lzo1x_1_12", lzo1x_1_12_compress, LZO1X_1_12_MEM_COMPRESS},
+    {"lzo1x_1_15", lzo1x_1_15_compress, LZO1X_1_15_MEM_COMPRESS},
+    {"lzo1x_999", lzo1x_999_compress, LZO1X_999_MEM_COMPRESS},
+    {"lzo1y_1", lzo1y_1_compress, LZO1Y_MEM_COMPRESS},
+    {"lzo1y_999", lzo1y_999_compress, LZO1Y_999_MEM_COMPRESS},
+    {"lzo1z_999", lzo1z_999_compress, LZO1Z_999_MEM_COMPRESS},
+    {"lzo1b_1", lzo1b_1_compress, LZO1B_MEM_COMPRESS},
+    {"lzo1b_9", lzo1b_9_compress, LZO1B_MEM_COMPRESS},
+    {"lzo1b_999", lzo1b_999_compress, LZO1B_999_MEM_COMPRESS},
+    {"lzo1c_1", lzo1c_1_compress, LZO1C_MEM_COMPRESS},
+    {"lzo1c_9", lzo1c_9_compress, LZO1C_MEM_COMPRESS},
+    {"lzo1c_999", lzo1c_999_compress, LZO1C_999_MEM_COMPRESS},
+    {"lzo1f_1", lzo1f_1_compress, LZO1F_MEM_COMPRESS},
+    {"lzo1f_999", lzo1f_999_compress, LZO1F_999_MEM_COMPRESS},
+    {"lzo2a_999", lzo2a_999_compress, LZO2A_999_MEM_COMPRESS},
+};
+
+static const struct {
+    const char *name;
+    compress_level_fn fn;
+    lzo_uint mem;
+} level_methods[] = {
+    {"lzo1x_999", lzo1x_999_compress_level, LZO1X_999_MEM_COMPRESS},
+    {"lzo1y_999", lzo1y_999_compress_level, LZO1Y_999_MEM_COMPRESS},
+    {"lzo1z_999", lzo1z_999_compress_level, LZO1Z_999_MEM_COMPRESS},
+};
+
+static const struct {
+    const char *name;
+    decompress_fn fn;
+} decoders[] = {
+    {"lzo1x", lzo1x_decompress_safe},
+    {"lzo1y", lzo1y_decompress_safe},
+    {"lzo1z", lzo1z_decompress_safe},
+    {"lzo2a", lzo2a_decompress_safe},
+};
+
+static void write_path(const char *path, const unsigned char *data, size_t len) {
+    FILE *f = fopen(path, "wb");
+    if (f == NULL || fwrite(data, 1, len, f) != len || fclose(f) != 0) {
+        perror(path);
+        exit(1);
+    }
+}
+
+static void write_file(const char *dir, const char *name, const char *suffix,
+                       const unsigned char *data, size_t len) {
+    char path[4096];
+    snprintf(path, sizeof(path), "%s/%s%s", dir, name, suffix);
+    write_path(path, data, len);
+}
+
+/* xorshift32, seeded so that the corpus is reproducible. */
+static void write_random(const char *path, size_t len) {
+    unsigned char *data = malloc(len);
+    unsigned int x = 1;
+    for (size_t i = 0; i < len; i++) {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        data[i] = (unsigned char)(x >> 24);
+    }
+    write_path(path, data, len);
+    free(data);
+}
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *f = fopen(path, "rb");
+    if (f == NULL) {
+        perror(path);
+        exit(1);
+    }
+    fseek(f, 0, SEEK_END);
+    *len = (size_t)ftell(f);
+    fseek(f, 0, SEEK_SET);
+    unsigned char *data = malloc(*len + 1);
+    if (fread(data, 1, *len, f) != *len) {
+        perror(path);
+        exit(1);
+    }
+    fclose(f);
+    return data;
+}
+
+/* Decodes each stream with liblzo2, returning the number that don't decode to the input. */
+static int check(const char *input, char **streams, int count) {
+    size_t in_len;
+    unsigned char *in = read_file(input, &in_len);
+    unsigned char *out = malloc(in_len + 1);
+    int failed = 0;
+    for (int i = 0; i < count; i++) {
+        const char *name = strrchr(streams[i], '/');
+        const char *method = strchr(name ? name + 1 : streams[i], '.');
+        decompress_fn fn = NULL;
+        for (size_t d = 0; method != NULL && d < sizeof(decoders) / sizeof(decoders[0]); d++) {
+            if (strncmp(method + 1, decoders[d].name, strlen(decoders[d].name)) == 0) {
+                fn = decoders[d].fn;
+            }
+        }
+        if (fn == NULL) {
+            fprintf(stderr, "%s: unknown method\n", streams[i]);
+            failed++;
+            continue;
+        }
+        size_t stream_len;
+        unsigned char *stream = read_file(streams[i], &stream_len);
+        /* One spare byte, so that output longer than the input is caught */
+        lzo_uint out_len = in_len + 1;
+        int r = fn(stream, stream_len, out, &out_len, NULL);
+        if (r != LZO_E_OK) {
+            fprintf(stderr, "%s: liblzo2 failed with %d\n", streams[i], r);
+            failed++;
+        } else if (out_len != in_len || memcmp(out, in, in_len) != 0) {
+            fprintf(stderr, "%s: liblzo2 decoded %lu bytes, differing from the %lu byte input\n",
+                    streams[i], (unsigned long)out_len, (unsigned long)in_len);
+            failed++;
+        }
+        free(stream);
+    }
+    free(out);
+    free(in);
+    return failed;
+}
+
+int main(int argc, char **argv) {
+    if (argc == 4 && strcmp(argv[1], "--random") == 0) {
+        write_random(argv[2], (size_t)strtoul(argv[3], NULL, 10));
+        return 0;
+    }
+    if (argc >= 3 && strcmp(argv[1], "--check") == 0) {
+        if (lzo_init() != LZO_E_OK) {
+            fprintf(stderr, "lzo_init failed\n");
+            return 1;
+        }
+        return check(argv[2], argv + 3, argc - 3) != 0;
+    }
+    if (argc < 4) {
+        fprintf(stderr, "usage: %s <output dir> <dictionary> <input file>...\n", argv[0]);
+        return 1;
+    }
+    if (lzo_init() != LZO_E_OK) {
+        fprintf(stderr, "lzo_init failed\n");
+        return 1;
+    }
+    size_t dict_len;
+    unsigned char *dict = read_file(argv[2], &dict_len);
+    write_file(argv[1], "dictionary", ".dict", dict, dict_len);
+    for (int i = 3; i < argc; i++) {
+        size_t in_len;
+        unsigned char *in = read_file(argv[i], &in_len);
+        const char *name = strrchr(argv[i], '/');
+        name = name ? name + 1 : argv[i];
+        char *base = strdup(name);
+        char *dot = strchr(base, '.');
+        if (dot) *dot = '\0';
+        write_file(argv[1], base, ".raw", in, in_len);
+
+        unsigned char *out = malloc(in_len + in_len / 8 + 128);
+        char suffix[64];
+        for (size_t m = 0; m < sizeof(methods) / sizeof(methods[0]); m++) {
+            void *wrkmem = malloc(methods[m].mem);
+            lzo_uint out_len = 0;
+            if (methods[m].fn(in, in_len, out, &out_len, wrkmem) != LZO_E_OK) {
+                fprintf(stderr, "%s: %s failed\n", argv[i], methods[m].name);
+                return 1;
+            }
+            snprintf(suffix, sizeof(suffix), ".%s.lzo", methods[m].name);
+            write_file(argv[1], base, suffix, out, out_len);
+            free(wrkmem);
+        }
+        for (size_t m = 0; m < sizeof(level_methods) / sizeof(level_methods[0]); m++) {
+            void *wrkmem = malloc(level_methods[m].mem);
+            for (int level = 1; level <= 9; level++) {
+                lzo_uint out_len = 0;
+                if (level_methods[m].fn(in, in_len, out, &out_len, wrkmem, NULL, 0, NULL, level) !=
+                    LZO_E_OK) {
+                    fprintf(stderr, "%s: %s level %d failed\n", argv[i], level_methods[m].name,
+                            level);
+                    return 1;
+                }
+                snprintf(suffix, sizeof(suffix), ".%s_%d.lzo", level_methods[m].name, level);
+                write_file(argv[1], base, suffix, out, out_len);
+            }
+            free(wrkmem);
+        }
+        void *wrkmem = malloc(LZO1X_999_MEM_COMPRESS);
+        lzo_uint out_len = 0;
+        if (lzo1x_999_compress_dict(in, in_len, out, &out_len, wrkmem, dict, dict_len) != LZO_E_OK) {
+            fprintf(stderr, "%s: lzo1x_999_dict failed\n", argv[i]);
+            return 1;
+        }
+        write_file(argv[1], base, ".lzo1x_999_dict.lzo", out, out_len);
+        for (int level = 1; level <= 9; level++) {
+            if (lzo1x_999_compress_level(in, in_len, out, &out_len, wrkmem, dict, dict_len, NULL,
+                                         level) != LZO_E_OK) {
+                fprintf(stderr, "%s: lzo1x_999_dict level %d failed\n", argv[i], level);
+                return 1;
+            }
+            snprintf(suffix, sizeof(suffix), ".lzo1x_999_dict_%d.lzo", level);
+            write_file(argv[1], base, suffix, out, out_len);
+        }
+        free(wrkmem);
+        free(out);
+        free(base);
+        free(in);
+    }
+    free(dict);
+    return 0;
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+# Builds the corpus generator against liblzo2 and regenerates tests/conformance/corpus, which
+# is committed. The inputs are deterministic, so the corpus only changes with liblzo2.
+# Requires liblzo2 headers and library (e.g. liblzo2-dev).
+set -e
+dir=$(cd "$(dirname "$0")" && pwd)
+root="$dir/../.."
+out="$dir/corpus"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+rm -rf "$out"
+mkdir -p "$out"
+${CC:-cc} -O2 -o "$tmp/generate" "$dir/generate.c" -llzo2
+head -c 16384 /dev/zero > "$tmp/zeros.bin"
+"$tmp/generate" --random "$tmp/random.bin" 16384
+"$tmp/generate" "$out" "$root/src/test2.txt" \
+    "$root/src/test1.txt" "$root/src/test2.txt" "$tmp/zeros.bin" "$tmp/random.bin"
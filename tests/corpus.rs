@@ -0,0 +1,77 @@
+//! Golden compressed sizes and hashes for the Canterbury and Calgary corpora.
+//!
+//! Fetch the corpora with `tests/corpus/fetch.sh`, then run with
+//! `cargo test --release --features corpus --test corpus`. After an intentional change to
+//! the encoder's output, set `LZOKAY_BLESS=1` to rewrite `tests/corpus/golden.txt`.
+#![cfg(feature = "corpus")]
+
+use std::{env, fs, path::Path};
+
+use lzokay::{compress::compress, decompress::decompress};
+
+/// 64-bit FNV-1a, stable across platforms and releases.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[test]
+fn test_corpus_golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let golden_path = dir.join("golden.txt");
+    let golden = fs::read_to_string(&golden_path).expect("Failed to read golden.txt");
+    let mut results = Vec::new();
+    for corpus in ["canterbury", "calgary"].iter() {
+        let corpus_dir = dir.join("data").join(corpus);
+        let mut paths = fs::read_dir(&corpus_dir)
+            .unwrap_or_else(|e| {
+                panic!("{}: {} (run tests/corpus/fetch.sh)", corpus_dir.display(), e)
+            })
+            .map(|entry| entry.expect("Failed to read corpus").path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let input = fs::read(&path).expect("Failed to read input");
+            let compressed = compress(&input).expect("Failed to compress");
+            let mut dst = vec![0u8; input.len()];
+            let size = decompress(&compressed, &mut dst).expect("Failed to decompress");
+            assert!(size == input.len() && dst == input, "{}: round trip failed", path.display());
+            let name = format!("{}/{}", corpus, path.file_name().unwrap().to_str().unwrap());
+            results.push(format!(
+                "{} {} {:016x} {} {:016x}",
+                name,
+                input.len(),
+                fnv1a(&input),
+                compressed.len(),
+                fnv1a(&compressed)
+            ));
+        }
+    }
+
+    if env::var_os("LZOKAY_BLESS").is_some() {
+        let mut out = String::from(golden.lines().next().unwrap_or_default());
+        for line in &results {
+            out.push('\n');
+            out.push_str(line);
+        }
+        out.push('\n');
+        fs::write(&golden_path, out).expect("Failed to write golden.txt");
+        return;
+    }
+    for line in &results {
+        let name = line.split(' ').next().unwrap();
+        let expected = golden.lines().find(|l| l.split(' ').next() == Some(name));
+        match expected {
+            Some(expected) => assert_eq!(line, expected, "{}: output changed", name),
+            None => panic!("{}: missing from golden.txt (run with LZOKAY_BLESS=1)", name),
+        }
+    }
+    // Every file of the corpora must be there, not only some of them
+    for expected in golden.lines().skip(1) {
+        let name = expected.split(' ').next().unwrap();
+        assert!(
+            results.iter().any(|l| l.split(' ').next() == Some(name)),
+            "{}: missing from tests/corpus/data (run tests/corpus/fetch.sh)",
+            name
+        );
+    }
+}
@@ -0,0 +1,10 @@
+#!/bin/sh
+# Downloads the Canterbury and Calgary corpora into tests/corpus/data.
+set -e
+dir=$(cd "$(dirname "$0")" && pwd)
+base=https://corpus.canterbury.ac.nz/resources
+for corpus in canterbury:cantrbry calgary:calgary; do
+    name=${corpus%%:*}
+    mkdir -p "$dir/data/$name"
+    curl -fsSL "$base/${corpus#*:}.tar.gz" | tar xz -C "$dir/data/$name"
+done
@@ -0,0 +1,78 @@
+//! Golden vectors for the byte-order-sensitive parts of each format: little-endian distances in
+//! LZO1X, big-endian fields in lzop files, and the checksums.
+//!
+//! These pass trivially on little-endian hosts. CI runs them on big-endian targets with `cross`:
+//!
+//! ```sh
+//! cross test --target s390x-unknown-linux-gnu --features lzop --test endian
+//! ```
+#![cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
+
+use lzokay::{
+    checksum::{adler32, crc32, ADLER32_INIT, CRC32_INIT},
+    compress::compress,
+    decompress::decompress,
+};
+
+/// Pseudo-random bytes from a small alphabet, with copies placed beyond the M3 distance limit
+/// so that compression emits M4 matches.
+fn input() -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+    let mut data: Vec<u8> = (0..40000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            b'a' + (state % 8) as u8
+        })
+        .collect();
+    data.extend_from_within(100..2100);
+    data.extend_from_within(30000..31000);
+    data
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(CRC32_INIT, b"123456789"), 0xcbf4_3926);
+    assert_eq!(adler32(ADLER32_INIT, b"123456789"), 0x091e_01de);
+}
+
+#[test]
+fn test_decompress_distances() {
+    // Four literals, then an M3 match of 5 bytes at distance 4, stored as little-endian
+    // (4 - 1) << 2
+    let src = [0x15, b'a', b'b', b'c', b'd', 0x23, 0x0c, 0x00, 0x11, 0x00, 0x00];
+    let mut dst = [0u8; 9];
+    assert_eq!(decompress(&src, &mut dst), Ok(9));
+    assert_eq!(&dst, b"abcdabcda");
+}
+
+#[test]
+fn test_compress() {
+    let input = input();
+    let compressed = compress(&input).expect("Failed to compress");
+    assert_eq!((compressed.len(), crc32(CRC32_INIT, &compressed)), (22738, 0x54db_6269));
+    let mut dst = vec![0u8; input.len()];
+    assert_eq!(decompress(&compressed, &mut dst), Ok(input.len()));
+    assert_eq!(dst, input);
+}
+
+#[test]
+#[cfg(feature = "lzop")]
+fn test_lzop() {
+    use std::io::{Read, Write};
+
+    use lzokay::lzop::{Header, Reader, Writer};
+
+    let header = Header { mtime: 0x0102_0304_0506, name: b"data".to_vec(), ..Header::default() };
+    let mut writer = Writer::with_header(Vec::new(), &header).expect("Failed to write header");
+    writer.write_all(&input()).expect("Failed to compress");
+    let file = writer.finish().expect("Failed to finish");
+    assert_eq!((file.len(), crc32(CRC32_INIT, &file)), (22800, 0x3655_3ac8));
+
+    let mut reader = Reader::new(&file[..]).expect("Failed to read header");
+    assert_eq!(reader.header(), &header);
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output).expect("Failed to decompress");
+    assert_eq!(output, input());
+}
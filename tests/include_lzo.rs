@@ -0,0 +1,29 @@
+//! `include_lzo!` paired with the runtime and const decompressors.
+#![cfg(all(feature = "macros", feature = "decompress"))]
+
+use lzokay::{decompress::decompress, include_lzo};
+
+const TEXT: (&[u8], usize) = include_lzo!("src/test1.txt");
+const EXPECTED: &[u8] = include_bytes!("../src/test1.txt");
+
+#[test]
+fn test_include_lzo() {
+    // Same output as the runtime compressor
+    assert_eq!(TEXT.0, include_bytes!("../src/test1.bin"));
+    assert_eq!(TEXT.1, EXPECTED.len());
+    let mut dst = vec![0u8; TEXT.1];
+    assert_eq!(decompress(TEXT.0, &mut dst), Ok(TEXT.1));
+    assert_eq!(dst, EXPECTED);
+}
+
+#[test]
+#[cfg(feature = "const-decompress")]
+fn test_include_lzo_const() {
+    use lzokay::decompress::decompress_const;
+
+    const DECOMPRESSED: [u8; TEXT.1] = match decompress_const::<{ TEXT.1 }>(TEXT.0) {
+        Ok((dst, _)) => dst,
+        Err(_) => panic!("Failed to decompress"),
+    };
+    assert_eq!(&DECOMPRESSED[..], EXPECTED);
+}
@@ -0,0 +1,75 @@
+//! Round trips of inputs larger than 4 GiB, checking that no position or length is truncated
+//! to 32 bits.
+//!
+//! Each test needs about 9 GiB of memory, so they're run one at a time. Run with
+//! `cargo test --release --features large-input --test large_input`.
+#![cfg(all(feature = "large-input", target_pointer_width = "64"))]
+
+use std::sync::Mutex;
+
+use lzokay::{
+    compress::{compress_with_dict, new_dict},
+    decompress::decompress,
+};
+
+/// Just over 4 GiB.
+const SIZE: usize = (1 << 32) + (1 << 20);
+
+/// Serializes the tests, so only one set of buffers is allocated at a time.
+static MEMORY: Mutex<()> = Mutex::new(());
+
+/// A de Bruijn sequence of every 3-byte string, generated from Lyndon words.
+///
+/// Repeated, it has no 3-byte match within the compressor's window, so it's encoded as one
+/// literal run.
+fn de_bruijn() -> Vec<u8> {
+    let mut seq = Vec::with_capacity(1 << 24);
+    let mut word: Vec<i32> = vec![-1];
+    while !word.is_empty() {
+        *word.last_mut().unwrap() += 1;
+        let len = word.len();
+        if 3 % len == 0 {
+            seq.extend(word.iter().map(|&b| b as u8));
+        }
+        while word.len() < 3 {
+            word.push(word[word.len() - len]);
+        }
+        while word.last() == Some(&255) {
+            word.pop();
+        }
+    }
+    seq
+}
+
+/// Compresses `pattern` repeated to `SIZE` bytes and checks the round trip, returning the
+/// compressed size.
+fn round_trip(pattern: &[u8], level: u8) -> usize {
+    let _guard = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    let input: Vec<u8> = pattern.iter().cycle().take(SIZE).copied().collect();
+    let mut dict = new_dict();
+    dict.set_level(level).unwrap();
+    let compressed = compress_with_dict(&input, &mut dict).expect("Failed to compress");
+    drop(input);
+
+    let mut dst = vec![0u8; SIZE];
+    assert_eq!(decompress(&compressed, &mut dst), Ok(SIZE));
+    for (i, chunk) in dst.chunks(pattern.len()).enumerate() {
+        assert!(chunk == &pattern[..chunk.len()], "Mismatch in chunk {}", i);
+    }
+    compressed.len()
+}
+
+#[test]
+fn test_literal_run() {
+    let pattern = de_bruijn();
+    assert_eq!(pattern.len(), 1 << 24);
+    // Nothing but literals
+    assert!(round_trip(&pattern, 1) > SIZE);
+}
+
+#[test]
+fn test_matches() {
+    // Varied enough to use every match type, with offsets past 4 GiB in the output
+    let pattern: Vec<u8> = (0..100_000u32).map(|i| (i * i / 7 % 251) as u8).collect();
+    assert!(round_trip(&pattern, 1) < SIZE / 2);
+}
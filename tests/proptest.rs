@@ -0,0 +1,70 @@
+//! Property-based round trip tests over structured inputs.
+#![cfg(all(feature = "compress", feature = "decompress", feature = "alloc"))]
+
+use lzokay::{
+    compress::{compress, compress_no_alloc, compress_worst_size, new_dict},
+    decompress::decompress,
+};
+use proptest::{collection::vec, prelude::*, sample::select};
+
+/// A run of one byte, a repeated short pattern, or random bytes.
+fn segment() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        (any::<u8>(), 1..300usize).prop_map(|(b, n)| vec![b; n]),
+        (vec(any::<u8>(), 1..16), 1..64usize).prop_map(|(pattern, n)| pattern.repeat(n)),
+        vec(any::<u8>(), 1..300),
+    ]
+}
+
+fn mixed() -> impl Strategy<Value = Vec<u8>> { vec(segment(), 0..32).prop_map(|s| s.concat()) }
+
+/// Inputs sized just around the M2 distance limit and the compressor's window size.
+fn boundary() -> impl Strategy<Value = Vec<u8>> {
+    (vec(segment(), 1..8), select(vec![0x800usize, 0xbfff, 0xc000]), -2isize..=2).prop_map(
+        |(segments, size, delta)| {
+            let seed = segments.concat();
+            seed.iter().cycle().take((size as isize + delta) as usize).copied().collect()
+        },
+    )
+}
+
+fn check_round_trip(input: &[u8]) -> Result<(), TestCaseError> {
+    let compressed = compress(input).expect("Failed to compress");
+    prop_assert!(compressed.len() <= compress_worst_size(input.len()));
+
+    // The no-alloc entry point must produce the same stream, within the worst-case size
+    let mut dst = vec![0u8; compress_worst_size(input.len())];
+    let size = compress_no_alloc(input, &mut dst, &mut new_dict()).expect("Failed to compress");
+    prop_assert_eq!(&dst[..size], compressed.as_slice());
+
+    let mut decompressed = vec![0u8; input.len()];
+    let size = decompress(&compressed, &mut decompressed).expect("Failed to decompress");
+    prop_assert_eq!(size, input.len());
+    prop_assert_eq!(decompressed, input);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn round_trip_mixed(input in mixed()) { check_round_trip(&input)?; }
+
+    #[test]
+    fn round_trip_random(input in vec(any::<u8>(), 0..4096)) { check_round_trip(&input)?; }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn round_trip_boundary(input in boundary()) { check_round_trip(&input)?; }
+}
+
+proptest! {
+    #[test]
+    fn decompress_never_panics(src in vec(any::<u8>(), 0..512), size in 0..4096usize) {
+        let mut dst = vec![0u8; size];
+        if let Ok(n) = decompress(&src, &mut dst) {
+            prop_assert!(n <= size);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+#include "wrapper.hpp"
+
+lzokay::EResult lzokay_compress(const uint8_t* src, size_t src_size, uint8_t* dst,
+                                size_t dst_size, size_t* out_size, lzokay::DictBase* dict) {
+  return lzokay::compress(src, src_size, dst, dst_size, *out_size, *dict);
+}
+
+lzokay::EResult lzokay_decompress(const uint8_t* src, size_t src_size, uint8_t* dst,
+                                  size_t dst_size, size_t* out_size) {
+  return lzokay::decompress(src, src_size, dst, dst_size, *out_size);
+}
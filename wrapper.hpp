@@ -1 +1,13 @@
+// C entry points into the upstream C++ implementation.
+//
+// Unlike the namespaced C++ functions, these have unmangled symbol names, so the generated
+// bindings in src/sys/bindings.rs are the same on every target.
+#pragma once
 #include <lzokay.hpp>
+
+extern "C" {
+lzokay::EResult lzokay_compress(const uint8_t* src, size_t src_size, uint8_t* dst,
+                                size_t dst_size, size_t* out_size, lzokay::DictBase* dict);
+lzokay::EResult lzokay_decompress(const uint8_t* src, size_t src_size, uint8_t* dst,
+                                  size_t dst_size, size_t* out_size);
+}